@@ -13,6 +13,8 @@ use testcontainers::{
 };
 
 mod diff_test_helper;
+#[cfg(test)]
+mod mirage_tests;
 mod models;
 mod sanity_test;
 mod sdk_tests;
@@ -0,0 +1,233 @@
+use ahash::AHashMap;
+use aptos_indexer_testing_framework::database::{PostgresTestDatabase, TestDatabase};
+use bigdecimal::BigDecimal;
+use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use processor::{
+    db::common::models::vault_models::{
+        vault_activities::VaultActivityModel,
+        vault_datas::{CurrentVault, VaultModel},
+        vault_lineage::VaultLineage,
+    },
+    processors::mirage_processor::{insert_to_db, reset_mirage_tables_from_version},
+    schema::{current_vaults, vault_lineage},
+    utils::database::{new_db_pool, run_pending_migrations, ArcDbPool},
+};
+
+async fn setup() -> (PostgresTestDatabase, PgConnection, ArcDbPool) {
+    let mut db = PostgresTestDatabase::new();
+    db.setup().await.unwrap();
+    let mut conn = PgConnection::establish(&db.get_db_url()).unwrap();
+    run_pending_migrations(&mut conn);
+    let pool = new_db_pool(&db.get_db_url(), None).await.unwrap();
+    (db, conn, pool)
+}
+
+fn vault(vault_id: &str, version: i64, collateral_amount: u64, borrow_part: u64) -> VaultModel {
+    VaultModel {
+        transaction_version: version,
+        write_set_change_index: 0,
+        owner_addr: "0xowner".to_string(),
+        collection_id: "0xc".to_string(),
+        vault_id: vault_id.to_string(),
+        collateral_amount: BigDecimal::from(collateral_amount),
+        borrow_part: BigDecimal::from(borrow_part),
+        transaction_timestamp: chrono::NaiveDateTime::default(),
+    }
+}
+
+fn merge(version: i64, src_vault_id: &str, dst_vault_id: &str) -> VaultActivityModel {
+    VaultActivityModel {
+        transaction_version: version,
+        event_creation_number: 0,
+        event_sequence_number: 0,
+        event_index: 0,
+        collection_id: "0xc".to_string(),
+        event_type: "MergeVaultEvent".to_string(),
+        vault_id: Some(dst_vault_id.to_string()),
+        src_vault_id: Some(src_vault_id.to_string()),
+        owner_addr: Some("0xowner".to_string()),
+        collateral_amount: None,
+        borrow_amount: None,
+        fee_amount: None,
+        socialized_amount: None,
+        collateralization_rate_before: None,
+        collateralization_rate_after: None,
+        new_interest_per_second: None,
+        transaction_timestamp: chrono::NaiveDateTime::default(),
+    }
+}
+
+/// Writes a batch of vault rows the way the mirage processor does
+async fn write_vaults(
+    pool: &ArcDbPool,
+    version: u64,
+    vault_datas: &[VaultModel],
+    current_vaults: &[CurrentVault],
+    vault_lineages: &[VaultLineage],
+) {
+    insert_to_db(
+        pool.clone(),
+        "mirage_processor",
+        version,
+        version,
+        &[],
+        &[],
+        &[],
+        vault_datas,
+        &[],
+        current_vaults,
+        vault_lineages,
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        &[],
+        false,
+        &AHashMap::new(),
+    )
+    .await
+    .unwrap();
+}
+
+fn current_vault(conn: &mut PgConnection, vault_id: &str) -> (BigDecimal, BigDecimal, bool) {
+    current_vaults::table
+        .filter(current_vaults::vault_id.eq(vault_id))
+        .select((
+            current_vaults::collateral_amount,
+            current_vaults::borrow_part,
+            current_vaults::is_closed,
+        ))
+        .first(conn)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_merge_written_before_earlier_batch() {
+    let (_db, mut conn, pool) = setup().await;
+
+    // The source is written at version 10 and merged into the destination at version 12, but
+    // the batch with the merge is written first
+    let src_write = vault("0xsrc", 10, 100, 40);
+    let dst_write = vault("0xdst", 12, 150, 60);
+    let (merge_lineages, merged_vaults) = VaultLineage::from_vault_activities(
+        &[merge(12, "0xsrc", "0xdst")],
+        &AHashMap::new(),
+        "0xsender",
+    );
+    let merge_current_vaults = [vec![CurrentVault::from(&dst_write)], merged_vaults].concat();
+    write_vaults(
+        &pool,
+        12,
+        &[dst_write],
+        &merge_current_vaults,
+        &merge_lineages,
+    )
+    .await;
+    write_vaults(
+        &pool,
+        10,
+        &[src_write.clone()],
+        &[CurrentVault::from(&src_write)],
+        &[],
+    )
+    .await;
+
+    assert_eq!(
+        current_vault(&mut conn, "0xsrc"),
+        (BigDecimal::from(0), BigDecimal::from(0), true)
+    );
+    assert_eq!(
+        current_vault(&mut conn, "0xdst"),
+        (BigDecimal::from(150), BigDecimal::from(60), false)
+    );
+    let lineage: (Option<BigDecimal>, Option<BigDecimal>) = vault_lineage::table
+        .select((vault_lineage::collateral_amount, vault_lineage::borrow_part))
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(
+        lineage,
+        (Some(BigDecimal::from(100)), Some(BigDecimal::from(40)))
+    );
+}
+
+#[tokio::test]
+async fn test_backfill_replays_merge() {
+    let (_db, mut conn, pool) = setup().await;
+
+    let src_write = vault("0xsrc", 10, 100, 40);
+    let dst_write = vault("0xdst", 11, 50, 20);
+    let merge_dst_write = vault("0xdst", 12, 150, 60);
+    let merge_batch = || {
+        let (lineages, merged_vaults) = VaultLineage::from_vault_activities(
+            &[merge(12, "0xsrc", "0xdst")],
+            &AHashMap::new(),
+            "0xsender",
+        );
+        let current_vaults = [vec![CurrentVault::from(&merge_dst_write)], merged_vaults].concat();
+        (current_vaults, lineages)
+    };
+    for write in [&src_write, &dst_write] {
+        write_vaults(
+            &pool,
+            write.transaction_version as u64,
+            &[write.clone()],
+            &[CurrentVault::from(write)],
+            &[],
+        )
+        .await;
+    }
+    let (current_vaults, lineages) = merge_batch();
+    write_vaults(
+        &pool,
+        12,
+        &[merge_dst_write.clone()],
+        &current_vaults,
+        &lineages,
+    )
+    .await;
+
+    // Both vaults are back to their state before the merge
+    reset_mirage_tables_from_version(pool.clone(), "mirage_processor", 12)
+        .await
+        .unwrap();
+    assert_eq!(
+        current_vault(&mut conn, "0xsrc"),
+        (BigDecimal::from(100), BigDecimal::from(40), false)
+    );
+    assert_eq!(
+        current_vault(&mut conn, "0xdst"),
+        (BigDecimal::from(50), BigDecimal::from(20), false)
+    );
+
+    let (current_vaults, lineages) = merge_batch();
+    write_vaults(
+        &pool,
+        12,
+        &[merge_dst_write.clone()],
+        &current_vaults,
+        &lineages,
+    )
+    .await;
+    assert_eq!(
+        current_vault(&mut conn, "0xsrc"),
+        (BigDecimal::from(0), BigDecimal::from(0), true)
+    );
+    assert_eq!(
+        current_vault(&mut conn, "0xdst"),
+        (BigDecimal::from(150), BigDecimal::from(60), false)
+    );
+    let lineage: (Option<BigDecimal>, Option<BigDecimal>) = vault_lineage::table
+        .select((vault_lineage::collateral_amount, vault_lineage::borrow_part))
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(
+        lineage,
+        (Some(BigDecimal::from(100)), Some(BigDecimal::from(40)))
+    );
+}
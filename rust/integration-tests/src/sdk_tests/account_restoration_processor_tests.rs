@@ -40,6 +40,7 @@ pub fn setup_account_restoration_processor_config(
             backfill_config: None,
             bootstrap_config: None,
             testing_config: Some(testing_config),
+            stream_config: None,
             mode: ProcessorMode::Testing,
         },
         processor_name,
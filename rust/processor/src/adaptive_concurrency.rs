@@ -0,0 +1,407 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Scales the number of active processing tasks and the transactions per batch while the
+//! processor runs. During a backfill batches queue up in the channel and Postgres has headroom,
+//! so more tasks are let through; once DB insertion slows down or the pool runs out of
+//! connections, tasks are parked again and batches get smaller.
+
+use crate::{
+    grpc_stream::TransactionsPBResponse,
+    utils::{
+        counters::{PB_CHANNEL_TXN_CHUNK_SIZE, PROCESSOR_ACTIVE_TASKS},
+        database::ArcDbPool,
+    },
+    worker::{BUFFER_SIZE, PROCESSOR_SERVICE_TYPE},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use server_framework::status::update_processor_progress;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::info;
+
+/// DB insertion time, as a multiple of the target, above which concurrency is reduced
+const OVERLOADED_DB_INSERTION_TIME_FACTOR: f64 = 1.5;
+/// Share of the channel that must be full before concurrency is increased, otherwise the
+/// processor is waiting on the stream rather than on its tasks
+const BACKLOGGED_CHANNEL_UTILIZATION: f64 = 0.5;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveConcurrencyConfig {
+    /// Fewest processing tasks pulling batches from the channel
+    #[serde(default = "AdaptiveConcurrencyConfig::default_min_concurrent_tasks")]
+    pub min_concurrent_tasks: usize,
+    /// Most processing tasks pulling batches from the channel. Defaults to
+    /// `number_concurrent_processing_tasks`
+    #[serde(default)]
+    pub max_concurrent_tasks: Option<usize>,
+    /// Fewest transactions per batch
+    #[serde(default = "AdaptiveConcurrencyConfig::default_min_pb_channel_txn_chunk_size")]
+    pub min_pb_channel_txn_chunk_size: usize,
+    /// Most transactions per batch. Defaults to `pb_channel_txn_chunk_size`
+    #[serde(default)]
+    pub max_pb_channel_txn_chunk_size: Option<usize>,
+    /// Average DB insertion time per batch to aim for
+    #[serde(default = "AdaptiveConcurrencyConfig::default_target_db_insertion_time_secs")]
+    pub target_db_insertion_time_secs: f64,
+    /// Share of the DB pool in use above which concurrency is reduced
+    #[serde(default = "AdaptiveConcurrencyConfig::default_max_db_pool_utilization")]
+    pub max_db_pool_utilization: f64,
+    /// Time between adjustments
+    #[serde(default = "AdaptiveConcurrencyConfig::default_adjustment_interval_secs")]
+    pub adjustment_interval_secs: u64,
+}
+
+impl AdaptiveConcurrencyConfig {
+    pub const fn default_min_concurrent_tasks() -> usize {
+        1
+    }
+
+    pub const fn default_min_pb_channel_txn_chunk_size() -> usize {
+        100
+    }
+
+    pub const fn default_target_db_insertion_time_secs() -> f64 {
+        1.0
+    }
+
+    pub const fn default_max_db_pool_utilization() -> f64 {
+        0.8
+    }
+
+    pub const fn default_adjustment_interval_secs() -> u64 {
+        10
+    }
+}
+
+/// What the processor looked like over the last interval
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadSample {
+    /// `None` if no batch was written
+    pub avg_db_insertion_time_secs: Option<f64>,
+    pub db_pool_utilization: f64,
+    pub channel_utilization: f64,
+}
+
+/// Shared between the worker, the processor tasks and the fetcher. Tasks with an index at or
+/// above [`Self::concurrent_tasks`] wait before taking another batch, and the fetcher reads
+/// the chunk size for every response it splits. A parked task never sees the channel close,
+/// so the first task that does ends the stream for the others.
+pub struct AdaptiveConcurrency {
+    min_concurrent_tasks: usize,
+    max_concurrent_tasks: usize,
+    min_pb_channel_txn_chunk_size: usize,
+    max_pb_channel_txn_chunk_size: usize,
+    target_db_insertion_time_secs: f64,
+    max_db_pool_utilization: f64,
+    adjustment_interval: Duration,
+    db_pool_size: u32,
+    concurrent_tasks: watch::Sender<usize>,
+    stream_ended: watch::Sender<bool>,
+    pb_channel_txn_chunk_size: Arc<AtomicUsize>,
+    // Sum and count of the DB insertion times since the last adjustment
+    db_insertion_times: Mutex<(f64, u64)>,
+}
+
+impl AdaptiveConcurrency {
+    /// Starts at the configured `number_concurrent_processing_tasks` and
+    /// `pb_channel_txn_chunk_size`, within the bounds.
+    pub fn new(
+        config: &AdaptiveConcurrencyConfig,
+        number_concurrent_processing_tasks: usize,
+        pb_channel_txn_chunk_size: usize,
+        db_pool_size: u32,
+    ) -> Self {
+        let min_concurrent_tasks = config.min_concurrent_tasks.max(1);
+        let max_concurrent_tasks = config
+            .max_concurrent_tasks
+            .unwrap_or(number_concurrent_processing_tasks)
+            .max(min_concurrent_tasks);
+        let min_pb_channel_txn_chunk_size = config.min_pb_channel_txn_chunk_size.max(1);
+        let max_pb_channel_txn_chunk_size = config
+            .max_pb_channel_txn_chunk_size
+            .unwrap_or(pb_channel_txn_chunk_size)
+            .max(min_pb_channel_txn_chunk_size);
+        let (concurrent_tasks, _) = watch::channel(
+            number_concurrent_processing_tasks.clamp(min_concurrent_tasks, max_concurrent_tasks),
+        );
+        Self {
+            min_concurrent_tasks,
+            max_concurrent_tasks,
+            min_pb_channel_txn_chunk_size,
+            max_pb_channel_txn_chunk_size,
+            target_db_insertion_time_secs: config.target_db_insertion_time_secs,
+            max_db_pool_utilization: config.max_db_pool_utilization,
+            adjustment_interval: Duration::from_secs(config.adjustment_interval_secs.max(1)),
+            db_pool_size: db_pool_size.max(1),
+            concurrent_tasks,
+            stream_ended: watch::channel(false).0,
+            pb_channel_txn_chunk_size: Arc::new(AtomicUsize::new(
+                pb_channel_txn_chunk_size
+                    .clamp(min_pb_channel_txn_chunk_size, max_pb_channel_txn_chunk_size),
+            )),
+            db_insertion_times: Mutex::new((0.0, 0)),
+        }
+    }
+
+    /// Number of processing tasks to spawn, some of which may be parked
+    pub fn max_concurrent_tasks(&self) -> usize {
+        self.max_concurrent_tasks
+    }
+
+    /// Number of processing tasks currently allowed to take batches
+    pub fn concurrent_tasks(&self) -> watch::Receiver<usize> {
+        self.concurrent_tasks.subscribe()
+    }
+
+    /// Waits until the task with `task_index` may take a batch. Returns false if the stream
+    /// ends first, see [`Self::end_stream`].
+    pub async fn wait_for_turn(&self, task_index: usize) -> bool {
+        let mut concurrent_tasks = self.concurrent_tasks.subscribe();
+        let mut stream_ended = self.stream_ended.subscribe();
+        tokio::select! {
+            _ = concurrent_tasks.wait_for(|concurrent_tasks| task_index < *concurrent_tasks) => true,
+            _ = stream_ended.wait_for(|stream_ended| *stream_ended) => false,
+        }
+    }
+
+    /// Releases the parked tasks once the channel has closed, e.g. at the ending version.
+    pub fn end_stream(&self) {
+        self.stream_ended.send_replace(true);
+    }
+
+    pub fn pb_channel_txn_chunk_size(&self) -> Arc<AtomicUsize> {
+        self.pb_channel_txn_chunk_size.clone()
+    }
+
+    pub fn record_db_insertion_time(&self, db_insertion_time_secs: f64) {
+        let mut db_insertion_times = self.db_insertion_times.lock().unwrap();
+        db_insertion_times.0 += db_insertion_time_secs;
+        db_insertion_times.1 += 1;
+    }
+
+    /// Backs off when batches take too long to insert or the pool is nearly exhausted, and
+    /// scales up when batches are queueing and the DB keeps up. The number of tasks is
+    /// adjusted first and the chunk size once it's at a bound.
+    pub fn next_limits(
+        &self,
+        sample: LoadSample,
+        concurrent_tasks: usize,
+        pb_channel_txn_chunk_size: usize,
+    ) -> (usize, usize) {
+        let overloaded = sample.db_pool_utilization > self.max_db_pool_utilization
+            || sample.avg_db_insertion_time_secs.is_some_and(|time| {
+                time > self.target_db_insertion_time_secs * OVERLOADED_DB_INSERTION_TIME_FACTOR
+            });
+        let has_headroom = sample
+            .avg_db_insertion_time_secs
+            .is_some_and(|time| time < self.target_db_insertion_time_secs)
+            && sample.channel_utilization >= BACKLOGGED_CHANNEL_UTILIZATION;
+
+        if overloaded {
+            if concurrent_tasks > self.min_concurrent_tasks {
+                let concurrent_tasks = (concurrent_tasks * 3 / 4)
+                    .min(concurrent_tasks - 1)
+                    .max(self.min_concurrent_tasks);
+                return (concurrent_tasks, pb_channel_txn_chunk_size);
+            }
+            let pb_channel_txn_chunk_size =
+                (pb_channel_txn_chunk_size / 2).max(self.min_pb_channel_txn_chunk_size);
+            return (concurrent_tasks, pb_channel_txn_chunk_size);
+        }
+        if has_headroom {
+            if concurrent_tasks < self.max_concurrent_tasks {
+                return (concurrent_tasks + 1, pb_channel_txn_chunk_size);
+            }
+            let pb_channel_txn_chunk_size = pb_channel_txn_chunk_size
+                .saturating_add((pb_channel_txn_chunk_size / 4).max(1))
+                .min(self.max_pb_channel_txn_chunk_size);
+            return (concurrent_tasks, pb_channel_txn_chunk_size);
+        }
+        (concurrent_tasks, pb_channel_txn_chunk_size)
+    }
+
+    fn sample(
+        &self,
+        db_pool: &ArcDbPool,
+        receiver: &kanal::AsyncReceiver<TransactionsPBResponse>,
+    ) -> LoadSample {
+        let (total_time, count) = std::mem::take(&mut *self.db_insertion_times.lock().unwrap());
+        let state = db_pool.state();
+        LoadSample {
+            avg_db_insertion_time_secs: (count > 0).then(|| total_time / count as f64),
+            db_pool_utilization: (state.connections - state.idle_connections) as f64
+                / self.db_pool_size as f64,
+            channel_utilization: receiver.len() as f64 / BUFFER_SIZE as f64,
+        }
+    }
+
+    /// Adjusts the limits every `adjustment_interval_secs` until aborted.
+    pub fn spawn_adjustment_loop(
+        self: Arc<Self>,
+        db_pool: ArcDbPool,
+        receiver: kanal::AsyncReceiver<TransactionsPBResponse>,
+        processor_name: &'static str,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.adjustment_interval);
+            loop {
+                let concurrent_tasks = *self.concurrent_tasks.borrow();
+                let pb_channel_txn_chunk_size =
+                    self.pb_channel_txn_chunk_size.load(Ordering::Relaxed);
+                PROCESSOR_ACTIVE_TASKS
+                    .with_label_values(&[processor_name])
+                    .set(concurrent_tasks as i64);
+                update_processor_progress(processor_name, |progress| {
+                    progress.batch_concurrency = concurrent_tasks as u64;
+                });
+                PB_CHANNEL_TXN_CHUNK_SIZE
+                    .with_label_values(&[processor_name])
+                    .set(pb_channel_txn_chunk_size as i64);
+
+                interval.tick().await;
+                let sample = self.sample(&db_pool, &receiver);
+                let (next_concurrent_tasks, next_pb_channel_txn_chunk_size) =
+                    self.next_limits(sample, concurrent_tasks, pb_channel_txn_chunk_size);
+                if (next_concurrent_tasks, next_pb_channel_txn_chunk_size)
+                    != (concurrent_tasks, pb_channel_txn_chunk_size)
+                {
+                    info!(
+                        processor_name = processor_name,
+                        service_type = PROCESSOR_SERVICE_TYPE,
+                        avg_db_insertion_time_secs = sample.avg_db_insertion_time_secs,
+                        db_pool_utilization = sample.db_pool_utilization,
+                        channel_utilization = sample.channel_utilization,
+                        concurrent_tasks = next_concurrent_tasks,
+                        pb_channel_txn_chunk_size = next_pb_channel_txn_chunk_size,
+                        "[Parser] Adjusted processing concurrency",
+                    );
+                }
+                self.concurrent_tasks.send_replace(next_concurrent_tasks);
+                self.pb_channel_txn_chunk_size
+                    .store(next_pb_channel_txn_chunk_size, Ordering::Relaxed);
+            }
+        })
+    }
+}
+
+/// Takes the next batch for the processing task with `task_index` with `recv`, parking the
+/// task first while `adaptive_concurrency` runs fewer tasks. Once `recv` fails, i.e. the channel
+/// closed, the parked tasks are released so they stop as well.
+pub async fn take_batch<T, F>(
+    adaptive_concurrency: Option<&AdaptiveConcurrency>,
+    task_index: usize,
+    recv: impl FnOnce() -> F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    if let Some(adaptive_concurrency) = adaptive_concurrency {
+        if !adaptive_concurrency.wait_for_turn(task_index).await {
+            anyhow::bail!(
+                "[Parser][T#{}] Stream ended while the task was parked.",
+                task_index
+            );
+        }
+    }
+    let result = recv().await;
+    if result.is_err() {
+        if let Some(adaptive_concurrency) = adaptive_concurrency {
+            adaptive_concurrency.end_stream();
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(avg_db_insertion_time_secs: f64, channel_utilization: f64) -> LoadSample {
+        LoadSample {
+            avg_db_insertion_time_secs: Some(avg_db_insertion_time_secs),
+            db_pool_utilization: 0.1,
+            channel_utilization,
+        }
+    }
+
+    #[test]
+    fn test_next_limits_stays_within_bounds() {
+        let config: AdaptiveConcurrencyConfig = serde_json::from_value(serde_json::json!({
+            "min_concurrent_tasks": 2,
+            "max_concurrent_tasks": 4,
+            "min_pb_channel_txn_chunk_size": 100,
+        }))
+        .unwrap();
+        let controller = AdaptiveConcurrency::new(&config, 10, 1000, 10);
+        assert_eq!(*controller.concurrent_tasks().borrow(), 4);
+
+        // Fast inserts but nothing queued: the stream is the bottleneck
+        assert_eq!(controller.next_limits(sample(0.1, 0.0), 3, 1000), (3, 1000));
+        // Fast inserts with a backlog: more tasks, then bigger batches up to the max
+        assert_eq!(controller.next_limits(sample(0.1, 0.9), 3, 1000), (4, 1000));
+        assert_eq!(controller.next_limits(sample(0.1, 0.9), 4, 500), (4, 625));
+        assert_eq!(controller.next_limits(sample(0.1, 0.9), 4, 1000), (4, 1000));
+        // Slow inserts: fewer tasks, then smaller batches down to the min
+        assert_eq!(controller.next_limits(sample(5.0, 0.9), 4, 1000), (3, 1000));
+        assert_eq!(controller.next_limits(sample(5.0, 0.9), 2, 1000), (2, 500));
+        assert_eq!(controller.next_limits(sample(5.0, 0.9), 2, 150), (2, 100));
+        // Pool nearly exhausted
+        let saturated = LoadSample {
+            db_pool_utilization: 0.9,
+            ..sample(0.1, 0.9)
+        };
+        assert_eq!(controller.next_limits(saturated, 3, 1000), (2, 1000));
+    }
+
+    #[tokio::test]
+    async fn test_parked_tasks_stop_when_bounded_stream_ends() {
+        let config: AdaptiveConcurrencyConfig = serde_json::from_value(serde_json::json!({
+            "min_concurrent_tasks": 1,
+            "max_concurrent_tasks": 4,
+        }))
+        .unwrap();
+        // Only the first of the four tasks is active
+        let controller = Arc::new(AdaptiveConcurrency::new(&config, 1, 1000, 10));
+        let (sender, receiver) = kanal::bounded_async::<u64>(BUFFER_SIZE);
+        let tasks = (0..controller.max_concurrent_tasks())
+            .map(|task_index| {
+                let controller = controller.clone();
+                let receiver = receiver.clone();
+                tokio::spawn(async move {
+                    let mut batches = vec![];
+                    while let Ok(batch) =
+                        take_batch(Some(controller.as_ref()), task_index, || async {
+                            receiver.recv().await.map_err(anyhow::Error::from)
+                        })
+                        .await
+                    {
+                        batches.push(batch);
+                    }
+                    batches
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for batch in 0..10 {
+            sender.send(batch).await.unwrap();
+        }
+        drop(sender);
+        let batches =
+            tokio::time::timeout(Duration::from_secs(5), futures::future::try_join_all(tasks))
+                .await
+                .expect("Parked tasks didn't stop at the end of the stream")
+                .unwrap();
+        assert_eq!(batches[0], (0..10).collect::<Vec<_>>());
+        assert!(batches[1..].iter().all(|batches| batches.is_empty()));
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright © Mirage Protocol
+
+//! Generates the Hasura metadata for the Mirage tables from the Diesel schema and merges it
+//! into the unified metadata file.
+//!
+//! Run from the `processor` crate directory:
+//! `cargo run --bin hasura_metadata` to rewrite the metadata in place, or
+//! `cargo run --bin hasura_metadata -- --check` to fail if it is out of date.
+
+use ahash::AHashMap;
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+const HASURA_SOURCE_NAME: &str = "indexer-v2";
+const HASURA_SCHEMA: &str = "public";
+const ANONYMOUS_ROLE: &str = "anonymous";
+const ANONYMOUS_ROW_LIMIT: u64 = 100;
+
+/// Columns that are bookkeeping for the indexer and are not exposed through the API.
+const HIDDEN_COLUMNS: &[&str] = &["inserted_at"];
+
+const MIRAGE_TABLES: &[&str] = &[
+    "current_limit_orders",
+    "current_positions",
+    "current_tpsls",
+    "current_vaults",
+    "limit_order_datas",
+    "market_activities",
+    "market_configs",
+    "market_datas",
+    "mirage_debt_store_datas",
+    "position_datas",
+    "position_histories",
+    "tpsl_datas",
+    "trade_datas",
+    "vault_activities",
+    "vault_collection_configs",
+    "vault_collection_datas",
+    "vault_datas",
+    "vault_lineage",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RelationshipKind {
+    Object,
+    Array,
+}
+
+struct Relationship {
+    table: &'static str,
+    name: &'static str,
+    kind: RelationshipKind,
+    remote_table: &'static str,
+    column_mapping: &'static [(&'static str, &'static str)],
+}
+
+const fn object(
+    table: &'static str,
+    name: &'static str,
+    remote_table: &'static str,
+    column_mapping: &'static [(&'static str, &'static str)],
+) -> Relationship {
+    Relationship {
+        table,
+        name,
+        kind: RelationshipKind::Object,
+        remote_table,
+        column_mapping,
+    }
+}
+
+const fn array(
+    table: &'static str,
+    name: &'static str,
+    remote_table: &'static str,
+    column_mapping: &'static [(&'static str, &'static str)],
+) -> Relationship {
+    Relationship {
+        table,
+        name,
+        kind: RelationshipKind::Array,
+        remote_table,
+        column_mapping,
+    }
+}
+
+const POSITION_ID: &[(&str, &str)] = &[("position_id", "position_id")];
+const STRATEGY_ID: &[(&str, &str)] = &[("strategy_id", "strategy_id")];
+const MARKET_ID: &[(&str, &str)] = &[("market_id", "market_id")];
+const VAULT_ID: &[(&str, &str)] = &[("vault_id", "vault_id")];
+const COLLECTION_ID: &[(&str, &str)] = &[("collection_id", "collection_id")];
+const MERGED_INTO_VAULT_ID: &[(&str, &str)] = &[("merged_into_vault_id", "vault_id")];
+const SRC_VAULT_ID: &[(&str, &str)] = &[("src_vault_id", "vault_id")];
+const DST_VAULT_ID: &[(&str, &str)] = &[("dst_vault_id", "vault_id")];
+const MERGED_VAULTS: &[(&str, &str)] = &[("vault_id", "dst_vault_id")];
+
+/// Market and vault data tables are versioned (one row per write), so anything pointing at
+/// them is an array relationship. Only the `current_*` tables are unique per id.
+#[rustfmt::skip]
+const RELATIONSHIPS: &[Relationship] = &[
+    // positions
+    array("current_positions", "trades", "trade_datas", POSITION_ID),
+    array("current_positions", "position_datas", "position_datas", POSITION_ID),
+    array("current_positions", "tpsls", "current_tpsls", POSITION_ID),
+    array("current_positions", "limit_orders", "current_limit_orders", POSITION_ID),
+    array("current_positions", "market_activities", "market_activities", POSITION_ID),
+    array("current_positions", "market_datas", "market_datas", MARKET_ID),
+    array("current_positions", "market_configs", "market_configs", MARKET_ID),
+    array("current_positions", "position_histories", "position_histories", POSITION_ID),
+    object("position_datas", "current_position", "current_positions", POSITION_ID),
+    object("position_histories", "current_position", "current_positions", POSITION_ID),
+    array("position_histories", "trades", "trade_datas", POSITION_ID),
+    object("trade_datas", "current_position", "current_positions", POSITION_ID),
+    object("market_activities", "current_position", "current_positions", POSITION_ID),
+    // markets
+    array("market_datas", "current_positions", "current_positions", MARKET_ID),
+    array("market_datas", "trades", "trade_datas", MARKET_ID),
+    array("market_configs", "current_positions", "current_positions", MARKET_ID),
+    // strategies
+    object("current_tpsls", "current_position", "current_positions", POSITION_ID),
+    array("current_tpsls", "tpsl_datas", "tpsl_datas", STRATEGY_ID),
+    object("tpsl_datas", "current_tpsl", "current_tpsls", STRATEGY_ID),
+    object("current_limit_orders", "current_position", "current_positions", POSITION_ID),
+    array("current_limit_orders", "limit_order_datas", "limit_order_datas", STRATEGY_ID),
+    object("limit_order_datas", "current_limit_order", "current_limit_orders", STRATEGY_ID),
+    // vaults
+    array("current_vaults", "vault_datas", "vault_datas", VAULT_ID),
+    array("current_vaults", "vault_activities", "vault_activities", VAULT_ID),
+    array("current_vaults", "vault_collection_datas", "vault_collection_datas", COLLECTION_ID),
+    array("current_vaults", "merged_vaults", "vault_lineage", MERGED_VAULTS),
+    object("current_vaults", "merged_into_vault", "current_vaults", MERGED_INTO_VAULT_ID),
+    object("vault_datas", "current_vault", "current_vaults", VAULT_ID),
+    object("vault_activities", "current_vault", "current_vaults", VAULT_ID),
+    object("vault_lineage", "src_vault", "current_vaults", SRC_VAULT_ID),
+    object("vault_lineage", "dst_vault", "current_vaults", DST_VAULT_ID),
+];
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the Diesel schema
+    #[clap(long, default_value = "src/db/postgres/schema.rs")]
+    schema: PathBuf,
+    /// Path to the Hasura metadata to update
+    #[clap(long, default_value = "../../hasura-api/metadata-json/unified.json")]
+    metadata: PathBuf,
+    /// Don't write anything, exit with an error if the metadata is out of date
+    #[clap(long)]
+    check: bool,
+}
+
+/// Returns the column names of every table in a Diesel `schema.rs`, in declaration order.
+fn parse_schema(schema: &str) -> Result<AHashMap<String, Vec<String>>> {
+    let table_re = Regex::new(r"diesel::table!\s*\{\s*(\w+)\s*\([^)]*\)\s*\{([^}]*)\}")?;
+    let column_re = Regex::new(r"(?m)^\s*(\w+)\s*->")?;
+
+    let mut tables = AHashMap::new();
+    for table in table_re.captures_iter(schema) {
+        let columns = column_re
+            .captures_iter(&table[2])
+            .map(|column| column[1].to_string())
+            .collect::<Vec<_>>();
+        tables.insert(table[1].to_string(), columns);
+    }
+    if tables.is_empty() {
+        bail!("No diesel::table! definitions found in schema");
+    }
+    Ok(tables)
+}
+
+fn relationship_json(relationship: &Relationship) -> Value {
+    let column_mapping = relationship
+        .column_mapping
+        .iter()
+        .map(|(local, remote)| (local.to_string(), json!(remote)))
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "name": relationship.name,
+        "using": {
+            "manual_configuration": {
+                "column_mapping": column_mapping,
+                "insertion_order": null,
+                "remote_table": {
+                    "name": relationship.remote_table,
+                    "schema": HASURA_SCHEMA,
+                },
+            },
+        },
+    })
+}
+
+fn table_json(name: &str, columns: &[String]) -> Value {
+    let mut columns = columns
+        .iter()
+        .filter(|column| !HIDDEN_COLUMNS.contains(&column.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    columns.sort();
+
+    let mut table = serde_json::Map::new();
+    table.insert(
+        "table".to_string(),
+        json!({ "name": name, "schema": HASURA_SCHEMA }),
+    );
+    for (key, kind) in [
+        ("object_relationships", RelationshipKind::Object),
+        ("array_relationships", RelationshipKind::Array),
+    ] {
+        let mut relationships = RELATIONSHIPS
+            .iter()
+            .filter(|r| r.table == name && r.kind == kind)
+            .collect::<Vec<_>>();
+        relationships.sort_by_key(|r| r.name);
+        if !relationships.is_empty() {
+            table.insert(
+                key.to_string(),
+                relationships.into_iter().map(relationship_json).collect(),
+            );
+        }
+    }
+    table.insert(
+        "select_permissions".to_string(),
+        json!([{
+            "role": ANONYMOUS_ROLE,
+            "permission": {
+                "columns": columns,
+                "filter": {},
+                "limit": ANONYMOUS_ROW_LIMIT,
+                "allow_aggregations": true,
+            },
+        }]),
+    );
+    Value::Object(table)
+}
+
+/// Builds the Hasura table entries for the Mirage tables, sorted by name.
+fn generate_mirage_tables(schema: &AHashMap<String, Vec<String>>) -> Result<Vec<Value>> {
+    let columns_of = |table: &str| {
+        schema
+            .get(table)
+            .with_context(|| format!("Table {} is not in the schema", table))
+    };
+    for relationship in RELATIONSHIPS {
+        let local_columns = columns_of(relationship.table)?;
+        let remote_columns = columns_of(relationship.remote_table)?;
+        for (local, remote) in relationship.column_mapping {
+            if !local_columns.iter().any(|c| c == local)
+                || !remote_columns.iter().any(|c| c == remote)
+            {
+                bail!(
+                    "Relationship {}.{} maps missing column {} -> {}.{}",
+                    relationship.table,
+                    relationship.name,
+                    local,
+                    relationship.remote_table,
+                    remote
+                );
+            }
+        }
+    }
+
+    let mut tables = MIRAGE_TABLES.to_vec();
+    tables.sort();
+    tables
+        .into_iter()
+        .map(|table| Ok(table_json(table, columns_of(table)?)))
+        .collect()
+}
+
+/// Replaces the Mirage entries of the indexer source with `mirage_tables`, leaving every
+/// other table untouched.
+fn merge_into_metadata(metadata: &mut Value, mirage_tables: Vec<Value>) -> Result<()> {
+    let source = metadata["metadata"]["sources"]
+        .as_array_mut()
+        .context("Metadata has no sources")?
+        .iter_mut()
+        .find(|source| source["name"] == HASURA_SOURCE_NAME)
+        .with_context(|| format!("Metadata has no {} source", HASURA_SOURCE_NAME))?;
+    let tables = source["tables"]
+        .as_array_mut()
+        .context("Metadata source has no tables")?;
+
+    tables.retain(|table| {
+        let is_mirage_table = table["table"]["schema"] == HASURA_SCHEMA
+            && table["table"]["name"]
+                .as_str()
+                .is_some_and(|name| MIRAGE_TABLES.contains(&name));
+        !is_mirage_table
+    });
+    tables.extend(mirage_tables);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let schema = std::fs::read_to_string(&args.schema)
+        .with_context(|| format!("Failed to read schema {}", args.schema.display()))?;
+    let existing = std::fs::read_to_string(&args.metadata)
+        .with_context(|| format!("Failed to read metadata {}", args.metadata.display()))?;
+
+    let mirage_tables = generate_mirage_tables(&parse_schema(&schema)?)?;
+    let mut metadata: Value = serde_json::from_str(&existing)?;
+    merge_into_metadata(&mut metadata, mirage_tables)?;
+    let updated = serde_json::to_string_pretty(&metadata)?;
+
+    if args.check {
+        if updated != existing {
+            bail!(
+                "{} is out of date, run `cargo run --bin hasura_metadata` to regenerate it",
+                args.metadata.display()
+            );
+        }
+        println!("{} is up to date", args.metadata.display());
+    } else if updated != existing {
+        std::fs::write(&args.metadata, updated)?;
+        println!("Updated {}", args.metadata.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schema() {
+        let schema = r#"
+diesel::table! {
+    current_vaults (vault_id) {
+        last_transaction_version -> Int8,
+        #[max_length = 66]
+        vault_id -> Varchar,
+        merged_into_vault_id -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    vault_lineage (transaction_version, event_index) {
+        transaction_version -> Int8,
+        event_index -> Int8,
+    }
+}
+"#;
+        let tables = parse_schema(schema).unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables["current_vaults"], vec![
+            "last_transaction_version",
+            "vault_id",
+            "merged_into_vault_id",
+            "inserted_at"
+        ]);
+        assert_eq!(tables["vault_lineage"], vec![
+            "transaction_version",
+            "event_index"
+        ]);
+    }
+
+    #[test]
+    fn test_table_json_hides_bookkeeping_columns() {
+        let columns = vec!["vault_id".to_string(), "inserted_at".to_string()];
+        let table = table_json("vault_lineage", &columns);
+        assert_eq!(
+            table["select_permissions"][0]["permission"]["columns"],
+            json!(["vault_id"])
+        );
+        assert_eq!(table["object_relationships"][0]["name"], "dst_vault");
+        assert!(table.get("array_relationships").is_none());
+    }
+}
@@ -1,11 +1,9 @@
-use crate::{bq_analytics::ParquetProcessorError, utils::counters::PARQUET_BUFFER_SIZE};
-use anyhow::{Context, Result};
-use chrono::{Datelike, Timelike};
-use google_cloud_storage::{
-    client::Client as GCSClient,
-    http::objects::upload::{Media, UploadObjectRequest, UploadType},
+use crate::{
+    bq_analytics::{object_store::ObjectStore, ParquetProcessorError},
+    utils::counters::PARQUET_BUFFER_SIZE,
 };
-use hyper::{body::HttpBody, Body};
+use anyhow::Result;
+use chrono::{Datelike, Timelike};
 use std::path::{Path, PathBuf};
 use tokio::time::{sleep, timeout, Duration};
 use tracing::{debug, error, info};
@@ -13,14 +11,15 @@ use tracing::{debug, error, info};
 const MAX_RETRIES: usize = 3;
 const INITIAL_DELAY_MS: u64 = 500;
 const TIMEOUT_SECONDS: u64 = 300;
-pub async fn upload_parquet_to_gcs(
-    client: &GCSClient,
+
+/// Uploads `buffer` as a new file of `table_name`, returning its object name.
+pub async fn upload_parquet(
+    object_store: &ObjectStore,
     buffer: Vec<u8>,
     table_name: &str,
-    bucket_name: &str,
     bucket_root: &Path,
     processor_name: String,
-) -> Result<(), ParquetProcessorError> {
+) -> Result<String, ParquetProcessorError> {
     if buffer.is_empty() {
         error!("The file is empty and has no data to upload.",);
         return Err(ParquetProcessorError::Other(
@@ -47,43 +46,35 @@ pub async fn upload_parquet_to_gcs(
         generate_parquet_file_path(bucket_root, table_name, highwater_s, highwater_ms, counter);
 
     let file_name = object_name.to_str().unwrap().to_owned();
-    let upload_type: UploadType = UploadType::Simple(Media::new(file_name.clone()));
-
-    let upload_request = UploadObjectRequest {
-        bucket: bucket_name.to_string(),
-        ..Default::default()
-    };
 
     let mut retry_count = 0;
     let mut delay = INITIAL_DELAY_MS;
 
     loop {
-        let data = Body::from(buffer.clone());
-        let size_hint = data.size_hint();
-        let size = size_hint.exact().context("Failed to get size hint")?;
         PARQUET_BUFFER_SIZE
             .with_label_values(&[&processor_name, table_name])
-            .set(size as i64);
+            .set(buffer.len() as i64);
 
         let upload_result = timeout(
             Duration::from_secs(TIMEOUT_SECONDS),
-            client.upload_object(&upload_request, data, &upload_type),
+            object_store.put(&file_name, buffer.clone()),
         )
         .await;
 
         match upload_result {
-            Ok(Ok(result)) => {
+            Ok(Ok(())) => {
                 info!(
                     table_name = table_name,
-                    file_name = result.name,
-                    "File uploaded successfully to GCS",
+                    file_name = file_name,
+                    object_store = object_store.name(),
+                    "File uploaded successfully",
                 );
-                return Ok(());
+                return Ok(file_name);
             },
             Ok(Err(e)) => {
-                error!("Failed to upload file to GCS: {}", e);
+                error!("Failed to upload file to {}: {}", object_store.name(), e);
                 if retry_count >= MAX_RETRIES {
-                    return Err(ParquetProcessorError::StorageError(e));
+                    return Err(e);
                 }
             },
             Err(e) => {
@@ -1,6 +1,8 @@
 use super::ParquetProcessingResult;
 use crate::{
-    bq_analytics::gcs_handler::upload_parquet_to_gcs,
+    bq_analytics::{
+        gcs_handler::upload_parquet, manifest::ManifestWriter, object_store::ObjectStore,
+    },
     gap_detectors::ProcessingResult,
     utils::{
         counters::{PARQUET_HANDLER_CURRENT_BUFFER_SIZE, PARQUET_STRUCT_SIZE},
@@ -10,7 +12,6 @@ use crate::{
 use ahash::AHashMap;
 use allocative::Allocative;
 use anyhow::{Context, Result};
-use google_cloud_storage::client::Client as GCSClient;
 use parquet::{
     file::{properties::WriterProperties, writer::SerializedFileWriter},
     record::RecordWriter,
@@ -73,6 +74,9 @@ where
     pub max_buffer_size: usize,
     pub last_upload_time: Instant,
     pub processor_name: String,
+    pub manifest_writer: ManifestWriter,
+    // Path of the table's files under bucket_root, versioned on incompatible schema changes
+    pub table_path: String,
 }
 
 fn create_new_writer(schema: Arc<Type>) -> Result<SerializedFileWriter<Vec<u8>>> {
@@ -110,6 +114,7 @@ where
     ) -> Result<Self> {
         // had to append unique id to avoid concurrent write issues
         let writer = create_new_writer(schema.clone())?;
+        let manifest_writer = ManifestWriter::new(&PathBuf::from(&bucket_root));
 
         Ok(Self {
             writer,
@@ -124,12 +129,14 @@ where
             max_buffer_size,
             last_upload_time: Instant::now(),
             processor_name,
+            manifest_writer,
+            table_path: ParquetType::TABLE_NAME.to_string(),
         })
     }
 
     pub async fn handle(
         &mut self,
-        gcs_client: &GCSClient,
+        object_store: &ObjectStore,
         changes: ParquetDataGeneric<ParquetType>,
     ) -> Result<()> {
         let parquet_structs = changes.data;
@@ -141,7 +148,7 @@ where
                 self.upload_interval.as_secs(),
                 ParquetType::TABLE_NAME
             );
-            if let Err(e) = self.upload_buffer(gcs_client).await {
+            if let Err(e) = self.upload_buffer(object_store).await {
                 error!("Failed to upload buffer: {}", e);
                 return Err(e);
             }
@@ -161,9 +168,9 @@ where
                     table_name = ParquetType::TABLE_NAME,
                     buffer_size = self.buffer_size_bytes,
                     max_buffer_size = self.max_buffer_size,
-                    "Max buffer size reached, uploading to GCS."
+                    "Max buffer size reached, uploading."
                 );
-                if let Err(e) = self.upload_buffer(gcs_client).await {
+                if let Err(e) = self.upload_buffer(object_store).await {
                     error!("Failed to upload buffer: {}", e);
                     return Err(e);
                 }
@@ -178,7 +185,7 @@ where
         Ok(())
     }
 
-    async fn upload_buffer(&mut self, gcs_client: &GCSClient) -> Result<()> {
+    async fn upload_buffer(&mut self, object_store: &ObjectStore) -> Result<()> {
         // This is to cover the case when interval duration has passed but buffer is empty
         if self.buffer.is_empty() {
             debug!("Buffer is empty, skipping upload.");
@@ -201,14 +208,17 @@ where
 
             return Ok(());
         }
+        // Batches can be buffered out of order, so the first and last structs aren't the bounds
         let start_version = self
             .buffer
-            .first()
-            .context("Buffer is not empty but has no first element")?
-            .version();
+            .iter()
+            .map(|item| item.version())
+            .min()
+            .context("Buffer is not empty but has no first element")?;
         let last = self
             .buffer
-            .last()
+            .iter()
+            .max_by_key(|item| item.version())
             .context("Buffer is not empty but has no last element")?;
         let end_version = last.version();
         let last_transaction_timestamp = naive_datetime_to_timestamp(last.get_timestamp());
@@ -236,15 +246,25 @@ where
 
         let bucket_root = PathBuf::from(&self.bucket_root);
 
-        upload_parquet_to_gcs(
-            gcs_client,
+        let file_name = upload_parquet(
+            object_store,
             upload_buffer,
-            ParquetType::TABLE_NAME,
-            &self.bucket_name,
+            &self.table_path,
             &bucket_root,
             self.processor_name.clone(),
         )
         .await?;
+        self.manifest_writer
+            .commit(
+                object_store,
+                &self.table_path,
+                file_name,
+                start_version,
+                end_version,
+                struct_buffer.len(),
+                &self.schema,
+            )
+            .await?;
 
         self.buffer_size_bytes = 0;
 
@@ -260,7 +280,7 @@ where
             table_name = ParquetType::TABLE_NAME,
             start_version = start_version,
             end_version = end_version,
-            "Uploaded parquet to GCS and sending result to gap detector."
+            "Uploaded parquet and sending result to gap detector."
         );
         self.gap_detector_sender
             .send(ProcessingResult::ParquetProcessingResult(
@@ -0,0 +1,308 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per table manifests of uploaded parquet files, `{bucket_root}/{table}/_manifest.json`.
+//!
+//! A manifest lists the files that make up the table, and is rewritten with a single put after
+//! every upload, so readers listing files from it always see a consistent snapshot. After a
+//! restart, the processor rewrites every version from its last status on, so files of earlier
+//! runs are superseded from the first version the new run wrote: they're dropped when they start
+//! after it, and readers skip their rows from `superseded_from_version` on otherwise.
+//!
+//! So the manifest doesn't grow with every upload, once it lists more than `MAX_MANIFEST_FILES`
+//! files the ones with the lowest versions are moved out to a segment, a list of files written
+//! once next to it. Segments are superseded like files, and their files' rows are skipped from
+//! the lower of the segment's and the file's `superseded_from_version`.
+
+use crate::bq_analytics::{object_store::ObjectStore, ParquetProcessorError};
+use ahash::AHashMap;
+use parquet::schema::{printer::print_schema, types::Type};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub const MANIFEST_FILE_NAME: &str = "_manifest.json";
+// Files listed in the manifest itself, half of them are moved to a segment beyond this
+pub const MAX_MANIFEST_FILES: usize = 1000;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ManifestFile {
+    pub path: String,
+    pub start_version: i64,
+    pub end_version: i64,
+    pub row_count: usize,
+    pub schema_hash: String,
+    // Files written by the same run of the processor share it
+    pub run_id: String,
+    // Rows from this version on were rewritten by a later run and must be skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_from_version: Option<i64>,
+}
+
+/// Files moved out of the manifest to `path`, a json list of [`ManifestFile`] never rewritten.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ManifestSegment {
+    pub path: String,
+    pub start_version: i64,
+    pub end_version: i64,
+    pub num_files: usize,
+    // Runs that wrote the files, which don't supersede them
+    pub run_ids: Vec<String>,
+    // Rows of every file from this version on were rewritten by a later run and must be skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_from_version: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TableManifest {
+    pub table_name: String,
+    // Incremented on every commit
+    pub snapshot_id: u64,
+    // Schema of the latest file
+    pub schema_hash: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<ManifestSegment>,
+    pub files: Vec<ManifestFile>,
+}
+
+fn supersede(superseded_from_version: &mut Option<i64>, version: i64) {
+    *superseded_from_version =
+        Some(superseded_from_version.map_or(version, |superseded| superseded.min(version)));
+}
+
+impl TableManifest {
+    fn add(&mut self, file: ManifestFile) {
+        let run_start_version = self
+            .files
+            .iter()
+            .filter(|existing| existing.run_id == file.run_id)
+            .map(|existing| existing.start_version)
+            .chain(
+                self.segments
+                    .iter()
+                    .filter(|segment| segment.run_ids.contains(&file.run_id))
+                    .map(|segment| segment.start_version),
+            )
+            .fold(file.start_version, i64::min);
+        self.files.retain(|existing| {
+            existing.run_id == file.run_id || existing.start_version < run_start_version
+        });
+        self.segments.retain(|segment| {
+            segment.run_ids.contains(&file.run_id) || segment.start_version < run_start_version
+        });
+        for existing in self.files.iter_mut() {
+            if existing.run_id != file.run_id && existing.end_version >= run_start_version {
+                supersede(&mut existing.superseded_from_version, run_start_version);
+            }
+        }
+        for segment in self.segments.iter_mut() {
+            if !segment.run_ids.contains(&file.run_id) && segment.end_version >= run_start_version {
+                supersede(&mut segment.superseded_from_version, run_start_version);
+            }
+        }
+        self.schema_hash.clone_from(&file.schema_hash);
+        self.files.push(file);
+        self.snapshot_id += 1;
+    }
+
+    /// Once there are more than `MAX_MANIFEST_FILES` files, moves the half with the lowest
+    /// versions to a segment at `path` and returns them, to be written there.
+    fn compact(&mut self, path: String) -> Option<Vec<ManifestFile>> {
+        if self.files.len() <= MAX_MANIFEST_FILES {
+            return None;
+        }
+        self.files.sort_by_key(|file| file.start_version);
+        let kept = self
+            .files
+            .split_off(self.files.len() - MAX_MANIFEST_FILES / 2);
+        let files = std::mem::replace(&mut self.files, kept);
+        let mut run_ids = files
+            .iter()
+            .map(|file| file.run_id.clone())
+            .collect::<Vec<_>>();
+        run_ids.sort();
+        run_ids.dedup();
+        self.segments.push(ManifestSegment {
+            path,
+            start_version: files.iter().map(|file| file.start_version).min()?,
+            end_version: files.iter().map(|file| file.end_version).max()?,
+            num_files: files.len(),
+            run_ids,
+            superseded_from_version: None,
+        });
+        Some(files)
+    }
+}
+
+/// Hex sha256 of the printed schema, which changes with any column, type or repetition.
+pub fn schema_hash(schema: &Type) -> String {
+    let mut printed = vec![];
+    print_schema(&mut printed, schema);
+    hex::encode(Sha256::digest(&printed))
+}
+
+/// Commits uploaded files to the manifests of their tables. Expects to be the only writer of
+/// these tables under its bucket root, so manifests are only read once.
+pub struct ManifestWriter {
+    bucket_root: PathBuf,
+    run_id: String,
+    manifests: AHashMap<String, TableManifest>,
+}
+
+impl ManifestWriter {
+    pub fn new(bucket_root: &Path) -> Self {
+        Self {
+            bucket_root: bucket_root.to_path_buf(),
+            run_id: chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string(),
+            manifests: AHashMap::new(),
+        }
+    }
+
+    fn manifest_path(&self, table_name: &str) -> String {
+        self.bucket_root
+            .join(table_name)
+            .join(MANIFEST_FILE_NAME)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn segment_path(&self, table_name: &str, snapshot_id: u64) -> String {
+        self.bucket_root
+            .join(table_name)
+            .join(format!("_manifest_{}.json", snapshot_id))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Adds the file uploaded at `path` to the manifest of `table_name` and writes it back.
+    pub async fn commit(
+        &mut self,
+        object_store: &ObjectStore,
+        table_name: &str,
+        path: String,
+        start_version: i64,
+        end_version: i64,
+        row_count: usize,
+        schema: &Type,
+    ) -> Result<(), ParquetProcessorError> {
+        let manifest_path = self.manifest_path(table_name);
+        if !self.manifests.contains_key(table_name) {
+            let manifest = match object_store.get(&manifest_path).await? {
+                Some(data) => serde_json::from_slice(&data).map_err(|e| {
+                    ParquetProcessorError::Other(format!(
+                        "Invalid manifest {}: {}",
+                        manifest_path, e
+                    ))
+                })?,
+                None => TableManifest {
+                    table_name: table_name.to_string(),
+                    ..Default::default()
+                },
+            };
+            self.manifests.insert(table_name.to_string(), manifest);
+        }
+        let mut manifest = self.manifests[table_name].clone();
+        manifest.add(ManifestFile {
+            path,
+            start_version,
+            end_version,
+            row_count,
+            schema_hash: schema_hash(schema),
+            run_id: self.run_id.clone(),
+            superseded_from_version: None,
+        });
+        // Written first, so the manifest never lists a segment that isn't there
+        let segment_path = self.segment_path(table_name, manifest.snapshot_id);
+        if let Some(files) = manifest.compact(segment_path.clone()) {
+            let data = serde_json::to_vec_pretty(&files)
+                .map_err(|e| ParquetProcessorError::Other(e.to_string()))?;
+            object_store.put(&segment_path, data).await?;
+        }
+
+        let data = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| ParquetProcessorError::Other(e.to_string()))?;
+        object_store.put(&manifest_path, data).await?;
+        info!(
+            table_name = table_name,
+            snapshot_id = manifest.snapshot_id,
+            num_files = manifest.files.len(),
+            num_segments = manifest.segments.len(),
+            "Committed parquet manifest",
+        );
+        // Only kept once written, so a failed commit is retried from the last snapshot
+        self.manifests.insert(table_name.to_string(), manifest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, start_version: i64, end_version: i64, run_id: &str) -> ManifestFile {
+        ManifestFile {
+            path: path.to_string(),
+            start_version,
+            end_version,
+            row_count: 1,
+            schema_hash: "hash".to_string(),
+            run_id: run_id.to_string(),
+            superseded_from_version: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_supersedes_earlier_runs() {
+        let mut manifest = TableManifest::default();
+        manifest.add(file("a", 0, 9, "1"));
+        manifest.add(file("b", 10, 19, "1"));
+        manifest.add(file("c", 20, 29, "1"));
+        // Restarted from version 15
+        manifest.add(file("d", 25, 34, "2"));
+        manifest.add(file("e", 15, 24, "2"));
+
+        assert_eq!(manifest.snapshot_id, 5);
+        let files = manifest
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.superseded_from_version))
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![
+            ("a", None),
+            ("b", Some(15)),
+            ("d", None),
+            ("e", None)
+        ]);
+    }
+
+    #[test]
+    fn test_manifest_compacts_into_segments() {
+        let mut manifest = TableManifest::default();
+        for i in 0..=MAX_MANIFEST_FILES as i64 {
+            manifest.add(file(&i.to_string(), i * 10, i * 10 + 9, "1"));
+        }
+        let files = manifest.compact("segment".to_string()).unwrap();
+
+        assert_eq!(files.len(), MAX_MANIFEST_FILES / 2 + 1);
+        assert_eq!(manifest.files.len(), MAX_MANIFEST_FILES / 2);
+        assert_eq!(manifest.files[0].start_version, files.len() as i64 * 10);
+        assert_eq!(manifest.segments, vec![ManifestSegment {
+            path: "segment".to_string(),
+            start_version: 0,
+            end_version: files.len() as i64 * 10 - 1,
+            num_files: files.len(),
+            run_ids: vec!["1".to_string()],
+            superseded_from_version: None,
+        }]);
+        assert!(manifest.compact("next_segment".to_string()).is_none());
+
+        // Restarted from version 15, in the segment
+        manifest.add(file("restarted", 15, 24, "2"));
+        assert!(manifest.files.iter().all(|f| f.run_id == "2"));
+        assert_eq!(manifest.segments[0].superseded_from_version, Some(15));
+        // Restarted from version 0, before the segment
+        manifest.add(file("restarted", 0, 9, "3"));
+        assert!(manifest.segments.is_empty());
+    }
+}
@@ -1,26 +1,30 @@
 pub mod gcs_handler;
 pub mod generic_parquet_processor;
+pub mod manifest;
+pub mod object_store;
+pub mod schema_versions;
 
 use crate::{
-    bq_analytics::generic_parquet_processor::{
-        GetTimeStamp, HasParquetSchema, HasVersion, NamedTable, ParquetDataGeneric,
-        ParquetHandler as GenericParquetHandler,
+    bq_analytics::{
+        generic_parquet_processor::{
+            GetTimeStamp, HasParquetSchema, HasVersion, NamedTable, ParquetDataGeneric,
+            ParquetHandler as GenericParquetHandler,
+        },
+        object_store::{ObjectStore, ObjectStoreConfig},
+        schema_versions::{resolve_table_path, SchemaChangePolicy},
     },
     gap_detectors::ProcessingResult,
     worker::PROCESSOR_SERVICE_TYPE,
 };
 use ahash::AHashMap;
 use allocative::Allocative;
-use google_cloud_storage::{
-    client::{Client as GCSClient, ClientConfig as GcsClientConfig},
-    http::Error as StorageError,
-};
+use google_cloud_storage::http::Error as StorageError;
 use kanal::AsyncSender;
 use parquet::record::RecordWriter;
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{Debug, Display, Formatter, Result as FormatResult},
-    sync::Arc,
+    path::Path,
 };
 use tokio::{io, time::Duration};
 use tracing::{debug, error, info};
@@ -92,6 +96,8 @@ pub fn create_parquet_handler_loop<ParquetType>(
     processor_name: &str,
     bucket_name: String,
     bucket_root: String,
+    object_store: ObjectStoreConfig,
+    schema_change_policy: SchemaChangePolicy,
     parquet_handler_response_channel_size: usize,
     max_buffer_size: usize,
     upload_interval: Duration,
@@ -130,16 +136,24 @@ where
     .expect("Failed to create parquet manager");
 
     tokio::spawn(async move {
-        let gcs_config = GcsClientConfig::default()
-            .with_auth()
+        let object_store = ObjectStore::new(&object_store, &bucket_name)
             .await
-            .expect("Failed to create GCS client config");
-        let gcs_client = Arc::new(GCSClient::new(gcs_config));
+            .expect("Failed to create object store");
+        // Panics before anything is written if the schema changed incompatibly
+        parquet_handler.table_path = resolve_table_path(
+            &object_store,
+            Path::new(&bucket_root),
+            ParquetType::TABLE_NAME,
+            &parquet_handler.schema,
+            schema_change_policy,
+        )
+        .await
+        .expect("Failed to check parquet schema");
 
         loop {
             match parquet_receiver.recv().await {
                 Ok(txn_pb_res) => {
-                    let result = parquet_handler.handle(&gcs_client, txn_pb_res).await;
+                    let result = parquet_handler.handle(&object_store, txn_pb_res).await;
 
                     match result {
                         Ok(_) => {
@@ -0,0 +1,210 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Schemas written per table, `{bucket_root}/{table}/_schemas.json`, checked on startup.
+//!
+//! Parquet schemas are derived from the structs, so changing a struct changes what's written to
+//! the table. Adding nullable columns or making columns nullable can still be loaded into the
+//! same BigQuery table; anything else is incompatible and, depending on the
+//! [`SchemaChangePolicy`], fails startup or starts a new version of the table, written under
+//! `{bucket_root}/{table}_v{version}`.
+
+use crate::bq_analytics::{manifest::schema_hash, object_store::ObjectStore};
+use anyhow::{Context, Result};
+use parquet::{
+    basic::Repetition,
+    schema::{parser::parse_message_type, printer::print_schema, types::Type},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+pub const SCHEMAS_FILE_NAME: &str = "_schemas.json";
+
+/// What to do when a table's schema changed incompatibly since it was last written.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaChangePolicy {
+    #[default]
+    Fail,
+    NewVersion,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SchemaVersion {
+    pub version: u32,
+    // Table path files of this version are written under
+    pub path: String,
+    pub schema_hash: String,
+    // Printed parquet message type
+    pub schema: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SchemaVersions {
+    pub table_name: String,
+    pub versions: Vec<SchemaVersion>,
+}
+
+fn print(schema: &Type) -> String {
+    let mut printed = vec![];
+    print_schema(&mut printed, schema);
+    String::from_utf8_lossy(&printed).into_owned()
+}
+
+/// Changes from `old` to `new` that files of both can't be loaded into one table for.
+pub fn incompatible_changes(old: &Type, new: &Type) -> Vec<String> {
+    let mut changes = vec![];
+    let new_fields = new.get_fields();
+    for old_field in old.get_fields() {
+        let name = old_field.name();
+        let Some(new_field) = new_fields.iter().find(|field| field.name() == name) else {
+            changes.push(format!("{} was removed", name));
+            continue;
+        };
+        let (old_info, new_info) = (old_field.get_basic_info(), new_field.get_basic_info());
+        let repetition_relaxed = old_info.repetition() == Repetition::REQUIRED
+            && new_info.repetition() == Repetition::OPTIONAL;
+        if old_info.repetition() != new_info.repetition() && !repetition_relaxed {
+            changes.push(format!(
+                "{} changed from {} to {}",
+                name,
+                old_info.repetition(),
+                new_info.repetition()
+            ));
+        }
+        match (old_field.is_primitive(), new_field.is_primitive()) {
+            (true, true) => {
+                if old_field.get_physical_type() != new_field.get_physical_type()
+                    || old_info.logical_type() != new_info.logical_type()
+                    || old_info.converted_type() != new_info.converted_type()
+                {
+                    changes.push(format!("{} changed type", name));
+                }
+            },
+            (false, false) => changes.extend(
+                incompatible_changes(old_field, new_field)
+                    .into_iter()
+                    .map(|change| format!("{}.{}", name, change)),
+            ),
+            _ => changes.push(format!("{} changed type", name)),
+        }
+    }
+    for new_field in new_fields {
+        let added = !old
+            .get_fields()
+            .iter()
+            .any(|f| f.name() == new_field.name());
+        if added && new_field.get_basic_info().repetition() != Repetition::OPTIONAL {
+            changes.push(format!(
+                "{} was added as {}",
+                new_field.name(),
+                new_field.get_basic_info().repetition()
+            ));
+        }
+    }
+    changes
+}
+
+/// Checks `schema` against the last one written for `table_name` and returns the table path
+/// to write files under, recording the schema if it's new.
+pub async fn resolve_table_path(
+    object_store: &ObjectStore,
+    bucket_root: &Path,
+    table_name: &str,
+    schema: &Type,
+    policy: SchemaChangePolicy,
+) -> Result<String> {
+    let versions_path = bucket_root
+        .join(table_name)
+        .join(SCHEMAS_FILE_NAME)
+        .to_string_lossy()
+        .into_owned();
+    let mut schema_versions: SchemaVersions = match object_store.get(&versions_path).await? {
+        Some(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("Invalid schema versions {}", versions_path))?,
+        None => SchemaVersions {
+            table_name: table_name.to_string(),
+            versions: vec![],
+        },
+    };
+    let hash = schema_hash(schema);
+    let new_version = |version: u32, path: String| SchemaVersion {
+        version,
+        path,
+        schema_hash: hash.clone(),
+        schema: print(schema),
+    };
+
+    match schema_versions.versions.last_mut() {
+        Some(latest) if latest.schema_hash == hash => return Ok(latest.path.clone()),
+        Some(latest) => {
+            let latest_schema = parse_message_type(&latest.schema)
+                .with_context(|| format!("Invalid schema of {} v{}", table_name, latest.version))?;
+            let changes = incompatible_changes(&latest_schema, schema);
+            if changes.is_empty() {
+                info!(
+                    table_name = table_name,
+                    version = latest.version,
+                    "Parquet schema changed compatibly"
+                );
+                *latest = new_version(latest.version, latest.path.clone());
+            } else {
+                anyhow::ensure!(
+                    policy == SchemaChangePolicy::NewVersion,
+                    "Parquet schema of {} changed incompatibly since v{}: {}. Revert the change \
+                     or set schema_change_policy to new_version to write a new table version",
+                    table_name,
+                    latest.version,
+                    changes.join(", ")
+                );
+                let version = latest.version + 1;
+                warn!(
+                    table_name = table_name,
+                    version = version,
+                    changes = changes.join(", "),
+                    "Parquet schema changed incompatibly, writing a new version of the table"
+                );
+                schema_versions
+                    .versions
+                    .push(new_version(version, format!("{}_v{}", table_name, version)));
+            }
+        },
+        None => schema_versions
+            .versions
+            .push(new_version(1, table_name.to_string())),
+    }
+
+    object_store
+        .put(&versions_path, serde_json::to_vec_pretty(&schema_versions)?)
+        .await?;
+    Ok(schema_versions.versions.last().unwrap().path.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(schema: &str) -> Type {
+        parse_message_type(schema).unwrap()
+    }
+
+    #[test]
+    fn test_incompatible_changes() {
+        let old =
+            parse("message schema { REQUIRED INT64 txn_version; OPTIONAL BYTE_ARRAY key (UTF8); }");
+        let added_optional = parse(
+            "message schema { OPTIONAL INT64 txn_version; OPTIONAL BYTE_ARRAY key (UTF8); \
+             OPTIONAL BOOLEAN is_deleted; }",
+        );
+        assert!(incompatible_changes(&old, &added_optional).is_empty());
+
+        let changed =
+            parse("message schema { REQUIRED INT32 txn_version; REQUIRED BOOLEAN is_deleted; }");
+        assert_eq!(incompatible_changes(&old, &changed), vec![
+            "txn_version changed type",
+            "key was removed",
+            "is_deleted was added as REQUIRED",
+        ]);
+    }
+}
@@ -2,8 +2,18 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    gap_detectors::DEFAULT_GAP_DETECTION_BATCH_SIZE, processors::ProcessorConfig,
-    transaction_filter::TransactionFilter, worker::Worker,
+    adaptive_concurrency::AdaptiveConcurrencyConfig,
+    file_stream::FileStreamConfig,
+    gap_detectors::DEFAULT_GAP_DETECTION_BATCH_SIZE,
+    processors::ProcessorConfig,
+    stream_archive::StreamArchiveConfig,
+    transaction_filter::TransactionFilter,
+    utils::{
+        bulk_load::BulkLoader,
+        database::{new_db_pool, ArcDbPool},
+        table_flags::TableFlags,
+    },
+    worker::{run_with_shared_stream, Worker, PROCESSOR_SERVICE_TYPE},
 };
 use ahash::AHashMap;
 use anyhow::{Context, Result};
@@ -11,7 +21,7 @@ use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use server_framework::RunnableConfig;
 use std::{collections::HashSet, env, time::Duration};
-use tracing::error;
+use tracing::{error, info};
 use url::Url;
 
 pub const QUERY_DEFAULT_RETRIES: u32 = 5;
@@ -60,6 +70,30 @@ pub struct IndexerGrpcProcessorConfig {
     // String vector for deprecated tables to skip db writes
     #[serde(default)]
     pub deprecated_tables: HashSet<String>,
+    // If set, only these tables are written to and every other table is skipped
+    #[serde(default)]
+    pub enabled_tables: HashSet<String>,
+    // Where transactions are read from, the GRPC data service by default
+    #[serde(default)]
+    pub transaction_source: TransactionSource,
+    // Records the GRPC stream to disk so it can be replayed with the files source
+    #[serde(default)]
+    pub stream_archive: Option<StreamArchiveConfig>,
+    // Time given to in-flight batches to finish after SIGTERM/SIGINT before exiting anyway
+    #[serde(default = "IndexerGrpcProcessorConfig::default_graceful_shutdown_timeout_secs")]
+    pub graceful_shutdown_timeout_secs: u64,
+    // Scales the active processing tasks and pb_channel_txn_chunk_size with DB latency and load
+    #[serde(default)]
+    pub adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+    // More processors to run off the same stream as processor_config, each with its own status
+    #[serde(default)]
+    pub additional_processor_configs: Vec<ProcessorConfig>,
+    // Writes every table of a batch and its processor status in a single db transaction
+    #[serde(default)]
+    pub atomic_batch_writes: bool,
+    // Tables written with COPY through a staging table instead of batched inserts, for backfills
+    #[serde(default)]
+    pub bulk_load_tables: HashSet<String>,
 }
 
 impl IndexerGrpcProcessorConfig {
@@ -80,7 +114,15 @@ impl IndexerGrpcProcessorConfig {
         transaction_filter: TransactionFilter,
         grpc_response_item_timeout_in_secs: u64,
         deprecated_tables: HashSet<String>,
+        enabled_tables: HashSet<String>,
         starting_version: Option<u64>,
+        transaction_source: TransactionSource,
+        stream_archive: Option<StreamArchiveConfig>,
+        graceful_shutdown_timeout_secs: u64,
+        adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+        additional_processor_configs: Vec<ProcessorConfig>,
+        atomic_batch_writes: bool,
+        bulk_load_tables: HashSet<String>,
     ) -> Result<Self> {
         // Load .env file if it exists
         dotenv().ok();
@@ -104,6 +146,7 @@ impl IndexerGrpcProcessorConfig {
             .ok()
             .and_then(|addr| Url::parse(&addr).ok())
             .or(indexer_grpc_data_service_address)
+            .or_else(|| transaction_source.stream_address())
             .ok_or_else(|| {
                 error!("Indexer GRPC data service address not configured. Please set PROCESSOR_INDEXER_GRPC_DATA_SERVICE_ADDRESS environment variable or provide it in the config file.");
                 anyhow::anyhow!("Indexer GRPC data service address not configured")
@@ -161,6 +204,12 @@ impl IndexerGrpcProcessorConfig {
             .and_then(|v| v.parse::<u64>().ok())
             .unwrap_or(grpc_response_item_timeout_in_secs);
 
+        // Get graceful shutdown timeout from environment variable if available
+        let graceful_shutdown_timeout_secs = env::var("PROCESSOR_GRACEFUL_SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(graceful_shutdown_timeout_secs);
+
         Ok(Self {
             processor_config,
             postgres_connection_string: Some(postgres_connection_string),
@@ -179,6 +228,14 @@ impl IndexerGrpcProcessorConfig {
             transaction_filter,
             grpc_response_item_timeout_in_secs,
             deprecated_tables,
+            enabled_tables,
+            transaction_source,
+            stream_archive,
+            graceful_shutdown_timeout_secs,
+            adaptive_concurrency,
+            additional_processor_configs,
+            atomic_batch_writes,
+            bulk_load_tables,
         })
     }
 
@@ -204,20 +261,30 @@ impl IndexerGrpcProcessorConfig {
     pub const fn default_grpc_response_item_timeout_in_secs() -> u64 {
         60
     }
-}
 
-#[async_trait::async_trait]
-impl RunnableConfig for IndexerGrpcProcessorConfig {
-    async fn run(&self) -> Result<()> {
-        let mut worker = Worker::new(
-            self.processor_config.clone(),
+    /// Default time to drain in-flight batches on shutdown. Defaults to 30 seconds.
+    pub const fn default_graceful_shutdown_timeout_secs() -> u64 {
+        30
+    }
+
+    async fn build_worker(
+        &self,
+        processor_config: ProcessorConfig,
+        db_pool: ArcDbPool,
+    ) -> Result<Worker> {
+        Worker::new(
+            processor_config,
             self.postgres_connection_string.clone().unwrap(),
-            self.indexer_grpc_data_service_address.clone().unwrap(),
+            self.indexer_grpc_data_service_address
+                .clone()
+                .or_else(|| self.transaction_source.stream_address())
+                .context("Indexer GRPC data service address not configured")?,
             self.grpc_http2_config.clone(),
             self.auth_token.clone(),
             self.starting_version,
             self.ending_version,
             self.number_concurrent_processing_tasks,
+            db_pool,
             self.db_pool_size,
             self.gap_detection_batch_size,
             self.parquet_gap_detection_batch_size,
@@ -227,12 +294,105 @@ impl RunnableConfig for IndexerGrpcProcessorConfig {
             self.transaction_filter.clone(),
             self.grpc_response_item_timeout_in_secs,
             self.deprecated_tables.clone(),
+            self.enabled_tables.clone(),
+            self.transaction_source.clone(),
+            self.stream_archive.clone(),
+            self.graceful_shutdown_timeout_secs,
+            self.adaptive_concurrency.clone(),
+            self.atomic_batch_writes,
         )
         .await
-        .context("Failed to build worker")?;
-        worker.run().await;
+        .context("Failed to build worker")
+    }
+
+    /// Fails if `deprecated_tables` or `enabled_tables` names a table that none of the
+    /// configured processors write, so a typo doesn't silently leave a table enabled.
+    fn validate_tables(&self) -> Result<()> {
+        let known_tables = std::iter::once(&self.processor_config)
+            .chain(self.additional_processor_configs.iter())
+            .fold(TableFlags::empty(), |tables, config| {
+                tables | config.tables()
+            });
+        let configured_tables = TableFlags::parse_names(&self.deprecated_tables)?
+            | TableFlags::parse_names(&self.enabled_tables)?;
+        let unknown_tables = configured_tables.difference(known_tables);
+        anyhow::ensure!(
+            unknown_tables.is_empty(),
+            "Tables {:?} aren't written by the configured processors, which write {:?}",
+            unknown_tables.names(),
+            known_tables.names()
+        );
         Ok(())
     }
+}
+
+#[async_trait::async_trait]
+impl RunnableConfig for IndexerGrpcProcessorConfig {
+    async fn run(&self) -> Result<()> {
+        self.validate_tables()?;
+        if self.atomic_batch_writes {
+            // Parquet batches are only done once their files are uploaded, not when processed
+            let parquet_processors = std::iter::once(&self.processor_config)
+                .chain(self.additional_processor_configs.iter())
+                .filter(|config| config.is_parquet_processor())
+                .map(|config| config.name())
+                .collect::<Vec<_>>();
+            anyhow::ensure!(
+                parquet_processors.is_empty(),
+                "atomic_batch_writes isn't supported by parquet processors {:?}",
+                parquet_processors
+            );
+        }
+        if !self.bulk_load_tables.is_empty() {
+            BulkLoader::init(
+                self.postgres_connection_string.as_ref().unwrap(),
+                self.bulk_load_tables.clone(),
+            )
+            .await?;
+        }
+
+        info!(
+            processor_name = self.processor_config.name(),
+            service_type = PROCESSOR_SERVICE_TYPE,
+            "[Parser] Creating connection pool"
+        );
+        // One pool for all processors, so `db_pool_size` caps the connections of the process
+        let db_pool = new_db_pool(
+            self.postgres_connection_string.as_ref().unwrap(),
+            self.db_pool_size,
+        )
+        .await
+        .context("Failed to create connection pool")?;
+        info!(
+            processor_name = self.processor_config.name(),
+            service_type = PROCESSOR_SERVICE_TYPE,
+            "[Parser] Finish creating the connection pool"
+        );
+        if self.additional_processor_configs.is_empty() {
+            let mut worker = self
+                .build_worker(self.processor_config.clone(), db_pool)
+                .await?;
+            return worker.run().await;
+        }
+
+        // Each processor tracks its own status, so names can't repeat
+        let mut processor_names = HashSet::new();
+        let mut workers = vec![];
+        for processor_config in
+            std::iter::once(&self.processor_config).chain(self.additional_processor_configs.iter())
+        {
+            anyhow::ensure!(
+                processor_names.insert(processor_config.name()),
+                "Processor {} is configured more than once",
+                processor_config.name()
+            );
+            workers.push(
+                self.build_worker(processor_config.clone(), db_pool.clone())
+                    .await?,
+            );
+        }
+        run_with_shared_stream(workers).await
+    }
 
     fn get_server_name(&self) -> String {
         // Get the part before the first _ and trim to 12 characters.
@@ -246,6 +406,28 @@ impl RunnableConfig for IndexerGrpcProcessorConfig {
     }
 }
 
+/// Where the worker reads transactions from. Files are useful to replay a fixed set of
+/// transactions, e.g. captured from mainnet, without access to a data service.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionSource {
+    #[default]
+    Grpc,
+    Files(FileStreamConfig),
+}
+
+impl TransactionSource {
+    /// Address reported in logs when no GRPC address is configured
+    pub fn stream_address(&self) -> Option<Url> {
+        match self {
+            TransactionSource::Grpc => None,
+            TransactionSource::Files(config) => std::fs::canonicalize(&config.path)
+                .ok()
+                .and_then(|path| Url::from_file_path(path).ok()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 #[serde(default)]
@@ -78,7 +78,17 @@ struct MarketActivityHelper {
     pub next_funding_rate: Option<BigDecimal>,
 }
 
-#[derive(Clone, Debug, Deserialize, FieldCount, Identifiable, Insertable, Serialize)]
+#[derive(
+    Clone,
+    Debug,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Queryable,
+    Selectable,
+    Serialize,
+)]
 #[diesel(primary_key(transaction_version, position_id))]
 #[diesel(table_name = trade_datas)]
 pub struct Trade {
@@ -1149,7 +1159,7 @@ impl MarketActivityModel {
                 );
 
                 MarketActivityHelper {
-                    event_type: String::from("TriggerLimitOrderEvent"),
+                    event_type: String::from("SettlePnlEvent"),
                     market_id: inner.market.get_reference_address(),
                     position_id: Some(inner.position.get_reference_address()),
                     strategy_id: None,
@@ -4,3 +4,5 @@ pub mod market_activities;
 pub mod market_datas;
 pub mod market_events;
 pub mod market_utils;
+pub mod position_histories;
+pub mod position_marks;
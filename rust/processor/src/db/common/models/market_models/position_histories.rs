@@ -0,0 +1,568 @@
+// Copyright © Mirage Protocol
+
+// This is required because a diesel macro makes clippy sad
+#![allow(clippy::extra_unused_lifetimes)]
+#![allow(clippy::unused_unit)]
+
+use super::market_activities::Trade;
+use crate::{
+    schema::{market_activities, position_datas, position_histories, trade_datas},
+    utils::database::MyDbConnection,
+};
+use ahash::{AHashMap, AHashSet};
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Array, BigInt, Text},
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const CLOSE_REASON_USER: &str = "USER";
+pub const CLOSE_REASON_TPSL: &str = "TPSL";
+pub const CLOSE_REASON_LIMIT_ORDER: &str = "LIMIT_ORDER";
+pub const CLOSE_REASON_LIQUIDATION: &str = "LIQUIDATION";
+
+const OPEN_POSITION_EVENT: &str = "OpenPositionEvent";
+const INCREASE_POSITION_SIZE_EVENT: &str = "IncreasePositionSizeEvent";
+const DECREASE_POSITION_SIZE_EVENT: &str = "DecreasePositionSizeEvent";
+const CLOSE_POSITION_EVENT: &str = "ClosePositionEvent";
+const LIQUIDATE_POSITION_EVENT: &str = "LiquidatePositionEvent";
+const TRIGGER_TPSL_EVENT: &str = "TriggerTpslEvent";
+const TRIGGER_LIMIT_ORDER_EVENT: &str = "TriggerLimitOrderEvent";
+
+/// Funding accumulators are fixed point with 8 decimals on-chain
+const FUNDING_PRECISION: i64 = 100_000_000;
+
+/// One lifecycle of a position, from the transaction that opened it until it was closed. A
+/// position object can be reopened after a close, which starts a new lifecycle.
+#[derive(Clone, Debug, Deserialize, FieldCount, Identifiable, Insertable, Serialize)]
+#[diesel(primary_key(position_id, open_transaction_version))]
+#[diesel(table_name = position_histories)]
+pub struct PositionHistory {
+    pub position_id: String,
+    pub market_id: String,
+    pub owner_addr: String,
+
+    // For a position opened before indexing started, its first indexed trade
+    pub open_transaction_version: i64,
+    pub open_transaction_timestamp: Option<chrono::NaiveDateTime>,
+    pub close_transaction_version: Option<i64>,
+    pub close_transaction_timestamp: Option<chrono::NaiveDateTime>,
+    pub close_reason: Option<String>,
+
+    pub max_position_size: BigDecimal,
+    pub realized_pnl: BigDecimal,
+    pub total_fees: BigDecimal,
+    pub total_funding: BigDecimal,
+    pub average_entry_price: Option<BigDecimal>,
+    pub average_exit_price: Option<BigDecimal>,
+
+    pub last_transaction_version: i64,
+}
+
+/// The fields of a `position_datas` row a history is built from
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = position_datas)]
+pub struct PositionWrite {
+    pub transaction_version: i64,
+    pub write_set_change_index: i64,
+    pub position_id: String,
+    pub position_size: BigDecimal,
+    pub last_funding_accumulated: BigDecimal,
+}
+
+/// A strategy trigger in `market_activities`, which tells why a position was closed
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = market_activities)]
+pub struct PositionTrigger {
+    pub transaction_version: i64,
+    pub position_id: Option<String>,
+    pub event_type: String,
+}
+
+impl PositionHistory {
+    /// Builds every lifecycle of the positions in `trades`. Entry price is the position's latest
+    /// opening price (already size weighted on-chain), exit price is the size weighted closing
+    /// price. Funding is the change in the position's funding checkpoint times the size it
+    /// applied to. Inputs don't need to be in any order.
+    pub fn from_position_changes(
+        trades: &[Trade],
+        writes: &[PositionWrite],
+        triggers: &[PositionTrigger],
+    ) -> Vec<Self> {
+        // A close in the same transaction as a strategy trigger was caused by that strategy
+        let mut tpsl_triggers = AHashSet::new();
+        let mut limit_order_triggers = AHashSet::new();
+        for trigger in triggers {
+            let trigger_set = match trigger.event_type.as_str() {
+                TRIGGER_TPSL_EVENT => &mut tpsl_triggers,
+                TRIGGER_LIMIT_ORDER_EVENT => &mut limit_order_triggers,
+                _ => continue,
+            };
+            if let Some(position_id) = &trigger.position_id {
+                trigger_set.insert((trigger.transaction_version, position_id.as_str()));
+            }
+        }
+
+        let mut trades_by_position: BTreeMap<&str, Vec<&Trade>> = BTreeMap::new();
+        for trade in trades {
+            trades_by_position
+                .entry(trade.position_id.as_str())
+                .or_default()
+                .push(trade);
+        }
+        let mut writes_by_position: AHashMap<&str, Vec<&PositionWrite>> = AHashMap::new();
+        for write in writes {
+            writes_by_position
+                .entry(write.position_id.as_str())
+                .or_default()
+                .push(write);
+        }
+
+        let mut histories = vec![];
+        for (position_id, mut trades) in trades_by_position {
+            trades.sort_by_key(|trade| trade.transaction_version);
+
+            // Every open starts a lifecycle. Trades before the first indexed open belong to a
+            // lifecycle that was opened before indexing started.
+            let mut lifecycles: Vec<Vec<&Trade>> = vec![];
+            for trade in trades {
+                match lifecycles.last_mut() {
+                    Some(lifecycle)
+                        if trade.event_type != OPEN_POSITION_EVENT
+                            && !is_closing(lifecycle.last().unwrap()) =>
+                    {
+                        lifecycle.push(trade)
+                    },
+                    _ => lifecycles.push(vec![trade]),
+                }
+            }
+
+            // Each position write belongs to the latest lifecycle started at or before it
+            let mut lifecycle_writes = vec![vec![]; lifecycles.len()];
+            let mut writes = writes_by_position.remove(position_id).unwrap_or_default();
+            writes.sort_by_key(|write| (write.transaction_version, write.write_set_change_index));
+            for write in writes {
+                let index = lifecycles
+                    .partition_point(|lifecycle| {
+                        lifecycle[0].transaction_version <= write.transaction_version
+                    })
+                    .saturating_sub(1);
+                lifecycle_writes[index].push(write);
+            }
+
+            for (lifecycle, writes) in lifecycles.iter().zip(lifecycle_writes) {
+                histories.push(Self::from_lifecycle(
+                    lifecycle,
+                    &writes,
+                    &tpsl_triggers,
+                    &limit_order_triggers,
+                ));
+            }
+        }
+        histories
+    }
+
+    fn from_lifecycle(
+        trades: &[&Trade],
+        writes: &[&PositionWrite],
+        tpsl_triggers: &AHashSet<(i64, &str)>,
+        limit_order_triggers: &AHashSet<(i64, &str)>,
+    ) -> Self {
+        let first_trade = trades[0];
+        let last_trade = trades[trades.len() - 1];
+
+        let (close_transaction_version, close_transaction_timestamp, close_reason) =
+            if is_closing(last_trade) {
+                let key = (
+                    last_trade.transaction_version,
+                    last_trade.position_id.as_str(),
+                );
+                let close_reason = if last_trade.event_type == LIQUIDATE_POSITION_EVENT {
+                    CLOSE_REASON_LIQUIDATION
+                } else if tpsl_triggers.contains(&key) {
+                    CLOSE_REASON_TPSL
+                } else if limit_order_triggers.contains(&key) {
+                    CLOSE_REASON_LIMIT_ORDER
+                } else {
+                    CLOSE_REASON_USER
+                };
+                (
+                    Some(last_trade.transaction_version),
+                    Some(last_trade.transaction_timestamp),
+                    Some(close_reason.to_string()),
+                )
+            } else {
+                (None, None, None)
+            };
+
+        let mut realized_pnl = BigDecimal::zero();
+        let mut total_fees = BigDecimal::zero();
+        let mut average_entry_price = None;
+        let mut exit_notional = BigDecimal::zero();
+        let mut exit_size = BigDecimal::zero();
+        for trade in trades {
+            realized_pnl += &trade.pnl;
+            total_fees += &trade.fee;
+            match trade.event_type.as_str() {
+                OPEN_POSITION_EVENT | INCREASE_POSITION_SIZE_EVENT => {
+                    average_entry_price = Some(trade.price.clone());
+                },
+                DECREASE_POSITION_SIZE_EVENT | CLOSE_POSITION_EVENT | LIQUIDATE_POSITION_EVENT => {
+                    exit_notional += &trade.price * &trade.position_size;
+                    exit_size += &trade.position_size;
+                },
+                _ => {},
+            }
+        }
+        let average_exit_price = (!exit_size.is_zero()).then(|| exit_notional / exit_size);
+
+        let max_position_size = writes
+            .iter()
+            .map(|write| &write.position_size)
+            .max()
+            .cloned()
+            .unwrap_or_default();
+        let total_funding = writes
+            .windows(2)
+            .map(|pair| {
+                (&pair[1].last_funding_accumulated - &pair[0].last_funding_accumulated)
+                    * &pair[0].position_size
+            })
+            .sum::<BigDecimal>()
+            / BigDecimal::from(FUNDING_PRECISION);
+
+        let last_transaction_version =
+            writes
+                .last()
+                .map_or(last_trade.transaction_version, |write| {
+                    write
+                        .transaction_version
+                        .max(last_trade.transaction_version)
+                });
+
+        Self {
+            position_id: last_trade.position_id.clone(),
+            market_id: last_trade.market_id.clone(),
+            owner_addr: last_trade.owner_addr.clone(),
+            open_transaction_version: first_trade.transaction_version,
+            open_transaction_timestamp: (first_trade.event_type == OPEN_POSITION_EVENT)
+                .then_some(first_trade.transaction_timestamp),
+            close_transaction_version,
+            close_transaction_timestamp,
+            close_reason,
+            max_position_size,
+            realized_pnl,
+            total_fees,
+            total_funding,
+            average_entry_price,
+            average_exit_price,
+            last_transaction_version,
+        }
+    }
+
+    /// Rebuilds the histories of `position_ids` touched by a batch starting at `from_version`.
+    /// Only the lifecycle the batch starts in and the ones after it are rebuilt, from the last
+    /// `open_transaction_version` at or before `from_version`; earlier lifecycles were closed
+    /// before the batch and don't change. A position whose first lifecycle is rebuilt, or that
+    /// has no history yet, is rebuilt from its first write.
+    ///
+    /// Callers must hold the positions' locks until their transaction ends, so batches processed
+    /// concurrently rebuild one after the other, and whichever rebuilds last sees the trades of
+    /// both. Histories built while an earlier batch is still being written are therefore fixed
+    /// once it lands.
+    pub async fn rebuild(
+        position_ids: &[String],
+        from_version: i64,
+        chunk_size: usize,
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<()> {
+        if position_ids.is_empty() {
+            return Ok(());
+        }
+
+        let lifecycle_opens = position_histories::table
+            .filter(position_histories::position_id.eq_any(position_ids))
+            .select((
+                position_histories::position_id,
+                position_histories::open_transaction_version,
+            ))
+            .load::<(String, i64)>(conn)
+            .await?;
+        let rebuild_from = Self::rebuild_from(&lifecycle_opens, from_version);
+        let (bounded_ids, unbounded_ids): (Vec<&str>, Vec<&str>) = position_ids
+            .iter()
+            .map(|position_id| position_id.as_str())
+            .partition(|position_id| rebuild_from.contains_key(position_id));
+        // Loaded from the earliest version any position is rebuilt from, then narrowed down
+        let min_version = rebuild_from.values().min().copied().unwrap_or_default();
+        let is_rebuilt = |position_id: &str, version: i64| {
+            rebuild_from
+                .get(position_id)
+                .map_or(true, |rebuild_from| version >= *rebuild_from)
+        };
+
+        let mut trades = trade_datas::table
+            .filter(
+                trade_datas::position_id
+                    .eq_any(unbounded_ids.clone())
+                    .or(trade_datas::position_id
+                        .eq_any(bounded_ids.clone())
+                        .and(trade_datas::transaction_version.ge(min_version))),
+            )
+            .select(Trade::as_select())
+            .load(conn)
+            .await?;
+        trades.retain(|trade| is_rebuilt(&trade.position_id, trade.transaction_version));
+        let mut writes = position_datas::table
+            .filter(
+                position_datas::position_id
+                    .eq_any(unbounded_ids.clone())
+                    .or(position_datas::position_id
+                        .eq_any(bounded_ids.clone())
+                        .and(position_datas::transaction_version.ge(min_version))),
+            )
+            .select(PositionWrite::as_select())
+            .load(conn)
+            .await?;
+        writes.retain(|write| is_rebuilt(&write.position_id, write.transaction_version));
+        let mut triggers = market_activities::table
+            .filter(
+                market_activities::position_id
+                    .eq_any(unbounded_ids.clone())
+                    .or(market_activities::position_id
+                        .eq_any(bounded_ids.clone())
+                        .and(market_activities::transaction_version.ge(min_version))),
+            )
+            .filter(
+                market_activities::event_type
+                    .eq_any([TRIGGER_TPSL_EVENT, TRIGGER_LIMIT_ORDER_EVENT]),
+            )
+            .select(PositionTrigger::as_select())
+            .load(conn)
+            .await?;
+        triggers.retain(|trigger| {
+            trigger
+                .position_id
+                .as_deref()
+                .is_some_and(|position_id| is_rebuilt(position_id, trigger.transaction_version))
+        });
+        let histories = Self::from_position_changes(&trades, &writes, &triggers);
+
+        let from_versions = position_ids
+            .iter()
+            .map(|position_id| {
+                rebuild_from
+                    .get(position_id.as_str())
+                    .copied()
+                    .unwrap_or(i64::MIN)
+            })
+            .collect::<Vec<_>>();
+        sql_query(DELETE_REBUILT_HISTORIES_QUERY)
+            .bind::<Array<Text>, _>(position_ids)
+            .bind::<Array<BigInt>, _>(from_versions)
+            .execute(conn)
+            .await?;
+        for chunk in histories.chunks(chunk_size) {
+            diesel::insert_into(position_histories::table)
+                .values(chunk)
+                .execute(conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Version each position is rebuilt from given the open versions of its lifecycles, absent
+    /// for positions rebuilt from their first write.
+    fn rebuild_from(lifecycle_opens: &[(String, i64)], from_version: i64) -> AHashMap<&str, i64> {
+        let mut opens_by_position: AHashMap<&str, Vec<i64>> = AHashMap::new();
+        for (position_id, open_transaction_version) in lifecycle_opens {
+            opens_by_position
+                .entry(position_id.as_str())
+                .or_default()
+                .push(*open_transaction_version);
+        }
+        opens_by_position
+            .into_iter()
+            .filter_map(|(position_id, opens)| {
+                // Writes before the first lifecycle's first trade belong to it, so it's rebuilt
+                // from the start
+                let first_open = opens.iter().min()?;
+                let last_open = opens.iter().filter(|open| **open <= from_version).max()?;
+                (last_open > first_open).then_some((position_id, *last_open))
+            })
+            .collect()
+    }
+}
+
+/// Deletes the histories of each position `$1[i]` opened at or after `$2[i]`
+const DELETE_REBUILT_HISTORIES_QUERY: &str = "
+DELETE FROM position_histories h
+USING unnest($1::text[], $2::bigint[]) AS r(position_id, from_version)
+WHERE h.position_id = r.position_id AND h.open_transaction_version >= r.from_version
+";
+
+fn is_closing(trade: &Trade) -> bool {
+    trade.event_type == CLOSE_POSITION_EVENT || trade.event_type == LIQUIDATE_POSITION_EVENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION_ID: &str = "0xp";
+
+    fn trade(version: i64, event_type: &str, size: i64, price: i64, fee: i64, pnl: i64) -> Trade {
+        Trade {
+            transaction_version: version,
+            market_id: "0xm".to_string(),
+            position_id: POSITION_ID.to_string(),
+            owner_addr: "0xowner".to_string(),
+            is_long: true,
+            position_size: BigDecimal::from(size),
+            price: BigDecimal::from(price),
+            fee: BigDecimal::from(fee),
+            pnl: BigDecimal::from(pnl),
+            event_type: event_type.to_string(),
+            transaction_timestamp: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    fn write(version: i64, size: i64, funding_accumulated: i64) -> PositionWrite {
+        PositionWrite {
+            transaction_version: version,
+            write_set_change_index: 0,
+            position_id: POSITION_ID.to_string(),
+            position_size: BigDecimal::from(size),
+            last_funding_accumulated: BigDecimal::from(funding_accumulated),
+        }
+    }
+
+    fn trigger(version: i64, event_type: &str) -> PositionTrigger {
+        PositionTrigger {
+            transaction_version: version,
+            position_id: Some(POSITION_ID.to_string()),
+            event_type: event_type.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_open_increase_close_reopen() {
+        let trades = [
+            trade(10, OPEN_POSITION_EVENT, 100, 50, 1, 0),
+            trade(20, INCREASE_POSITION_SIZE_EVENT, 50, 60, 2, 0),
+            trade(30, DECREASE_POSITION_SIZE_EVENT, 100, 70, 3, 0),
+            trade(40, CLOSE_POSITION_EVENT, 50, 40, 4, 25),
+            trade(50, OPEN_POSITION_EVENT, 10, 45, 5, 0),
+        ];
+        let writes = [
+            write(10, 100, 0),
+            write(20, 150, 200_000_000),
+            write(30, 50, 300_000_000),
+            write(40, 0, 400_000_000),
+            write(50, 10, 900_000_000),
+        ];
+        // Given out of order, as loaded from the db
+        let mut histories = PositionHistory::from_position_changes(
+            &trades.iter().rev().cloned().collect::<Vec<_>>(),
+            &writes,
+            &[trigger(40, TRIGGER_TPSL_EVENT)],
+        );
+        histories.sort_by_key(|history| history.open_transaction_version);
+        assert_eq!(histories.len(), 2);
+
+        let closed = &histories[0];
+        assert_eq!(closed.open_transaction_version, 10);
+        assert!(closed.open_transaction_timestamp.is_some());
+        assert_eq!(closed.close_transaction_version, Some(40));
+        assert_eq!(closed.close_reason.as_deref(), Some(CLOSE_REASON_TPSL));
+        assert_eq!(closed.max_position_size, BigDecimal::from(150));
+        assert_eq!(closed.realized_pnl, BigDecimal::from(25));
+        assert_eq!(closed.total_fees, BigDecimal::from(10));
+        assert_eq!(closed.average_entry_price, Some(BigDecimal::from(60)));
+        // (70 * 100 + 40 * 50) / 150
+        assert_eq!(closed.average_exit_price, Some(BigDecimal::from(60)));
+        // 2 * 100 + 1 * 150 + 1 * 50, not counting the reopen's checkpoint
+        assert_eq!(closed.total_funding, BigDecimal::from(400));
+        assert_eq!(closed.last_transaction_version, 40);
+
+        let reopened = &histories[1];
+        assert_eq!(reopened.open_transaction_version, 50);
+        assert_eq!(reopened.close_transaction_version, None);
+        assert_eq!(reopened.close_reason, None);
+        assert_eq!(reopened.max_position_size, BigDecimal::from(10));
+        assert_eq!(reopened.total_fees, BigDecimal::from(5));
+        assert_eq!(reopened.total_funding, BigDecimal::zero());
+        assert_eq!(reopened.average_entry_price, Some(BigDecimal::from(45)));
+        assert_eq!(reopened.average_exit_price, None);
+    }
+
+    #[test]
+    fn test_close_reasons() {
+        let trades = [
+            trade(10, OPEN_POSITION_EVENT, 100, 50, 0, 0),
+            trade(20, CLOSE_POSITION_EVENT, 100, 50, 0, 0),
+            trade(30, OPEN_POSITION_EVENT, 100, 50, 0, 0),
+            trade(40, CLOSE_POSITION_EVENT, 100, 50, 0, 0),
+            trade(50, OPEN_POSITION_EVENT, 100, 50, 0, 0),
+            trade(60, LIQUIDATE_POSITION_EVENT, 100, 50, 0, 0),
+        ];
+        let triggers = [
+            trigger(40, TRIGGER_LIMIT_ORDER_EVENT),
+            // Settling pnl doesn't close the position
+            trigger(20, "SettlePnlEvent"),
+        ];
+        let mut histories = PositionHistory::from_position_changes(&trades, &[], &triggers);
+        histories.sort_by_key(|history| history.open_transaction_version);
+
+        let close_reasons = histories
+            .iter()
+            .map(|history| history.close_reason.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(close_reasons, [
+            Some(CLOSE_REASON_USER),
+            Some(CLOSE_REASON_LIMIT_ORDER),
+            Some(CLOSE_REASON_LIQUIDATION),
+        ]);
+    }
+
+    #[test]
+    fn test_opened_before_indexing() {
+        let trades = [
+            trade(20, DECREASE_POSITION_SIZE_EVENT, 50, 70, 1, 0),
+            trade(30, CLOSE_POSITION_EVENT, 50, 70, 1, 10),
+        ];
+        let writes = [write(10, 100, 0), write(20, 50, 100_000_000)];
+        let histories = PositionHistory::from_position_changes(&trades, &writes, &[]);
+
+        assert_eq!(histories.len(), 1);
+        assert_eq!(histories[0].open_transaction_version, 20);
+        assert_eq!(histories[0].open_transaction_timestamp, None);
+        assert_eq!(histories[0].average_entry_price, None);
+        assert_eq!(histories[0].max_position_size, BigDecimal::from(100));
+        assert_eq!(histories[0].total_funding, BigDecimal::from(100));
+        assert_eq!(histories[0].close_transaction_version, Some(30));
+    }
+
+    #[test]
+    fn test_rebuild_from_last_open_before_batch() {
+        let lifecycle_opens = [
+            ("0xa".to_string(), 10),
+            ("0xa".to_string(), 50),
+            ("0xa".to_string(), 90),
+            ("0xb".to_string(), 10),
+            ("0xc".to_string(), 80),
+        ];
+        let rebuild_from = PositionHistory::rebuild_from(&lifecycle_opens, 60);
+        // The lifecycle the batch starts in and the later one
+        assert_eq!(rebuild_from.get("0xa"), Some(&50));
+        // Only has its first lifecycle, or only ones after the batch, so rebuilt entirely
+        assert_eq!(rebuild_from.get("0xb"), None);
+        assert_eq!(rebuild_from.get("0xc"), None);
+        assert_eq!(rebuild_from.len(), 1);
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright © Mirage Protocol
+
+// This is required because a diesel macro makes clippy sad
+#![allow(clippy::extra_unused_lifetimes)]
+#![allow(clippy::unused_unit)]
+
+use super::{
+    market_activities::Trade,
+    market_datas::{MarketCollectionModel, MarketConfigModel},
+};
+use crate::{
+    schema::{current_positions, market_configs, market_datas, position_datas, trade_datas},
+    utils::database::MyDbConnection,
+};
+use ahash::AHashMap;
+use bigdecimal::{BigDecimal, One, Zero};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Array, Nullable, Numeric, Text},
+};
+use diesel_async::RunQueryDsl;
+
+/// Prices, sizes, funding accumulators and the maintenance margin ratio are fixed point with 8
+/// decimals on-chain
+const PRECISION: i64 = 100_000_000;
+
+/// Trades filled at the market's oracle price. The market resource only references its oracle
+/// object, which holds no price, so these fills are the latest oracle price seen for a market.
+/// Increases report the position's new average entry price instead.
+const MARK_PRICE_EVENTS: [&str; 4] = [
+    "OpenPositionEvent",
+    "DecreasePositionSizeEvent",
+    "ClosePositionEvent",
+    "LiquidatePositionEvent",
+];
+
+/// What a market contributes to the marks of its positions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketMarkInputs {
+    pub mark_price: Option<BigDecimal>,
+    pub long_funding_accumulated_per_unit: Option<BigDecimal>,
+    pub short_funding_accumulated_per_unit: Option<BigDecimal>,
+    pub maintenance_margin: Option<BigDecimal>,
+}
+
+/// The fields of a position's latest `position_datas` row its marks are computed from
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = position_datas)]
+pub struct PositionMarkState {
+    pub position_id: String,
+    pub market_id: String,
+    pub side: String,
+    pub margin_amount: BigDecimal,
+    pub last_settled_price: BigDecimal,
+    pub position_size: BigDecimal,
+    pub last_funding_accumulated: BigDecimal,
+}
+
+/// The marks kept on `current_positions`, all null once a position is closed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PositionMark {
+    pub position_id: String,
+    pub mark_price: Option<BigDecimal>,
+    pub unrealized_pnl: Option<BigDecimal>,
+    pub accrued_funding: Option<BigDecimal>,
+    pub liquidation_price: Option<BigDecimal>,
+}
+
+impl MarketMarkInputs {
+    /// The latest inputs written in a batch, by market. Inputs the batch didn't write are `None`.
+    pub fn from_batch(
+        trades: &[Trade],
+        market_datas: &[MarketCollectionModel],
+        market_configs: &[MarketConfigModel],
+    ) -> AHashMap<String, Self> {
+        let mut prices = AHashMap::new();
+        for trade in trades {
+            if MARK_PRICE_EVENTS.contains(&trade.event_type.as_str()) {
+                keep_latest(
+                    &mut prices,
+                    &trade.market_id,
+                    (trade.transaction_version, 0),
+                    &trade.price,
+                );
+            }
+        }
+        let mut fundings = AHashMap::new();
+        for market in market_datas {
+            keep_latest(
+                &mut fundings,
+                &market.market_id,
+                (market.transaction_version, market.write_set_change_index),
+                (
+                    &market.long_funding_accumulated_per_unit,
+                    &market.short_funding_accumulated_per_unit,
+                ),
+            );
+        }
+        let mut maintenance_margins = AHashMap::new();
+        for config in market_configs {
+            keep_latest(
+                &mut maintenance_margins,
+                &config.market_id,
+                (config.transaction_version, config.write_set_change_index),
+                &config.maintenance_margin,
+            );
+        }
+
+        let mut inputs: AHashMap<String, Self> = AHashMap::new();
+        for (market_id, (_, price)) in prices {
+            inputs.entry(market_id.to_string()).or_default().mark_price = Some(price.clone());
+        }
+        for (market_id, (_, (long_funding, short_funding))) in fundings {
+            let market = inputs.entry(market_id.to_string()).or_default();
+            market.long_funding_accumulated_per_unit = Some(long_funding.clone());
+            market.short_funding_accumulated_per_unit = Some(short_funding.clone());
+        }
+        for (market_id, (_, maintenance_margin)) in maintenance_margins {
+            inputs
+                .entry(market_id.to_string())
+                .or_default()
+                .maintenance_margin = Some(maintenance_margin.clone());
+        }
+        inputs
+    }
+
+    /// Whether any input written in the batch differs from the market's `previous` inputs
+    fn changes(&self, previous: &Self) -> bool {
+        fn differs(new: &Option<BigDecimal>, old: &Option<BigDecimal>) -> bool {
+            new.is_some() && new != old
+        }
+        differs(&self.mark_price, &previous.mark_price)
+            || differs(
+                &self.long_funding_accumulated_per_unit,
+                &previous.long_funding_accumulated_per_unit,
+            )
+            || differs(
+                &self.short_funding_accumulated_per_unit,
+                &previous.short_funding_accumulated_per_unit,
+            )
+            || differs(&self.maintenance_margin, &previous.maintenance_margin)
+    }
+
+    /// The latest inputs of `market_ids` written before `before_version`
+    pub async fn load(
+        market_ids: &[String],
+        before_version: i64,
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<AHashMap<String, Self>> {
+        let mut inputs = AHashMap::new();
+        // One lookup per market walks its index backwards from the version, which a
+        // DISTINCT ON over all the markets' history wouldn't
+        for market_id in market_ids {
+            let mark_price = trade_datas::table
+                .filter(trade_datas::market_id.eq(market_id))
+                .filter(trade_datas::event_type.eq_any(MARK_PRICE_EVENTS))
+                .filter(trade_datas::transaction_version.lt(before_version))
+                .order(trade_datas::transaction_version.desc())
+                .select(trade_datas::price)
+                .first::<BigDecimal>(conn)
+                .await
+                .optional()?;
+            let funding = market_datas::table
+                .filter(market_datas::market_id.eq(market_id))
+                .filter(market_datas::transaction_version.lt(before_version))
+                .order((
+                    market_datas::transaction_version.desc(),
+                    market_datas::write_set_change_index.desc(),
+                ))
+                .select((
+                    market_datas::long_funding_accumulated_per_unit,
+                    market_datas::short_funding_accumulated_per_unit,
+                ))
+                .first::<(BigDecimal, BigDecimal)>(conn)
+                .await
+                .optional()?;
+            let maintenance_margin = market_configs::table
+                .filter(market_configs::market_id.eq(market_id))
+                .filter(market_configs::transaction_version.lt(before_version))
+                .order((
+                    market_configs::transaction_version.desc(),
+                    market_configs::write_set_change_index.desc(),
+                ))
+                .select(market_configs::maintenance_margin)
+                .first::<Option<BigDecimal>>(conn)
+                .await
+                .optional()?
+                .flatten();
+            let (long_funding, short_funding) = funding.unzip();
+            inputs.insert(market_id.clone(), Self {
+                mark_price,
+                long_funding_accumulated_per_unit: long_funding,
+                short_funding_accumulated_per_unit: short_funding,
+                maintenance_margin,
+            });
+        }
+        Ok(inputs)
+    }
+}
+
+impl PositionMark {
+    /// Marks `position` to the market's latest oracle price. A position is liquidated once
+    /// `margin + pnl - funding` falls below `maintenance_margin * size * price`, which is solved
+    /// for the price.
+    pub fn new(position: &PositionMarkState, market: &MarketMarkInputs) -> Self {
+        let precision = BigDecimal::from(PRECISION);
+        let size = &position.position_size;
+        let is_long = position.side == "LONG";
+
+        let unrealized_pnl = market.mark_price.as_ref().map(|mark_price| {
+            let pnl = (mark_price - &position.last_settled_price) * size / &precision;
+            if is_long {
+                pnl
+            } else {
+                -pnl
+            }
+        });
+        let market_funding = if is_long {
+            &market.long_funding_accumulated_per_unit
+        } else {
+            &market.short_funding_accumulated_per_unit
+        };
+        let accrued_funding = market_funding.as_ref().map(|funding_accumulated| {
+            (funding_accumulated - &position.last_funding_accumulated) * size / &precision
+        });
+
+        let maintenance_margin = market.maintenance_margin.clone().unwrap_or_default() / &precision;
+        let liquidation_price = accrued_funding
+            .as_ref()
+            .filter(|_| !size.is_zero())
+            .and_then(|funding| {
+                let settled_notional = &position.last_settled_price * size / &precision;
+                let (numerator, denominator) = if is_long {
+                    (
+                        funding - &position.margin_amount + settled_notional,
+                        size * (BigDecimal::one() - maintenance_margin),
+                    )
+                } else {
+                    (
+                        &position.margin_amount - funding + settled_notional,
+                        size * (BigDecimal::one() + maintenance_margin),
+                    )
+                };
+                if denominator.is_zero() {
+                    return None;
+                }
+                let price = numerator * &precision / denominator;
+                Some(
+                    if is_long {
+                        price.max(BigDecimal::zero())
+                    } else {
+                        price
+                    },
+                )
+            });
+
+        Self {
+            position_id: position.position_id.clone(),
+            mark_price: market.mark_price.clone(),
+            unrealized_pnl,
+            accrued_funding,
+            liquidation_price,
+        }
+    }
+
+    /// Positions whose marks a batch may have changed: the ones it wrote, and the open positions
+    /// of markets whose price, funding or maintenance margin it changed. Markets that only had
+    /// their open interest or margins written are left alone. Callers must hold the locks of
+    /// these markets and of the markets of `position_ids`, so a position opened concurrently is
+    /// either seen here or refreshed with these inputs by its own batch.
+    pub async fn affected_positions(
+        position_ids: &[String],
+        batch_inputs: &AHashMap<String, MarketMarkInputs>,
+        start_version: i64,
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<Vec<String>> {
+        let market_ids = batch_inputs.keys().cloned().collect::<Vec<_>>();
+        let previous_inputs = MarketMarkInputs::load(&market_ids, start_version, conn).await?;
+        let no_inputs = MarketMarkInputs::default();
+        let changed_market_ids = batch_inputs
+            .iter()
+            .filter(|(market_id, inputs)| {
+                inputs.changes(previous_inputs.get(*market_id).unwrap_or(&no_inputs))
+            })
+            .map(|(market_id, _)| market_id.clone())
+            .collect::<Vec<_>>();
+        if position_ids.is_empty() && changed_market_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        current_positions::table
+            .filter(
+                current_positions::position_id.eq_any(position_ids).or(
+                    current_positions::market_id
+                        .eq_any(changed_market_ids)
+                        .and(current_positions::is_closed.eq(false)),
+                ),
+            )
+            .select(current_positions::position_id)
+            .load(conn)
+            .await
+    }
+
+    /// Recomputes the marks of `position_ids` from the latest rows written for them and their
+    /// markets, and writes the ones that changed. Callers must hold the positions' locks, so
+    /// the last batch to refresh a position reads what every batch before it wrote.
+    pub async fn refresh(
+        position_ids: &[String],
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<()> {
+        if position_ids.is_empty() {
+            return Ok(());
+        }
+
+        let closed_position_ids = current_positions::table
+            .filter(current_positions::position_id.eq_any(position_ids))
+            .filter(current_positions::is_closed.eq(true))
+            .select(current_positions::position_id)
+            .load::<String>(conn)
+            .await?;
+        let positions = position_datas::table
+            .filter(position_datas::position_id.eq_any(position_ids))
+            .distinct_on(position_datas::position_id)
+            .order((
+                position_datas::position_id,
+                position_datas::transaction_version.desc(),
+                position_datas::write_set_change_index.desc(),
+            ))
+            .select(PositionMarkState::as_select())
+            .load(conn)
+            .await?;
+        let mut market_ids = positions
+            .iter()
+            .map(|position| position.market_id.clone())
+            .collect::<Vec<_>>();
+        market_ids.sort();
+        market_ids.dedup();
+        let markets = MarketMarkInputs::load(&market_ids, i64::MAX, conn).await?;
+
+        let no_inputs = MarketMarkInputs::default();
+        let mut marks = positions
+            .iter()
+            .map(|position| {
+                let market = markets.get(&position.market_id).unwrap_or(&no_inputs);
+                (position.position_id.as_str(), Self::new(position, market))
+            })
+            .collect::<AHashMap<_, _>>();
+        for position_id in &closed_position_ids {
+            marks.remove(position_id.as_str());
+        }
+
+        // Closed positions, and positions never written, have their marks cleared
+        let mut mark_prices = vec![];
+        let mut unrealized_pnls = vec![];
+        let mut accrued_fundings = vec![];
+        let mut liquidation_prices = vec![];
+        for position_id in position_ids {
+            let mark = marks.remove(position_id.as_str()).unwrap_or_default();
+            mark_prices.push(mark.mark_price);
+            unrealized_pnls.push(mark.unrealized_pnl);
+            accrued_fundings.push(mark.accrued_funding);
+            liquidation_prices.push(mark.liquidation_price);
+        }
+        sql_query(UPDATE_POSITION_MARKS_QUERY)
+            .bind::<Array<Text>, _>(position_ids)
+            .bind::<Array<Nullable<Numeric>>, _>(mark_prices)
+            .bind::<Array<Nullable<Numeric>>, _>(unrealized_pnls)
+            .bind::<Array<Nullable<Numeric>>, _>(accrued_fundings)
+            .bind::<Array<Nullable<Numeric>>, _>(liquidation_prices)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Keeps the value written last for each market
+fn keep_latest<'a, T>(
+    latest: &mut AHashMap<&'a str, ((i64, i64), T)>,
+    market_id: &'a str,
+    version: (i64, i64),
+    value: T,
+) {
+    match latest.get(market_id) {
+        Some((latest_version, _)) if *latest_version > version => {},
+        _ => {
+            latest.insert(market_id, (version, value));
+        },
+    }
+}
+
+/// Only rows whose marks changed are written
+const UPDATE_POSITION_MARKS_QUERY: &str = "
+UPDATE current_positions cp SET
+    mark_price = u.mark_price,
+    unrealized_pnl = u.unrealized_pnl,
+    accrued_funding = u.accrued_funding,
+    liquidation_price = u.liquidation_price
+FROM unnest($1::text[], $2::numeric[], $3::numeric[], $4::numeric[], $5::numeric[])
+    AS u(position_id, mark_price, unrealized_pnl, accrued_funding, liquidation_price)
+WHERE cp.position_id = u.position_id
+    AND (cp.mark_price, cp.unrealized_pnl, cp.accrued_funding, cp.liquidation_price)
+        IS DISTINCT FROM (u.mark_price, u.unrealized_pnl, u.accrued_funding, u.liquidation_price)
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(side: &str, margin_amount: i64) -> PositionMarkState {
+        PositionMarkState {
+            position_id: "0xp".to_string(),
+            market_id: "0xm".to_string(),
+            side: side.to_string(),
+            margin_amount: BigDecimal::from(margin_amount),
+            last_settled_price: BigDecimal::from(100 * PRECISION),
+            position_size: BigDecimal::from(2 * PRECISION),
+            last_funding_accumulated: BigDecimal::zero(),
+        }
+    }
+
+    fn market() -> MarketMarkInputs {
+        MarketMarkInputs {
+            mark_price: Some(BigDecimal::from(110 * PRECISION)),
+            long_funding_accumulated_per_unit: Some(BigDecimal::from(PRECISION)),
+            short_funding_accumulated_per_unit: Some(BigDecimal::from(PRECISION)),
+            // 5%
+            maintenance_margin: Some(BigDecimal::from(5_000_000)),
+        }
+    }
+
+    #[test]
+    fn test_long_position_mark() {
+        let mark = PositionMark::new(&position("LONG", 50 * PRECISION), &market());
+
+        assert_eq!(mark.mark_price, Some(BigDecimal::from(110 * PRECISION)));
+        // (110 - 100) * 2
+        assert_eq!(mark.unrealized_pnl, Some(BigDecimal::from(20 * PRECISION)));
+        // 1 * 2
+        assert_eq!(mark.accrued_funding, Some(BigDecimal::from(2 * PRECISION)));
+        // At 80, margin + pnl - funding = 50 - 40 - 2 = 8 = 5% * 2 * 80
+        assert_eq!(
+            mark.liquidation_price,
+            Some(BigDecimal::from(80 * PRECISION))
+        );
+    }
+
+    #[test]
+    fn test_short_position_mark() {
+        let mark = PositionMark::new(&position("SHORT", 54 * PRECISION), &market());
+
+        assert_eq!(mark.unrealized_pnl, Some(BigDecimal::from(-20 * PRECISION)));
+        assert_eq!(mark.accrued_funding, Some(BigDecimal::from(2 * PRECISION)));
+        // At 120, margin + pnl - funding = 54 - 40 - 2 = 12 = 5% * 2 * 120
+        assert_eq!(
+            mark.liquidation_price,
+            Some(BigDecimal::from(120 * PRECISION))
+        );
+    }
+
+    #[test]
+    fn test_mark_without_market_inputs() {
+        let mut empty = position("LONG", 50 * PRECISION);
+        empty.position_size = BigDecimal::zero();
+        let mark = PositionMark::new(&empty, &market());
+        assert_eq!(mark.liquidation_price, None);
+
+        let mark = PositionMark::new(
+            &position("LONG", 50 * PRECISION),
+            &MarketMarkInputs::default(),
+        );
+        assert_eq!(mark, PositionMark {
+            position_id: "0xp".to_string(),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_market_inputs_changes() {
+        let previous = market();
+        let mut batch = MarketMarkInputs {
+            mark_price: previous.mark_price.clone(),
+            ..Default::default()
+        };
+        // A fill at the last price, or a market write with unchanged funding, changes nothing
+        assert!(!batch.changes(&previous));
+
+        batch.long_funding_accumulated_per_unit = Some(BigDecimal::from(2 * PRECISION));
+        assert!(batch.changes(&previous));
+        assert!(market().changes(&MarketMarkInputs::default()));
+    }
+}
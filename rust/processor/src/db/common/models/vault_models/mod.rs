@@ -3,4 +3,5 @@
 pub mod vault_activities;
 pub mod vault_datas;
 pub mod vault_events;
+pub mod vault_lineage;
 pub mod vault_utils;
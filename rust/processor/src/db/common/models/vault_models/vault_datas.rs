@@ -7,14 +7,16 @@
 
 use crate::{
     db::common::models::vault_models::vault_utils::{Vault, VaultCollection},
-    schema::{vault_collection_configs, vault_collection_datas, vault_datas},
-    utils::util::{
-        bigdecimal_to_u64, parse_timestamp_secs, standardize_address, ObjectOwnerMapping,
+    schema::{current_vaults, vault_collection_configs, vault_collection_datas, vault_datas},
+    utils::{
+        database::MyDbConnection,
+        util::{bigdecimal_to_u64, parse_timestamp_secs, standardize_address, ObjectOwnerMapping},
     },
 };
 use aptos_protos::transaction::v1::WriteResource;
 use bigdecimal::BigDecimal;
-use diesel::prelude::*;
+use diesel::{prelude::*, sql_query, sql_types::BigInt};
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
@@ -87,6 +89,35 @@ pub struct VaultModel {
     pub transaction_timestamp: chrono::NaiveDateTime,
 }
 
+#[derive(
+    Clone,
+    Debug,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Queryable,
+    Selectable,
+    Serialize,
+)]
+#[diesel(primary_key(vault_id))]
+#[diesel(table_name = current_vaults)]
+pub struct CurrentVault {
+    pub last_transaction_version: i64,
+
+    pub collection_id: String,
+    pub vault_id: String,
+    pub owner_addr: String,
+
+    pub collateral_amount: BigDecimal,
+    pub borrow_part: BigDecimal,
+
+    pub is_closed: bool,
+    pub merged_into_vault_id: Option<String>,
+
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
 impl VaultCollectionModel {
     /// Fungible asset is part of an object and we need to get the object first to get owner address
     pub fn from_write_resource(
@@ -204,3 +235,71 @@ impl VaultModel {
         Ok(None)
     }
 }
+
+impl CurrentVault {
+    /// Rolls the current vaults last written at or after `from_version` back to their state
+    /// before it, as recorded in `vault_datas` and `vault_lineage`, and deletes the vaults
+    /// first written from there. Both history tables must already be rolled back.
+    pub async fn roll_back_to_version(
+        from_version: i64,
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<()> {
+        sql_query(ROLL_BACK_CURRENT_VAULTS_QUERY)
+            .bind::<BigInt, _>(from_version)
+            .execute(conn)
+            .await?;
+        diesel::delete(
+            current_vaults::table.filter(current_vaults::last_transaction_version.ge(from_version)),
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A vault's state is its latest write, or its merge into another vault, which is applied after
+/// the writes of the merge transaction
+const ROLL_BACK_CURRENT_VAULTS_QUERY: &str = "
+INSERT INTO current_vaults (
+    last_transaction_version, collection_id, vault_id, owner_addr, collateral_amount,
+    borrow_part, is_closed, merged_into_vault_id, transaction_timestamp
+)
+SELECT DISTINCT ON (vault_id) *
+FROM (
+    SELECT transaction_version, collection_id, vault_id, owner_addr, collateral_amount,
+        borrow_part, false AS is_closed, NULL AS merged_into_vault_id, transaction_timestamp
+    FROM vault_datas
+    UNION ALL
+    SELECT transaction_version, collection_id, src_vault_id, owner_addr, 0, 0, true,
+        dst_vault_id, transaction_timestamp
+    FROM vault_lineage
+) s
+WHERE vault_id IN (SELECT vault_id FROM current_vaults WHERE last_transaction_version >= $1)
+ORDER BY vault_id, transaction_version DESC, is_closed DESC
+ON CONFLICT (vault_id) DO UPDATE SET
+    last_transaction_version = excluded.last_transaction_version,
+    collection_id = excluded.collection_id,
+    owner_addr = excluded.owner_addr,
+    collateral_amount = excluded.collateral_amount,
+    borrow_part = excluded.borrow_part,
+    is_closed = excluded.is_closed,
+    merged_into_vault_id = excluded.merged_into_vault_id,
+    transaction_timestamp = excluded.transaction_timestamp,
+    inserted_at = NOW()
+";
+
+impl From<&VaultModel> for CurrentVault {
+    fn from(vault: &VaultModel) -> Self {
+        Self {
+            last_transaction_version: vault.transaction_version,
+            collection_id: vault.collection_id.clone(),
+            vault_id: vault.vault_id.clone(),
+            owner_addr: vault.owner_addr.clone(),
+            collateral_amount: vault.collateral_amount.clone(),
+            borrow_part: vault.borrow_part.clone(),
+            is_closed: false,
+            merged_into_vault_id: None,
+            transaction_timestamp: vault.transaction_timestamp,
+        }
+    }
+}
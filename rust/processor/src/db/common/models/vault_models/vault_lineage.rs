@@ -0,0 +1,244 @@
+// Copyright © Mirage Protocol
+
+// This is required because a diesel macro makes clippy sad
+#![allow(clippy::extra_unused_lifetimes)]
+#![allow(clippy::unused_unit)]
+
+use super::{vault_activities::VaultActivityModel, vault_datas::CurrentVault};
+use crate::{schema::vault_lineage, utils::database::MyDbConnection};
+use ahash::AHashMap;
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{
+    sql_query,
+    sql_types::{Array, Text},
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+const MERGE_VAULT_EVENT_TYPE: &str = "MergeVaultEvent";
+
+/// Links a merged (source) vault to the vault it was merged into, so per-vault history
+/// can be followed across merges.
+#[derive(Clone, Debug, Deserialize, FieldCount, Identifiable, Insertable, Serialize)]
+#[diesel(primary_key(transaction_version, event_index))]
+#[diesel(table_name = vault_lineage)]
+pub struct VaultLineage {
+    pub transaction_version: i64,
+    pub event_index: i64,
+
+    pub collection_id: String,
+    pub src_vault_id: String,
+    pub dst_vault_id: String,
+    pub owner_addr: Option<String>,
+
+    /// State of the source vault before the merge, if it is known
+    pub collateral_amount: Option<BigDecimal>,
+    pub borrow_part: Option<BigDecimal>,
+
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
+impl VaultLineage {
+    /// Builds the lineage rows for every MergeVaultEvent in a transaction, along with the
+    /// current state of the source vaults, which are closed.
+    ///
+    /// The destination vault isn't updated here: the merge moves the source's collateral and
+    /// debt by writing the destination's vault resource, so its totals come from `txn_vaults`,
+    /// the vaults written by this transaction, like any other vault write.
+    ///
+    /// The state of a source vault before the merge is only known here if it was written
+    /// earlier in the batch, in `batch_vaults`. Otherwise it is filled in from `vault_datas`
+    /// once the batch is written, see [`VaultLineage::refresh_source_states`].
+    pub fn from_vault_activities(
+        vault_activities: &[VaultActivityModel],
+        batch_vaults: &AHashMap<String, CurrentVault>,
+        sender_address: &str,
+    ) -> (Vec<Self>, Vec<CurrentVault>) {
+        let mut lineages = vec![];
+        // Source vaults closed by the merges of this transaction
+        let mut merged_vaults: AHashMap<String, CurrentVault> = AHashMap::new();
+
+        for activity in vault_activities {
+            if activity.event_type != MERGE_VAULT_EVENT_TYPE {
+                continue;
+            }
+            let (src_vault_id, dst_vault_id) =
+                match (activity.src_vault_id.as_ref(), activity.vault_id.as_ref()) {
+                    (Some(src), Some(dst)) => (src.clone(), dst.clone()),
+                    _ => {
+                        tracing::warn!(
+                            transaction_version = activity.transaction_version,
+                            event_index = activity.event_index,
+                            "MergeVaultEvent is missing the source or destination vault"
+                        );
+                        continue;
+                    },
+                };
+            let prior_src_state = batch_vaults.get(&src_vault_id);
+
+            let owner_addr = activity
+                .owner_addr
+                .clone()
+                .or_else(|| prior_src_state.map(|v| v.owner_addr.clone()))
+                .unwrap_or_else(|| sender_address.to_string());
+
+            lineages.push(Self {
+                transaction_version: activity.transaction_version,
+                event_index: activity.event_index,
+                collection_id: activity.collection_id.clone(),
+                src_vault_id: src_vault_id.clone(),
+                dst_vault_id: dst_vault_id.clone(),
+                owner_addr: Some(owner_addr.clone()),
+                collateral_amount: prior_src_state.map(|v| v.collateral_amount.clone()),
+                borrow_part: prior_src_state.map(|v| v.borrow_part.clone()),
+                transaction_timestamp: activity.transaction_timestamp,
+            });
+
+            merged_vaults.insert(src_vault_id.clone(), CurrentVault {
+                last_transaction_version: activity.transaction_version,
+                collection_id: activity.collection_id.clone(),
+                vault_id: src_vault_id,
+                owner_addr,
+                collateral_amount: BigDecimal::zero(),
+                borrow_part: BigDecimal::zero(),
+                is_closed: true,
+                merged_into_vault_id: Some(dst_vault_id),
+                transaction_timestamp: activity.transaction_timestamp,
+            });
+        }
+
+        (lineages, merged_vaults.into_values().collect())
+    }
+
+    /// Sets the source state of the merges of `vault_ids` to the last `vault_datas` row of the
+    /// source before the merge.
+    ///
+    /// Batches can be written in any order, so this runs both for the merges a batch writes and
+    /// for the vaults it writes, under the locks of those vaults: whichever of a merge and an
+    /// earlier write of its source is written last sees the other.
+    pub async fn refresh_source_states(
+        vault_ids: &[String],
+        conn: &mut MyDbConnection,
+    ) -> diesel::QueryResult<()> {
+        sql_query(REFRESH_SOURCE_STATES_QUERY)
+            .bind::<Array<Text>, _>(vault_ids)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+const REFRESH_SOURCE_STATES_QUERY: &str = "
+UPDATE vault_lineage l
+SET collateral_amount = v.collateral_amount, borrow_part = v.borrow_part
+FROM vault_lineage m
+CROSS JOIN LATERAL (
+    SELECT collateral_amount, borrow_part
+    FROM vault_datas
+    WHERE vault_id = m.src_vault_id AND transaction_version < m.transaction_version
+    ORDER BY transaction_version DESC, write_set_change_index DESC
+    LIMIT 1
+) v
+WHERE m.src_vault_id = ANY($1)
+    AND l.transaction_version = m.transaction_version
+    AND l.event_index = m.event_index
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault(
+        vault_id: &str,
+        version: i64,
+        collateral_amount: u64,
+        borrow_part: u64,
+    ) -> CurrentVault {
+        CurrentVault {
+            last_transaction_version: version,
+            collection_id: "0xc".to_string(),
+            vault_id: vault_id.to_string(),
+            owner_addr: "0xowner".to_string(),
+            collateral_amount: BigDecimal::from(collateral_amount),
+            borrow_part: BigDecimal::from(borrow_part),
+            is_closed: false,
+            merged_into_vault_id: None,
+            transaction_timestamp: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    fn merge(version: i64, src_vault_id: &str, dst_vault_id: &str) -> VaultActivityModel {
+        VaultActivityModel {
+            transaction_version: version,
+            event_creation_number: 0,
+            event_sequence_number: 0,
+            event_index: 0,
+            collection_id: "0xc".to_string(),
+            event_type: MERGE_VAULT_EVENT_TYPE.to_string(),
+            vault_id: Some(dst_vault_id.to_string()),
+            src_vault_id: Some(src_vault_id.to_string()),
+            owner_addr: None,
+            collateral_amount: None,
+            borrow_amount: None,
+            fee_amount: None,
+            socialized_amount: None,
+            collateralization_rate_before: None,
+            collateralization_rate_after: None,
+            new_interest_per_second: None,
+            transaction_timestamp: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    fn by_id(vaults: Vec<CurrentVault>) -> AHashMap<String, CurrentVault> {
+        vaults
+            .into_iter()
+            .map(|v| (v.vault_id.clone(), v))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_within_batch() {
+        let batch_vaults = by_id(vec![
+            vault("0xsrc", 10, 100, 40),
+            vault("0xdst", 11, 50, 20),
+        ]);
+        let (lineages, vaults) = VaultLineage::from_vault_activities(
+            &[merge(12, "0xsrc", "0xdst")],
+            &batch_vaults,
+            "0xsender",
+        );
+
+        assert_eq!(lineages.len(), 1);
+        assert_eq!(lineages[0].collateral_amount, Some(BigDecimal::from(100)));
+        assert_eq!(lineages[0].borrow_part, Some(BigDecimal::from(40)));
+        assert_eq!(lineages[0].owner_addr.as_deref(), Some("0xowner"));
+
+        // The destination's new totals come from the merge transaction's own write
+        let vaults = by_id(vaults);
+        assert_eq!(vaults.len(), 1);
+        let src = &vaults["0xsrc"];
+        assert!(src.is_closed);
+        assert_eq!(src.merged_into_vault_id.as_deref(), Some("0xdst"));
+        assert_eq!(src.collateral_amount, BigDecimal::zero());
+    }
+
+    #[test]
+    fn test_merge_of_vault_written_in_earlier_batch() {
+        let batch_vaults = by_id(vec![vault("0xdst", 11, 50, 20)]);
+        let (lineages, vaults) = VaultLineage::from_vault_activities(
+            &[merge(12, "0xsrc", "0xdst")],
+            &batch_vaults,
+            "0xsender",
+        );
+
+        // Filled in from vault_datas once written
+        assert_eq!(lineages[0].collateral_amount, None);
+        assert_eq!(lineages[0].borrow_part, None);
+        assert_eq!(lineages[0].owner_addr.as_deref(), Some("0xsender"));
+
+        let vaults = by_id(vaults);
+        assert_eq!(vaults.len(), 1);
+        assert!(vaults["0xsrc"].is_closed);
+    }
+}
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS vault_lineage;
+DROP TABLE IF EXISTS current_vaults;
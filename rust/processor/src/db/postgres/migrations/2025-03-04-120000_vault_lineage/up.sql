@@ -0,0 +1,45 @@
+-- Your SQL goes here
+
+-- current vaults
+CREATE TABLE current_vaults (
+  last_transaction_version BIGINT NOT NULL,
+
+  collection_id VARCHAR(66) NOT NULL,
+  vault_id VARCHAR(66) NOT NULL,
+  owner_addr VARCHAR(66) NOT NULL,
+
+  collateral_amount NUMERIC NOT NULL,
+  borrow_part NUMERIC NOT NULL,
+
+  is_closed BOOLEAN NOT NULL,
+  merged_into_vault_id VARCHAR(66),
+
+  transaction_timestamp TIMESTAMP NOT NULL,
+  inserted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  -- Constraints
+  PRIMARY KEY (vault_id)
+);
+CREATE INDEX current_vaults_owner on current_vaults (owner_addr);
+CREATE INDEX current_vaults_cid on current_vaults (collection_id);
+
+-- vault merges, src -> dst
+CREATE TABLE vault_lineage (
+  transaction_version BIGINT NOT NULL,
+  event_index BIGINT NOT NULL,
+
+  collection_id VARCHAR(66) NOT NULL,
+  src_vault_id VARCHAR(66) NOT NULL,
+  dst_vault_id VARCHAR(66) NOT NULL,
+  owner_addr VARCHAR(66),
+
+  -- last known src state before the merge, null if it wasn't written earlier in the batch
+  collateral_amount NUMERIC,
+  borrow_part NUMERIC,
+
+  transaction_timestamp TIMESTAMP NOT NULL,
+  inserted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  -- Constraints
+  PRIMARY KEY (transaction_version, event_index)
+);
+CREATE INDEX vault_lineage_src on vault_lineage (src_vault_id);
+CREATE INDEX vault_lineage_dst on vault_lineage (dst_vault_id);
@@ -0,0 +1,31 @@
+-- Your SQL goes here
+
+-- one row per position, rebuilt from trade_datas and position_datas whenever the position trades
+CREATE TABLE position_histories (
+  position_id VARCHAR(66) NOT NULL,
+  market_id VARCHAR(66) NOT NULL,
+  owner_addr VARCHAR(66) NOT NULL,
+
+  open_transaction_version BIGINT,
+  open_transaction_timestamp TIMESTAMP,
+  -- null while the position is open
+  close_transaction_version BIGINT,
+  close_transaction_timestamp TIMESTAMP,
+  -- USER, TPSL, LIMIT_ORDER or LIQUIDATION
+  close_reason VARCHAR(16),
+
+  max_position_size NUMERIC NOT NULL,
+  realized_pnl NUMERIC NOT NULL,
+  total_fees NUMERIC NOT NULL,
+  total_funding NUMERIC NOT NULL,
+  average_entry_price NUMERIC,
+  average_exit_price NUMERIC,
+
+  last_transaction_version BIGINT NOT NULL,
+  inserted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  -- Constraints
+  PRIMARY KEY (position_id)
+);
+CREATE INDEX position_histories_owner on position_histories (owner_addr);
+CREATE INDEX position_histories_market on position_histories (market_id);
+CREATE INDEX position_histories_close_version on position_histories (close_transaction_version);
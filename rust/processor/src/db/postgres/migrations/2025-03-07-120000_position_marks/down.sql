@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS position_datas_pid_tv;
+DROP INDEX IF EXISTS trade_datas_pid;
+DROP INDEX IF EXISTS trade_datas_mid_tv;
+ALTER TABLE current_positions
+DROP COLUMN IF EXISTS mark_price,
+DROP COLUMN IF EXISTS unrealized_pnl,
+DROP COLUMN IF EXISTS accrued_funding,
+DROP COLUMN IF EXISTS liquidation_price;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- mark to market for open positions, null once the position is closed
+ALTER TABLE current_positions
+ADD COLUMN IF NOT EXISTS mark_price NUMERIC,
+ADD COLUMN IF NOT EXISTS unrealized_pnl NUMERIC,
+ADD COLUMN IF NOT EXISTS accrued_funding NUMERIC,
+ADD COLUMN IF NOT EXISTS liquidation_price NUMERIC;
+
+-- latest position / trade lookups used to rebuild marks and position histories
+CREATE INDEX IF NOT EXISTS position_datas_pid_tv on position_datas (position_id, transaction_version);
+CREATE INDEX IF NOT EXISTS trade_datas_pid on trade_datas (position_id);
+CREATE INDEX IF NOT EXISTS trade_datas_mid_tv on trade_datas (market_id, transaction_version);
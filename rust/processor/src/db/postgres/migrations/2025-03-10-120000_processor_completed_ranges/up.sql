@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Batches a processor finished ahead of its contiguous `processor_status`, so they can be
+-- skipped after a restart instead of being reprocessed.
+CREATE TABLE IF NOT EXISTS processor_completed_ranges (
+  processor VARCHAR(100) NOT NULL,
+  start_version BIGINT NOT NULL,
+  end_version BIGINT NOT NULL,
+  last_updated TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (processor, start_version)
+);
@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS market_activities_pid_et;
+DELETE FROM position_histories ph
+WHERE EXISTS (
+    SELECT 1
+    FROM position_histories later
+    WHERE later.position_id = ph.position_id
+      AND later.open_transaction_version > ph.open_transaction_version
+  );
+ALTER TABLE position_histories DROP CONSTRAINT position_histories_pkey;
+ALTER TABLE position_histories
+ADD PRIMARY KEY (position_id);
+ALTER TABLE position_histories
+ALTER COLUMN open_transaction_version DROP NOT NULL;
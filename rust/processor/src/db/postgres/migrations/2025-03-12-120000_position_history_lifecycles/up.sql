@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+-- settling pnl was recorded as a limit order trigger, which made settled positions look closed by
+-- a limit order. Limit order triggers always have a strategy.
+UPDATE market_activities
+SET event_type = 'SettlePnlEvent'
+WHERE event_type = 'TriggerLimitOrderEvent'
+  AND strategy_id IS NULL;
+
+-- one row per lifecycle of a position, so a reopened position keeps its earlier histories
+UPDATE position_histories ph
+SET open_transaction_version = COALESCE(
+    (
+      SELECT MIN(td.transaction_version)
+      FROM trade_datas td
+      WHERE td.position_id = ph.position_id
+    ),
+    ph.last_transaction_version
+  )
+WHERE ph.open_transaction_version IS NULL;
+ALTER TABLE position_histories
+ALTER COLUMN open_transaction_version
+SET NOT NULL;
+ALTER TABLE position_histories DROP CONSTRAINT position_histories_pkey;
+ALTER TABLE position_histories
+ADD PRIMARY KEY (position_id, open_transaction_version);
+-- histories are rebuilt from the strategy triggers of their positions
+CREATE INDEX IF NOT EXISTS market_activities_pid_et on market_activities (position_id, event_type);
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Versions the Mirage processor was reindexed from, each applied once
+CREATE TABLE IF NOT EXISTS mirage_backfills (
+  processor VARCHAR(100) NOT NULL,
+  from_version BIGINT NOT NULL,
+  applied_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (processor, from_version)
+);
+-- these used to be recorded as backfill_processor_status rows
+INSERT INTO mirage_backfills (processor, from_version, applied_at)
+SELECT substring(
+    backfill_alias
+    FROM '^(.*)_from_[0-9]+$'
+  ),
+  backfill_start_version,
+  last_updated
+FROM backfill_processor_status
+WHERE backfill_alias ~ '^mirage_processor_from_[0-9]+$' ON CONFLICT DO NOTHING;
+DELETE FROM backfill_processor_status
+WHERE backfill_alias ~ '^mirage_processor_from_[0-9]+$';
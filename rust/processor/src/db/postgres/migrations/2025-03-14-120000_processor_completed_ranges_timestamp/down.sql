@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE processor_completed_ranges
+DROP COLUMN IF EXISTS last_transaction_timestamp;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Timestamp of each range's last transaction, so the status takes it over when it reaches the range
+ALTER TABLE processor_completed_ranges
+ADD COLUMN IF NOT EXISTS last_transaction_timestamp TIMESTAMP;
@@ -10,6 +10,7 @@ pub mod events_models;
 pub mod fungible_asset_models;
 pub mod ledger_info;
 pub mod object_models;
+pub mod processor_completed_ranges;
 pub mod processor_status;
 pub mod property_map;
 pub mod resources;
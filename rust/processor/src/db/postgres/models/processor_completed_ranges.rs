@@ -0,0 +1,82 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::extra_unused_lifetimes)]
+
+use crate::{
+    gap_detectors::range_set::VersionRangeSet, schema::processor_completed_ranges,
+    utils::database::DbPoolConnection,
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = processor_completed_ranges)]
+/// A range of versions processed ahead of `processor_status`
+pub struct ProcessorCompletedRange {
+    pub processor: String,
+    pub start_version: i64,
+    pub end_version: i64,
+    pub last_transaction_timestamp: Option<chrono::NaiveDateTime>,
+}
+
+impl ProcessorCompletedRange {
+    /// Loads the ranges completed past `from_version`, the next version to process.
+    pub async fn get_by_processor(
+        processor_name: &str,
+        from_version: u64,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<VersionRangeSet> {
+        let ranges = processor_completed_ranges::table
+            .filter(processor_completed_ranges::processor.eq(processor_name))
+            .filter(processor_completed_ranges::end_version.ge(from_version as i64))
+            .select((
+                processor_completed_ranges::start_version,
+                processor_completed_ranges::end_version,
+            ))
+            .load::<(i64, i64)>(conn)
+            .await?;
+
+        let mut range_set = VersionRangeSet::default();
+        for (start_version, end_version) in ranges {
+            range_set.insert((start_version as u64).max(from_version), end_version as u64);
+        }
+        Ok(range_set)
+    }
+
+    /// Replaces the processor's ranges with `ranges`.
+    pub async fn replace_for_processor(
+        processor_name: &str,
+        ranges: &VersionRangeSet,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<()> {
+        let rows = ranges
+            .iter()
+            .map(|(start_version, end_version)| Self {
+                processor: processor_name.to_string(),
+                start_version: start_version as i64,
+                end_version: end_version as i64,
+                last_transaction_timestamp: None,
+            })
+            .collect::<Vec<_>>();
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                diesel::delete(
+                    processor_completed_ranges::table
+                        .filter(processor_completed_ranges::processor.eq(processor_name)),
+                )
+                .execute(conn)
+                .await?;
+                if !rows.is_empty() {
+                    diesel::insert_into(processor_completed_ranges::table)
+                        .values(&rows)
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -3,8 +3,11 @@
 
 #![allow(clippy::extra_unused_lifetimes)]
 
-use crate::{schema::processor_status, utils::database::DbPoolConnection};
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use crate::{
+    schema::{processor_completed_ranges, processor_status},
+    utils::database::{execute_with_better_error_conn, DbPoolConnection},
+};
+use diesel::{pg::upsert::excluded, ExpressionMethods, OptionalExtension, QueryDsl};
 use diesel_async::RunQueryDsl;
 
 #[derive(AsChangeset, Debug, Insertable)]
@@ -16,6 +19,131 @@ pub struct ProcessorStatus {
     pub last_transaction_timestamp: Option<chrono::NaiveDateTime>,
 }
 
+impl ProcessorStatus {
+    /// Moves the status up to `last_success_version`, creating it if it doesn't exist. Batches
+    /// written with `record_batch` need the status in place to move it forward.
+    pub async fn ensure_at_least(
+        processor_name: &str,
+        last_success_version: i64,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<()> {
+        execute_with_better_error_conn(
+            conn,
+            diesel::insert_into(processor_status::table)
+                .values((
+                    processor_status::processor.eq(processor_name),
+                    processor_status::last_success_version.eq(last_success_version),
+                ))
+                .on_conflict(processor_status::processor)
+                .do_update()
+                .set((
+                    processor_status::last_success_version
+                        .eq(excluded(processor_status::last_success_version)),
+                    processor_status::last_updated.eq(diesel::dsl::now),
+                )),
+            Some(" WHERE processor_status.last_success_version < EXCLUDED.last_success_version "),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Records the batch `[start_version, end_version]` as processed. A batch that follows the
+    /// status moves it to `end_version`, or further when the ranges completed after it are now
+    /// contiguous; a batch ahead of it is added to `processor_completed_ranges` instead, so the
+    /// status never skips a version.
+    ///
+    /// The status row is locked until the surrounding transaction ends, so concurrent batches
+    /// record one after the other and none misses a range another is about to add.
+    pub async fn record_batch(
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+        last_transaction_timestamp: Option<chrono::NaiveDateTime>,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<()> {
+        let (start_version, end_version) = (start_version as i64, end_version as i64);
+        let last_success_version = processor_status::table
+            .filter(processor_status::processor.eq(processor_name))
+            .select(processor_status::last_success_version)
+            .for_update()
+            .first::<i64>(conn)
+            .await?;
+
+        if last_success_version >= end_version {
+            // Already covered, e.g. a refetched batch that was processed after all
+            return Ok(());
+        }
+        if last_success_version < start_version - 1 {
+            diesel::insert_into(processor_completed_ranges::table)
+                .values((
+                    processor_completed_ranges::processor.eq(processor_name),
+                    processor_completed_ranges::start_version.eq(start_version),
+                    processor_completed_ranges::end_version.eq(end_version),
+                    processor_completed_ranges::last_transaction_timestamp
+                        .eq(last_transaction_timestamp),
+                ))
+                .on_conflict((
+                    processor_completed_ranges::processor,
+                    processor_completed_ranges::start_version,
+                ))
+                .do_update()
+                .set((
+                    processor_completed_ranges::end_version
+                        .eq(excluded(processor_completed_ranges::end_version)),
+                    processor_completed_ranges::last_transaction_timestamp.eq(excluded(
+                        processor_completed_ranges::last_transaction_timestamp,
+                    )),
+                    processor_completed_ranges::last_updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)
+                .await?;
+            return Ok(());
+        }
+
+        // The batch follows the status, which takes over the ranges it now reaches
+        let ranges = processor_completed_ranges::table
+            .filter(processor_completed_ranges::processor.eq(processor_name))
+            .filter(processor_completed_ranges::end_version.gt(end_version))
+            .order(processor_completed_ranges::start_version)
+            .select((
+                processor_completed_ranges::start_version,
+                processor_completed_ranges::end_version,
+                processor_completed_ranges::last_transaction_timestamp,
+            ))
+            .load::<(i64, i64, Option<chrono::NaiveDateTime>)>(conn)
+            .await?;
+        let (mut new_version, mut new_timestamp) = (end_version, last_transaction_timestamp);
+        for (range_start_version, range_end_version, range_timestamp) in ranges {
+            if range_start_version > new_version + 1 {
+                break;
+            }
+            if range_end_version > new_version {
+                new_version = range_end_version;
+                new_timestamp = range_timestamp.or(new_timestamp);
+            }
+        }
+
+        diesel::delete(
+            processor_completed_ranges::table
+                .filter(processor_completed_ranges::processor.eq(processor_name))
+                .filter(processor_completed_ranges::end_version.le(new_version)),
+        )
+        .execute(conn)
+        .await?;
+        diesel::update(
+            processor_status::table.filter(processor_status::processor.eq(processor_name)),
+        )
+        .set((
+            processor_status::last_success_version.eq(new_version),
+            processor_status::last_updated.eq(diesel::dsl::now),
+            processor_status::last_transaction_timestamp.eq(new_timestamp),
+        ))
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
 #[derive(AsChangeset, Debug, Queryable)]
 #[diesel(table_name = processor_status)]
 /// Only tracking the latest version successfully processed
@@ -554,6 +554,10 @@ diesel::table! {
         event_index -> Int8,
         transaction_timestamp -> Timestamp,
         inserted_at -> Timestamp,
+        mark_price -> Nullable<Numeric>,
+        unrealized_pnl -> Nullable<Numeric>,
+        accrued_funding -> Nullable<Numeric>,
+        liquidation_price -> Nullable<Numeric>,
     }
 }
 
@@ -769,6 +773,25 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    current_vaults (vault_id) {
+        last_transaction_version -> Int8,
+        #[max_length = 66]
+        collection_id -> Varchar,
+        #[max_length = 66]
+        vault_id -> Varchar,
+        #[max_length = 66]
+        owner_addr -> Varchar,
+        collateral_amount -> Numeric,
+        borrow_part -> Numeric,
+        is_closed -> Bool,
+        #[max_length = 66]
+        merged_into_vault_id -> Nullable<Varchar>,
+        transaction_timestamp -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     delegated_staking_activities (transaction_version, event_index) {
         transaction_version -> Int8,
@@ -1076,6 +1099,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    mirage_backfills (processor, from_version) {
+        #[max_length = 100]
+        processor -> Varchar,
+        from_version -> Int8,
+        applied_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     mirage_debt_store_datas (transaction_version, write_set_change_index) {
         transaction_version -> Int8,
@@ -1190,6 +1222,42 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    position_histories (position_id, open_transaction_version) {
+        #[max_length = 66]
+        position_id -> Varchar,
+        #[max_length = 66]
+        market_id -> Varchar,
+        #[max_length = 66]
+        owner_addr -> Varchar,
+        open_transaction_version -> Int8,
+        open_transaction_timestamp -> Nullable<Timestamp>,
+        close_transaction_version -> Nullable<Int8>,
+        close_transaction_timestamp -> Nullable<Timestamp>,
+        #[max_length = 16]
+        close_reason -> Nullable<Varchar>,
+        max_position_size -> Numeric,
+        realized_pnl -> Numeric,
+        total_fees -> Numeric,
+        total_funding -> Numeric,
+        average_entry_price -> Nullable<Numeric>,
+        average_exit_price -> Nullable<Numeric>,
+        last_transaction_version -> Int8,
+        inserted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    processor_completed_ranges (processor, start_version) {
+        #[max_length = 100]
+        processor -> Varchar,
+        start_version -> Int8,
+        end_version -> Int8,
+        last_updated -> Timestamp,
+        last_transaction_timestamp -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     processor_status (processor) {
         #[max_length = 100]
@@ -1672,6 +1740,25 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    vault_lineage (transaction_version, event_index) {
+        transaction_version -> Int8,
+        event_index -> Int8,
+        #[max_length = 66]
+        collection_id -> Varchar,
+        #[max_length = 66]
+        src_vault_id -> Varchar,
+        #[max_length = 66]
+        dst_vault_id -> Varchar,
+        #[max_length = 66]
+        owner_addr -> Nullable<Varchar>,
+        collateral_amount -> Nullable<Numeric>,
+        borrow_part -> Nullable<Numeric>,
+        transaction_timestamp -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     write_set_changes (transaction_version, index) {
         transaction_version -> Int8,
@@ -1738,6 +1825,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     current_token_royalty_v1,
     current_token_v2_metadata,
     current_tpsls,
+    current_vaults,
     delegated_staking_activities,
     delegated_staking_pool_balances,
     delegated_staking_pools,
@@ -1754,12 +1842,15 @@ diesel::allow_tables_to_appear_in_same_query!(
     market_activities,
     market_configs,
     market_datas,
+    mirage_backfills,
     mirage_debt_store_datas,
     move_modules,
     move_resources,
     nft_points,
     objects,
     position_datas,
+    position_histories,
+    processor_completed_ranges,
     processor_status,
     proposal_votes,
     public_key_auth_keys,
@@ -1783,6 +1874,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     vault_collection_configs,
     vault_collection_datas,
     vault_datas,
+    vault_lineage,
     write_set_changes,
     write_set_size_info,
 );
@@ -0,0 +1,315 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replays transactions from files on disk instead of the GRPC stream, feeding the same
+//! channel as [`crate::grpc_stream::create_fetcher_loop`].
+//!
+//! Supported files:
+//! * `.json`: a single transaction (the format used by `aptos-indexer-test-transactions`), an
+//!   array of transactions, or a `TransactionsResponse`.
+//! * `.pb`: a protobuf encoded `TransactionsResponse`, as sent by the GRPC stream.
+//! * `.pb.gz` and `.pb.gz.partial`: files recorded by [`crate::stream_archive`].
+
+use crate::{
+    grpc_stream::TransactionsPBResponse,
+    stream_archive::{archive_file_overlaps, is_readable_archive_file, read_archive_file},
+    transaction_filter::TransactionFilter,
+    utils::counters::{ProcessorStep, LATEST_PROCESSED_VERSION, NUM_TRANSACTIONS_PROCESSED_COUNT},
+};
+use anyhow::{bail, Context, Result};
+use aptos_protos::{indexer::v1::TransactionsResponse, transaction::v1::Transaction};
+use kanal::AsyncSender;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use server_framework::status::update_processor_progress;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileStreamConfig {
+    /// A transaction file, or a directory of them (not recursive)
+    pub path: PathBuf,
+    /// Chain id to check the db against, since files don't carry one
+    pub chain_id: u64,
+}
+
+/// Reads every transaction under `path`, sorted by version and deduplicated.
+pub fn read_transactions(path: &Path) -> Result<Vec<Transaction>> {
+    read_transactions_in_range(path, 0, u64::MAX)
+}
+
+/// Reads the transactions under `path` in `[start_version, end_version]`, sorted by version and
+/// deduplicated. Archive files whose names show they hold none of the range aren't read.
+pub fn read_transactions_in_range(
+    path: &Path,
+    start_version: u64,
+    end_version: u64,
+) -> Result<Vec<Transaction>> {
+    let files = if path.is_dir() {
+        let mut files = std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory {}", path.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.retain(|file| file.is_file());
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut transactions = vec![];
+    for file in files {
+        let mut file_transactions = match file.extension().and_then(|ext| ext.to_str()) {
+            _ if is_readable_archive_file(&file) => {
+                if !archive_file_overlaps(&file, start_version, end_version) {
+                    continue;
+                }
+                read_archive_file(&file).map(|responses| {
+                    responses
+                        .into_iter()
+                        .flat_map(|response| response.transactions)
+                        .collect()
+                })
+            },
+            Some("json") => read_file(&file).and_then(|bytes| parse_json_transactions(&bytes)),
+            Some("pb") => read_file(&file).and_then(|bytes| {
+                TransactionsResponse::decode(bytes.as_slice())
+                    .map(|response| response.transactions)
+                    .map_err(anyhow::Error::from)
+            }),
+            _ => {
+                info!(
+                    file = file.display().to_string(),
+                    "[Parser] Skipping unknown file"
+                );
+                continue;
+            },
+        }
+        .with_context(|| format!("Failed to parse transactions from {}", file.display()))?;
+        file_transactions.retain(|txn| txn.version >= start_version && txn.version <= end_version);
+        transactions.append(&mut file_transactions);
+    }
+
+    transactions.sort_by_key(|txn| txn.version);
+    transactions.dedup_by_key(|txn| txn.version);
+    Ok(transactions)
+}
+
+fn read_file(file: &Path) -> Result<Vec<u8>> {
+    std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))
+}
+
+fn parse_json_transactions(bytes: &[u8]) -> Result<Vec<Transaction>> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)?;
+    Ok(match value {
+        serde_json::Value::Array(_) => serde_json::from_value(value)?,
+        serde_json::Value::Object(ref fields) if fields.contains_key("transactions") => {
+            serde_json::from_value::<TransactionsResponse>(value)?.transactions
+        },
+        serde_json::Value::Object(_) => vec![serde_json::from_value(value)?],
+        _ => bail!("Expected a transaction, an array of transactions or a TransactionsResponse"),
+    })
+}
+
+/// Sends the transactions from `config.path` in `[starting_version, request_ending_version]`
+/// to the processor tasks. Dropping `txn_sender` on return closes the channel, so the tasks
+/// stop once they have drained it.
+///
+/// Files usually hold a sparse set of versions, so each batch claims the versions since the
+/// previous batch as well, the same way filtered out transactions are handled for GRPC.
+pub async fn create_file_fetcher_loop(
+    txn_sender: AsyncSender<TransactionsPBResponse>,
+    config: FileStreamConfig,
+    starting_version: u64,
+    request_ending_version: Option<u64>,
+    processor_name: String,
+    transaction_filter: TransactionFilter,
+    // The number of transactions per protobuf batch, read for every batch
+    pb_channel_txn_chunk_size: Arc<AtomicUsize>,
+) {
+    let source = config.path.display().to_string();
+    info!(
+        processor_name = processor_name,
+        service_type = crate::worker::PROCESSOR_SERVICE_TYPE,
+        source,
+        start_version = starting_version,
+        end_version = request_ending_version,
+        "[Parser] Reading transactions from files",
+    );
+    let transactions = read_transactions_in_range(
+        &config.path,
+        starting_version,
+        request_ending_version.unwrap_or(u64::MAX),
+    )
+    .unwrap_or_else(|e| {
+        error!(
+            processor_name = processor_name,
+            source,
+            error = ?e,
+            "[Parser] Error reading transactions from files"
+        );
+        panic!("[Parser] Error reading transactions from files");
+    });
+
+    let step = ProcessorStep::ReceivedTxnsFromGrpc.get_step();
+    let label = ProcessorStep::ReceivedTxnsFromGrpc.get_label();
+    let mut next_version = starting_version;
+    let mut transactions = transactions.into_iter().peekable();
+    while transactions.peek().is_some() {
+        let mut chunk = transactions
+            .by_ref()
+            .take(pb_channel_txn_chunk_size.load(Ordering::Relaxed).max(1))
+            .collect::<Vec<_>>();
+        let start_version = next_version;
+        let end_version = chunk.last().unwrap().version;
+        let start_txn_timestamp = chunk.first().unwrap().timestamp;
+        let end_txn_timestamp = chunk.last().unwrap().timestamp;
+        let size_in_bytes = chunk.iter().map(|txn| txn.encoded_len() as u64).sum();
+        next_version = end_version + 1;
+
+        chunk.retain(|txn| transaction_filter.include(txn));
+
+        LATEST_PROCESSED_VERSION
+            .with_label_values(&[&processor_name, step, label, "-"])
+            .set(end_version as i64);
+        update_processor_progress(&processor_name, |progress| {
+            progress.stream_head_version = Some(end_version);
+        });
+        NUM_TRANSACTIONS_PROCESSED_COUNT
+            .with_label_values(&[&processor_name, step, label, "-"])
+            .inc_by(end_version - start_version + 1);
+
+        let txn_pb = TransactionsPBResponse {
+            transactions: chunk,
+            chain_id: config.chain_id,
+            start_version,
+            end_version,
+            start_txn_timestamp,
+            end_txn_timestamp,
+            size_in_bytes,
+        };
+        if let Err(e) = txn_sender.send(txn_pb).await {
+            error!(
+                processor_name = processor_name,
+                source,
+                error = ?e,
+                "[Parser] Error sending file transactions to channel."
+            );
+            panic!("[Parser] Error sending file transactions to channel.")
+        }
+    }
+
+    info!(
+        processor_name = processor_name,
+        service_type = crate::worker::PROCESSOR_SERVICE_TYPE,
+        source,
+        last_version = next_version as i64 - 1,
+        "[Parser] Finished reading transactions from files.",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn transaction(version: u64) -> Transaction {
+        Transaction {
+            version,
+            ..Default::default()
+        }
+    }
+
+    fn versions(transactions: &[Transaction]) -> Vec<u64> {
+        transactions.iter().map(|txn| txn.version).collect()
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_stream_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_json_transactions() {
+        let dir = test_dir("json");
+        let single = serde_json::to_vec(&transaction(5)).unwrap();
+        std::fs::write(dir.join("a.json"), single).unwrap();
+        let array = serde_json::to_vec(&vec![transaction(3), transaction(5)]).unwrap();
+        std::fs::write(dir.join("b.json"), array).unwrap();
+        let response = serde_json::to_vec(&TransactionsResponse {
+            transactions: vec![transaction(1), transaction(8)],
+            ..Default::default()
+        })
+        .unwrap();
+        std::fs::write(dir.join("c.json"), response).unwrap();
+        std::fs::write(dir.join("README.md"), "not transactions").unwrap();
+
+        assert_eq!(versions(&read_transactions(&dir).unwrap()), vec![
+            1, 3, 5, 8
+        ]);
+        assert_eq!(
+            versions(&read_transactions(&dir.join("b.json")).unwrap()),
+            vec![3, 5]
+        );
+
+        std::fs::write(dir.join("d.json"), "1").unwrap();
+        assert!(read_transactions(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_archive_transactions() {
+        let dir = test_dir("archive");
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        for versions in [[10, 11], [12, 13]] {
+            let response = TransactionsResponse {
+                transactions: versions.into_iter().map(transaction).collect(),
+                ..Default::default()
+            };
+            encoder
+                .write_all(&response.encode_length_delimited_to_vec())
+                .unwrap();
+        }
+        // A file still being written holds the flushed responses, without the gzip trailer
+        encoder.flush().unwrap();
+        let partial = encoder.get_ref().clone();
+        let bytes = encoder.finish().unwrap();
+        std::fs::write(
+            dir.join("00000000000000000010-00000000000000000013.pb.gz"),
+            &bytes,
+        )
+        .unwrap();
+        std::fs::write(dir.join("00000000000000000010.pb.gz.partial"), partial).unwrap();
+        let response = TransactionsResponse {
+            transactions: vec![transaction(14)],
+            ..Default::default()
+        };
+        std::fs::write(dir.join("response.pb"), response.encode_to_vec()).unwrap();
+
+        assert_eq!(versions(&read_transactions(&dir).unwrap()), vec![
+            10, 11, 12, 13, 14
+        ]);
+
+        // Archive files outside of the range aren't read, so a corrupt one doesn't matter
+        std::fs::write(
+            dir.join("00000000000000000000-00000000000000000009.pb.gz"),
+            "corrupt",
+        )
+        .unwrap();
+        assert!(read_transactions(&dir).is_err());
+        assert_eq!(
+            versions(&read_transactions_in_range(&dir, 11, 12).unwrap()),
+            vec![11, 12]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
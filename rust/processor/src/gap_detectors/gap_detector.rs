@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    gap_detectors::{GapDetectorResult, GapDetectorTrait, ProcessingResult},
+    gap_detectors::{
+        range_set::VersionRangeSet, GapDetectorResult, GapDetectorTrait, ProcessingResult,
+    },
     processors::DefaultProcessingResult,
 };
 use ahash::AHashMap;
@@ -13,6 +15,8 @@ pub struct DefaultGapDetector {
     next_version_to_process: u64,
     seen_versions: AHashMap<u64, DefaultProcessingResult>,
     last_success_batch: Option<DefaultProcessingResult>,
+    // Ranges completed before a restart, skipped by the processor tasks
+    restored_ranges: VersionRangeSet,
 }
 
 pub struct DefaultGapDetectorResult {
@@ -26,7 +30,10 @@ impl GapDetectorTrait for DefaultGapDetector {
         match result {
             ProcessingResult::DefaultProcessingResult(result) => {
                 // Check for gaps
-                if self.next_version_to_process != result.start_version {
+                if result.end_version < self.next_version_to_process {
+                    // Already covered, e.g. by refetching the range while this batch was slow
+                    tracing::debug!("Batch already processed");
+                } else if self.next_version_to_process != result.start_version {
                     self.seen_versions.insert(result.start_version, result);
                     tracing::debug!("Gap detected");
                 } else {
@@ -56,9 +63,36 @@ impl DefaultGapDetector {
             next_version_to_process: starting_version,
             seen_versions: AHashMap::new(),
             last_success_batch: None,
+            restored_ranges: VersionRangeSet::default(),
         }
     }
 
+    pub fn with_restored_ranges(mut self, restored_ranges: VersionRangeSet) -> Self {
+        self.restored_ranges = restored_ranges;
+        self
+    }
+
+    /// The versions between `next_version_to_process` and the earliest batch finished ahead of
+    /// it, if any
+    pub fn missing_range(&self) -> Option<(u64, u64)> {
+        let next_seen_version = self.seen_versions.keys().min()?;
+        Some((self.next_version_to_process, next_seen_version - 1))
+    }
+
+    /// Batches finished ahead of `next_version_to_process`, including the restored ones
+    pub fn pending_ranges(&self) -> VersionRangeSet {
+        let mut ranges = VersionRangeSet::default();
+        for (start_version, end_version) in self.restored_ranges.iter() {
+            if end_version >= self.next_version_to_process {
+                ranges.insert(start_version.max(self.next_version_to_process), end_version);
+            }
+        }
+        for batch in self.seen_versions.values() {
+            ranges.insert(batch.start_version, batch.end_version);
+        }
+        ranges
+    }
+
     fn update_prev_batch(&mut self, result: DefaultProcessingResult) {
         let mut new_prev_batch = result;
         while let Some(next_version) = self.seen_versions.remove(&(new_prev_batch.end_version + 1))
@@ -76,6 +110,63 @@ mod test {
     use super::*;
     use crate::gap_detectors::DEFAULT_GAP_DETECTION_BATCH_SIZE;
 
+    fn batch(start_version: u64, end_version: u64) -> ProcessingResult {
+        ProcessingResult::DefaultProcessingResult(DefaultProcessingResult {
+            start_version,
+            end_version,
+            last_transaction_timestamp: None,
+            processing_duration_in_secs: 0.0,
+            db_insertion_duration_in_secs: 0.0,
+        })
+    }
+
+    fn next_version_to_process(result: GapDetectorResult) -> u64 {
+        match result {
+            GapDetectorResult::DefaultGapDetectorResult(res) => res.next_version_to_process,
+            _ => panic!("Invalid result type"),
+        }
+    }
+
+    #[test]
+    fn test_missing_range() {
+        let mut gap_detector = DefaultGapDetector::new(0);
+        assert_eq!(gap_detector.missing_range(), None);
+
+        gap_detector.process_versions(batch(0, 99)).unwrap();
+        assert_eq!(gap_detector.missing_range(), None);
+
+        gap_detector.process_versions(batch(300, 399)).unwrap();
+        gap_detector.process_versions(batch(200, 299)).unwrap();
+        assert_eq!(gap_detector.missing_range(), Some((100, 199)));
+
+        gap_detector.process_versions(batch(100, 149)).unwrap();
+        assert_eq!(gap_detector.missing_range(), Some((150, 199)));
+    }
+
+    #[test]
+    fn test_batch_already_covered() {
+        let mut gap_detector = DefaultGapDetector::new(0);
+        gap_detector.process_versions(batch(100, 199)).unwrap();
+        // The refetched range fills the gap before the slow batch arrives
+        assert_eq!(
+            next_version_to_process(gap_detector.process_versions(batch(0, 99)).unwrap()),
+            200
+        );
+
+        let result = gap_detector.process_versions(batch(0, 99)).unwrap();
+        assert_eq!(next_version_to_process(result), 200);
+        assert_eq!(gap_detector.missing_range(), None);
+        assert!(gap_detector.pending_ranges().is_empty());
+        assert_eq!(
+            gap_detector
+                .last_success_batch
+                .as_ref()
+                .unwrap()
+                .end_version,
+            199
+        );
+    }
+
     #[tokio::test]
     async fn detect_gap_test() {
         let starting_version = 0;
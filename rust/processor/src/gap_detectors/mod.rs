@@ -1,25 +1,43 @@
 use crate::{
     bq_analytics::ParquetProcessingResult,
+    db::postgres::models::processor_completed_ranges::ProcessorCompletedRange,
     gap_detectors::{
         gap_detector::{DefaultGapDetector, DefaultGapDetectorResult},
         parquet_gap_detector::{ParquetFileGapDetectorInner, ParquetFileGapDetectorResult},
+        range_fetcher::{InFlightBatches, RangeFetcher, REFETCH_MAX_RETRIES},
+        range_set::VersionRangeSet,
     },
     processors::{DefaultProcessingResult, Processor, ProcessorTrait},
-    utils::counters::{PARQUET_PROCESSOR_DATA_GAP_COUNT, PROCESSOR_DATA_GAP_COUNT},
+    utils::{
+        counters::{PARQUET_PROCESSOR_DATA_GAP_COUNT, PROCESSOR_DATA_GAP_COUNT},
+        util::parse_timestamp,
+    },
     worker::PROCESSOR_SERVICE_TYPE,
 };
 use anyhow::Result;
+use aptos_protos::util::timestamp::Timestamp;
 use enum_dispatch::enum_dispatch;
 use kanal::AsyncReceiver;
-use std::sync::{Arc, Mutex};
+use server_framework::status::update_processor_progress;
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::{sync::watch, task::JoinHandle};
 
 pub mod gap_detector;
 pub mod parquet_gap_detector;
+pub mod range_fetcher;
+pub mod range_set;
 
 // Size of a gap (in txn version) before gap detected
 pub const DEFAULT_GAP_DETECTION_BATCH_SIZE: u64 = 500;
 // Number of seconds between each processor status update
 const UPDATE_PROCESSOR_STATUS_SECS: u64 = 1;
+// Number of seconds a range must stay missing, with no task processing it, before it's refetched
+const MISSING_RANGE_TIMEOUT_SECS: u64 = 30;
+// Number of seconds between checks for a lost range when no results come in
+const MISSING_RANGE_CHECK_SECS: u64 = 5;
 
 #[enum_dispatch(GapDetectorTrait)]
 #[derive(Clone)]
@@ -56,12 +74,213 @@ pub enum ProcessingResult {
     ParquetProcessingResult(ParquetProcessingResult),
 }
 
+/// Writes the latest processor status not yet written, if any.
+async fn flush_processor_status(
+    processor: &Processor,
+    pending_status: &mut Option<(u64, Option<Timestamp>)>,
+) {
+    if let Some((version, last_transaction_timestamp)) = pending_status.take() {
+        processor
+            .update_last_processed_version(version, last_transaction_timestamp)
+            .await
+            .unwrap();
+    }
+}
+
+/// Reports the gap backlog and the status not yet written, if any, on the status endpoint.
+fn report_progress(
+    processor_name: &str,
+    num_gaps: u64,
+    pending_status: &Option<(u64, Option<Timestamp>)>,
+) {
+    update_processor_progress(processor_name, |progress| {
+        progress.gap_detector_backlog = num_gaps;
+        if let Some((version, last_transaction_timestamp)) = pending_status {
+            progress.last_processed_version = Some(*version);
+            progress.last_transaction_timestamp = last_transaction_timestamp
+                .as_ref()
+                .map(|t| parse_timestamp(t, *version as i64));
+        }
+    });
+}
+
+/// Persists the versions finished ahead of the processor status, so a restart can skip them.
+/// For parquet processors these are the versions whose files have all been uploaded.
+async fn persist_pending_ranges(
+    processor: &Processor,
+    gap_detector: &GapDetector,
+    persisted_ranges: &mut VersionRangeSet,
+) {
+    let ranges = match gap_detector {
+        GapDetector::DefaultGapDetector(gap_detector) => gap_detector.pending_ranges(),
+        GapDetector::ParquetFileGapDetector(gap_detector) => {
+            gap_detector.lock().unwrap().pending_ranges()
+        },
+    };
+    if &ranges == persisted_ranges {
+        return;
+    }
+    let result = async {
+        let mut conn = processor.get_pool().get().await?;
+        ProcessorCompletedRange::replace_for_processor(processor.name(), &ranges, &mut conn)
+            .await?;
+        anyhow::Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => *persisted_ranges = ranges,
+        // Not fatal, the ranges are only reprocessed if we restart
+        Err(e) => tracing::warn!(
+            processor_name = processor.name(),
+            error = ?e,
+            "[Parser] Failed to persist completed ranges"
+        ),
+    }
+}
+
+/// Whether the missing range `[start_version, end_version]` is lost rather than still being
+/// processed: it has been missing for `MISSING_RANGE_TIMEOUT_SECS` and no task holds any of it.
+/// `missing_since` tracks when the current missing range was first seen.
+fn is_missing_range_lost(
+    missing_since: &mut Option<(u64, Instant)>,
+    in_flight_batches: &InFlightBatches,
+    start_version: u64,
+    end_version: u64,
+) -> bool {
+    let since = match *missing_since {
+        Some((version, since)) if version == start_version => since,
+        _ => missing_since.insert((start_version, Instant::now())).1,
+    };
+    since.elapsed().as_secs() >= MISSING_RANGE_TIMEOUT_SECS
+        && !in_flight_batches.overlaps(start_version, end_version)
+}
+
+/// Starts refetching the range missing before the earliest out-of-order batch, unless a refetch
+/// is already running. The range is only refetched once it is lost, unless `stopped` is set
+/// because the processor tasks have stopped and nothing else is left to fill it.
+fn start_refetch(
+    processor: &Arc<Processor>,
+    gap_detector: &GapDetector,
+    range_fetcher: &Option<RangeFetcher>,
+    missing_since: &mut Option<(u64, Instant)>,
+    refetch_task: &mut Option<JoinHandle<ProcessingResult>>,
+    stopped: bool,
+) {
+    let (Some(range_fetcher), None) = (range_fetcher, refetch_task.as_ref()) else {
+        return;
+    };
+    let Some((start_version, end_version)) = range_to_refetch(
+        gap_detector,
+        &range_fetcher.in_flight_batches,
+        missing_since,
+        stopped,
+    ) else {
+        return;
+    };
+    let processor = processor.clone();
+    let range_fetcher = range_fetcher.clone();
+    *refetch_task = Some(tokio::spawn(async move {
+        refetch_missing_range(&processor, &range_fetcher, start_version, end_version).await
+    }));
+}
+
+/// The missing range to refetch now, if any, see [`start_refetch`].
+fn range_to_refetch(
+    gap_detector: &GapDetector,
+    in_flight_batches: &InFlightBatches,
+    missing_since: &mut Option<(u64, Instant)>,
+    stopped: bool,
+) -> Option<(u64, u64)> {
+    let GapDetector::DefaultGapDetector(gap_detector) = gap_detector else {
+        return None;
+    };
+    let (start_version, end_version) = gap_detector.missing_range()?;
+    (stopped || is_missing_range_lost(missing_since, in_flight_batches, start_version, end_version))
+        .then_some((start_version, end_version))
+}
+
+/// Waits for the running refetch, if any, and clears it.
+async fn wait_for_refetch(
+    refetch_task: &mut Option<JoinHandle<ProcessingResult>>,
+) -> ProcessingResult {
+    let Some(task) = refetch_task.as_mut() else {
+        return std::future::pending().await;
+    };
+    let result = task.await.expect("[Parser] Refetch task has panicked");
+    *refetch_task = None;
+    result
+}
+
+/// Refetches and processes the range missing before the earliest out-of-order batch, retrying
+/// with a backoff and panicking if it keeps failing.
+async fn refetch_missing_range(
+    processor: &Processor,
+    range_fetcher: &RangeFetcher,
+    start_version: u64,
+    end_version: u64,
+) -> ProcessingResult {
+    let processor_name = processor.name();
+    let mut retries = 0;
+    loop {
+        tracing::info!(
+            processor_name,
+            service_type = PROCESSOR_SERVICE_TYPE,
+            start_version,
+            end_version,
+            retries,
+            "[Parser] Refetching missing range",
+        );
+        match range_fetcher
+            .refetch(processor, processor_name, start_version, end_version)
+            .await
+        {
+            Ok(result) => return result,
+            Err(e) => {
+                retries += 1;
+                tracing::error!(
+                    processor_name,
+                    service_type = PROCESSOR_SERVICE_TYPE,
+                    start_version,
+                    end_version,
+                    retries,
+                    error = ?e,
+                    "[Parser] Error refetching missing range",
+                );
+                if retries >= REFETCH_MAX_RETRIES {
+                    panic!(
+                        "[Parser] Failed to refetch versions {} to {} after {} retries: {:?}",
+                        start_version, end_version, retries, e
+                    );
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(1 << retries)).await;
+            },
+        }
+    }
+}
+
+/// Tracks processed batches and writes the processor status at most every
+/// `UPDATE_PROCESSOR_STATUS_SECS`. Once `shutdown` is set, the results already sent are
+/// handled, the final status is written and the loop returns. Set it only after the processor
+/// tasks have stopped sending.
+///
+/// With a `range_fetcher`, once `gap_detection_batch_size` batches are waiting on a missing
+/// range that is lost, see [`is_missing_range_lost`], the range is fetched and processed in a
+/// separate task while the loop keeps handling results. The range is also checked every
+/// `MISSING_RANGE_CHECK_SECS` in case no more results come in, and any range still missing
+/// once the results stop is refetched before the final status is written.
+///
+/// Without `write_status`, the status and completed ranges are left to the batches, which
+/// record them in their own transaction, and are only reported on the status endpoint.
 pub async fn create_gap_detector_status_tracker_loop(
     mut gap_detector: GapDetector,
     gap_detector_receiver: AsyncReceiver<ProcessingResult>,
     processor: Processor,
     gap_detection_batch_size: u64,
+    mut shutdown: watch::Receiver<bool>,
+    range_fetcher: Option<RangeFetcher>,
+    write_status: bool,
 ) {
+    let processor = Arc::new(processor);
     let processor_name = processor.name();
     tracing::info!(
         processor_name = processor_name,
@@ -70,46 +289,121 @@ pub async fn create_gap_detector_status_tracker_loop(
     );
 
     let mut last_update_time = std::time::Instant::now();
+    // Latest status that was throttled, written on shutdown
+    let mut pending_status = None;
+    let mut persisted_ranges = VersionRangeSet::default();
+    let mut shutting_down = false;
+    // The missing range being waited on, by start version, and when it was first seen
+    let mut missing_since = None;
+    // A missing range being refetched, its result is handled like any other
+    let mut refetch_task: Option<JoinHandle<ProcessingResult>> = None;
+    let mut missing_range_check =
+        tokio::time::interval(std::time::Duration::from_secs(MISSING_RANGE_CHECK_SECS));
     loop {
-        match gap_detector_receiver.recv().await {
+        let result = if shutting_down {
+            let result = gap_detector_receiver.try_recv();
+            if !matches!(result, Ok(Some(_))) {
+                // No task is left to fill the missing range
+                start_refetch(
+                    &processor,
+                    &gap_detector,
+                    &range_fetcher,
+                    &mut missing_since,
+                    &mut refetch_task,
+                    true,
+                );
+            }
+            match result {
+                Ok(Some(result)) => Ok(result),
+                _ if refetch_task.is_some() => Ok(wait_for_refetch(&mut refetch_task).await),
+                _ => {
+                    if write_status {
+                        flush_processor_status(&processor, &mut pending_status).await;
+                        persist_pending_ranges(&processor, &gap_detector, &mut persisted_ranges)
+                            .await;
+                    }
+                    tracing::info!(
+                        processor_name,
+                        service_type = PROCESSOR_SERVICE_TYPE,
+                        "[Parser] Gap detector flushed and stopped",
+                    );
+                    return;
+                },
+            }
+        } else {
+            tokio::select! {
+                result = gap_detector_receiver.recv() => result,
+                result = wait_for_refetch(&mut refetch_task) => Ok(result),
+                _ = missing_range_check.tick() => {
+                    start_refetch(
+                        &processor,
+                        &gap_detector,
+                        &range_fetcher,
+                        &mut missing_since,
+                        &mut refetch_task,
+                        false,
+                    );
+                    continue;
+                },
+                _ = shutdown.wait_for(|shutdown| *shutdown) => {
+                    shutting_down = true;
+                    continue;
+                },
+            }
+        };
+        match result {
             Ok(ProcessingResult::DefaultProcessingResult(result)) => {
+                if let Some(range_fetcher) = &range_fetcher {
+                    range_fetcher.in_flight_batches.remove(result.start_version);
+                }
                 match gap_detector
                     .process_versions(ProcessingResult::DefaultProcessingResult(result))
                 {
-                    Ok(res) => {
-                        match res {
-                            GapDetectorResult::DefaultGapDetectorResult(res) => {
-                                PROCESSOR_DATA_GAP_COUNT
-                                    .with_label_values(&[processor_name])
-                                    .set(res.num_gaps as i64);
-                                if res.num_gaps >= gap_detection_batch_size {
-                                    tracing::debug!(
+                    Ok(res) => match res {
+                        GapDetectorResult::DefaultGapDetectorResult(res) => {
+                            PROCESSOR_DATA_GAP_COUNT
+                                .with_label_values(&[processor_name])
+                                .set(res.num_gaps as i64);
+                            if res.num_gaps >= gap_detection_batch_size {
+                                tracing::debug!(
                                     processor_name,
                                     gap_start_version = res.next_version_to_process,
                                     num_gaps = res.num_gaps,
                                     "[Parser] Processed {gap_detection_batch_size} batches with a gap",
                                 );
-                                    // We don't panic as everything downstream will panic if it doesn't work/receive
-                                }
-                                if let Some(res_last_success_batch) = res.last_success_batch {
-                                    if last_update_time.elapsed().as_secs()
-                                        >= UPDATE_PROCESSOR_STATUS_SECS
-                                    {
-                                        processor
-                                            .update_last_processed_version(
-                                                res_last_success_batch.end_version,
-                                                res_last_success_batch.last_transaction_timestamp,
-                                            )
-                                            .await
-                                            .unwrap();
-                                        last_update_time = std::time::Instant::now();
-                                    }
-                                }
-                            },
-                            _ => {
-                                panic!("Invalid result type");
-                            },
-                        }
+                                start_refetch(
+                                    &processor,
+                                    &gap_detector,
+                                    &range_fetcher,
+                                    &mut missing_since,
+                                    &mut refetch_task,
+                                    false,
+                                );
+                            }
+                            if let Some(res_last_success_batch) = res.last_success_batch {
+                                pending_status = Some((
+                                    res_last_success_batch.end_version,
+                                    res_last_success_batch.last_transaction_timestamp,
+                                ));
+                            }
+                            report_progress(processor_name, res.num_gaps, &pending_status);
+                            if write_status
+                                && last_update_time.elapsed().as_secs()
+                                    >= UPDATE_PROCESSOR_STATUS_SECS
+                            {
+                                flush_processor_status(&processor, &mut pending_status).await;
+                                persist_pending_ranges(
+                                    &processor,
+                                    &gap_detector,
+                                    &mut persisted_ranges,
+                                )
+                                .await;
+                                last_update_time = std::time::Instant::now();
+                            }
+                        },
+                        _ => {
+                            panic!("Invalid result type");
+                        },
                     },
                     Err(e) => {
                         tracing::error!(
@@ -149,6 +443,11 @@ pub async fn create_gap_detector_status_tracker_loop(
                                     // We don't panic as everything downstream will panic if it doesn't work/receive
                                 }
 
+                                pending_status = Some((
+                                    res.last_success_version,
+                                    res.last_transaction_timestamp,
+                                ));
+                                report_progress(processor_name, res.num_gaps, &pending_status);
                                 if last_update_time.elapsed().as_secs()
                                     >= UPDATE_PROCESSOR_STATUS_SECS
                                 {
@@ -157,13 +456,13 @@ pub async fn create_gap_detector_status_tracker_loop(
                                         processor_name,
                                         "Updating last processed version"
                                     );
-                                    processor
-                                        .update_last_processed_version(
-                                            res.last_success_version,
-                                            res.last_transaction_timestamp,
-                                        )
-                                        .await
-                                        .unwrap();
+                                    flush_processor_status(&processor, &mut pending_status).await;
+                                    persist_pending_ranges(
+                                        &processor,
+                                        &gap_detector,
+                                        &mut persisted_ranges,
+                                    )
+                                    .await;
                                     last_update_time = std::time::Instant::now();
                                 } else {
                                     tracing::info!("Not Updating last processed version");
@@ -192,8 +491,73 @@ pub async fn create_gap_detector_status_tracker_loop(
                     error = ?e,
                     "[Parser] Gap detector channel has been closed",
                 );
-                return;
+                // Finishes a running refetch before writing the final status
+                shutting_down = true;
             },
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A gap detector for versions 0 to 999 in batches of 100, with batch 800 to 899 missing
+    fn gap_detector_missing_batch() -> GapDetector {
+        let mut gap_detector = GapDetector::DefaultGapDetector(DefaultGapDetector::new(0));
+        for start_version in (0..1000).step_by(100).filter(|&v| v != 800) {
+            gap_detector
+                .process_versions(ProcessingResult::DefaultProcessingResult(
+                    DefaultProcessingResult {
+                        start_version,
+                        end_version: start_version + 99,
+                        last_transaction_timestamp: None,
+                        processing_duration_in_secs: 0.0,
+                        db_insertion_duration_in_secs: 0.0,
+                    },
+                ))
+                .unwrap();
+        }
+        gap_detector
+    }
+
+    #[test]
+    fn test_missing_range_refetched_when_bounded_stream_ends() {
+        let gap_detector = gap_detector_missing_batch();
+        let in_flight_batches = InFlightBatches::default();
+        let mut missing_since = None;
+
+        // Not lost yet, and far fewer batches than the gap detection batch size wait on it
+        assert_eq!(
+            range_to_refetch(&gap_detector, &in_flight_batches, &mut missing_since, false),
+            None
+        );
+        // Nothing else fills it once the processor tasks have stopped
+        assert_eq!(
+            range_to_refetch(&gap_detector, &in_flight_batches, &mut missing_since, true),
+            Some((800, 899))
+        );
+    }
+
+    #[test]
+    fn test_missing_range_refetched_once_lost() {
+        let gap_detector = gap_detector_missing_batch();
+        let in_flight_batches = InFlightBatches::default();
+        let mut missing_since = Some((
+            800,
+            Instant::now() - Duration::from_secs(MISSING_RANGE_TIMEOUT_SECS),
+        ));
+
+        in_flight_batches.insert(800, 899);
+        assert_eq!(
+            range_to_refetch(&gap_detector, &in_flight_batches, &mut missing_since, false),
+            None
+        );
+        in_flight_batches.remove(800);
+        assert_eq!(
+            range_to_refetch(&gap_detector, &in_flight_batches, &mut missing_since, false),
+            Some((800, 899))
+        );
+    }
+}
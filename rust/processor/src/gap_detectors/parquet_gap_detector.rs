@@ -1,7 +1,9 @@
 // // Copyright © Aptos Foundation
 // // SPDX-License-Identifier: Apache-2.0
 
-use crate::gap_detectors::{GapDetectorResult, GapDetectorTrait, ProcessingResult};
+use crate::gap_detectors::{
+    range_set::VersionRangeSet, GapDetectorResult, GapDetectorTrait, ProcessingResult,
+};
 use ahash::{AHashMap, AHashSet};
 use anyhow::Result;
 use std::{
@@ -23,6 +25,8 @@ pub struct ParquetFileGapDetectorInner {
     version_counters: AHashMap<i64, i64>,
     seen_versions: AHashSet<i64>,
     max_version: i64,
+    // Ranges completed before a restart, skipped by the processor tasks
+    restored_ranges: VersionRangeSet,
 }
 
 pub struct ParquetFileGapDetectorResult {
@@ -38,9 +42,38 @@ impl ParquetFileGapDetectorInner {
             version_counters: AHashMap::new(),
             seen_versions: AHashSet::new(),
             max_version: 0,
+            restored_ranges: VersionRangeSet::default(),
         }
     }
 
+    pub fn with_restored_ranges(mut self, restored_ranges: VersionRangeSet) -> Self {
+        self.restored_ranges = restored_ranges;
+        self
+    }
+
+    /// Versions past `next_version_to_process` whose structs have all been uploaded, including
+    /// the restored ranges
+    pub fn pending_ranges(&self) -> VersionRangeSet {
+        let next_version_to_process = self.next_version_to_process as u64;
+        let mut ranges = VersionRangeSet::default();
+        for (start_version, end_version) in self.restored_ranges.iter() {
+            if end_version >= next_version_to_process {
+                ranges.insert(start_version.max(next_version_to_process), end_version);
+            }
+        }
+        let mut completed_versions = self
+            .version_counters
+            .iter()
+            .filter(|(&version, &count)| count == 0 && version >= self.next_version_to_process)
+            .map(|(&version, _)| version as u64)
+            .collect::<Vec<_>>();
+        completed_versions.sort_unstable();
+        for version in completed_versions {
+            ranges.insert(version, version);
+        }
+        ranges
+    }
+
     pub fn update_struct_map(
         &mut self,
         txn_version_to_struct_count: AHashMap<i64, i64>,
@@ -171,3 +204,47 @@ impl GapDetectorTrait for ParquetFileGapDetectorInner {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bq_analytics::ParquetProcessingResult;
+
+    fn uploaded(versions: &[i64]) -> ProcessingResult {
+        ProcessingResult::ParquetProcessingResult(ParquetProcessingResult {
+            start_version: *versions.iter().min().unwrap(),
+            end_version: *versions.iter().max().unwrap(),
+            last_transaction_timestamp: None,
+            txn_version_to_struct_count: None,
+            parquet_processed_structs: Some(versions.iter().map(|&v| (v, 1)).collect()),
+            table_name: "transactions".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_pending_ranges() {
+        let mut restored_ranges = VersionRangeSet::default();
+        restored_ranges.insert(5, 6);
+        restored_ranges.insert(20, 29);
+        let mut detector =
+            ParquetFileGapDetectorInner::new(10).with_restored_ranges(restored_ranges);
+        // One struct per version, except version 13 which has none
+        detector.update_struct_map((10..=12).chain(14..=19).map(|v| (v, 1)).collect(), 10, 19);
+
+        detector.process_versions(uploaded(&[14, 15, 17])).unwrap();
+        assert_eq!(detector.pending_ranges().iter().collect::<Vec<_>>(), vec![
+            (13, 15),
+            (17, 17),
+            (20, 29)
+        ]);
+
+        detector
+            .process_versions(uploaded(&[10, 11, 12, 16]))
+            .unwrap();
+        assert_eq!(detector.next_version_to_process, 17);
+        assert_eq!(detector.pending_ranges().iter().collect::<Vec<_>>(), vec![
+            (17, 17),
+            (20, 29)
+        ]);
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::{IndexerGrpcHttp2Config, TransactionSource},
+    file_stream::read_transactions_in_range,
+    gap_detectors::ProcessingResult,
+    grpc_stream::{fetch_version_range, TransactionsPBResponse},
+    processors::Processor,
+    transaction_filter::TransactionFilter,
+    worker::do_processor,
+};
+use anyhow::Result;
+use prost::Message;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use url::Url;
+
+/// Number of times a missing range is refetched before giving up
+pub const REFETCH_MAX_RETRIES: u64 = 5;
+
+/// Batches taken off the channel by the processor tasks whose results the gap detector hasn't
+/// received yet, by start version. A missing range that overlaps one is still being processed.
+#[derive(Clone, Debug, Default)]
+pub struct InFlightBatches(Arc<Mutex<BTreeMap<u64, u64>>>);
+
+impl InFlightBatches {
+    pub fn insert(&self, start_version: u64, end_version: u64) {
+        self.0.lock().unwrap().insert(start_version, end_version);
+    }
+
+    pub fn remove(&self, start_version: u64) {
+        self.0.lock().unwrap().remove(&start_version);
+    }
+
+    /// Whether any batch in flight holds a version in `[start_version, end_version]`
+    pub fn overlaps(&self, start_version: u64, end_version: u64) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .range(..=end_version)
+            .any(|(_, &batch_end_version)| batch_end_version >= start_version)
+    }
+}
+
+/// Fetches and processes a version range outside of the main stream, so the gap detector can
+/// fill a batch that never arrived instead of waiting on it.
+#[derive(Clone)]
+pub struct RangeFetcher {
+    pub transaction_source: TransactionSource,
+    pub indexer_grpc_data_service_address: Url,
+    pub grpc_http2_config: IndexerGrpcHttp2Config,
+    pub grpc_response_item_timeout: Duration,
+    pub auth_token: String,
+    pub transaction_filter: TransactionFilter,
+    pub chain_id: u64,
+    pub atomic_batch_writes: bool,
+    pub in_flight_batches: InFlightBatches,
+}
+
+impl RangeFetcher {
+    async fn fetch(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<TransactionsPBResponse> {
+        match &self.transaction_source {
+            TransactionSource::Grpc => {
+                fetch_version_range(
+                    self.indexer_grpc_data_service_address.clone(),
+                    self.grpc_http2_config.grpc_http2_ping_interval_in_secs(),
+                    self.grpc_http2_config.grpc_http2_ping_timeout_in_secs(),
+                    self.grpc_http2_config.grpc_connection_timeout_secs(),
+                    self.grpc_response_item_timeout,
+                    start_version,
+                    end_version,
+                    self.auth_token.clone(),
+                    processor_name.to_string(),
+                )
+                .await
+            },
+            TransactionSource::Files(config) => {
+                let transactions =
+                    read_transactions_in_range(&config.path, start_version, end_version)?;
+                Ok(TransactionsPBResponse {
+                    chain_id: config.chain_id,
+                    start_version,
+                    end_version,
+                    start_txn_timestamp: transactions.first().and_then(|txn| txn.timestamp),
+                    end_txn_timestamp: transactions.last().and_then(|txn| txn.timestamp),
+                    size_in_bytes: transactions
+                        .iter()
+                        .map(|txn| txn.encoded_len() as u64)
+                        .sum(),
+                    transactions,
+                })
+            },
+        }
+    }
+
+    /// Fetches `[start_version, end_version]`, applies the transaction filter and processes it.
+    pub async fn refetch(
+        &self,
+        processor: &Processor,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult> {
+        let mut transactions_pb = self
+            .fetch(processor_name, start_version, end_version)
+            .await?;
+        anyhow::ensure!(
+            transactions_pb.chain_id == self.chain_id,
+            "Refetched range is for chain {}, expected {}",
+            transactions_pb.chain_id,
+            self.chain_id
+        );
+        transactions_pb
+            .transactions
+            .retain(|txn| self.transaction_filter.include(txn));
+        do_processor(
+            transactions_pb,
+            processor,
+            self.chain_id,
+            processor_name,
+            &self.auth_token,
+            false,
+            self.atomic_batch_writes,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight_batches_overlap() {
+        let in_flight_batches = InFlightBatches::default();
+        in_flight_batches.insert(100, 199);
+        in_flight_batches.insert(300, 399);
+        assert!(in_flight_batches.overlaps(150, 160));
+        assert!(in_flight_batches.overlaps(0, 100));
+        assert!(in_flight_batches.overlaps(199, 300));
+        assert!(!in_flight_batches.overlaps(200, 299));
+        assert!(!in_flight_batches.overlaps(0, 99));
+
+        in_flight_batches.remove(100);
+        assert!(!in_flight_batches.overlaps(0, 299));
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+/// A set of versions stored as disjoint, non-adjacent inclusive ranges.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionRangeSet {
+    // start_version -> end_version
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl VersionRangeSet {
+    /// Adds `[start_version, end_version]`, merging it with overlapping or adjacent ranges.
+    pub fn insert(&mut self, start_version: u64, end_version: u64) {
+        if start_version > end_version {
+            return;
+        }
+        let (mut start, mut end) = (start_version, end_version);
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..=start).next_back() {
+            if prev_end.saturating_add(1) >= start {
+                start = prev_start;
+                end = end.max(prev_end);
+            }
+        }
+        let merged = self
+            .ranges
+            .range(start..=end.saturating_add(1))
+            .map(|(&range_start, _)| range_start)
+            .collect::<Vec<_>>();
+        for range_start in merged {
+            let range_end = self.ranges.remove(&range_start).unwrap();
+            end = end.max(range_end);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn contains(&self, version: u64) -> bool {
+        self.ranges
+            .range(..=version)
+            .next_back()
+            .is_some_and(|(_, &end)| version <= end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.ranges.iter().map(|(&start, &end)| (start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_merges_adjacent_and_overlapping_ranges() {
+        let mut ranges = VersionRangeSet::default();
+        ranges.insert(100, 199);
+        ranges.insert(300, 399);
+        ranges.insert(200, 249);
+        ranges.insert(240, 260);
+        assert_eq!(ranges.iter().collect::<Vec<_>>(), vec![
+            (100, 260),
+            (300, 399)
+        ]);
+
+        ranges.insert(261, 299);
+        assert_eq!(ranges.iter().collect::<Vec<_>>(), vec![(100, 399)]);
+        assert!(ranges.contains(100));
+        assert!(ranges.contains(399));
+        assert!(!ranges.contains(99));
+        assert!(!ranges.contains(400));
+    }
+}
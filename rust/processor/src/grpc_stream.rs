@@ -1,10 +1,13 @@
-use crate::utils::{
-    counters::{
-        ProcessorStep, FETCHER_THREAD_CHANNEL_SIZE, LATEST_PROCESSED_VERSION,
-        NUM_TRANSACTIONS_FILTERED_OUT_COUNT, NUM_TRANSACTIONS_PROCESSED_COUNT,
-        PROCESSED_BYTES_COUNT, TRANSACTION_UNIX_TIMESTAMP,
+use crate::{
+    stream_archive::{StreamArchive, StreamArchiveConfig},
+    utils::{
+        counters::{
+            ProcessorStep, FETCHER_THREAD_CHANNEL_SIZE, LATEST_PROCESSED_VERSION,
+            NUM_TRANSACTIONS_FILTERED_OUT_COUNT, NUM_TRANSACTIONS_PROCESSED_COUNT,
+            PROCESSED_BYTES_COUNT, TRANSACTION_UNIX_TIMESTAMP,
+        },
+        util::{timestamp_to_iso, timestamp_to_unixtime},
     },
-    util::{timestamp_to_iso, timestamp_to_unixtime},
 };
 use aptos_moving_average::MovingAverage;
 use aptos_protos::{
@@ -17,7 +20,14 @@ use futures_util::StreamExt;
 use itertools::Itertools;
 use kanal::AsyncSender;
 use prost::Message;
-use std::time::Duration;
+use server_framework::status::update_processor_progress;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::time::timeout;
 use tonic::{Response, Streaming};
 use tracing::{debug, error, info};
@@ -296,12 +306,77 @@ pub async fn get_chain_id(
     }
 }
 
+/// Fetches `[start_version, end_version]` over its own connection, e.g. to fill a gap left by
+/// the main stream. Transactions aren't filtered.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_version_range(
+    indexer_grpc_data_service_address: Url,
+    indexer_grpc_http2_ping_interval: Duration,
+    indexer_grpc_http2_ping_timeout: Duration,
+    indexer_grpc_reconnection_timeout_secs: Duration,
+    indexer_grpc_response_item_timeout_secs: Duration,
+    start_version: u64,
+    end_version: u64,
+    auth_token: String,
+    processor_name: String,
+) -> anyhow::Result<TransactionsPBResponse> {
+    let mut resp_stream = get_stream(
+        indexer_grpc_data_service_address,
+        indexer_grpc_http2_ping_interval,
+        indexer_grpc_http2_ping_timeout,
+        indexer_grpc_reconnection_timeout_secs,
+        start_version,
+        Some(end_version),
+        auth_token,
+        processor_name,
+    )
+    .await
+    .into_inner();
+
+    let mut transactions: Vec<Transaction> = vec![];
+    let mut chain_id = None;
+    let mut size_in_bytes = 0;
+    while transactions
+        .last()
+        .map_or(true, |txn| txn.version < end_version)
+    {
+        let response = timeout(indexer_grpc_response_item_timeout_secs, resp_stream.next())
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out waiting for a response"))?
+            .ok_or_else(|| anyhow::anyhow!("Stream ended before version {}", end_version))??;
+        let next_version = transactions
+            .last()
+            .map_or(start_version, |txn| txn.version + 1);
+        anyhow::ensure!(
+            response.transactions.first().map(|txn| txn.version) == Some(next_version),
+            "Expected a response starting at version {}",
+            next_version
+        );
+        chain_id = chain_id.or(response.chain_id);
+        size_in_bytes += response.encoded_len() as u64;
+        transactions.extend(response.transactions);
+    }
+    transactions.retain(|txn| txn.version <= end_version);
+
+    Ok(TransactionsPBResponse {
+        chain_id: chain_id.ok_or_else(|| anyhow::anyhow!("Chain id doesn't exist"))?,
+        start_version,
+        end_version,
+        start_txn_timestamp: transactions.first().and_then(|txn| txn.timestamp),
+        end_txn_timestamp: transactions.last().and_then(|txn| txn.timestamp),
+        size_in_bytes,
+        transactions,
+    })
+}
+
 /// Gets a batch of transactions from the stream. Batch size is set in the grpc server.
 /// The number of batches depends on our config
 /// There could be several special scenarios:
 /// 1. If we lose the connection, we will try reconnecting X times within Y seconds before crashing.
 /// 2. If we specified an end version and we hit that, we will stop fetching, but we will make sure that
 ///    all existing transactions are processed
+///
+/// If `stream_archive` is set, every response is also recorded to disk before filtering.
 pub async fn create_fetcher_loop(
     txn_sender: AsyncSender<TransactionsPBResponse>,
     indexer_grpc_data_service_address: Url,
@@ -314,9 +389,14 @@ pub async fn create_fetcher_loop(
     auth_token: String,
     processor_name: String,
     transaction_filter: crate::transaction_filter::TransactionFilter,
-    // The number of transactions per protobuf batch
-    pb_channel_txn_chunk_size: usize,
+    // The number of transactions per protobuf batch, read for every response
+    pb_channel_txn_chunk_size: Arc<AtomicUsize>,
+    stream_archive: Option<StreamArchiveConfig>,
 ) {
+    let stream_archive = stream_archive.map(|config| {
+        StreamArchive::spawn(config, processor_name.clone())
+            .expect("[Parser] Failed to start the stream archive")
+    });
     info!(
         processor_name = processor_name,
         service_type = crate::worker::PROCESSOR_SERVICE_TYPE,
@@ -386,6 +466,10 @@ pub async fn create_fetcher_loop(
 
                         let num_txns = r.transactions.len();
 
+                        if let Some(stream_archive) = &stream_archive {
+                            stream_archive.archive(r.clone()).await;
+                        }
+
                         // Filter out the txns we don't care about
                         r.transactions.retain(|txn| transaction_filter.include(txn));
 
@@ -435,6 +519,9 @@ pub async fn create_fetcher_loop(
                         LATEST_PROCESSED_VERSION
                             .with_label_values(&[&processor_name, step, label, "-"])
                             .set(end_version as i64);
+                        update_processor_progress(&processor_name, |progress| {
+                            progress.stream_head_version = Some(end_version);
+                        });
                         TRANSACTION_UNIX_TIMESTAMP
                             .with_label_values(&[&processor_name, step, label, "-"])
                             .set(
@@ -453,6 +540,8 @@ pub async fn create_fetcher_loop(
                         let txn_channel_send_latency = std::time::Instant::now();
 
                         //potentially break txn_pb into many `TransactionsPBResponse` that are each `pb_channel_txn_chunk_size` txns max in size
+                        let pb_channel_txn_chunk_size =
+                            pb_channel_txn_chunk_size.load(Ordering::Relaxed).max(1);
                         if num_txn_post_filter < pb_channel_txn_chunk_size {
                             // We only need to send one; avoid the chunk/clone
                             let txn_pb = TransactionsPBResponse {
@@ -632,6 +721,9 @@ pub async fn create_fetcher_loop(
                 connection_id,
                 "[Parser] Transaction fetcher send channel is closed."
             );
+            if let Some(stream_archive) = stream_archive {
+                stream_archive.close().await;
+            }
             break;
         } else {
             // The rest is to see if we need to reconnect
@@ -16,16 +16,19 @@ extern crate canonical_json;
 extern crate parquet;
 extern crate parquet_derive;
 
-pub use config::IndexerGrpcProcessorConfig;
+pub use config::{IndexerGrpcProcessorConfig, TransactionSource};
 
+pub mod adaptive_concurrency;
 pub mod bq_analytics;
 mod config;
 pub mod db;
+pub mod file_stream;
 pub mod gap_detectors;
 pub mod grpc_stream;
 pub mod processors;
 #[path = "db/postgres/schema.rs"]
 pub mod schema;
+pub mod stream_archive;
 pub mod transaction_filter;
 pub mod utils;
 pub mod worker;
@@ -9,7 +9,10 @@ use crate::{
     },
     gap_detectors::ProcessingResult,
     schema,
-    utils::database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+    utils::{
+        database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+        table_flags::TableFlags,
+    },
 };
 use ahash::AHashMap;
 use anyhow::bail;
@@ -23,13 +26,19 @@ use tracing::error;
 pub struct AccountTransactionsProcessor {
     connection_pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
+    deprecated_tables: TableFlags,
 }
 
 impl AccountTransactionsProcessor {
-    pub fn new(connection_pool: ArcDbPool, per_table_chunk_sizes: AHashMap<String, usize>) -> Self {
+    pub fn new(
+        connection_pool: ArcDbPool,
+        per_table_chunk_sizes: AHashMap<String, usize>,
+        deprecated_tables: TableFlags,
+    ) -> Self {
         Self {
             connection_pool,
             per_table_chunk_sizes,
+            deprecated_tables,
         }
     }
 }
@@ -105,6 +114,15 @@ impl ProcessorTrait for AccountTransactionsProcessor {
         let processing_start = std::time::Instant::now();
         let last_transaction_timestamp = transactions.last().unwrap().timestamp;
 
+        // Nothing else is written, so skip parsing too
+        let transactions = if self
+            .deprecated_tables
+            .contains(TableFlags::ACCOUNT_TRANSACTIONS)
+        {
+            vec![]
+        } else {
+            transactions
+        };
         let account_transactions: Vec<_> = transactions
             .into_par_iter()
             .map(|txn| {
@@ -402,16 +402,17 @@ impl ProcessorTrait for AnsProcessor {
             self.config.ans_v2_contract_address.clone(),
         );
 
-        let postgres_current_ans_lookup_v2: Vec<CurrentAnsLookupV2> = all_current_ans_lookups_v2
-            .into_iter()
-            .map(CurrentAnsLookupV2::from_raw)
-            .collect();
+        let mut postgres_current_ans_lookup_v2: Vec<CurrentAnsLookupV2> =
+            all_current_ans_lookups_v2
+                .into_iter()
+                .map(CurrentAnsLookupV2::from_raw)
+                .collect();
 
-        let postgres_ans_lookup_v2: Vec<AnsLookupV2> = all_ans_lookups_v2
+        let mut postgres_ans_lookup_v2: Vec<AnsLookupV2> = all_ans_lookups_v2
             .into_iter()
             .map(AnsLookupV2::from_raw)
             .collect();
-        let postgres_current_ans_primary_name_v2: Vec<CurrentAnsPrimaryNameV2> =
+        let mut postgres_current_ans_primary_name_v2: Vec<CurrentAnsPrimaryNameV2> =
             all_current_ans_primary_names_v2
                 .into_iter()
                 .map(CurrentAnsPrimaryNameV2::from_raw)
@@ -452,6 +453,21 @@ impl ProcessorTrait for AnsProcessor {
         {
             all_current_ans_primary_names.clear();
         }
+        if self.deprecated_tables.contains(TableFlags::ANS_LOOKUP_V2) {
+            postgres_ans_lookup_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_ANS_LOOKUP_V2)
+        {
+            postgres_current_ans_lookup_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_ANS_PRIMARY_NAME_V2)
+        {
+            postgres_current_ans_primary_name_v2.clear();
+        }
 
         // Insert values to db
         let tx_result = insert_to_db(
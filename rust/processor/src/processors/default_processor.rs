@@ -237,12 +237,12 @@ impl ProcessorTrait for DefaultProcessor {
         let mut postgres_table_items: Vec<TableItem> =
             raw_table_items.iter().map(TableItem::from_raw).collect();
 
-        let postgres_current_table_items: Vec<CurrentTableItem> = raw_current_table_items
+        let mut postgres_current_table_items: Vec<CurrentTableItem> = raw_current_table_items
             .iter()
             .map(CurrentTableItem::from_raw)
             .collect();
 
-        let postgres_block_metadata_transactions: Vec<BlockMetadataTransactionModel> =
+        let mut postgres_block_metadata_transactions: Vec<BlockMetadataTransactionModel> =
             raw_block_metadata_transactions
                 .into_iter()
                 .map(BlockMetadataTransactionModel::from_raw)
@@ -262,6 +262,12 @@ impl ProcessorTrait for DefaultProcessor {
         if flags.contains(TableFlags::TABLE_METADATAS) {
             postgres_table_metadata.clear();
         }
+        if flags.contains(TableFlags::CURRENT_TABLE_ITEMS) {
+            postgres_current_table_items.clear();
+        }
+        if flags.contains(TableFlags::BLOCK_METADATA_TRANSACTIONS) {
+            postgres_block_metadata_transactions.clear();
+        }
 
         let processing_duration_in_secs = processing_start.elapsed().as_secs_f64();
         let db_insertion_start = std::time::Instant::now();
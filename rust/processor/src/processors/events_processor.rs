@@ -9,7 +9,10 @@ use crate::{
     },
     gap_detectors::ProcessingResult,
     schema,
-    utils::database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+    utils::{
+        database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+        table_flags::TableFlags,
+    },
 };
 use ahash::AHashMap;
 use anyhow::bail;
@@ -26,13 +29,19 @@ use tracing::error;
 pub struct EventsProcessor {
     connection_pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
+    deprecated_tables: TableFlags,
 }
 
 impl EventsProcessor {
-    pub fn new(connection_pool: ArcDbPool, per_table_chunk_sizes: AHashMap<String, usize>) -> Self {
+    pub fn new(
+        connection_pool: ArcDbPool,
+        per_table_chunk_sizes: AHashMap<String, usize>,
+        deprecated_tables: TableFlags,
+    ) -> Self {
         Self {
             connection_pool,
             per_table_chunk_sizes,
+            deprecated_tables,
         }
     }
 }
@@ -108,7 +117,12 @@ impl ProcessorTrait for EventsProcessor {
         let processing_start = std::time::Instant::now();
         let last_transaction_timestamp = transactions.last().unwrap().timestamp;
 
-        let events = process_transactions(transactions);
+        // Nothing else is written, so skip parsing too
+        let events = if self.deprecated_tables.contains(TableFlags::EVENTS) {
+            vec![]
+        } else {
+            process_transactions(transactions)
+        };
 
         let processing_duration_in_secs = processing_start.elapsed().as_secs_f64();
         let db_insertion_start = std::time::Instant::now();
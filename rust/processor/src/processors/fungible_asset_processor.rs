@@ -372,13 +372,13 @@ impl ProcessorTrait for FungibleAssetProcessor {
             fa_to_coin_mappings,
         ) = parse_v2_coin(&transactions, None).await;
 
-        let postgres_fungible_asset_activities: Vec<FungibleAssetActivity> =
+        let mut postgres_fungible_asset_activities: Vec<FungibleAssetActivity> =
             raw_fungible_asset_activities
                 .into_iter()
                 .map(FungibleAssetActivity::from_raw)
                 .collect();
 
-        let postgres_fungible_asset_metadata: Vec<FungibleAssetMetadataModel> =
+        let mut postgres_fungible_asset_metadata: Vec<FungibleAssetMetadataModel> =
             raw_fungible_asset_metadata
                 .into_iter()
                 .map(FungibleAssetMetadataModel::from_raw)
@@ -395,7 +395,7 @@ impl ProcessorTrait for FungibleAssetProcessor {
                 .map(CurrentUnifiedFungibleAssetBalance::from_raw)
                 .collect();
 
-        let postgres_fa_to_coin_mappings: Vec<FungibleAssetToCoinMapping> = fa_to_coin_mappings
+        let mut postgres_fa_to_coin_mappings: Vec<FungibleAssetToCoinMapping> = fa_to_coin_mappings
             .into_iter()
             .map(FungibleAssetToCoinMapping::from_raw)
             .collect();
@@ -416,6 +416,27 @@ impl ProcessorTrait for FungibleAssetProcessor {
             coin_supply.clear();
         }
 
+        if self
+            .deprecated_tables
+            .contains(TableFlags::FUNGIBLE_ASSET_ACTIVITIES)
+        {
+            postgres_fungible_asset_activities.clear();
+        }
+
+        if self
+            .deprecated_tables
+            .contains(TableFlags::FUNGIBLE_ASSET_METADATA)
+        {
+            postgres_fungible_asset_metadata.clear();
+        }
+
+        if self
+            .deprecated_tables
+            .contains(TableFlags::FUNGIBLE_ASSET_TO_COIN_MAPPINGS)
+        {
+            postgres_fa_to_coin_mappings.clear();
+        }
+
         let tx_result = insert_to_db(
             self.get_pool(),
             self.name(),
@@ -9,31 +9,47 @@ use crate::{
                 LimitOrderModel, MarketCollectionModel, MarketConfigModel, PositionModel, TpSlModel,
             },
             market_utils::{Strategy, StrategyObjectMapping},
+            position_histories::PositionHistory,
+            position_marks::{MarketMarkInputs, PositionMark},
         },
         mirage_models::mirage_debt_store::MirageDebtStoreModel,
         object_models::v2_object_utils::ObjectWithMetadata,
         token_v2_models::v2_token_utils::V2TokenEvent,
         vault_models::{
             vault_activities::VaultActivityModel,
-            vault_datas::{VaultCollectionModel, VaultConfigModel, VaultModel},
+            vault_datas::{CurrentVault, VaultCollectionModel, VaultConfigModel, VaultModel},
+            vault_lineage::VaultLineage,
         },
     },
+    db::postgres::models::processor_status::ProcessorStatusQuery,
     gap_detectors::ProcessingResult,
     schema,
     utils::{
         counters::PROCESSOR_UNKNOWN_TYPE_COUNT,
-        database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+        database::{
+            execute_in_chunks, get_config_table_chunk_size, run_in_transaction, ArcDbPool,
+            MyDbConnection,
+        },
+        table_flags::TableFlags,
         util::{parse_timestamp, standardize_address, ObjectOwnerMapping},
     },
 };
 
-use ahash::AHashMap;
-use anyhow::bail;
+use ahash::{AHashMap, AHashSet};
+use anyhow::{bail, Context};
 use aptos_protos::transaction::v1::{transaction::TxnData, write_set_change::Change, Transaction};
 use aptos_types::account_address::{create_resource_address, AccountAddress};
 use async_trait::async_trait;
 use core::hash::Hash;
-use diesel::{pg::Pg, query_builder::QueryFragment, upsert::excluded, ExpressionMethods};
+use diesel::{
+    pg::Pg,
+    query_builder::QueryFragment,
+    sql_query,
+    sql_types::{Array, BigInt, Text},
+    upsert::excluded,
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use std::{env, fmt::Debug};
@@ -45,10 +61,16 @@ use crate::db::postgres::models::resources::FromWriteResource;
 pub struct MirageProcessorConfig {
     #[serde(default)]
     pub deployer_address: Option<String>,
+    /// Deletes Mirage rows at or above this version and reindexes from it on startup
+    #[serde(default)]
+    pub backfill_from_version: Option<u64>,
 }
 
 impl MirageProcessorConfig {
-    pub fn new(deployer_address: Option<String>) -> Result<Self, anyhow::Error> {
+    pub fn new(
+        deployer_address: Option<String>,
+        backfill_from_version: Option<u64>,
+    ) -> Result<Self, anyhow::Error> {
         // Load .env file if it exists
         dotenv().ok();
 
@@ -60,7 +82,19 @@ impl MirageProcessorConfig {
                 anyhow::anyhow!("Deployer address not configured. Please set MIRAGE_PROCESSOR_DEPLOYER_ADDRESS environment variable or provide it in the config file.")
             })?;
 
-        Ok(Self { deployer_address: Some(deployer_address) })
+        let backfill_from_version = env::var("MIRAGE_PROCESSOR_BACKFILL_FROM_VERSION")
+            .ok()
+            .map(|v| {
+                v.parse()
+                    .context("MIRAGE_PROCESSOR_BACKFILL_FROM_VERSION must be a version")
+            })
+            .transpose()?
+            .or(backfill_from_version);
+
+        Ok(Self {
+            deployer_address: Some(deployer_address),
+            backfill_from_version,
+        })
     }
 }
 
@@ -68,6 +102,7 @@ pub struct MirageProcessor {
     connection_pool: ArcDbPool,
     config: MirageProcessorConfig,
     per_table_chunk_sizes: AHashMap<String, usize>,
+    deprecated_tables: TableFlags,
 }
 
 impl MirageProcessor {
@@ -75,6 +110,7 @@ impl MirageProcessor {
         connection_pool: ArcDbPool,
         config: MirageProcessorConfig,
         per_table_chunk_sizes: AHashMap<String, usize>,
+        deprecated_tables: TableFlags,
     ) -> Self {
         tracing::info!(
             "Initializing MirageProcessor deployer address: {:?}",
@@ -85,6 +121,7 @@ impl MirageProcessor {
             connection_pool,
             config,
             per_table_chunk_sizes,
+            deprecated_tables,
         }
     }
 }
@@ -100,6 +137,120 @@ impl Debug for MirageProcessor {
     }
 }
 
+/// Every Mirage table and the column holding the version its rows were last written at, but
+/// `current_vaults`, which is rolled back rather than deleted.
+const MIRAGE_TABLE_VERSION_COLUMNS: &[(&str, &str)] = &[
+    ("mirage_debt_store_datas", "transaction_version"),
+    ("vault_collection_datas", "transaction_version"),
+    ("vault_collection_configs", "transaction_version"),
+    ("vault_datas", "transaction_version"),
+    ("vault_activities", "transaction_version"),
+    ("vault_lineage", "transaction_version"),
+    ("market_datas", "transaction_version"),
+    ("market_configs", "transaction_version"),
+    ("position_datas", "transaction_version"),
+    ("tpsl_datas", "transaction_version"),
+    ("limit_order_datas", "transaction_version"),
+    ("trade_datas", "transaction_version"),
+    ("market_activities", "transaction_version"),
+    ("current_positions", "last_transaction_version"),
+    ("current_tpsls", "last_transaction_version"),
+    ("current_limit_orders", "last_transaction_version"),
+    ("position_histories", "last_transaction_version"),
+];
+
+/// Deletes every Mirage row written at or after `from_version` and rewinds the processor status
+/// so that range is reindexed, all in a single db transaction. Current vaults last touched in
+/// the range are rolled back to their state before it, as merges replayed from there close
+/// their source vault based on it. Other current state rows last touched in the range are
+/// deleted and get rebuilt as the range is replayed.
+///
+/// Each `from_version` is recorded in `mirage_backfills` and only applied once, so restarting
+/// with the same config doesn't wipe the progress made since.
+pub async fn reset_mirage_tables_from_version(
+    pool: ArcDbPool,
+    processor_name: &'static str,
+    from_version: u64,
+) -> anyhow::Result<()> {
+    use schema::{mirage_backfills, processor_completed_ranges, processor_status};
+
+    let mut conn = pool.get().await?;
+    let from_version = from_version as i64;
+    let last_success_version = ProcessorStatusQuery::get_by_processor(processor_name, &mut conn)
+        .await?
+        .map(|status| status.last_success_version);
+
+    let applied = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let is_new_backfill = diesel::insert_into(mirage_backfills::table)
+                    .values((
+                        mirage_backfills::processor.eq(processor_name),
+                        mirage_backfills::from_version.eq(from_version),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .await?
+                    > 0;
+                if !is_new_backfill {
+                    return Ok(false);
+                }
+
+                for (table, version_column) in MIRAGE_TABLE_VERSION_COLUMNS {
+                    sql_query(format!(
+                        "DELETE FROM {} WHERE {} >= $1",
+                        table, version_column
+                    ))
+                    .bind::<BigInt, _>(from_version)
+                    .execute(conn)
+                    .await?;
+                }
+                CurrentVault::roll_back_to_version(from_version, conn).await?;
+
+                // Batches completed ahead of the status would otherwise be skipped on replay
+                diesel::delete(
+                    processor_completed_ranges::table
+                        .filter(processor_completed_ranges::processor.eq(processor_name)),
+                )
+                .execute(conn)
+                .await?;
+
+                let status =
+                    processor_status::table.filter(processor_status::processor.eq(processor_name));
+                if from_version == 0 {
+                    diesel::delete(status).execute(conn).await?;
+                } else {
+                    // Never move the status forward if the processor hasn't reached the version yet
+                    diesel::update(
+                        status.filter(processor_status::last_success_version.ge(from_version)),
+                    )
+                    .set(processor_status::last_success_version.eq(from_version - 1))
+                    .execute(conn)
+                    .await?;
+                }
+                Ok(true)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    if applied {
+        tracing::info!(
+            processor_name = processor_name,
+            from_version = from_version,
+            previous_last_success_version = last_success_version,
+            "[Parser] Deleted Mirage rows for backfill, reindexing from version",
+        );
+    } else {
+        tracing::info!(
+            processor_name = processor_name,
+            from_version = from_version,
+            "[Parser] Backfill was already applied, resuming from processor status",
+        );
+    }
+    Ok(())
+}
+
 pub async fn insert_to_db(
     conn: ArcDbPool,
     name: &'static str,
@@ -110,6 +261,8 @@ pub async fn insert_to_db(
     vault_configs: &[VaultConfigModel],
     vault_datas: &[VaultModel],
     vault_activities: &[VaultActivityModel],
+    current_vaults: &[CurrentVault],
+    vault_lineages: &[VaultLineage],
     market_collection_datas: &[MarketCollectionModel],
     market_configs: &[MarketConfigModel],
     position_datas: &[PositionModel],
@@ -120,6 +273,8 @@ pub async fn insert_to_db(
     current_tpsls: &[CurrentTpsl],
     current_limit_orders: &[CurrentLimitOrder],
     market_activities: &[MarketActivityModel],
+    position_history_ids: &[String],
+    refresh_position_marks: bool,
     per_table_chunk_sizes: &AHashMap<String, usize>,
 ) -> Result<(), diesel::result::Error> {
     tracing::trace!(
@@ -228,6 +383,18 @@ pub async fn insert_to_db(
             per_table_chunk_sizes,
         ),
     );
+    let cv = execute_in_chunks(
+        conn.clone(),
+        insert_current_vaults_query,
+        current_vaults,
+        get_config_table_chunk_size::<CurrentVault>("current_vaults", per_table_chunk_sizes),
+    );
+    let vl = execute_in_chunks(
+        conn.clone(),
+        insert_vault_lineage_query,
+        vault_lineages,
+        get_config_table_chunk_size::<VaultLineage>("vault_lineage", per_table_chunk_sizes),
+    );
     let ma = execute_in_chunks(
         conn.clone(),
         insert_market_activities_query,
@@ -244,6 +411,8 @@ pub async fn insert_to_db(
         vc_res,
         vd_res,
         va_res,
+        cv_res,
+        vl_res,
         mcd_res,
         mc_res,
         pd_res,
@@ -254,15 +423,105 @@ pub async fn insert_to_db(
         ctp_res,
         cl_res,
         ma_res,
-    ) = tokio::join!(cfd, vcd, vc, vd, va, mcd, mc, pd, tpd, lod, td, cp, ctp, cl, ma);
+    ) = tokio::join!(cfd, vcd, vc, vd, va, cv, vl, mcd, mc, pd, tpd, lod, td, cp, ctp, cl, ma);
 
     for res in [
-        cfd_res, vcd_res, vc_res, vd_res, va_res, mcd_res, mc_res, pd_res, tpd_res, lod_res,
-        td_res, cp_res, ctp_res, cl_res, ma_res,
+        cfd_res, vcd_res, vc_res, vd_res, va_res, cv_res, vl_res, mcd_res, mc_res, pd_res, tpd_res,
+        lod_res, td_res, cp_res, ctp_res, cl_res, ma_res,
     ] {
         res?;
     }
 
+    // Merges are written with the state of their source vault as of the batch, which may miss
+    // writes of the source by earlier batches that aren't in yet. The lineage of the vaults
+    // written here is refreshed from vault_datas under their locks, so whichever of the two is
+    // written last fixes it up.
+    let lineage_vault_ids = vault_lineages
+        .iter()
+        .map(|l| l.src_vault_id.clone())
+        .chain(vault_datas.iter().map(|v| v.vault_id.clone()))
+        .collect::<AHashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    if !lineage_vault_ids.is_empty() {
+        run_in_transaction(conn.clone(), |conn| {
+            async move {
+                lock_objects(&lineage_vault_ids, conn).await?;
+                VaultLineage::refresh_source_states(&lineage_vault_ids, conn).await
+            }
+            .scope_boxed()
+        })
+        .await?;
+    }
+
+    // Histories and marks are derived from the rows above, so they can only be written once
+    // those are in. Both are written under the locks of the positions they cover, taken at
+    // once so concurrent batches can't deadlock. Marks also depend on their market, whose
+    // lock is taken first: a batch opening a position and a batch changing its market's inputs
+    // can't both miss each other's rows.
+    let mark_position_ids = if refresh_position_marks {
+        position_datas
+            .iter()
+            .map(|p| p.position_id.clone())
+            .chain(current_positions.iter().map(|p| p.position_id.clone()))
+            .collect::<AHashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    let market_mark_inputs = if refresh_position_marks {
+        MarketMarkInputs::from_batch(trades, market_collection_datas, market_configs)
+    } else {
+        AHashMap::new()
+    };
+    let mark_market_ids = if refresh_position_marks {
+        position_datas
+            .iter()
+            .map(|p| p.market_id.clone())
+            .chain(current_positions.iter().map(|p| p.market_id.clone()))
+            .chain(market_mark_inputs.keys().cloned())
+            .collect::<AHashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    if !position_history_ids.is_empty()
+        || !mark_position_ids.is_empty()
+        || !market_mark_inputs.is_empty()
+    {
+        let chunk_size = get_config_table_chunk_size::<PositionHistory>(
+            "position_histories",
+            per_table_chunk_sizes,
+        );
+        run_in_transaction(conn.clone(), |conn| {
+            async move {
+                lock_objects(&mark_market_ids, conn).await?;
+                let mark_position_ids = PositionMark::affected_positions(
+                    &mark_position_ids,
+                    &market_mark_inputs,
+                    start_version as i64,
+                    conn,
+                )
+                .await?;
+                let locked_position_ids = [position_history_ids, &mark_position_ids[..]].concat();
+                lock_objects(&locked_position_ids, conn).await?;
+
+                PositionHistory::rebuild(
+                    position_history_ids,
+                    start_version as i64,
+                    chunk_size,
+                    conn,
+                )
+                .await?;
+                PositionMark::refresh(&mark_position_ids, conn).await
+            }
+            .scope_boxed()
+        })
+        .await?;
+    }
+
     Ok(())
 }
 
@@ -310,6 +569,49 @@ fn insert_vault_activities_query(
     )
 }
 
+fn insert_current_vaults_query(
+    items_to_insert: Vec<CurrentVault>,
+) -> (
+    impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send,
+    Option<&'static str>,
+) {
+    use schema::current_vaults::dsl::*;
+    (
+        diesel::insert_into(schema::current_vaults::table)
+            .values(items_to_insert)
+            .on_conflict(vault_id)
+            .do_update()
+            .set((
+                last_transaction_version.eq(excluded(last_transaction_version)),
+                collection_id.eq(excluded(collection_id)),
+                owner_addr.eq(excluded(owner_addr)),
+                collateral_amount.eq(excluded(collateral_amount)),
+                borrow_part.eq(excluded(borrow_part)),
+                is_closed.eq(excluded(is_closed)),
+                merged_into_vault_id.eq(excluded(merged_into_vault_id)),
+                transaction_timestamp.eq(excluded(transaction_timestamp)),
+                inserted_at.eq(excluded(inserted_at)),
+            )),
+        Some("WHERE current_vaults.last_transaction_version <= excluded.last_transaction_version"),
+    )
+}
+
+fn insert_vault_lineage_query(
+    items_to_insert: Vec<VaultLineage>,
+) -> (
+    impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send,
+    Option<&'static str>,
+) {
+    use schema::vault_lineage::dsl::*;
+    (
+        diesel::insert_into(schema::vault_lineage::table)
+            .values(items_to_insert)
+            .on_conflict((transaction_version, event_index))
+            .do_nothing(),
+        None,
+    )
+}
+
 fn insert_market_collection_datas_query(
     items_to_insert: Vec<MarketCollectionModel>,
 ) -> (
@@ -476,6 +778,25 @@ fn insert_current_limit_orders_query(
     )
 }
 
+/// Takes a transaction level lock per object, e.g. position or vault, always in the same order
+/// so concurrent batches can't deadlock
+async fn lock_objects(object_ids: &[String], conn: &mut MyDbConnection) -> diesel::QueryResult<()> {
+    sql_query(LOCK_OBJECTS_QUERY)
+        .bind::<Array<Text>, _>(object_ids)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+const LOCK_OBJECTS_QUERY: &str = "
+SELECT pg_advisory_xact_lock(l.key)
+FROM (
+    SELECT DISTINCT hashtextextended(object_id, 0) AS key
+    FROM unnest($1::text[]) AS object_id
+    ORDER BY key
+) l
+";
+
 fn insert_market_activities_query(
     items_to_insert: Vec<MarketActivityModel>,
 ) -> (
@@ -587,12 +908,17 @@ impl ProcessorTrait for MirageProcessor {
                 anyhow::anyhow!("Deployer address not configured. Please set MIRAGE_PROCESSOR_DEPLOYER_ADDRESS environment variable or provide it in the config file.")
             })?;
 
+        // Disabled tables aren't parsed at all
+        let flags = self.deprecated_tables;
+
         let (
             mirage_debt_stores,
             vault_collection_datas,
             vault_configs,
             vault_datas,
             vault_activities,
+            current_vaults,
+            vault_lineages,
             market_collection_datas,
             market_configs,
             position_datas,
@@ -603,7 +929,8 @@ impl ProcessorTrait for MirageProcessor {
             current_tpsls,
             current_limit_orders,
             market_activities,
-        ) = parse_mirage_protocol(&transactions, deployer_address).await;
+            position_history_ids,
+        ) = parse_mirage_protocol(&transactions, deployer_address, flags).await;
 
         let processing_duration_in_secs = processing_start.elapsed().as_secs_f64();
         let db_insertion_start = std::time::Instant::now();
@@ -618,6 +945,8 @@ impl ProcessorTrait for MirageProcessor {
             &vault_configs,
             &vault_datas,
             &vault_activities,
+            &current_vaults,
+            &vault_lineages,
             &market_collection_datas,
             &market_configs,
             &position_datas,
@@ -628,6 +957,9 @@ impl ProcessorTrait for MirageProcessor {
             &current_tpsls,
             &current_limit_orders,
             &market_activities,
+            &position_history_ids,
+            // Marks are written to current_positions
+            !flags.contains(TableFlags::CURRENT_POSITIONS),
             &self.per_table_chunk_sizes,
         )
         .await;
@@ -661,15 +993,20 @@ impl ProcessorTrait for MirageProcessor {
     }
 }
 
+/// Parses the Mirage tables from `transactions`, skipping the tables in `disabled_tables` and
+/// the parsing only they need.
 pub async fn parse_mirage_protocol(
     transactions: &[Transaction],
     deployer_address: &str,
+    disabled_tables: TableFlags,
 ) -> (
     Vec<MirageDebtStoreModel>,
     Vec<VaultCollectionModel>,
     Vec<VaultConfigModel>,
     Vec<VaultModel>,
     Vec<VaultActivityModel>,
+    Vec<CurrentVault>,
+    Vec<VaultLineage>,
     Vec<MarketCollectionModel>,
     Vec<MarketConfigModel>,
     Vec<PositionModel>,
@@ -680,6 +1017,7 @@ pub async fn parse_mirage_protocol(
     Vec<CurrentTpsl>,
     Vec<CurrentLimitOrder>,
     Vec<MarketActivityModel>,
+    Vec<String>,
 ) {
     let deployer_account_address =
         AccountAddress::from_hex(deployer_address).expect("Failed to parse deployer address");
@@ -690,12 +1028,25 @@ pub async fn parse_mirage_protocol(
         &create_resource_address(deployer_account_address, "MIRAGE_MARKET".as_bytes())
             .to_standard_string();
 
+    // Whether any of `tables` is written
+    let enabled = |tables: TableFlags| !disabled_tables.contains(tables);
+    let vault_state_tables =
+        TableFlags::VAULT_DATAS | TableFlags::CURRENT_VAULTS | TableFlags::VAULT_LINEAGE;
+    let market_event_tables = TableFlags::TRADE_DATAS
+        | TableFlags::CURRENT_POSITIONS
+        | TableFlags::CURRENT_TPSLS
+        | TableFlags::CURRENT_LIMIT_ORDERS
+        | TableFlags::MARKET_ACTIVITIES
+        | TableFlags::POSITION_HISTORIES;
+
     let mut mirage_debt_stores = vec![];
 
     let mut vault_collection_datas = vec![];
     let mut vault_configs = vec![];
     let mut vault_datas = vec![];
     let mut all_vault_activities = vec![];
+    let mut all_current_vaults: AHashMap<String, CurrentVault> = AHashMap::new();
+    let mut all_vault_lineages: Vec<VaultLineage> = vec![];
 
     let mut all_limit_orders = vec![];
 
@@ -709,6 +1060,8 @@ pub async fn parse_mirage_protocol(
     let mut all_current_tpsls: AHashMap<String, CurrentTpsl> = AHashMap::new();
     let mut all_current_limit_orders: AHashMap<String, CurrentLimitOrder> = AHashMap::new();
     let mut all_market_activities: Vec<MarketActivityModel> = vec![];
+    // Histories are rebuilt for every position that traded or changed in the batch
+    let mut position_history_ids = vec![];
 
     // Helper function to update the latest transaction in the HashMap
     fn update_latest<T, K>(map: &mut AHashMap<K, T>, items: Vec<T>, get_id: impl Fn(&T) -> K)
@@ -746,6 +1099,8 @@ pub async fn parse_mirage_protocol(
             let txn_version = txn.version as i64;
             let txn_timestamp = parse_timestamp(txn.timestamp.as_ref().unwrap(), txn_version);
             let transaction_info = txn.info.as_ref().expect("Transaction info doesn't exist!");
+            let sender_address = standardize_address(&txn_inner.request.as_ref().unwrap().sender);
+            let mut current_vaults: Vec<CurrentVault> = vec![];
 
             // First pass to get all the object owners from the write_set
             for wsc in transaction_info.changes.iter() {
@@ -782,25 +1137,8 @@ pub async fn parse_mirage_protocol(
             for (index, wsc) in transaction_info.changes.iter().enumerate() {
                 if let Change::WriteResource(write_resource) = wsc.change.as_ref().unwrap() {
                     let wsc_index = index as i64;
-                    if let Some(mirage_debt_store) = MirageDebtStoreModel::from_write_resource(
-                        write_resource,
-                        wsc_index,
-                        txn_version,
-                        txn_timestamp,
-                        mirage_module_address,
-                    )
-                    .unwrap_or_else(|e| {
-                        tracing::error!(
-                            transaction_version = txn_version,
-                            index = index,
-                                error = ?e,
-                            "[Parser] error parsing DebtStore");
-                        panic!("[Parser] error parsing DebtStore");
-                    }) {
-                        mirage_debt_stores.push(mirage_debt_store);
-                    }
-                    if let Some((vault_collection, vault_config)) =
-                        VaultCollectionModel::from_write_resource(
+                    if enabled(TableFlags::MIRAGE_DEBT_STORE_DATAS) {
+                        if let Some(mirage_debt_store) = MirageDebtStoreModel::from_write_resource(
                             write_resource,
                             wsc_index,
                             txn_version,
@@ -809,113 +1147,206 @@ pub async fn parse_mirage_protocol(
                         )
                         .unwrap_or_else(|e| {
                             tracing::error!(
-                            transaction_version = txn_version,
-                            index = index,
+                                transaction_version = txn_version,
+                                index = index,
                                 error = ?e,
-                            "[Parser] error parsing VaultCollection");
-                            panic!("[Parser] error parsing VaultCollection");
-                        })
-                    {
-                        vault_collection_datas.push(vault_collection);
-                        vault_configs.push(vault_config);
+                                "[Parser] error parsing DebtStore");
+                            panic!("[Parser] error parsing DebtStore");
+                        }) {
+                            mirage_debt_stores.push(mirage_debt_store);
+                        }
                     }
-                    if let Some(vault_data) = VaultModel::get_from_write_resource(
-                        write_resource,
-                        txn_version,
-                        wsc_index,
-                        txn_timestamp,
-                        &object_owners,
-                        mirage_module_address,
-                    )
-                    .unwrap()
-                    {
-                        vault_datas.push(vault_data);
+                    if enabled(
+                        TableFlags::VAULT_COLLECTION_DATAS | TableFlags::VAULT_COLLECTION_CONFIGS,
+                    ) {
+                        if let Some((vault_collection, vault_config)) =
+                            VaultCollectionModel::from_write_resource(
+                                write_resource,
+                                wsc_index,
+                                txn_version,
+                                txn_timestamp,
+                                mirage_module_address,
+                            )
+                            .unwrap_or_else(|e| {
+                                tracing::error!(
+                                transaction_version = txn_version,
+                                index = index,
+                                    error = ?e,
+                                "[Parser] error parsing VaultCollection");
+                                panic!("[Parser] error parsing VaultCollection");
+                            })
+                        {
+                            if enabled(TableFlags::VAULT_COLLECTION_DATAS) {
+                                vault_collection_datas.push(vault_collection);
+                            }
+                            if enabled(TableFlags::VAULT_COLLECTION_CONFIGS) {
+                                vault_configs.push(vault_config);
+                            }
+                        }
                     }
-                    if let Some((market_collection, market_config)) =
-                        MarketCollectionModel::from_write_resource(
+                    if enabled(vault_state_tables) {
+                        if let Some(vault_data) = VaultModel::get_from_write_resource(
                             write_resource,
-                            index as i64,
                             txn_version,
+                            wsc_index,
                             txn_timestamp,
-                            market_module_address,
+                            &object_owners,
+                            mirage_module_address,
                         )
-                        .unwrap_or_else(|e| {
-                            tracing::error!(
-                            transaction_version = txn_version,
-                            index = index,
-                                error = ?e,
-                            "[Parser] error parsing MarketCollection");
-                            panic!("[Parser] error parsing MarketCollection");
-                        })
-                    {
-                        market_datas.push(market_collection);
-                        market_configs.push(market_config);
+                        .unwrap()
+                        {
+                            current_vaults.push(CurrentVault::from(&vault_data));
+                            if enabled(TableFlags::VAULT_DATAS) {
+                                vault_datas.push(vault_data);
+                            }
+                        }
                     }
-                    if let Some(position_data) = PositionModel::get_from_write_resource(
-                        write_resource,
-                        txn_version,
-                        wsc_index,
-                        txn_timestamp,
-                        &object_owners,
-                        market_module_address,
-                    )
-                    .unwrap()
-                    {
-                        position_datas.push(position_data);
+                    if enabled(TableFlags::MARKET_DATAS | TableFlags::MARKET_CONFIGS) {
+                        if let Some((market_collection, market_config)) =
+                            MarketCollectionModel::from_write_resource(
+                                write_resource,
+                                index as i64,
+                                txn_version,
+                                txn_timestamp,
+                                market_module_address,
+                            )
+                            .unwrap_or_else(|e| {
+                                tracing::error!(
+                                transaction_version = txn_version,
+                                index = index,
+                                    error = ?e,
+                                "[Parser] error parsing MarketCollection");
+                                panic!("[Parser] error parsing MarketCollection");
+                            })
+                        {
+                            if enabled(TableFlags::MARKET_DATAS) {
+                                market_datas.push(market_collection);
+                            }
+                            if enabled(TableFlags::MARKET_CONFIGS) {
+                                market_configs.push(market_config);
+                            }
+                        }
                     }
-                    if let Some(tpsl_data) = TpSlModel::get_from_write_resource(
-                        write_resource,
-                        txn_version,
-                        wsc_index,
-                        txn_timestamp,
-                        &object_owners,
-                        &strategy_objects,
-                        market_module_address,
-                    )
-                    .unwrap()
-                    {
-                        tpsl_datas.push(tpsl_data);
+                    if enabled(TableFlags::POSITION_DATAS | TableFlags::POSITION_HISTORIES) {
+                        if let Some(position_data) = PositionModel::get_from_write_resource(
+                            write_resource,
+                            txn_version,
+                            wsc_index,
+                            txn_timestamp,
+                            &object_owners,
+                            market_module_address,
+                        )
+                        .unwrap()
+                        {
+                            if enabled(TableFlags::POSITION_HISTORIES) {
+                                position_history_ids.push(position_data.position_id.clone());
+                            }
+                            if enabled(TableFlags::POSITION_DATAS) {
+                                position_datas.push(position_data);
+                            }
+                        }
                     }
-                    if let Some(limit_order) = LimitOrderModel::get_from_write_resource(
-                        write_resource,
-                        txn_version,
-                        wsc_index,
-                        txn_timestamp,
-                        &object_owners,
-                        &strategy_objects,
-                        market_module_address,
-                    )
-                    .unwrap()
-                    {
-                        all_limit_orders.push(limit_order);
+                    if enabled(TableFlags::TPSL_DATAS) {
+                        if let Some(tpsl_data) = TpSlModel::get_from_write_resource(
+                            write_resource,
+                            txn_version,
+                            wsc_index,
+                            txn_timestamp,
+                            &object_owners,
+                            &strategy_objects,
+                            market_module_address,
+                        )
+                        .unwrap()
+                        {
+                            tpsl_datas.push(tpsl_data);
+                        }
+                    }
+                    if enabled(TableFlags::LIMIT_ORDER_DATAS) {
+                        if let Some(limit_order) = LimitOrderModel::get_from_write_resource(
+                            write_resource,
+                            txn_version,
+                            wsc_index,
+                            txn_timestamp,
+                            &object_owners,
+                            &strategy_objects,
+                            market_module_address,
+                        )
+                        .unwrap()
+                        {
+                            all_limit_orders.push(limit_order);
+                        }
                     }
                 }
             }
 
             // process events
-            let mut vault_activities =
-                VaultActivityModel::from_transaction(txn, &object_owners, mirage_module_address);
-            let (
-                mut trades,
-                current_positions,
-                current_tpsls,
-                current_limit_orders,
-                mut market_activities,
-            ) = MarketActivityModel::from_transaction(txn, &object_owners, market_module_address);
-
-            update_latest(&mut all_current_positions, current_positions, |pos| {
-                pos.position_id.clone()
-            });
-            update_latest(&mut all_current_limit_orders, current_limit_orders, |pos| {
-                pos.strategy_id.clone()
-            });
-            update_latest(&mut all_current_tpsls, current_tpsls, |pos| {
-                pos.strategy_id.clone()
-            });
-
-            all_vault_activities.append(&mut vault_activities);
-            all_trades.append(&mut trades);
-            all_market_activities.append(&mut market_activities);
+            if enabled(TableFlags::VAULT_ACTIVITIES | vault_state_tables) {
+                let mut vault_activities = VaultActivityModel::from_transaction(
+                    txn,
+                    &object_owners,
+                    mirage_module_address,
+                );
+
+                // Merges are resolved against the vault state prior to this transaction, then
+                // the vaults they close are applied after this transaction's own vault writes
+                let (mut vault_lineages, merged_vaults) = VaultLineage::from_vault_activities(
+                    &vault_activities,
+                    &all_current_vaults,
+                    &sender_address,
+                );
+                update_latest(&mut all_current_vaults, current_vaults, |v| {
+                    v.vault_id.clone()
+                });
+                update_latest(&mut all_current_vaults, merged_vaults, |v| {
+                    v.vault_id.clone()
+                });
+
+                if enabled(TableFlags::VAULT_ACTIVITIES) {
+                    all_vault_activities.append(&mut vault_activities);
+                }
+                if enabled(TableFlags::VAULT_LINEAGE) {
+                    all_vault_lineages.append(&mut vault_lineages);
+                }
+            }
+
+            if enabled(market_event_tables) {
+                let (
+                    mut trades,
+                    current_positions,
+                    current_tpsls,
+                    current_limit_orders,
+                    mut market_activities,
+                ) = MarketActivityModel::from_transaction(
+                    txn,
+                    &object_owners,
+                    market_module_address,
+                );
+
+                if enabled(TableFlags::POSITION_HISTORIES) {
+                    position_history_ids.extend(trades.iter().map(|t| t.position_id.clone()));
+                }
+                if enabled(TableFlags::CURRENT_POSITIONS) {
+                    update_latest(&mut all_current_positions, current_positions, |pos| {
+                        pos.position_id.clone()
+                    });
+                }
+                if enabled(TableFlags::CURRENT_LIMIT_ORDERS) {
+                    update_latest(&mut all_current_limit_orders, current_limit_orders, |pos| {
+                        pos.strategy_id.clone()
+                    });
+                }
+                if enabled(TableFlags::CURRENT_TPSLS) {
+                    update_latest(&mut all_current_tpsls, current_tpsls, |pos| {
+                        pos.strategy_id.clone()
+                    });
+                }
+                if enabled(TableFlags::TRADE_DATAS) {
+                    all_trades.append(&mut trades);
+                }
+                if enabled(TableFlags::MARKET_ACTIVITIES) {
+                    all_market_activities.append(&mut market_activities);
+                }
+            }
         }
     }
 
@@ -924,6 +1355,12 @@ pub async fn parse_mirage_protocol(
     let mut all_current_limit_orders: Vec<CurrentLimitOrder> =
         all_current_limit_orders.into_values().collect();
     let mut all_current_tpsls: Vec<CurrentTpsl> = all_current_tpsls.into_values().collect();
+    // Also tracked for the lineage, but only written to current_vaults
+    let mut all_current_vaults: Vec<CurrentVault> = if enabled(TableFlags::CURRENT_VAULTS) {
+        all_current_vaults.into_values().collect()
+    } else {
+        vec![]
+    };
 
     // Sort by PK
     mirage_debt_stores.sort_by(|a, b| a.object_address.cmp(&b.object_address));
@@ -931,6 +1368,7 @@ pub async fn parse_mirage_protocol(
     vault_collection_datas.sort_by(|a, b| a.collection_id.cmp(&b.collection_id));
     vault_datas
         .sort_by(|a, b| (&a.vault_id, &a.collection_id).cmp(&(&b.vault_id, &b.collection_id)));
+    all_current_vaults.sort_by(|a, b| a.vault_id.cmp(&b.vault_id));
 
     all_limit_orders.sort_by(|a, b| a.strategy_id.cmp(&b.strategy_id));
 
@@ -945,6 +1383,8 @@ pub async fn parse_mirage_protocol(
     all_current_positions.sort_by(|a, b| a.position_id.cmp(&b.position_id));
     all_current_tpsls.sort_by(|a, b| a.position_id.cmp(&b.position_id));
     all_current_limit_orders.sort_by(|a, b| a.strategy_id.cmp(&b.strategy_id));
+    position_history_ids.sort();
+    position_history_ids.dedup();
 
     (
         mirage_debt_stores,
@@ -952,6 +1392,8 @@ pub async fn parse_mirage_protocol(
         vault_configs,
         vault_datas,
         all_vault_activities,
+        all_current_vaults,
+        all_vault_lineages,
         market_datas,
         market_configs,
         position_datas,
@@ -962,5 +1404,6 @@ pub async fn parse_mirage_protocol(
         all_current_tpsls,
         all_current_limit_orders,
         all_market_activities,
+        position_history_ids,
     )
 }
@@ -59,6 +59,7 @@ use crate::{
     utils::{
         counters::{GOT_CONNECTION_COUNT, UNABLE_TO_GET_CONNECTION_COUNT},
         database::{execute_with_better_error, ArcDbPool, DbPoolConnection},
+        table_flags::TableFlags,
         util::parse_timestamp,
     },
 };
@@ -240,6 +241,110 @@ impl ProcessorConfig {
                 | ProcessorConfig::ParquetUserTransactionsProcessor(_)
         )
     }
+
+    /// Tables the processor writes to Postgres, which `deprecated_tables` and `enabled_tables`
+    /// can turn off. Includes tables a processor used to write so older configs still load.
+    /// Parquet processors, the monitoring processor and the NFT metadata processor write none.
+    pub fn tables(&self) -> TableFlags {
+        match self {
+            ProcessorConfig::AccountTransactionsProcessor => TableFlags::ACCOUNT_TRANSACTIONS,
+            ProcessorConfig::AnsProcessor(_) => {
+                TableFlags::CURRENT_ANS_LOOKUP_V2
+                    | TableFlags::CURRENT_ANS_PRIMARY_NAME_V2
+                    | TableFlags::ANS_LOOKUP_V2
+                    | TableFlags::ANS_PRIMARY_NAME_V2
+                    | TableFlags::CURRENT_ANS_LOOKUP
+                    | TableFlags::CURRENT_ANS_PRIMARY_NAME
+                    | TableFlags::ANS_LOOKUP
+                    | TableFlags::ANS_PRIMARY_NAME
+            },
+            ProcessorConfig::DefaultProcessor => {
+                TableFlags::TRANSACTIONS
+                    | TableFlags::WRITE_SET_CHANGES
+                    | TableFlags::MOVE_RESOURCES
+                    | TableFlags::TABLE_ITEMS
+                    | TableFlags::TABLE_METADATAS
+                    | TableFlags::MOVE_MODULES
+                    | TableFlags::CURRENT_TABLE_ITEMS
+                    | TableFlags::BLOCK_METADATA_TRANSACTIONS
+            },
+            ProcessorConfig::EventsProcessor => TableFlags::EVENTS,
+            ProcessorConfig::FungibleAssetProcessor => {
+                TableFlags::FUNGIBLE_ASSET_BALANCES
+                    | TableFlags::CURRENT_FUNGIBLE_ASSET_BALANCES
+                    | TableFlags::FUNGIBLE_ASSET_ACTIVITIES
+                    | TableFlags::FUNGIBLE_ASSET_METADATA
+                    | TableFlags::CURRENT_UNIFIED_FUNGIBLE_ASSET_BALANCES
+                    | TableFlags::CURRENT_FUNGIBLE_ASSET_BALANCES_LEGACY
+                    | TableFlags::FUNGIBLE_ASSET_TO_COIN_MAPPINGS
+                    | TableFlags::COIN_SUPPLY
+            },
+            ProcessorConfig::ObjectsProcessor(_) => {
+                TableFlags::OBJECTS | TableFlags::CURRENT_OBJECTS
+            },
+            ProcessorConfig::StakeProcessor(_) => {
+                TableFlags::DELEGATED_STAKING_ACTIVITIES
+                    | TableFlags::DELEGATED_STAKING_POOLS
+                    | TableFlags::DELEGATED_STAKING_POOL_BALANCES
+                    | TableFlags::CURRENT_DELEGATED_STAKING_POOL_BALANCES
+                    | TableFlags::DELEGATOR_BALANCES
+                    | TableFlags::CURRENT_DELEGATOR_BALANCES
+                    | TableFlags::CURRENT_DELEGATED_VOTER
+                    | TableFlags::CURRENT_STAKING_POOL_VOTER
+                    | TableFlags::PROPOSAL_VOTES
+            },
+            ProcessorConfig::TokenV2Processor(_) => {
+                TableFlags::TOKEN_ACTIVITIES_V2
+                    | TableFlags::CURRENT_TOKEN_OWNERSHIPS_V2
+                    | TableFlags::CURRENT_TOKEN_DATAS_V2
+                    | TableFlags::CURRENT_TOKEN_PENDING_CLAIMS
+                    | TableFlags::CURRENT_COLLECTIONS_V2
+                    | TableFlags::CURRENT_TOKEN_V2_METADATA
+                    | TableFlags::COLLECTIONS_V2
+                    | TableFlags::TOKEN_OWNERSHIPS_V2
+                    | TableFlags::TOKEN_DATAS_V2
+                    | TableFlags::CURRENT_TOKEN_ROYALTY_V1
+            },
+            ProcessorConfig::TransactionMetadataProcessor => {
+                TableFlags::TRANSACTION_SIZE_INFO
+                    | TableFlags::EVENT_SIZE_INFO
+                    | TableFlags::WRITE_SET_SIZE
+            },
+            ProcessorConfig::UserTransactionProcessor => {
+                TableFlags::USER_TRANSACTIONS | TableFlags::SIGNATURES
+            },
+            ProcessorConfig::MirageProcessor(_) => {
+                TableFlags::MIRAGE_DEBT_STORE_DATAS
+                    | TableFlags::VAULT_COLLECTION_DATAS
+                    | TableFlags::VAULT_COLLECTION_CONFIGS
+                    | TableFlags::VAULT_DATAS
+                    | TableFlags::VAULT_ACTIVITIES
+                    | TableFlags::CURRENT_VAULTS
+                    | TableFlags::VAULT_LINEAGE
+                    | TableFlags::MARKET_DATAS
+                    | TableFlags::MARKET_CONFIGS
+                    | TableFlags::POSITION_DATAS
+                    | TableFlags::TPSL_DATAS
+                    | TableFlags::LIMIT_ORDER_DATAS
+                    | TableFlags::TRADE_DATAS
+                    | TableFlags::CURRENT_POSITIONS
+                    | TableFlags::CURRENT_TPSLS
+                    | TableFlags::CURRENT_LIMIT_ORDERS
+                    | TableFlags::MARKET_ACTIVITIES
+                    | TableFlags::POSITION_HISTORIES
+            },
+            ProcessorConfig::MonitoringProcessor
+            | ProcessorConfig::NftMetadataProcessor(_)
+            | ProcessorConfig::ParquetDefaultProcessor(_)
+            | ProcessorConfig::ParquetFungibleAssetActivitiesProcessor(_)
+            | ProcessorConfig::ParquetFungibleAssetProcessor(_)
+            | ProcessorConfig::ParquetTransactionMetadataProcessor(_)
+            | ProcessorConfig::ParquetAnsProcessor(_)
+            | ProcessorConfig::ParquetEventsProcessor(_)
+            | ProcessorConfig::ParquetTokenV2Processor(_)
+            | ProcessorConfig::ParquetUserTransactionsProcessor(_) => TableFlags::empty(),
+        }
+    }
 }
 
 /// This enum contains all the processors defined in this crate.
@@ -187,12 +187,15 @@ impl ProcessorTrait for ObjectsProcessor {
             query_retry_delay_ms,
         };
 
-        let (mut raw_all_objects, raw_all_current_objects) =
+        let (mut raw_all_objects, mut raw_all_current_objects) =
             process_objects(transactions, &mut Some(db_connection)).await;
 
         if self.deprecated_tables.contains(TableFlags::OBJECTS) {
             raw_all_objects.clear();
         }
+        if self.deprecated_tables.contains(TableFlags::CURRENT_OBJECTS) {
+            raw_all_current_objects.clear();
+        }
 
         let postgres_objects: Vec<Object> =
             raw_all_objects.into_iter().map(Object::from_raw).collect();
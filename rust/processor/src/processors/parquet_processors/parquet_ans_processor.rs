@@ -5,6 +5,7 @@ use super::ParquetProcessorTrait;
 use crate::{
     bq_analytics::{
         create_parquet_handler_loop, generic_parquet_processor::ParquetDataGeneric,
+        object_store::ObjectStoreConfig, schema_versions::SchemaChangePolicy,
         ParquetProcessingResult,
     },
     db::postgres::models::ans_models::{
@@ -32,6 +33,10 @@ pub struct ParquetAnsProcessorConfig {
     pub google_application_credentials: Option<String>,
     pub bucket_name: String,
     pub bucket_root: String,
+    #[serde(default)]
+    pub object_store: ObjectStoreConfig,
+    #[serde(default)]
+    pub schema_change_policy: SchemaChangePolicy,
     pub parquet_handler_response_channel_size: usize,
     pub max_buffer_size: usize,
     pub ans_v1_primary_names_table_handle: String,
@@ -65,6 +70,8 @@ impl ParquetAnsProcessor {
             ProcessorName::ParquetAnsProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -4,6 +4,7 @@
 use crate::{
     bq_analytics::{
         create_parquet_handler_loop, generic_parquet_processor::ParquetDataGeneric,
+        object_store::ObjectStoreConfig, schema_versions::SchemaChangePolicy,
         ParquetProcessingResult,
     },
     db::parquet::models::default_models::{
@@ -34,6 +35,10 @@ pub struct ParquetDefaultProcessorConfig {
     pub google_application_credentials: Option<String>,
     pub bucket_name: String,
     pub bucket_root: String,
+    #[serde(default)]
+    pub object_store: ObjectStoreConfig,
+    #[serde(default)]
+    pub schema_change_policy: SchemaChangePolicy,
     pub parquet_handler_response_channel_size: usize,
     pub max_buffer_size: usize,
     pub parquet_upload_interval: u64,
@@ -68,6 +73,8 @@ impl ParquetDefaultProcessor {
             ProcessorName::ParquetDefaultProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -78,6 +85,8 @@ impl ParquetDefaultProcessor {
             ProcessorName::ParquetDefaultProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -88,6 +97,8 @@ impl ParquetDefaultProcessor {
             ProcessorName::ParquetDefaultProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -98,6 +109,8 @@ impl ParquetDefaultProcessor {
             ProcessorName::ParquetDefaultProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -107,6 +120,8 @@ impl ParquetDefaultProcessor {
             ProcessorName::ParquetDefaultProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
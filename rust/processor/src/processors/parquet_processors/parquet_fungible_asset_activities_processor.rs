@@ -5,6 +5,7 @@ use super::ParquetProcessorTrait;
 use crate::{
     bq_analytics::{
         create_parquet_handler_loop, generic_parquet_processor::ParquetDataGeneric,
+        object_store::ObjectStoreConfig, schema_versions::SchemaChangePolicy,
         ParquetProcessingResult,
     },
     db::{
@@ -48,6 +49,10 @@ pub struct ParquetFungibleAssetActivitiesProcessorConfig {
     pub google_application_credentials: Option<String>,
     pub bucket_name: String,
     pub bucket_root: String,
+    #[serde(default)]
+    pub object_store: ObjectStoreConfig,
+    #[serde(default)]
+    pub schema_change_policy: SchemaChangePolicy,
     pub parquet_handler_response_channel_size: usize,
     pub max_buffer_size: usize,
     pub parquet_upload_interval: u64,
@@ -79,6 +84,8 @@ impl ParquetFungibleAssetActivitiesProcessor {
             ProcessorName::ParquetFungibleAssetActivitiesProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -5,6 +5,7 @@ use super::ParquetProcessorTrait;
 use crate::{
     bq_analytics::{
         create_parquet_handler_loop, generic_parquet_processor::ParquetDataGeneric,
+        object_store::ObjectStoreConfig, schema_versions::SchemaChangePolicy,
         ParquetProcessingResult,
     },
     db::{
@@ -40,6 +41,10 @@ pub struct ParquetFungibleAssetProcessorConfig {
     pub google_application_credentials: Option<String>,
     pub bucket_name: String,
     pub bucket_root: String,
+    #[serde(default)]
+    pub object_store: ObjectStoreConfig,
+    #[serde(default)]
+    pub schema_change_policy: SchemaChangePolicy,
     pub parquet_handler_response_channel_size: usize,
     pub max_buffer_size: usize,
     pub parquet_upload_interval: u64,
@@ -70,6 +75,8 @@ impl ParquetFungibleAssetProcessor {
             ProcessorName::ParquetFungibleAssetProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
@@ -80,6 +87,8 @@ impl ParquetFungibleAssetProcessor {
             ProcessorName::ParquetFungibleAssetProcessor.into(),
             config.bucket_name.clone(),
             config.bucket_root.clone(),
+            config.object_store.clone(),
+            config.schema_change_policy,
             config.parquet_handler_response_channel_size,
             config.max_buffer_size,
             config.parquet_upload_interval_in_secs(),
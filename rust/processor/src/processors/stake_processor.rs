@@ -37,6 +37,7 @@ use crate::{
     schema,
     utils::{
         database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool, DbPoolConnection},
+        table_flags::TableFlags,
         util::{parse_timestamp, standardize_address},
     },
     IndexerGrpcProcessorConfig,
@@ -67,6 +68,7 @@ pub struct StakeProcessor {
     connection_pool: ArcDbPool,
     config: StakeProcessorConfig,
     per_table_chunk_sizes: AHashMap<String, usize>,
+    deprecated_tables: TableFlags,
 }
 
 impl StakeProcessor {
@@ -74,11 +76,13 @@ impl StakeProcessor {
         connection_pool: ArcDbPool,
         config: StakeProcessorConfig,
         per_table_chunk_sizes: AHashMap<String, usize>,
+        deprecated_tables: TableFlags,
     ) -> Self {
         Self {
             connection_pool,
             config,
             per_table_chunk_sizes,
+            deprecated_tables,
         }
     }
 }
@@ -614,10 +618,10 @@ impl ProcessorTrait for StakeProcessor {
             raw_all_delegator_activities,
             raw_all_delegator_balances,
             raw_all_current_delegator_balances,
-            all_delegator_pools,
+            mut all_delegator_pools,
             raw_all_delegator_pool_balances,
             raw_all_current_delegator_pool_balances,
-            all_current_delegated_voter,
+            mut all_current_delegated_voter,
         ) = match parse_stake_data(
             &transactions,
             Some(conn),
@@ -638,35 +642,64 @@ impl ProcessorTrait for StakeProcessor {
                 bail!(e)
             },
         };
-        let all_delegator_balances: Vec<DelegatorBalance> = raw_all_delegator_balances
+        let mut all_delegator_balances: Vec<DelegatorBalance> = raw_all_delegator_balances
             .into_iter()
             .map(DelegatorBalance::from_raw)
             .collect::<Vec<_>>();
-        let all_current_delegator_balances = raw_all_current_delegator_balances
+        let mut all_current_delegator_balances = raw_all_current_delegator_balances
             .into_iter()
             .map(CurrentDelegatorBalance::from_raw)
             .collect::<Vec<_>>();
-        let all_delegator_pool_balances = raw_all_delegator_pool_balances
+        let mut all_delegator_pool_balances = raw_all_delegator_pool_balances
             .into_iter()
             .map(DelegatorPoolBalance::from_raw)
             .collect::<Vec<_>>();
-        let all_current_delegator_pool_balances = raw_all_current_delegator_pool_balances
+        let mut all_current_delegator_pool_balances = raw_all_current_delegator_pool_balances
             .into_iter()
             .map(CurrentDelegatorPoolBalance::from_raw)
             .collect::<Vec<_>>();
-        let all_delegator_activities = raw_all_delegator_activities
+        let mut all_delegator_activities = raw_all_delegator_activities
             .into_iter()
             .map(DelegatedStakingActivity::from_raw)
             .collect::<Vec<_>>();
-        let all_proposal_votes = raw_all_proposal_votes
+        let mut all_proposal_votes = raw_all_proposal_votes
             .into_iter()
             .map(ProposalVote::from_raw)
             .collect::<Vec<_>>();
-        let all_current_stake_pool_voters = raw_all_current_stake_pool_voters
+        let mut all_current_stake_pool_voters = raw_all_current_stake_pool_voters
             .into_iter()
             .map(CurrentStakingPoolVoter::from_raw)
             .collect::<Vec<_>>();
 
+        let flags = self.deprecated_tables;
+        if flags.contains(TableFlags::CURRENT_STAKING_POOL_VOTER) {
+            all_current_stake_pool_voters.clear();
+        }
+        if flags.contains(TableFlags::PROPOSAL_VOTES) {
+            all_proposal_votes.clear();
+        }
+        if flags.contains(TableFlags::DELEGATED_STAKING_ACTIVITIES) {
+            all_delegator_activities.clear();
+        }
+        if flags.contains(TableFlags::DELEGATOR_BALANCES) {
+            all_delegator_balances.clear();
+        }
+        if flags.contains(TableFlags::CURRENT_DELEGATOR_BALANCES) {
+            all_current_delegator_balances.clear();
+        }
+        if flags.contains(TableFlags::DELEGATED_STAKING_POOLS) {
+            all_delegator_pools.clear();
+        }
+        if flags.contains(TableFlags::DELEGATED_STAKING_POOL_BALANCES) {
+            all_delegator_pool_balances.clear();
+        }
+        if flags.contains(TableFlags::CURRENT_DELEGATED_STAKING_POOL_BALANCES) {
+            all_current_delegator_pool_balances.clear();
+        }
+        if flags.contains(TableFlags::CURRENT_DELEGATED_VOTER) {
+            all_current_delegated_voter.clear();
+        }
+
         let processing_duration_in_secs = processing_start.elapsed().as_secs_f64();
         let db_insertion_start = std::time::Instant::now();
 
@@ -630,7 +630,7 @@ impl ProcessorTrait for TokenV2Processor {
             mut collections_v2,
             raw_token_datas_v2,
             raw_token_ownerships_v2,
-            current_collections_v2,
+            mut current_collections_v2,
             raw_current_token_datas_v2,
             raw_current_deleted_token_datas_v2,
             raw_current_token_ownerships_v2,
@@ -646,12 +646,13 @@ impl ProcessorTrait for TokenV2Processor {
         )
         .await;
 
-        let postgres_current_token_claims: Vec<CurrentTokenPendingClaim> = raw_current_token_claims
-            .into_iter()
-            .map(CurrentTokenPendingClaim::from_raw)
-            .collect();
+        let mut postgres_current_token_claims: Vec<CurrentTokenPendingClaim> =
+            raw_current_token_claims
+                .into_iter()
+                .map(CurrentTokenPendingClaim::from_raw)
+                .collect();
 
-        let postgres_current_token_royalties_v1: Vec<CurrentTokenRoyaltyV1> =
+        let mut postgres_current_token_royalties_v1: Vec<CurrentTokenRoyaltyV1> =
             raw_current_token_royalties_v1
                 .into_iter()
                 .map(CurrentTokenRoyaltyV1::from_raw)
@@ -663,7 +664,7 @@ impl ProcessorTrait for TokenV2Processor {
                 .map(CurrentTokenV2Metadata::from_raw)
                 .collect();
 
-        let postgres_token_activities_v2: Vec<TokenActivityV2> = raw_token_activities_v2
+        let mut postgres_token_activities_v2: Vec<TokenActivityV2> = raw_token_activities_v2
             .into_iter()
             .map(TokenActivityV2::from_raw)
             .collect();
@@ -673,12 +674,13 @@ impl ProcessorTrait for TokenV2Processor {
             .map(TokenDataV2::from_raw)
             .collect();
 
-        let postgres_current_token_datas_v2: Vec<CurrentTokenDataV2> = raw_current_token_datas_v2
-            .into_iter()
-            .map(CurrentTokenDataV2::from_raw)
-            .collect();
+        let mut postgres_current_token_datas_v2: Vec<CurrentTokenDataV2> =
+            raw_current_token_datas_v2
+                .into_iter()
+                .map(CurrentTokenDataV2::from_raw)
+                .collect();
 
-        let postgres_current_deleted_token_datas_v2: Vec<CurrentTokenDataV2> =
+        let mut postgres_current_deleted_token_datas_v2: Vec<CurrentTokenDataV2> =
             raw_current_deleted_token_datas_v2
                 .into_iter()
                 .map(CurrentTokenDataV2::from_raw)
@@ -689,13 +691,13 @@ impl ProcessorTrait for TokenV2Processor {
             .map(TokenOwnershipV2::from_raw)
             .collect();
 
-        let postgres_current_token_ownerships_v2: Vec<CurrentTokenOwnershipV2> =
+        let mut postgres_current_token_ownerships_v2: Vec<CurrentTokenOwnershipV2> =
             raw_current_token_ownerships_v2
                 .into_iter()
                 .map(CurrentTokenOwnershipV2::from_raw)
                 .collect();
 
-        let postgres_current_deleted_token_ownerships_v2: Vec<CurrentTokenOwnershipV2> =
+        let mut postgres_current_deleted_token_ownerships_v2: Vec<CurrentTokenOwnershipV2> =
             raw_current_deleted_token_ownerships_v2
                 .into_iter()
                 .map(CurrentTokenOwnershipV2::from_raw)
@@ -722,6 +724,44 @@ impl ProcessorTrait for TokenV2Processor {
         {
             postgres_current_token_v2_metadata.clear();
         }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_COLLECTIONS_V2)
+        {
+            current_collections_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_TOKEN_DATAS_V2)
+        {
+            postgres_current_token_datas_v2.clear();
+            postgres_current_deleted_token_datas_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_TOKEN_OWNERSHIPS_V2)
+        {
+            postgres_current_token_ownerships_v2.clear();
+            postgres_current_deleted_token_ownerships_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::TOKEN_ACTIVITIES_V2)
+        {
+            postgres_token_activities_v2.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_TOKEN_ROYALTY_V1)
+        {
+            postgres_current_token_royalties_v1.clear();
+        }
+        if self
+            .deprecated_tables
+            .contains(TableFlags::CURRENT_TOKEN_PENDING_CLAIMS)
+        {
+            postgres_current_token_claims.clear();
+        }
 
         let tx_result = insert_to_db(
             self.get_pool(),
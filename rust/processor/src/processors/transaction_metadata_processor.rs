@@ -9,7 +9,10 @@ use crate::{
     },
     gap_detectors::ProcessingResult,
     schema,
-    utils::database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+    utils::{
+        database::{execute_in_chunks, get_config_table_chunk_size, ArcDbPool},
+        table_flags::TableFlags,
+    },
 };
 use ahash::AHashMap;
 use anyhow::bail;
@@ -22,13 +25,19 @@ use tracing::{error, warn};
 pub struct TransactionMetadataProcessor {
     connection_pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
+    deprecated_tables: TableFlags,
 }
 
 impl TransactionMetadataProcessor {
-    pub fn new(connection_pool: ArcDbPool, per_table_chunk_sizes: AHashMap<String, usize>) -> Self {
+    pub fn new(
+        connection_pool: ArcDbPool,
+        per_table_chunk_sizes: AHashMap<String, usize>,
+        deprecated_tables: TableFlags,
+    ) -> Self {
         Self {
             connection_pool,
             per_table_chunk_sizes,
+            deprecated_tables,
         }
     }
 }
@@ -183,6 +192,17 @@ impl ProcessorTrait for TransactionMetadataProcessor {
             }
         }
 
+        let flags = self.deprecated_tables;
+        if flags.contains(TableFlags::TRANSACTION_SIZE_INFO) {
+            transaction_sizes.clear();
+        }
+        if flags.contains(TableFlags::EVENT_SIZE_INFO) {
+            event_sizes.clear();
+        }
+        if flags.contains(TableFlags::WRITE_SET_SIZE) {
+            write_set_sizes.clear();
+        }
+
         let processing_duration_in_secs = processing_start.elapsed().as_secs_f64();
         let db_insertion_start = std::time::Instant::now();
 
@@ -239,6 +239,9 @@ pub fn user_transaction_parse(
     if deprecated_tables.contains(TableFlags::SIGNATURES) {
         signatures.clear();
     }
+    if deprecated_tables.contains(TableFlags::USER_TRANSACTIONS) {
+        user_transactions.clear();
+    }
 
     (user_transactions, signatures)
 }
@@ -0,0 +1,357 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Records the `TransactionsResponse`s received from the GRPC stream to disk, so a batch that
+//! broke a processor can be replayed later with [`crate::file_stream`].
+//!
+//! Each archive file is a gzip stream of length delimited `TransactionsResponse`s named
+//! `{first_version}-{last_version}.pb.gz`, with versions zero padded so the names sort in
+//! version order. The file being written is kept as `{first_version}.pb.gz.partial` until it is
+//! rotated, and is flushed after every response so it can be replayed even if the processor
+//! crashed. Once the archive grows past `max_total_size_bytes`, the oldest files are removed.
+
+use anyhow::{Context, Result};
+use aptos_protos::indexer::v1::TransactionsResponse;
+use flate2::{write::GzEncoder, Compression};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+pub const ARCHIVE_FILE_EXTENSION: &str = "pb.gz";
+const PARTIAL_FILE_EXTENSION: &str = "pb.gz.partial";
+/// Number of responses buffered for the writer thread before the fetcher waits on it
+const ARCHIVE_CHANNEL_SIZE: usize = 10;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamArchiveConfig {
+    /// Directory the archive files are written to
+    pub path: PathBuf,
+    /// Uncompressed bytes written to a file before it's rotated
+    #[serde(default = "StreamArchiveConfig::default_max_file_size_bytes")]
+    pub max_file_size_bytes: u64,
+    /// Compressed size of the whole archive before the oldest files are removed
+    #[serde(default = "StreamArchiveConfig::default_max_total_size_bytes")]
+    pub max_total_size_bytes: u64,
+}
+
+impl StreamArchiveConfig {
+    /// 256MB
+    pub const fn default_max_file_size_bytes() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    /// 10GB
+    pub const fn default_max_total_size_bytes() -> u64 {
+        10 * 1024 * 1024 * 1024
+    }
+}
+
+/// Handle to the thread writing the archive, so compression doesn't hold up the fetcher.
+pub struct StreamArchive {
+    sender: mpsc::Sender<TransactionsResponse>,
+    writer_thread: std::thread::JoinHandle<()>,
+}
+
+impl StreamArchive {
+    pub fn spawn(config: StreamArchiveConfig, processor_name: String) -> Result<Self> {
+        std::fs::create_dir_all(&config.path).with_context(|| {
+            format!(
+                "Failed to create archive directory {}",
+                config.path.display()
+            )
+        })?;
+        let (sender, mut receiver) = mpsc::channel::<TransactionsResponse>(ARCHIVE_CHANNEL_SIZE);
+        let writer_thread = std::thread::Builder::new()
+            .name("stream-archive".to_string())
+            .spawn(move || {
+                let mut writer = ArchiveWriter::new(config);
+                while let Some(response) = receiver.blocking_recv() {
+                    if let Err(e) = writer.write(&response) {
+                        error!(
+                            processor_name = processor_name,
+                            error = ?e,
+                            "[Parser] Error writing to the stream archive"
+                        );
+                    }
+                }
+                if let Err(e) = writer.rotate() {
+                    error!(
+                        processor_name = processor_name,
+                        error = ?e,
+                        "[Parser] Error closing the stream archive"
+                    );
+                }
+            })
+            .context("Failed to spawn the stream archive thread")?;
+        Ok(Self {
+            sender,
+            writer_thread,
+        })
+    }
+
+    pub async fn archive(&self, response: TransactionsResponse) {
+        if self.sender.send(response).await.is_err() {
+            error!("[Parser] Stream archive thread stopped, dropping response");
+        }
+    }
+
+    /// Writes out the pending responses and closes the current file.
+    pub async fn close(self) {
+        drop(self.sender);
+        let writer_thread = self.writer_thread;
+        if tokio::task::spawn_blocking(move || writer_thread.join())
+            .await
+            .is_err()
+        {
+            error!("[Parser] Stream archive thread panicked");
+        }
+    }
+}
+
+struct OpenArchiveFile {
+    path: PathBuf,
+    encoder: GzEncoder<BufWriter<File>>,
+    start_version: u64,
+    end_version: u64,
+    bytes_written: u64,
+}
+
+struct ArchiveWriter {
+    config: StreamArchiveConfig,
+    current: Option<OpenArchiveFile>,
+}
+
+impl ArchiveWriter {
+    fn new(config: StreamArchiveConfig) -> Self {
+        Self {
+            config,
+            current: None,
+        }
+    }
+
+    fn write(&mut self, response: &TransactionsResponse) -> Result<()> {
+        let (start_version, end_version) =
+            match (response.transactions.first(), response.transactions.last()) {
+                (Some(first), Some(last)) => (first.version, last.version),
+                _ => return Ok(()),
+            };
+        if self.current.is_none() {
+            let path = self
+                .config
+                .path
+                .join(format!("{:020}.{}", start_version, PARTIAL_FILE_EXTENSION));
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            self.current = Some(OpenArchiveFile {
+                path,
+                encoder: GzEncoder::new(BufWriter::new(file), Compression::default()),
+                start_version,
+                end_version,
+                bytes_written: 0,
+            });
+        }
+
+        let current = self.current.as_mut().unwrap();
+        let bytes = response.encode_length_delimited_to_vec();
+        current.encoder.write_all(&bytes)?;
+        current.encoder.flush()?;
+        current.end_version = end_version;
+        current.bytes_written += bytes.len() as u64;
+        if current.bytes_written >= self.config.max_file_size_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current file, if any, and applies the size cap.
+    fn rotate(&mut self) -> Result<()> {
+        let Some(current) = self.current.take() else {
+            return Ok(());
+        };
+        current.encoder.finish()?.flush()?;
+        let path = self.config.path.join(format!(
+            "{:020}-{:020}.{}",
+            current.start_version, current.end_version, ARCHIVE_FILE_EXTENSION
+        ));
+        std::fs::rename(&current.path, &path)
+            .with_context(|| format!("Failed to rename {}", current.path.display()))?;
+        info!(
+            file = path.display().to_string(),
+            start_version = current.start_version,
+            end_version = current.end_version,
+            "[Parser] Rotated stream archive file"
+        );
+        remove_oldest_files(&self.config.path, self.config.max_total_size_bytes)
+    }
+}
+
+fn remove_oldest_files(dir: &Path, max_total_size_bytes: u64) -> Result<()> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_archive_file(&path) {
+            let size = std::fs::metadata(&path)?.len();
+            files.push((path, size));
+        }
+    }
+    files.sort();
+
+    let mut total_size: u64 = files.iter().map(|(_, size)| size).sum();
+    for (path, size) in files {
+        if total_size <= max_total_size_bytes {
+            break;
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        info!(
+            file = path.display().to_string(),
+            "[Parser] Removed stream archive file over the size cap"
+        );
+        total_size -= size;
+    }
+    Ok(())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(&format!(".{}", extension)))
+}
+
+/// Whether `path` is a complete archive file.
+pub fn is_archive_file(path: &Path) -> bool {
+    has_extension(path, ARCHIVE_FILE_EXTENSION)
+}
+
+/// Whether `path` is a complete archive file, or one that was still being written.
+pub fn is_readable_archive_file(path: &Path) -> bool {
+    is_archive_file(path) || has_extension(path, PARTIAL_FILE_EXTENSION)
+}
+
+/// Whether the archive file at `path` may hold a version in `[start_version, end_version]`, going
+/// by its name. A file still being written is only named after its first version, and a file
+/// that isn't named like an archive file may hold anything.
+pub fn archive_file_overlaps(path: &Path, start_version: u64, end_version: u64) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let versions =
+        if let Some(versions) = name.strip_suffix(&format!(".{}", ARCHIVE_FILE_EXTENSION)) {
+            versions.split_once('-').and_then(|(first, last)| {
+                Some((first.parse::<u64>().ok()?, last.parse::<u64>().ok()?))
+            })
+        } else {
+            name.strip_suffix(&format!(".{}", PARTIAL_FILE_EXTENSION))
+                .and_then(|first| first.parse::<u64>().ok())
+                .map(|first| (first, u64::MAX))
+        };
+    versions.map_or(true, |(first_version, last_version)| {
+        first_version <= end_version && last_version >= start_version
+    })
+}
+
+/// Reads back the responses written to an archive file. A file that was cut off mid write,
+/// e.g. by a crash, yields the responses that were fully flushed.
+pub fn read_archive_file(path: &Path) -> Result<Vec<TransactionsResponse>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut bytes = vec![];
+    if let Err(e) = flate2::read::GzDecoder::new(file).read_to_end(&mut bytes) {
+        if e.kind() != ErrorKind::UnexpectedEof {
+            return Err(e.into());
+        }
+        warn!(
+            file = path.display().to_string(),
+            "[Parser] Archive file is truncated"
+        );
+    }
+
+    let mut buf = bytes.as_slice();
+    let mut responses = vec![];
+    while !buf.is_empty() {
+        match TransactionsResponse::decode_length_delimited(&mut buf) {
+            Ok(response) => responses.push(response),
+            Err(e) => {
+                warn!(
+                    file = path.display().to_string(),
+                    error = ?e,
+                    "[Parser] Ignoring incomplete response at the end of archive file"
+                );
+                break;
+            },
+        }
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_protos::transaction::v1::Transaction;
+
+    fn response(versions: std::ops::RangeInclusive<u64>) -> TransactionsResponse {
+        TransactionsResponse {
+            transactions: versions
+                .map(|version| Transaction {
+                    version,
+                    ..Default::default()
+                })
+                .collect(),
+            chain_id: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_archive_rotates_and_reads_back() {
+        let dir = std::env::temp_dir().join(format!("stream_archive_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut writer = ArchiveWriter::new(StreamArchiveConfig {
+            path: dir.clone(),
+            max_file_size_bytes: 1,
+            max_total_size_bytes: StreamArchiveConfig::default_max_total_size_bytes(),
+        });
+        writer.write(&response(0..=9)).unwrap();
+        writer.write(&response(10..=19)).unwrap();
+
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        let names = files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            "00000000000000000000-00000000000000000009.pb.gz",
+            "00000000000000000010-00000000000000000019.pb.gz",
+        ]);
+        let responses = read_archive_file(&files[1]).unwrap();
+        assert_eq!(responses, vec![response(10..=19)]);
+
+        assert!(!archive_file_overlaps(&files[0], 10, 19));
+        assert!(archive_file_overlaps(&files[1], 15, 25));
+        assert!(archive_file_overlaps(
+            Path::new("00000000000000000010.pb.gz.partial"),
+            100,
+            199
+        ));
+        assert!(!archive_file_overlaps(
+            Path::new("00000000000000000010.pb.gz.partial"),
+            0,
+            9
+        ));
+
+        remove_oldest_files(&dir, 0).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -1,9 +1,10 @@
 use aptos_protos::transaction::v1::{
     transaction::{TransactionType, TxnData},
     transaction_payload::Payload,
-    Transaction,
+    write_set_change::Change,
+    EntryFunctionId, Event, Transaction, UserTransactionRequest,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Allows filtering transactions based on various criteria
 /// The criteria are combined with `AND`
@@ -14,11 +15,196 @@ use serde::{Deserialize, Serialize};
 #[serde(default)]
 pub struct TransactionFilter {
     // Only allow transactions from these contract addresses
+    #[serde(deserialize_with = "deserialize_addresses")]
     focus_contract_addresses: Option<ahash::HashSet<String>>,
     // Skip transactions from these sender addresses
+    #[serde(deserialize_with = "deserialize_addresses")]
     skip_sender_addresses: Option<ahash::HashSet<String>>,
     // Skip all transactions that aren't user transactions
     focus_user_transactions: bool,
+    // Only allow transactions matching this predicate. Unlike the criteria above, it also
+    // applies to non-user transactions.
+    predicate: Option<TransactionPredicate>,
+}
+
+/// A composable condition on a transaction, e.g. in yaml:
+/// ```yaml
+/// predicate:
+///   any:
+///     - event_type_prefix: "0x1::coin::"
+///     - write_resource_type_prefix: "0xcafe::vault::Vault"
+///     - not:
+///         sender: "0x7"
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionPredicate {
+    /// Emits an event whose type starts with the prefix
+    EventTypePrefix(TypePrefix),
+    /// Writes a resource whose type starts with the prefix
+    WriteResourceTypePrefix(TypePrefix),
+    /// Calls an entry function whose `address::module::name` starts with the prefix
+    EntryFunctionPrefix(TypePrefix),
+    /// Is a user transaction sent by this address
+    Sender(#[serde(deserialize_with = "deserialize_address")] String),
+    /// Is a user transaction
+    UserTransaction,
+    Any(Vec<TransactionPredicate>),
+    All(Vec<TransactionPredicate>),
+    Not(Box<TransactionPredicate>),
+}
+
+/// A prefix of a Move type or function, e.g. `0x1::coin::` or `0x1::coin::transfer`. The
+/// leading address is normalized, so `0x1::coin` and `0x0000...0001::coin` are the same
+/// prefix. Nested type arguments are matched as written.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TypePrefix {
+    address: String,
+    rest: String,
+}
+
+impl TryFrom<String> for TypePrefix {
+    type Error = String;
+
+    fn try_from(prefix: String) -> Result<Self, Self::Error> {
+        let (address, rest) = prefix.split_once("::").unwrap_or((&prefix, ""));
+        if !address.starts_with("0x") {
+            return Err(format!("Type prefix {} must start with an address", prefix));
+        }
+        Ok(Self {
+            address: normalize_address(address),
+            rest: rest.to_string(),
+        })
+    }
+}
+
+impl From<TypePrefix> for String {
+    fn from(prefix: TypePrefix) -> Self {
+        if prefix.rest.is_empty() {
+            format!("0x{}", prefix.address)
+        } else {
+            format!("0x{}::{}", prefix.address, prefix.rest)
+        }
+    }
+}
+
+impl TypePrefix {
+    fn matches_type(&self, type_str: &str) -> bool {
+        match type_str.split_once("::") {
+            Some((address, rest)) => {
+                trim_address(address) == self.address && rest.starts_with(&self.rest)
+            },
+            None => false,
+        }
+    }
+
+    fn matches_function(&self, function: &EntryFunctionId) -> bool {
+        let Some(module) = function.module.as_ref() else {
+            return false;
+        };
+        if trim_address(&module.address) != self.address {
+            return false;
+        }
+        // Compare against `module::name` without allocating it
+        match self.rest.strip_prefix(module.name.as_str()) {
+            Some("") => true,
+            Some(rest) => match rest.strip_prefix("::") {
+                Some(name_prefix) => function.name.starts_with(name_prefix),
+                None => "::".starts_with(rest),
+            },
+            None => module.name.starts_with(&self.rest),
+        }
+    }
+}
+
+/// Address without the `0x` and leading zeros, so short and long forms compare equal
+fn trim_address(address: &str) -> &str {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    let trimmed = hex.trim_start_matches('0');
+    if trimmed.is_empty() && !hex.is_empty() {
+        "0"
+    } else {
+        trimmed
+    }
+}
+
+fn normalize_address(address: &str) -> String {
+    trim_address(address).to_lowercase()
+}
+
+fn deserialize_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|address| normalize_address(&address))
+}
+
+fn deserialize_addresses<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ahash::HashSet<String>>, D::Error> {
+    Ok(Option::<ahash::HashSet<String>>::deserialize(deserializer)?
+        .map(|addresses| addresses.iter().map(|a| normalize_address(a)).collect()))
+}
+
+fn user_request(transaction: &Transaction) -> Option<&UserTransactionRequest> {
+    match transaction.txn_data.as_ref() {
+        Some(TxnData::User(user_transaction)) => user_transaction.request.as_ref(),
+        _ => None,
+    }
+}
+
+fn events(transaction: &Transaction) -> &[Event] {
+    match transaction.txn_data.as_ref() {
+        Some(TxnData::BlockMetadata(tx_inner)) => &tx_inner.events,
+        Some(TxnData::Genesis(tx_inner)) => &tx_inner.events,
+        Some(TxnData::User(tx_inner)) => &tx_inner.events,
+        Some(TxnData::Validator(tx_inner)) => &tx_inner.events,
+        _ => &[],
+    }
+}
+
+fn entry_function(request: &UserTransactionRequest) -> Option<&EntryFunctionId> {
+    match request.payload.as_ref()?.payload.as_ref()? {
+        Payload::EntryFunctionPayload(efp) => efp.function.as_ref(),
+        _ => None,
+    }
+}
+
+impl TransactionPredicate {
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        match self {
+            TransactionPredicate::EventTypePrefix(prefix) => events(transaction)
+                .iter()
+                .any(|event| prefix.matches_type(&event.type_str)),
+            TransactionPredicate::WriteResourceTypePrefix(prefix) => transaction
+                .info
+                .as_ref()
+                .map(|info| {
+                    info.changes
+                        .iter()
+                        .any(|change| match change.change.as_ref() {
+                            Some(Change::WriteResource(resource)) => {
+                                prefix.matches_type(&resource.type_str)
+                            },
+                            _ => false,
+                        })
+                })
+                .unwrap_or(false),
+            TransactionPredicate::EntryFunctionPrefix(prefix) => user_request(transaction)
+                .and_then(entry_function)
+                .is_some_and(|function| prefix.matches_function(function)),
+            TransactionPredicate::Sender(sender) => user_request(transaction)
+                .is_some_and(|request| trim_address(&request.sender) == sender.as_str()),
+            TransactionPredicate::UserTransaction => {
+                transaction.r#type == TransactionType::User as i32
+            },
+            TransactionPredicate::Any(predicates) => predicates
+                .iter()
+                .any(|predicate| predicate.matches(transaction)),
+            TransactionPredicate::All(predicates) => predicates
+                .iter()
+                .all(|predicate| predicate.matches(transaction)),
+            TransactionPredicate::Not(predicate) => !predicate.matches(transaction),
+        }
+    }
 }
 
 impl TransactionFilter {
@@ -26,17 +212,27 @@ impl TransactionFilter {
         focus_contract_addresses: Option<ahash::HashSet<String>>,
         skip_sender_addresses: Option<ahash::HashSet<String>>,
         focus_user_transactions: bool,
+        predicate: Option<TransactionPredicate>,
     ) -> Self {
-        // TODO: normalize addresses
+        let normalize = |addresses: ahash::HashSet<String>| {
+            addresses.iter().map(|a| normalize_address(a)).collect()
+        };
         Self {
-            focus_contract_addresses,
-            skip_sender_addresses,
+            focus_contract_addresses: focus_contract_addresses.map(normalize),
+            skip_sender_addresses: skip_sender_addresses.map(normalize),
             focus_user_transactions,
+            predicate,
         }
     }
 
     /// Returns true if the transaction should be included
     pub fn include(&self, transaction: &Transaction) -> bool {
+        if let Some(predicate) = &self.predicate {
+            if !predicate.matches(transaction) {
+                return false;
+            }
+        }
+
         // If we're only focusing on user transactions, skip if it's not a user transaction
 
         let is_user_txn = transaction.r#type == TransactionType::User as i32;
@@ -49,27 +245,19 @@ impl TransactionFilter {
             return true;
         }
 
-        if let Some(TxnData::User(user_transaction)) = transaction.txn_data.as_ref() {
-            if let Some(utr) = user_transaction.request.as_ref() {
-                // Skip if sender is in the skip list
-                if let Some(skip_sender_addresses) = &self.skip_sender_addresses {
-                    if skip_sender_addresses.contains(&utr.sender) {
-                        return false;
-                    }
+        if let Some(utr) = user_request(transaction) {
+            // Skip if sender is in the skip list
+            if let Some(skip_sender_addresses) = &self.skip_sender_addresses {
+                if skip_sender_addresses.contains(trim_address(&utr.sender)) {
+                    return false;
                 }
+            }
 
-                if let Some(focus_contract_addresses) = &self.focus_contract_addresses {
-                    // Skip if focus contract addresses are set and the transaction isn't in the list
-                    if let Some(payload) = utr.payload.as_ref() {
-                        if let Some(Payload::EntryFunctionPayload(efp)) = payload.payload.as_ref() {
-                            if let Some(function) = efp.function.as_ref() {
-                                if let Some(module) = function.module.as_ref() {
-                                    if !focus_contract_addresses.contains(&module.address) {
-                                        return false;
-                                    }
-                                }
-                            }
-                        }
+            if let Some(focus_contract_addresses) = &self.focus_contract_addresses {
+                // Skip if focus contract addresses are set and the transaction isn't in the list
+                if let Some(module) = entry_function(utr).and_then(|f| f.module.as_ref()) {
+                    if !focus_contract_addresses.contains(trim_address(&module.address)) {
+                        return false;
                     }
                 }
             }
@@ -78,3 +266,78 @@ impl TransactionFilter {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_protos::transaction::v1::{
+        EntryFunctionPayload, MoveModuleId, TransactionPayload, UserTransaction,
+    };
+
+    fn user_transaction(sender: &str, function: &str, event_types: &[&str]) -> Transaction {
+        let mut parts = function.split("::");
+        let (address, module, name) = (parts.next(), parts.next(), parts.next());
+        Transaction {
+            r#type: TransactionType::User as i32,
+            txn_data: Some(TxnData::User(UserTransaction {
+                request: Some(UserTransactionRequest {
+                    sender: sender.to_string(),
+                    payload: Some(TransactionPayload {
+                        payload: Some(Payload::EntryFunctionPayload(EntryFunctionPayload {
+                            function: Some(EntryFunctionId {
+                                module: Some(MoveModuleId {
+                                    address: address.unwrap().to_string(),
+                                    name: module.unwrap().to_string(),
+                                }),
+                                name: name.unwrap().to_string(),
+                            }),
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                events: event_types
+                    .iter()
+                    .map(|type_str| Event {
+                        type_str: type_str.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_predicates_normalize_addresses_and_compose() {
+        let filter: TransactionFilter = serde_json::from_value(serde_json::json!({
+            "skip_sender_addresses": ["0x0007"],
+            "predicate": {
+                "any": [
+                    { "event_type_prefix": "0x00cafe::market::" },
+                    { "all": [
+                        { "entry_function_prefix": "0x1::coin::trans" },
+                        { "not": {
+                            "sender": "0x0000000000000000000000000000000000000000000000000000000000000008"
+                        } },
+                    ] },
+                ],
+            },
+        }))
+        .unwrap();
+
+        let keeper = user_transaction("0x9", "0x1::keeper::run", &["0xcafe::market::TradeEvent"]);
+        let transfer = user_transaction("0x9", "0x1::coin::transfer", &[]);
+        let transfer_from_8 = user_transaction("0x8", "0x1::coin::transfer", &[]);
+        let skipped_sender =
+            user_transaction("0x7", "0x1::keeper::run", &["0xcafe::market::TradeEvent"]);
+        let unrelated = user_transaction("0x9", "0x1::coin::register", &["0x1::coin::Event"]);
+
+        assert!(filter.include(&keeper));
+        assert!(filter.include(&transfer));
+        assert!(!filter.include(&transfer_from_8));
+        assert!(!filter.include(&skipped_sender));
+        assert!(!filter.include(&unrelated));
+    }
+}
@@ -0,0 +1,524 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bulk loading with Postgres binary `COPY`, for backfills of high volume tables.
+//!
+//! Rows are copied into a temporary staging table and merged into the real table with a single
+//! `INSERT ... SELECT`. The merge reuses the conflict clause of the table's usual insert query,
+//! so rows end up the same as with [`execute_in_chunks`], without being bound by the bind
+//! parameter limit. Inserts leaving a column to its `DEFAULT` are done in chunks as usual, as
+//! `COPY` has no equivalent.
+//!
+//! In a [`BatchTransaction`], the rows are copied into an unlogged staging table on the
+//! loader's own connection instead, and merged on the transaction's connection, so the batch
+//! still commits or rolls back as a whole.
+//!
+//! [`BatchTransaction`]: crate::utils::database::BatchTransaction
+//! [`execute_in_chunks`]: crate::utils::database::execute_in_chunks
+
+use crate::utils::database::{clean_data_for_db, connect_client, Backend, MyDbConnection};
+use anyhow::{Context, Result};
+use diesel::{
+    pg::{Pg, PgMetadataLookup, PgQueryBuilder, PgTypeMetadata},
+    query_builder::{bind_collector::RawBytesBindCollector, QueryBuilder, QueryFragment},
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use futures_util::SinkExt;
+use once_cell::sync::OnceCell;
+use prost::bytes::{BufMut, Bytes, BytesMut};
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, Transaction};
+
+static BULK_LOADER: OnceCell<BulkLoader> = OnceCell::new();
+// Staging tables created so far, to name the next one
+static STAGING_TABLE_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Age after which a staging table is left over from a process that died before dropping it.
+/// Batch transactions, which keep their staging tables until they end, are much shorter.
+const STALE_STAGING_TABLE_SECS: i64 = 3600;
+
+/// Header of the binary `COPY` format: signature, flags and header extension length
+const COPY_BINARY_HEADER: &[u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+
+pub struct BulkLoader {
+    database_url: String,
+    tables: HashSet<String>,
+    // Idle connections, reused by later loads
+    clients: Mutex<Vec<Client>>,
+}
+
+impl BulkLoader {
+    fn new(database_url: &str, tables: HashSet<String>) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            tables,
+            clients: Mutex::new(vec![]),
+        }
+    }
+
+    /// Bulk loads `tables`, given by name, e.g. `events`, whenever they're written through
+    /// `execute_in_chunks`. Can only be called once.
+    pub async fn init(database_url: &str, tables: HashSet<String>) -> Result<()> {
+        let loader = Self::new(database_url, tables);
+        loader.drop_stale_staging_tables().await?;
+        BULK_LOADER
+            .set(loader)
+            .map_err(|_| anyhow::anyhow!("Bulk loader is already initialized"))
+    }
+
+    /// Drops the unlogged staging tables of processes that died before dropping them. Tables
+    /// of other processes that may still be in use are kept.
+    async fn drop_stale_staging_tables(&self) -> Result<()> {
+        let client = self.client().await?;
+        let result = async {
+            let rows = client
+                .query(
+                    "SELECT relname::text FROM pg_class WHERE relkind = 'r' \
+                     AND relpersistence = 'u' AND relname LIKE 'bulk\\_load\\_%' \
+                     AND relnamespace = current_schema()::regnamespace",
+                    &[],
+                )
+                .await?;
+            let stale_before =
+                chrono::Utc::now().timestamp_micros() - STALE_STAGING_TABLE_SECS * 1_000_000;
+            for row in rows {
+                let staging_table: String = row.get(0);
+                if staging_table_created_at(&staging_table)
+                    .is_some_and(|created_at| created_at < stale_before)
+                {
+                    client
+                        .batch_execute(&format!("DROP TABLE IF EXISTS \"{}\"", staging_table))
+                        .await?;
+                    tracing::info!(
+                        staging_table = staging_table.as_str(),
+                        "Dropped stale bulk load staging table"
+                    );
+                }
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        self.release(client).await;
+        result.context("Failed to drop stale bulk load staging tables")
+    }
+
+    async fn client(&self) -> Result<Client> {
+        if let Some(client) = self.clients.lock().await.pop() {
+            if !client.is_closed() {
+                return Ok(client);
+            }
+        }
+        connect_client(&self.database_url)
+            .await
+            .context("Failed to connect bulk loader")
+    }
+
+    /// Returns `client` to the idle connections, unless it's closed.
+    async fn release(&self, client: Client) {
+        if !client.is_closed() {
+            self.clients.lock().await.push(client);
+        }
+    }
+
+    async fn copy_in(&self, statement: &InsertStatement, data: Bytes) -> Result<()> {
+        let mut client = self.client().await?;
+        let result = async {
+            let transaction = client.transaction().await?;
+            let staging_table = format!("\"bulk_load_{}\"", statement.table);
+            transaction
+                .batch_execute(&format!(
+                    "CREATE TEMP TABLE {} (LIKE \"{}\" INCLUDING DEFAULTS) ON COMMIT DROP",
+                    staging_table, statement.table
+                ))
+                .await?;
+            copy_rows(&transaction, &staging_table, statement, data).await?;
+            transaction
+                .batch_execute(&statement.merge_sql(&staging_table))
+                .await?;
+            transaction.commit().await?;
+            anyhow::Ok(())
+        }
+        .await;
+        self.release(client).await;
+        result
+    }
+
+    /// Copies into a staging table, then merges it on `conn`, within the transaction it's in.
+    async fn copy_in_transaction(
+        &'static self,
+        conn: &mut MyDbConnection,
+        statement: &InsertStatement,
+        data: Bytes,
+    ) -> Result<()> {
+        let staging_table = self.stage(statement, data).await?;
+        let merge_sql = statement.merge_sql(&staging_table);
+        // In a savepoint, so the transaction survives a failed merge and it can be retried
+        let result = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                async move { diesel::sql_query(merge_sql).execute(conn).await }.scope_boxed()
+            })
+            .await;
+        self.drop_staging_table(staging_table);
+        result?;
+        Ok(())
+    }
+
+    /// Copies `data` into a new unlogged staging table and returns its name. The table is
+    /// committed, so the batch transaction's connection can read it.
+    async fn stage(&self, statement: &InsertStatement, data: Bytes) -> Result<String> {
+        // Unique across processes too, as they share the database
+        let staging_table = format!(
+            "\"bulk_load_{}_{}_{}\"",
+            chrono::Utc::now().timestamp_micros(),
+            std::process::id(),
+            STAGING_TABLE_COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut client = self.client().await?;
+        let result = async {
+            let transaction = client.transaction().await?;
+            transaction
+                .batch_execute(&format!(
+                    "CREATE UNLOGGED TABLE {} (LIKE \"{}\" INCLUDING DEFAULTS)",
+                    staging_table, statement.table
+                ))
+                .await?;
+            copy_rows(&transaction, &staging_table, statement, data).await?;
+            transaction.commit().await?;
+            anyhow::Ok(())
+        }
+        .await;
+        self.release(client).await;
+        result.map(|_| staging_table)
+    }
+
+    /// Drops `staging_table` in the background. The drop waits for the lock the batch
+    /// transaction took by merging it, so it only goes through once that transaction ends.
+    fn drop_staging_table(&'static self, staging_table: String) {
+        tokio::spawn(async move {
+            let result = async {
+                let client = self.client().await?;
+                let result = client
+                    .batch_execute(&format!("DROP TABLE IF EXISTS {}", staging_table))
+                    .await;
+                self.release(client).await;
+                anyhow::Ok(result?)
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!(
+                    staging_table = staging_table.as_str(),
+                    error = ?e,
+                    "Failed to drop bulk load staging table"
+                );
+            }
+        });
+    }
+}
+
+/// Creation time, in microseconds, in the name of a staging table made by [`BulkLoader::stage`]
+fn staging_table_created_at(staging_table: &str) -> Option<i64> {
+    staging_table
+        .strip_prefix("bulk_load_")?
+        .split('_')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Copies `data` into `staging_table` with binary `COPY`.
+async fn copy_rows(
+    transaction: &Transaction<'_>,
+    staging_table: &str,
+    statement: &InsertStatement,
+    data: Bytes,
+) -> Result<()> {
+    let sink = transaction
+        .copy_in(&format!(
+            "COPY {} ({}) FROM STDIN (FORMAT binary)",
+            staging_table, statement.columns
+        ))
+        .await?;
+    futures_util::pin_mut!(sink);
+    sink.send(data).await?;
+    sink.as_mut().finish().await?;
+    Ok(())
+}
+
+/// Copies `items_to_insert` into their table if it's bulk loaded, returning whether it was.
+/// With `conn`, the connection of a batch transaction, they're merged in that transaction.
+/// Like `execute_in_chunks`, a failed load is retried once with null bytes removed.
+pub async fn try_copy_in<U, T>(
+    build_query: fn(Vec<T>) -> (U, Option<&'static str>),
+    items_to_insert: &[T],
+    conn: Option<&mut MyDbConnection>,
+) -> Result<bool>
+where
+    U: QueryFragment<Backend>,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone,
+{
+    match BULK_LOADER.get() {
+        Some(loader) => copy_in_with(loader, build_query, items_to_insert, conn).await,
+        None => Ok(false),
+    }
+}
+
+async fn copy_in_with<U, T>(
+    loader: &'static BulkLoader,
+    build_query: fn(Vec<T>) -> (U, Option<&'static str>),
+    items_to_insert: &[T],
+    mut conn: Option<&mut MyDbConnection>,
+) -> Result<bool>
+where
+    U: QueryFragment<Backend>,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone,
+{
+    let Some(first_item) = items_to_insert.first() else {
+        return Ok(false);
+    };
+    let (query, _) = build_query(vec![first_item.clone()]);
+    let Ok(sql) = to_sql(&query) else {
+        return Ok(false);
+    };
+    let Some(table) = InsertStatement::table(&sql).filter(|table| loader.tables.contains(*table))
+    else {
+        return Ok(false);
+    };
+    let table = table.to_string();
+
+    // Parsed from all the rows, as any of them may leave a column to its default
+    let (query, additional_where_clause) = build_query(items_to_insert.to_vec());
+    let mut statement = match to_sql(&query).and_then(|sql| InsertStatement::parse(&sql)) {
+        Ok(statement) => statement,
+        Err(e) => {
+            tracing::warn!(
+                table = table.as_str(),
+                error = ?e,
+                "Can't bulk load, inserting in chunks instead"
+            );
+            return Ok(false);
+        },
+    };
+    statement.additional_where_clause = additional_where_clause;
+
+    if let Err(e) = copy_query(loader, &statement, &query, conn.as_deref_mut()).await {
+        tracing::warn!(
+            table = table.as_str(),
+            error = ?e,
+            "Bulk load failed, retrying with cleaned data"
+        );
+        // Removing null bytes leaves the statement as it is, only the binds change
+        let (query, _) = build_query(clean_data_for_db(items_to_insert.to_vec(), true));
+        copy_query(loader, &statement, &query, conn).await?;
+    }
+    Ok(true)
+}
+
+async fn copy_query<U>(
+    loader: &'static BulkLoader,
+    statement: &InsertStatement,
+    query: &U,
+    conn: Option<&mut MyDbConnection>,
+) -> Result<()>
+where
+    U: QueryFragment<Backend>,
+{
+    let data = statement.copy_data(&collect_binds(query)?)?;
+    match conn {
+        Some(conn) => loader.copy_in_transaction(conn, statement, data).await,
+        None => loader.copy_in(statement, data).await,
+    }
+}
+
+fn to_sql<U: QueryFragment<Backend>>(query: &U) -> Result<String> {
+    let mut query_builder = PgQueryBuilder::default();
+    query.to_sql(&mut query_builder, &Pg)?;
+    Ok(query_builder.finish())
+}
+
+/// Binds of `query` in the binary format, which is also the one `COPY` expects
+fn collect_binds<U: QueryFragment<Backend>>(query: &U) -> Result<Vec<Option<Vec<u8>>>> {
+    let mut bind_collector = RawBytesBindCollector::<Pg>::new();
+    query.collect_binds(&mut bind_collector, &mut NoMetadataLookup, &Pg)?;
+    Ok(bind_collector.binds)
+}
+
+/// Type oids aren't needed, the staging table's columns give the types. Values of custom types
+/// are serialized the same way whatever their oid.
+struct NoMetadataLookup;
+
+impl PgMetadataLookup for NoMetadataLookup {
+    fn lookup_type(&mut self, _type_name: &str, _schema: Option<&str>) -> PgTypeMetadata {
+        PgTypeMetadata::new(0, 0)
+    }
+}
+
+/// An `INSERT INTO table (columns) VALUES (...), ... [ON CONFLICT ...]` statement built by
+/// diesel, split up so its values can be copied and its conflict clause reused by the merge.
+#[derive(Debug, PartialEq)]
+struct InsertStatement {
+    table: String,
+    columns: String,
+    num_columns: usize,
+    // Index of the bind in each cell, row after row
+    values: Vec<usize>,
+    on_conflict: String,
+    additional_where_clause: Option<&'static str>,
+}
+
+impl InsertStatement {
+    /// Table of an `INSERT` statement, without parsing the rest of it
+    fn table(sql: &str) -> Option<&str> {
+        let (table, _) = sql.strip_prefix("INSERT INTO ")?.split_once(" (")?;
+        Some(table.trim_matches('"'))
+    }
+
+    fn parse(sql: &str) -> Result<Self> {
+        let unexpected = || format!("Can't bulk load query {}", sql);
+        let rest = sql.strip_prefix("INSERT INTO ").with_context(unexpected)?;
+        let (table, rest) = rest.split_once(" (").with_context(unexpected)?;
+        let (columns, rest) = rest.split_once(") VALUES ").with_context(unexpected)?;
+        let (values, on_conflict) = rest.split_at(rest.find(" ON CONFLICT").unwrap_or(rest.len()));
+        let values = values
+            .split(|c: char| c == '(' || c == ')' || c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                // COPY has no equivalent of DEFAULT, and copying null instead would break
+                // columns that aren't nullable
+                anyhow::ensure!(value != "DEFAULT", "Can't bulk load DEFAULT values");
+                value
+                    .strip_prefix('$')
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .map(|index| index - 1)
+                    .with_context(unexpected)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let num_columns = columns.split(", ").count();
+        anyhow::ensure!(values.len() % num_columns == 0, unexpected());
+        Ok(Self {
+            table: table.trim_matches('"').to_string(),
+            columns: columns.to_string(),
+            num_columns,
+            values,
+            on_conflict: on_conflict.to_string(),
+            additional_where_clause: None,
+        })
+    }
+
+    /// Rows in the binary `COPY` format
+    fn copy_data(&self, binds: &[Option<Vec<u8>>]) -> Result<Bytes> {
+        let mut data = BytesMut::new();
+        data.put_slice(COPY_BINARY_HEADER);
+        for row in self.values.chunks(self.num_columns) {
+            data.put_i16(self.num_columns as i16);
+            for value in row {
+                let bind = binds
+                    .get(*value)
+                    .with_context(|| format!("Missing bind ${}", value + 1))?;
+                match bind {
+                    Some(bytes) => {
+                        data.put_i32(bytes.len() as i32);
+                        data.put_slice(bytes);
+                    },
+                    None => data.put_i32(-1),
+                }
+            }
+        }
+        // File trailer
+        data.put_i16(-1);
+        Ok(data.freeze())
+    }
+
+    fn merge_sql(&self, staging_table: &str) -> String {
+        // Same as execute_with_better_error, a clause already filtering the conflict wins
+        let where_clause = self
+            .additional_where_clause
+            .filter(|_| !self.on_conflict.to_lowercase().contains("where"))
+            .unwrap_or_default();
+        format!(
+            "INSERT INTO \"{}\" ({}) SELECT {} FROM {}{} {}",
+            self.table, self.columns, self.columns, staging_table, self.on_conflict, where_clause
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_copy_insert_statement() {
+        let mut statement = InsertStatement::parse(
+            "INSERT INTO \"events\" (\"a\", \"b\") VALUES ($1, $2), ($3, $4) ON CONFLICT (\"a\") DO NOTHING",
+        )
+        .unwrap();
+        assert_eq!(statement.table, "events");
+        assert_eq!(statement.values, vec![0, 1, 2, 3]);
+        statement.additional_where_clause = Some(" WHERE events.a <= excluded.a ");
+        assert_eq!(
+            statement.merge_sql("\"bulk_load_events\""),
+            "INSERT INTO \"events\" (\"a\", \"b\") SELECT \"a\", \"b\" FROM \"bulk_load_events\" ON CONFLICT (\"a\") DO NOTHING  WHERE events.a <= excluded.a "
+        );
+
+        let data = statement
+            .copy_data(&[Some(vec![1]), None, Some(vec![2, 3]), None])
+            .unwrap();
+        let mut expected = COPY_BINARY_HEADER.to_vec();
+        expected.extend_from_slice(&[0, 2, 0, 0, 0, 1, 1, 0xFF, 0xFF, 0xFF, 0xFF]);
+        expected.extend_from_slice(&[0, 2, 0, 0, 0, 2, 2, 3, 0xFF, 0xFF, 0xFF, 0xFF]);
+        expected.extend_from_slice(&[0xFF, 0xFF]);
+        assert_eq!(data.as_ref(), expected.as_slice());
+
+        // Columns left to their default may not be nullable, so they aren't copied as null
+        assert!(InsertStatement::parse(
+            "INSERT INTO \"events\" (\"a\", \"b\") VALUES ($1, $2), ($3, DEFAULT)"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_staging_table_created_at() {
+        assert_eq!(
+            staging_table_created_at("bulk_load_1700000000000000_42_7"),
+            Some(1_700_000_000_000_000)
+        );
+        assert_eq!(staging_table_created_at("bulk_load_events"), None);
+        assert_eq!(staging_table_created_at("events"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unparsable_query_is_not_bulk_loaded() {
+        let loader = Box::leak(Box::new(BulkLoader::new(
+            "postgresql://localhost/unused",
+            HashSet::from(["events".to_string()]),
+        )));
+        // Falls back to the usual inserts without connecting
+        let build_query: fn(Vec<i64>) -> (diesel::query_builder::SqlQuery, Option<&'static str>) =
+            |_| {
+                (
+                    diesel::sql_query("INSERT INTO \"events\" (\"a\") SELECT 1"),
+                    None,
+                )
+            };
+        assert!(!copy_in_with(loader, build_query, &[1], None).await.unwrap());
+        let build_query: fn(Vec<i64>) -> (diesel::query_builder::SqlQuery, Option<&'static str>) =
+            |_| (diesel::sql_query("DELETE FROM \"events\""), None);
+        assert!(!copy_in_with(loader, build_query, &[1], None).await.unwrap());
+        // Any row leaving a column to its default is inserted in chunks
+        let build_query: fn(Vec<i64>) -> (diesel::query_builder::SqlQuery, Option<&'static str>) =
+            |items| {
+                let sql = if items.len() == 1 {
+                    "INSERT INTO \"events\" (\"a\") VALUES ($1)"
+                } else {
+                    "INSERT INTO \"events\" (\"a\") VALUES ($1), (DEFAULT)"
+                };
+                (diesel::sql_query(sql), None)
+            };
+        assert!(!copy_in_with(loader, build_query, &[1, 2], None)
+            .await
+            .unwrap());
+    }
+}
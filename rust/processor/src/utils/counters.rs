@@ -3,9 +3,11 @@
 
 use once_cell::sync::Lazy;
 use prometheus::{
-    register_gauge_vec, register_histogram_vec, register_int_counter, register_int_counter_vec,
-    register_int_gauge_vec, GaugeVec, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+    core::Collector, register_gauge_vec, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge_vec, GaugeVec, HistogramVec, IntCounter,
+    IntCounterVec, IntGaugeVec,
 };
+use std::collections::BTreeMap;
 
 pub enum ProcessorStep {
     ReceivedTxnsFromGrpc,
@@ -89,6 +91,29 @@ pub static PROCESSOR_SUCCESSES_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Number of rows sent to the db, by model
+pub static DB_ROWS_WRITTEN_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_db_rows_written_count",
+        "Number of rows sent to the db, by model",
+        &["model"]
+    )
+    .unwrap()
+});
+
+/// Rows sent to the db since the process started, by model
+pub fn db_rows_written_by_model() -> BTreeMap<String, u64> {
+    DB_ROWS_WRITTEN_COUNT
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            let model = metric.get_label().first()?.get_value().to_string();
+            Some((model, metric.get_counter().get_value() as u64))
+        })
+        .collect()
+}
+
 /// Number of times the connection pool has timed out when trying to get a connection
 pub static UNABLE_TO_GET_CONNECTION_COUNT: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
@@ -187,6 +212,26 @@ pub static FETCHER_THREAD_CHANNEL_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Number of processing tasks allowed to take batches, with adaptive concurrency
+pub static PROCESSOR_ACTIVE_TASKS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_processor_active_tasks",
+        "Number of processing tasks allowed to take batches",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Maximum number of transactions per batch sent to the processing tasks, with adaptive concurrency
+pub static PB_CHANNEL_TXN_CHUNK_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_processor_pb_channel_txn_chunk_size",
+        "Maximum number of transactions per batch sent to the processing tasks",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
 /// Overall processing time for a single batch of transactions (per task)
 pub static SINGLE_BATCH_PROCESSING_TIME_IN_SECS: Lazy<HistogramVec> = Lazy::new(|| {
     register_histogram_vec!(
@@ -1,7 +1,7 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::utils::util::remove_null_bytes;
+use crate::utils::{bulk_load, counters::DB_ROWS_WRITTEN_COUNT, util::remove_null_bytes};
 use ahash::AHashMap;
 use diesel::{
     query_builder::{AstPass, Query, QueryFragment},
@@ -12,11 +12,13 @@ use diesel_async::{
         bb8::{Pool, PooledConnection},
         AsyncDieselConnectionManager, ManagerConfig, PoolError,
     },
-    AsyncPgConnection, RunQueryDsl,
+    scoped_futures::{ScopedBoxFuture, ScopedFutureExt},
+    AnsiTransactionManager, AsyncConnection, AsyncPgConnection, RunQueryDsl, TransactionManager,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use futures_util::{future::BoxFuture, FutureExt};
-use std::sync::Arc;
+use std::{future::Future, sync::Arc};
+use tokio::sync::{Mutex, MutexGuard};
 
 pub type Backend = diesel::pg::Pg;
 
@@ -29,6 +31,61 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/postgres/mi
 
 pub const DEFAULT_MAX_POOL_SIZE: u32 = 150;
 
+tokio::task_local! {
+    /// Connection of the [`BatchTransaction`] the current task writes in, if any
+    static BATCH_TRANSACTION: Arc<Mutex<DbPoolConnection<'static>>>;
+}
+
+/// A transaction that every write made through [`execute_in_chunks`] and
+/// [`execute_with_better_error`] joins while running in [`BatchTransaction::scope`], so all
+/// the tables written for a batch commit together. Chunks are then written one after the other
+/// on the transaction's connection instead of concurrently on the pool.
+///
+/// Dropping it without committing returns the connection with the transaction still open,
+/// which the pool discards as broken, so nothing is committed.
+pub struct BatchTransaction {
+    conn: Arc<Mutex<DbPoolConnection<'static>>>,
+}
+
+impl BatchTransaction {
+    pub async fn begin(pool: &ArcDbPool) -> anyhow::Result<Self> {
+        let mut conn = pool.get_owned().await?;
+        <AnsiTransactionManager as TransactionManager<MyDbConnection>>::begin_transaction(
+            &mut *conn,
+        )
+        .await?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` with its writes going to this transaction. Tasks spawned by `f` don't join it.
+    pub async fn scope<F: Future>(&self, f: F) -> F::Output {
+        BATCH_TRANSACTION.scope(self.conn.clone(), f).await
+    }
+
+    /// The transaction's connection, for queries that don't go through the helpers above
+    pub async fn conn(&self) -> MutexGuard<'_, DbPoolConnection<'static>> {
+        self.conn.lock().await
+    }
+
+    pub async fn commit(self) -> QueryResult<()> {
+        let mut conn = self.conn.lock().await;
+        <AnsiTransactionManager as TransactionManager<MyDbConnection>>::commit_transaction(
+            &mut **conn,
+        )
+        .await
+    }
+
+    pub async fn rollback(self) -> QueryResult<()> {
+        let mut conn = self.conn.lock().await;
+        <AnsiTransactionManager as TransactionManager<MyDbConnection>>::rollback_transaction(
+            &mut **conn,
+        )
+        .await
+    }
+}
+
 #[derive(QueryId)]
 /// Using this will append a where clause at the end of the string upsert function
 ///
@@ -58,34 +115,42 @@ pub fn clean_data_for_db<T: serde::Serialize + for<'de> serde::Deserialize<'de>>
 }
 
 fn establish_connection(database_url: &str) -> BoxFuture<ConnectionResult<AsyncPgConnection>> {
-    use native_tls::{Certificate, TlsConnector};
-    use postgres_native_tls::MakeTlsConnector;
-
     (async move {
-        let (url, cert_path) = parse_and_clean_db_url(database_url);
-        let cert = std::fs::read(cert_path.unwrap()).expect("Could not read certificate");
-
-        let cert = Certificate::from_pem(&cert).expect("Could not parse certificate");
-        let connector = TlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .add_root_certificate(cert)
-            .build()
-            .expect("Could not build TLS connector");
-        let connector = MakeTlsConnector::new(connector);
-
-        let (client, connection) = tokio_postgres::connect(&url, connector)
+        let client = connect_client(database_url)
             .await
             .expect("Could not connect to database");
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
         AsyncPgConnection::try_from(client).await
     })
     .boxed()
 }
 
+/// Connects a plain tokio-postgres client over TLS, with the url's `sslrootcert`.
+pub async fn connect_client(
+    database_url: &str,
+) -> Result<tokio_postgres::Client, tokio_postgres::Error> {
+    use native_tls::{Certificate, TlsConnector};
+    use postgres_native_tls::MakeTlsConnector;
+
+    let (url, cert_path) = parse_and_clean_db_url(database_url);
+    let cert = std::fs::read(cert_path.unwrap()).expect("Could not read certificate");
+
+    let cert = Certificate::from_pem(&cert).expect("Could not parse certificate");
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .add_root_certificate(cert)
+        .build()
+        .expect("Could not build TLS connector");
+    let connector = MakeTlsConnector::new(connector);
+
+    let (client, connection) = tokio_postgres::connect(&url, connector).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+    Ok(client)
+}
+
 fn parse_and_clean_db_url(url: &str) -> (String, Option<String>) {
     let mut db_url = url::Url::parse(url).expect("Could not parse database url");
     let mut cert_path = None;
@@ -133,6 +198,33 @@ where
     U: QueryFragment<Backend> + diesel::query_builder::QueryId + Send + 'static,
     T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone + Send + 'static,
 {
+    if let Ok(transaction) = BATCH_TRANSACTION.try_with(Arc::clone) {
+        let mut conn = transaction.lock().await;
+        if !bulk_load::try_copy_in(build_query, items_to_insert, Some(&mut **conn))
+            .await
+            .map_err(bulk_load_error)?
+        {
+            for chunk in items_to_insert.chunks(chunk_size) {
+                execute_or_retry_cleaned_in_transaction(&mut conn, build_query, chunk.to_vec())
+                    .await?;
+            }
+        }
+        DB_ROWS_WRITTEN_COUNT
+            .with_label_values(&[model_name::<T>()])
+            .inc_by(items_to_insert.len() as u64);
+        return Ok(());
+    }
+
+    if bulk_load::try_copy_in(build_query, items_to_insert, None)
+        .await
+        .map_err(bulk_load_error)?
+    {
+        DB_ROWS_WRITTEN_COUNT
+            .with_label_values(&[model_name::<T>()])
+            .inc_by(items_to_insert.len() as u64);
+        return Ok(());
+    }
+
     let tasks = items_to_insert
         .chunks(chunk_size)
         .map(|chunk| {
@@ -153,9 +245,25 @@ where
         res?
     }
 
+    DB_ROWS_WRITTEN_COUNT
+        .with_label_values(&[model_name::<T>()])
+        .inc_by(items_to_insert.len() as u64);
     Ok(())
 }
 
+fn bulk_load_error(e: anyhow::Error) -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::UnableToSendCommand,
+        Box::new(format!("{:?}", e)),
+    )
+}
+
+/// Name of the model type without its module path, e.g. `CurrentVault`.
+fn model_name<T>() -> &'static str {
+    let type_name = std::any::type_name::<T>();
+    type_name.rsplit("::").next().unwrap_or(type_name)
+}
+
 pub async fn execute_with_better_error<U>(
     pool: ArcDbPool,
     query: U,
@@ -164,6 +272,11 @@ pub async fn execute_with_better_error<U>(
 where
     U: QueryFragment<Backend> + diesel::query_builder::QueryId + Send,
 {
+    if let Ok(transaction) = BATCH_TRANSACTION.try_with(Arc::clone) {
+        let mut conn = transaction.lock().await;
+        return execute_with_better_error_conn(&mut conn, query, additional_where_clause).await;
+    }
+
     let original_query = diesel::debug_query::<Backend, _>(&query).to_string();
     // This is needed because if we don't insert any row, then diesel makes a call like this
     // SELECT 1 FROM TABLE WHERE 1=0
@@ -190,6 +303,29 @@ where
     res
 }
 
+/// Runs `f` in a transaction, nested in the current [`BatchTransaction`] if there is one.
+pub async fn run_in_transaction<'a, R, F>(pool: ArcDbPool, f: F) -> QueryResult<R>
+where
+    F: for<'r> FnOnce(&'r mut MyDbConnection) -> ScopedBoxFuture<'a, 'r, QueryResult<R>>
+        + Send
+        + 'a,
+    R: Send + 'a,
+{
+    if let Ok(transaction) = BATCH_TRANSACTION.try_with(Arc::clone) {
+        let mut conn = transaction.lock().await;
+        return conn.transaction(f).await;
+    }
+
+    let mut conn = pool.get().await.map_err(|e| {
+        tracing::warn!("Error getting connection from pool: {:?}", e);
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UnableToSendCommand,
+            Box::new(e.to_string()),
+        )
+    })?;
+    conn.transaction(f).await
+}
+
 /// Returns the entry for the config hashmap, or the default field count for the insert.
 ///
 /// Given diesel has a limit of how many parameters can be inserted in a single operation (u16::MAX),
@@ -260,6 +396,32 @@ where
     Ok(())
 }
 
+/// Like [`execute_or_retry_cleaned`], on a connection in a transaction. The first attempt runs
+/// in a savepoint, as a failed statement would otherwise abort the whole transaction.
+async fn execute_or_retry_cleaned_in_transaction<U, T>(
+    conn: &mut MyDbConnection,
+    build_query: fn(Vec<T>) -> (U, Option<&'static str>),
+    items: Vec<T>,
+) -> Result<(), diesel::result::Error>
+where
+    U: QueryFragment<Backend> + diesel::query_builder::QueryId + Send + 'static,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de> + Clone,
+{
+    let (query, additional_where_clause) = build_query(items.clone());
+    let res = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            async move { execute_with_better_error_conn(conn, query, additional_where_clause).await }
+                .scope_boxed()
+        })
+        .await;
+    if res.is_err() {
+        let cleaned_items = clean_data_for_db(items, true);
+        let (cleaned_query, additional_where_clause) = build_query(cleaned_items);
+        execute_with_better_error_conn(conn, cleaned_query, additional_where_clause).await?;
+    }
+    Ok(())
+}
+
 pub fn run_pending_migrations<DB: diesel::backend::Backend>(conn: &mut impl MigrationHarness<DB>) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("[Parser] Migrations failed!");
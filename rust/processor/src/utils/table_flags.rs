@@ -21,6 +21,7 @@ bitflags! {
         const FUNGIBLE_ASSET_METADATA = 1 << 14;
         const CURRENT_UNIFIED_FUNGIBLE_ASSET_BALANCES = 1 << 15;
         const CURRENT_FUNGIBLE_ASSET_BALANCES_LEGACY = 1 << 16;
+        const FUNGIBLE_ASSET_TO_COIN_MAPPINGS = 1 << 17;
 
         // Objects Processor: 21-30
         const OBJECTS = 1 << 21;
@@ -66,6 +67,8 @@ bitflags! {
 
         // transaction metadata 91-100
         const WRITE_SET_SIZE = 1 << 91;
+        const TRANSACTION_SIZE_INFO = 1 << 92;
+        const EVENT_SIZE_INFO = 1 << 93;
 
         // Deprecated Tables 101-110
         const COIN_SUPPLY = 1 << 101;
@@ -74,6 +77,26 @@ bitflags! {
         const ANS_PRIMARY_NAME_V2 = 1 << 104;
         const ANS_LOOKUP = 1 << 105;
         const ANS_PRIMARY_NAME = 1 << 106;
+
+        // Mirage Processor: 107-127
+        const MIRAGE_DEBT_STORE_DATAS = 1 << 107;
+        const VAULT_COLLECTION_DATAS = 1 << 108;
+        const VAULT_COLLECTION_CONFIGS = 1 << 109;
+        const VAULT_DATAS = 1 << 110;
+        const VAULT_ACTIVITIES = 1 << 111;
+        const CURRENT_VAULTS = 1 << 112;
+        const VAULT_LINEAGE = 1 << 113;
+        const MARKET_DATAS = 1 << 114;
+        const MARKET_CONFIGS = 1 << 115;
+        const POSITION_DATAS = 1 << 116;
+        const TPSL_DATAS = 1 << 117;
+        const LIMIT_ORDER_DATAS = 1 << 118;
+        const TRADE_DATAS = 1 << 119;
+        const CURRENT_POSITIONS = 1 << 120;
+        const CURRENT_TPSLS = 1 << 121;
+        const CURRENT_LIMIT_ORDERS = 1 << 122;
+        const MARKET_ACTIVITIES = 1 << 123;
+        const POSITION_HISTORIES = 1 << 124;
     }
 }
 
@@ -87,4 +110,30 @@ impl TableFlags {
         }
         flags
     }
+
+    /// Like [`Self::from_set`], but fails on names that aren't a table, so typos in the
+    /// config don't silently write everything.
+    pub fn parse_names(names: &HashSet<String>) -> anyhow::Result<Self> {
+        let mut flags = TableFlags::empty();
+        let mut unknown = vec![];
+        for name in names {
+            match TableFlags::from_name(name) {
+                Some(flag) => flags |= flag,
+                None => unknown.push(name.as_str()),
+            }
+        }
+        if !unknown.is_empty() {
+            unknown.sort_unstable();
+            anyhow::bail!(
+                "Unknown tables {:?}, table names are upper case, e.g. TABLE_ITEMS",
+                unknown
+            );
+        }
+        Ok(flags)
+    }
+
+    /// Upper case names of the tables in `self`, as used in the config
+    pub fn names(&self) -> Vec<&'static str> {
+        self.iter_names().map(|(name, _)| name).collect()
+    }
 }
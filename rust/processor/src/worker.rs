@@ -2,11 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    config::IndexerGrpcHttp2Config,
-    db::postgres::models::{ledger_info::LedgerInfo, processor_status::ProcessorStatusQuery},
+    adaptive_concurrency::{take_batch, AdaptiveConcurrency, AdaptiveConcurrencyConfig},
+    config::{IndexerGrpcHttp2Config, TransactionSource},
+    db::postgres::models::{
+        ledger_info::LedgerInfo,
+        processor_completed_ranges::ProcessorCompletedRange,
+        processor_status::{ProcessorStatus, ProcessorStatusQuery},
+    },
     gap_detectors::{
-        create_gap_detector_status_tracker_loop, gap_detector::DefaultGapDetector,
-        parquet_gap_detector::ParquetFileGapDetectorInner, GapDetector, ProcessingResult,
+        create_gap_detector_status_tracker_loop,
+        gap_detector::DefaultGapDetector,
+        parquet_gap_detector::ParquetFileGapDetectorInner,
+        range_fetcher::{InFlightBatches, RangeFetcher},
+        range_set::VersionRangeSet,
+        GapDetector, ProcessingResult,
     },
     grpc_stream::TransactionsPBResponse,
     processors::{
@@ -15,7 +24,9 @@ use crate::{
         default_processor::DefaultProcessor,
         events_processor::EventsProcessor,
         fungible_asset_processor::FungibleAssetProcessor,
-        mirage_processor::MirageProcessor,
+        mirage_processor::{
+            reset_mirage_tables_from_version, MirageProcessor, MirageProcessorConfig,
+        },
         monitoring_processor::MonitoringProcessor,
         nft_metadata_processor::NftMetadataProcessor,
         objects_processor::ObjectsProcessor,
@@ -36,34 +47,47 @@ use crate::{
         DefaultProcessingResult, Processor, ProcessorConfig, ProcessorTrait,
     },
     schema::ledger_infos,
+    stream_archive::StreamArchiveConfig,
     transaction_filter::TransactionFilter,
     utils::{
         counters::{
-            ProcessorStep, GRPC_LATENCY_BY_PROCESSOR_IN_SECS, LATEST_PROCESSED_VERSION,
-            NUM_TRANSACTIONS_PROCESSED_COUNT, PB_CHANNEL_FETCH_WAIT_TIME_SECS,
-            PROCESSED_BYTES_COUNT, PROCESSOR_DATA_PROCESSED_LATENCY_IN_SECS,
-            PROCESSOR_DATA_RECEIVED_LATENCY_IN_SECS, PROCESSOR_ERRORS_COUNT,
-            PROCESSOR_INVOCATIONS_COUNT, PROCESSOR_SUCCESSES_COUNT,
+            db_rows_written_by_model, ProcessorStep, GRPC_LATENCY_BY_PROCESSOR_IN_SECS,
+            LATEST_PROCESSED_VERSION, NUM_TRANSACTIONS_PROCESSED_COUNT,
+            PB_CHANNEL_FETCH_WAIT_TIME_SECS, PROCESSED_BYTES_COUNT,
+            PROCESSOR_DATA_PROCESSED_LATENCY_IN_SECS, PROCESSOR_DATA_RECEIVED_LATENCY_IN_SECS,
+            PROCESSOR_ERRORS_COUNT, PROCESSOR_INVOCATIONS_COUNT, PROCESSOR_SUCCESSES_COUNT,
             SINGLE_BATCH_DB_INSERTION_TIME_IN_SECS, SINGLE_BATCH_PARSING_TIME_IN_SECS,
             SINGLE_BATCH_PROCESSING_TIME_IN_SECS, TRANSACTION_UNIX_TIMESTAMP,
         },
         database::{
-            execute_with_better_error_conn, new_db_pool, run_pending_migrations, ArcDbPool,
+            execute_with_better_error_conn, run_pending_migrations, ArcDbPool, BatchTransaction,
+            DEFAULT_MAX_POOL_SIZE,
         },
         table_flags::TableFlags,
-        util::{time_diff_since_pb_timestamp_in_secs, timestamp_to_iso, timestamp_to_unixtime},
+        util::{
+            parse_timestamp, time_diff_since_pb_timestamp_in_secs, timestamp_to_iso,
+            timestamp_to_unixtime,
+        },
     },
 };
 use ahash::AHashMap;
 use anyhow::{Context, Result};
 use aptos_moving_average::MovingAverage;
+use aptos_protos::transaction::v1::Transaction;
 use kanal::AsyncSender;
+use server_framework::status::update_processor_progress;
 use std::{
     collections::HashSet,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
-use tokio::task::JoinHandle;
-use tracing::{debug, error, info};
+use tokio::{
+    sync::watch,
+    task::{AbortHandle, JoinHandle},
+};
+use tracing::{debug, error, info, warn};
 use url::Url;
 
 // this is how large the fetch queue should be. Each bucket should have a max of 80MB or so, so a batch
@@ -92,6 +116,11 @@ pub struct Worker {
     pub transaction_filter: TransactionFilter,
     pub grpc_response_item_timeout_in_secs: u64,
     pub deprecated_tables: TableFlags,
+    pub transaction_source: TransactionSource,
+    pub stream_archive: Option<StreamArchiveConfig>,
+    pub graceful_shutdown_timeout_secs: u64,
+    pub adaptive_concurrency: Option<Arc<AdaptiveConcurrency>>,
+    pub atomic_batch_writes: bool,
 }
 
 impl Worker {
@@ -105,6 +134,8 @@ impl Worker {
         starting_version: Option<u64>,
         ending_version: Option<u64>,
         number_concurrent_processing_tasks: Option<usize>,
+        // Shared by the workers of a shared stream, so their connections add up to its size
+        conn_pool: ArcDbPool,
         db_pool_size: Option<u32>,
         gap_detection_batch_size: u64,
         parquet_gap_detection_batch_size: u64,
@@ -115,30 +146,47 @@ impl Worker {
         transaction_filter: TransactionFilter,
         grpc_response_item_timeout_in_secs: u64,
         deprecated_tables: HashSet<String>,
+        enabled_tables: HashSet<String>,
+        transaction_source: TransactionSource,
+        stream_archive: Option<StreamArchiveConfig>,
+        graceful_shutdown_timeout_secs: u64,
+        adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+        atomic_batch_writes: bool,
     ) -> Result<Self> {
         let processor_name = processor_config.name();
         info!(processor_name = processor_name, "[Parser] Kicking off");
 
-        info!(
-            processor_name = processor_name,
-            service_type = PROCESSOR_SERVICE_TYPE,
-            "[Parser] Creating connection pool"
-        );
-        let conn_pool = new_db_pool(&postgres_connection_string, db_pool_size)
-            .await
-            .context("Failed to create connection pool")?;
-        info!(
-            processor_name = processor_name,
-            service_type = PROCESSOR_SERVICE_TYPE,
-            "[Parser] Finish creating the connection pool"
-        );
+        // Mirage settings can be overridden from the environment
+        let processor_config = match processor_config {
+            ProcessorConfig::MirageProcessor(config) => ProcessorConfig::MirageProcessor(
+                MirageProcessorConfig::new(config.deployer_address, config.backfill_from_version)?,
+            ),
+            processor_config => processor_config,
+        };
+
         let number_concurrent_processing_tasks = number_concurrent_processing_tasks.unwrap_or(10);
+        let adaptive_concurrency = adaptive_concurrency.map(|config| {
+            Arc::new(AdaptiveConcurrency::new(
+                &config,
+                number_concurrent_processing_tasks,
+                pb_channel_txn_chunk_size,
+                db_pool_size.unwrap_or(DEFAULT_MAX_POOL_SIZE),
+            ))
+        });
 
-        let mut deprecated_tables_flags = TableFlags::empty();
-        for table in deprecated_tables.iter() {
-            if let Some(flags) = TableFlags::from_name(table) {
-                deprecated_tables_flags |= flags;
-            }
+        // Tables are skipped when disabled, or when an allowlist is set and they aren't on it.
+        let mut skipped_tables = TableFlags::parse_names(&deprecated_tables)?;
+        if !enabled_tables.is_empty() {
+            skipped_tables |= !TableFlags::parse_names(&enabled_tables)?;
+        }
+        let deprecated_tables_flags = skipped_tables & processor_config.tables();
+        if !deprecated_tables_flags.is_empty() {
+            info!(
+                processor_name = processor_name,
+                service_type = PROCESSOR_SERVICE_TYPE,
+                skipped_tables = ?deprecated_tables_flags.names(),
+                "[Parser] Skipping writes to tables"
+            );
         }
 
         Ok(Self {
@@ -160,6 +208,11 @@ impl Worker {
             transaction_filter,
             grpc_response_item_timeout_in_secs,
             deprecated_tables: deprecated_tables_flags,
+            transaction_source,
+            stream_archive,
+            graceful_shutdown_timeout_secs,
+            adaptive_concurrency,
+            atomic_batch_writes,
         })
     }
 
@@ -169,7 +222,35 @@ impl Worker {
     /// 3. Start a loop to consume from the buffer. We will have Y threads to process the transactions in parallel. (Y should be less than X for obvious reasons)
     ///   * Note that the batches will be sequential so we won't have problems with gaps
     /// 4. We will keep track of the last processed version and monitoring things like TPS
-    pub async fn run(&mut self) {
+    /// 5. On SIGTERM/SIGINT, stop fetching, let in-flight batches finish and write the final
+    ///    processor status, giving up after `graceful_shutdown_timeout_secs`
+    /// 6. With an `ending_version`, return once every version up to it has been recorded in
+    ///    `processor_status`, logging a summary of the run
+    /// 7. With `adaptive_concurrency`, Y tasks are spawned up front but only some of them take
+    ///    batches, and the batch size changes, depending on DB latency and load
+    pub async fn run(&mut self) -> Result<()> {
+        let run_time = std::time::Instant::now();
+        let (starting_version, completed_ranges) = self.prepare().await;
+
+        // Create a transaction fetcher thread that will continuously fetch transactions from the GRPC stream
+        // and write into a channel
+        // TODO: change channel size based on number_concurrent_processing_tasks
+        let (tx, receiver) = kanal::bounded_async::<TransactionsPBResponse>(BUFFER_SIZE);
+        let fetcher_task = self.spawn_fetcher(tx, starting_version);
+
+        self.process(
+            receiver,
+            fetcher_task.abort_handle(),
+            starting_version,
+            completed_ranges,
+            run_time,
+        )
+        .await
+    }
+
+    /// Runs the migrations, works out the starting version and checks the chain id. Returns the
+    /// starting version and the ranges already completed past it.
+    async fn prepare(&mut self) -> (u64, VersionRangeSet) {
         let processor_name = self.processor_config.name();
         info!(
             processor_name = processor_name,
@@ -185,6 +266,29 @@ impl Worker {
             "[Parser] Finished migrations"
         );
 
+        if let ProcessorConfig::MirageProcessor(config) = &self.processor_config {
+            if let Some(backfill_from_version) = config.backfill_from_version {
+                // The backfill decides where to start: from its version when it's applied, and
+                // from the processor status after that
+                if let Some(starting_version) = self.starting_version.take() {
+                    warn!(
+                        processor_name = processor_name,
+                        service_type = PROCESSOR_SERVICE_TYPE,
+                        starting_version = starting_version,
+                        backfill_from_version = backfill_from_version,
+                        "[Parser] Ignoring starting_version, backfill_from_version is set"
+                    );
+                }
+                reset_mirage_tables_from_version(
+                    self.db_pool.clone(),
+                    processor_name,
+                    backfill_from_version,
+                )
+                .await
+                .expect("[Parser] Failed to reset Mirage tables for backfill");
+            }
+        }
+
         let starting_version_from_db = self
             .get_start_version()
             .await
@@ -200,6 +304,45 @@ impl Worker {
 
         let starting_version = self.starting_version.unwrap_or(starting_version_from_db);
 
+        // Batches finished ahead of the processor status before a restart. Only when resuming,
+        // since a configured starting version is usually meant to reprocess.
+        let completed_ranges = if self.starting_version.is_none() {
+            let mut conn = self
+                .db_pool
+                .get()
+                .await
+                .expect("[Parser] Failed to get connection");
+            ProcessorCompletedRange::get_by_processor(processor_name, starting_version, &mut conn)
+                .await
+                .expect("[Parser] Database error when getting completed ranges")
+        } else {
+            VersionRangeSet::default()
+        };
+        // Batches written atomically move the status forward themselves, from the version
+        // before the first one
+        if self.atomic_batch_writes {
+            let mut conn = self
+                .db_pool
+                .get()
+                .await
+                .expect("[Parser] Failed to get connection");
+            ProcessorStatus::ensure_at_least(
+                processor_name,
+                starting_version as i64 - 1,
+                &mut conn,
+            )
+            .await
+            .expect("[Parser] Database error when initializing the processor status");
+        }
+        if !completed_ranges.is_empty() {
+            info!(
+                processor_name = processor_name,
+                service_type = PROCESSOR_SERVICE_TYPE,
+                completed_ranges = ?completed_ranges.iter().collect::<Vec<_>>(),
+                "[Parser] Skipping ranges completed before the restart",
+            );
+        }
+
         info!(
             processor_name = processor_name,
             service_type = PROCESSOR_SERVICE_TYPE,
@@ -210,24 +353,37 @@ impl Worker {
             "[Parser] Building processor",
         );
 
-        let concurrent_tasks = self.number_concurrent_processing_tasks;
-
         // get the chain id
-        let chain_id = crate::grpc_stream::get_chain_id(
-            self.indexer_grpc_data_service_address.clone(),
-            self.grpc_http2_config.grpc_http2_ping_interval_in_secs(),
-            self.grpc_http2_config.grpc_http2_ping_timeout_in_secs(),
-            self.grpc_http2_config.grpc_connection_timeout_secs(),
-            self.auth_token.clone(),
-            processor_name.to_string(),
-        )
-        .await;
+        let chain_id = match &self.transaction_source {
+            TransactionSource::Grpc => {
+                crate::grpc_stream::get_chain_id(
+                    self.indexer_grpc_data_service_address.clone(),
+                    self.grpc_http2_config.grpc_http2_ping_interval_in_secs(),
+                    self.grpc_http2_config.grpc_http2_ping_timeout_in_secs(),
+                    self.grpc_http2_config.grpc_connection_timeout_secs(),
+                    self.auth_token.clone(),
+                    processor_name.to_string(),
+                )
+                .await
+            },
+            TransactionSource::Files(file_stream_config) => file_stream_config.chain_id,
+        };
         self.check_or_update_chain_id(chain_id as i64)
             .await
             .unwrap();
 
         self.grpc_chain_id = Some(chain_id);
 
+        (starting_version, completed_ranges)
+    }
+
+    /// Spawns the task streaming transactions from `starting_version` into `tx`
+    fn spawn_fetcher(
+        &self,
+        tx: AsyncSender<TransactionsPBResponse>,
+        starting_version: u64,
+    ) -> JoinHandle<()> {
+        let processor_name = self.processor_config.name();
         let ending_version = self.ending_version;
         let indexer_grpc_data_service_address = self.indexer_grpc_data_service_address.clone();
         let indexer_grpc_http2_ping_interval =
@@ -236,18 +392,19 @@ impl Worker {
             self.grpc_http2_config.grpc_http2_ping_timeout_in_secs();
         let indexer_grpc_reconnection_timeout_secs =
             self.grpc_http2_config.grpc_connection_timeout_secs();
-        let pb_channel_txn_chunk_size = self.pb_channel_txn_chunk_size;
+        let pb_channel_txn_chunk_size = match &self.adaptive_concurrency {
+            Some(adaptive_concurrency) => adaptive_concurrency.pb_channel_txn_chunk_size(),
+            None => Arc::new(AtomicUsize::new(self.pb_channel_txn_chunk_size)),
+        };
 
-        // Create a transaction fetcher thread that will continuously fetch transactions from the GRPC stream
-        // and write into a channel
-        // TODO: change channel size based on number_concurrent_processing_tasks
-        let (tx, receiver) = kanal::bounded_async::<TransactionsPBResponse>(BUFFER_SIZE);
         let request_ending_version = self.ending_version;
         let auth_token = self.auth_token.clone();
         let transaction_filter = self.transaction_filter.clone();
         let grpc_response_item_timeout =
             std::time::Duration::from_secs(self.grpc_response_item_timeout_in_secs);
-        let fetcher_task = tokio::spawn(async move {
+        let transaction_source = self.transaction_source.clone();
+        let stream_archive = self.stream_archive.clone();
+        tokio::spawn(async move {
             info!(
                 processor_name = processor_name,
                 service_type = PROCESSOR_SERVICE_TYPE,
@@ -256,22 +413,60 @@ impl Worker {
                 "[Parser] Starting fetcher thread"
             );
 
-            crate::grpc_stream::create_fetcher_loop(
-                tx.clone(),
-                indexer_grpc_data_service_address.clone(),
-                indexer_grpc_http2_ping_interval,
-                indexer_grpc_http2_ping_timeout,
-                indexer_grpc_reconnection_timeout_secs,
-                grpc_response_item_timeout,
-                starting_version,
-                request_ending_version,
-                auth_token.clone(),
-                processor_name.to_string(),
-                transaction_filter,
-                pb_channel_txn_chunk_size,
-            )
-            .await
-        });
+            match transaction_source {
+                TransactionSource::Grpc => {
+                    crate::grpc_stream::create_fetcher_loop(
+                        tx.clone(),
+                        indexer_grpc_data_service_address.clone(),
+                        indexer_grpc_http2_ping_interval,
+                        indexer_grpc_http2_ping_timeout,
+                        indexer_grpc_reconnection_timeout_secs,
+                        grpc_response_item_timeout,
+                        starting_version,
+                        request_ending_version,
+                        auth_token.clone(),
+                        processor_name.to_string(),
+                        transaction_filter,
+                        pb_channel_txn_chunk_size,
+                        stream_archive,
+                    )
+                    .await
+                },
+                TransactionSource::Files(file_stream_config) => {
+                    // Moved so the channel closes once every file is sent
+                    crate::file_stream::create_file_fetcher_loop(
+                        tx,
+                        file_stream_config,
+                        starting_version,
+                        request_ending_version,
+                        processor_name.to_string(),
+                        transaction_filter,
+                        pb_channel_txn_chunk_size,
+                    )
+                    .await
+                },
+            }
+        })
+    }
+
+    /// Processes the batches from `receiver` until it closes, the ending version is reached or
+    /// the processor shuts down, see [`Self::run`]
+    async fn process(
+        &self,
+        receiver: kanal::AsyncReceiver<TransactionsPBResponse>,
+        fetcher_task: AbortHandle,
+        starting_version: u64,
+        completed_ranges: VersionRangeSet,
+        run_time: std::time::Instant,
+    ) -> Result<()> {
+        let processor_name = self.processor_config.name();
+        let chain_id = self
+            .grpc_chain_id
+            .expect("GRPC chain ID has not been fetched yet!");
+        let concurrent_tasks = match &self.adaptive_concurrency {
+            Some(adaptive_concurrency) => adaptive_concurrency.max_concurrent_tasks(),
+            None => self.number_concurrent_processing_tasks,
+        };
 
         // Create a gap detector task that will panic if there is a gap in the processing
         let (gap_detector_sender, gap_detector_receiver) =
@@ -296,23 +491,65 @@ impl Worker {
 
         let gap_detector = if is_parquet_processor {
             GapDetector::ParquetFileGapDetector(Arc::new(Mutex::new(
-                ParquetFileGapDetectorInner::new(starting_version),
+                ParquetFileGapDetectorInner::new(starting_version)
+                    .with_restored_ranges(completed_ranges.clone()),
             )))
         } else {
-            GapDetector::DefaultGapDetector(DefaultGapDetector::new(starting_version))
+            GapDetector::DefaultGapDetector(
+                DefaultGapDetector::new(starting_version)
+                    .with_restored_ranges(completed_ranges.clone()),
+            )
         };
         let gap_detector_clone = gap_detector.clone();
 
-        tokio::spawn(async move {
+        // Parquet batches are only tracked once their files are uploaded, so a missing range
+        // can't be processed out of band
+        let in_flight_batches = (!is_parquet_processor).then(InFlightBatches::default);
+        let range_fetcher = in_flight_batches
+            .clone()
+            .map(|in_flight_batches| RangeFetcher {
+                transaction_source: self.transaction_source.clone(),
+                indexer_grpc_data_service_address: self.indexer_grpc_data_service_address.clone(),
+                grpc_http2_config: self.grpc_http2_config.clone(),
+                grpc_response_item_timeout: std::time::Duration::from_secs(
+                    self.grpc_response_item_timeout_in_secs,
+                ),
+                auth_token: self.auth_token.clone(),
+                transaction_filter: self.transaction_filter.clone(),
+                chain_id,
+                atomic_batch_writes: self.atomic_batch_writes,
+                in_flight_batches,
+            });
+
+        // Batches written atomically record their own progress
+        let write_status = !self.atomic_batch_writes;
+        let (gap_detector_shutdown_sender, gap_detector_shutdown_receiver) = watch::channel(false);
+        let gap_detector_task = tokio::spawn(async move {
             create_gap_detector_status_tracker_loop(
                 gap_detector_clone,
                 gap_detector_receiver,
                 processor,
                 gap_detection_batch_size,
+                gap_detector_shutdown_receiver,
+                range_fetcher,
+                write_status,
             )
             .await;
         });
 
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            wait_for_shutdown_signal().await;
+            info!(
+                processor_name = processor_name,
+                service_type = PROCESSOR_SERVICE_TYPE,
+                "[Parser] Received shutdown signal, draining in-flight batches"
+            );
+            shutdown_sender.send_replace(true);
+            // Keep the sender alive so receivers don't see the channel close
+            std::future::pending::<()>().await;
+        });
+
         // This is the consumer side of the channel. These are the major states:
         // 1. We're backfilling so we should expect many concurrent threads to process transactions
         // 2. We're caught up so we should expect a single thread to process transactions
@@ -328,7 +565,9 @@ impl Worker {
             "[Parser] Spawning concurrent parallel processor tasks",
         );
 
-        let mut processor_tasks = vec![fetcher_task];
+        let num_transactions_processed = Arc::new(AtomicU64::new(0));
+        let completed_ranges = Arc::new(completed_ranges);
+        let mut processor_tasks = vec![];
         for task_index in 0..concurrent_tasks {
             let join_handle: JoinHandle<()> = self
                 .launch_processor_task(
@@ -336,10 +575,29 @@ impl Worker {
                     receiver.clone(),
                     gap_detector_sender.clone(),
                     gap_detector.clone(),
+                    shutdown_receiver.clone(),
+                    num_transactions_processed.clone(),
+                    completed_ranges.clone(),
+                    in_flight_batches.clone(),
                 )
                 .await;
             processor_tasks.push(join_handle);
         }
+        drop(gap_detector_sender);
+        // With adaptive concurrency, the adjustment loop reports how many tasks are active
+        update_processor_progress(processor_name, |progress| {
+            progress.batch_concurrency = concurrent_tasks as u64;
+        });
+        let adjustment_task = self
+            .adaptive_concurrency
+            .clone()
+            .map(|adaptive_concurrency| {
+                adaptive_concurrency.spawn_adjustment_loop(
+                    self.db_pool.clone(),
+                    receiver.clone(),
+                    processor_name,
+                )
+            });
 
         info!(
             processor_name = processor_name,
@@ -349,10 +607,78 @@ impl Worker {
             "[Parser] Processor tasks spawned",
         );
 
-        // Await the processor tasks: this is forever
-        futures::future::try_join_all(processor_tasks)
+        // Await the processor tasks: this is forever, unless we reach the ending version or
+        // shut down
+        let mut shutdown = shutdown_receiver.clone();
+        let processor_tasks = futures::future::try_join_all(processor_tasks);
+        tokio::pin!(processor_tasks);
+        tokio::select! {
+            res = &mut processor_tasks => {
+                res.expect("[Processor] Processor tasks have died");
+                fetcher_task.abort();
+                if let Some(adjustment_task) = &adjustment_task {
+                    adjustment_task.abort();
+                }
+            },
+            _ = shutdown.wait_for(|shutdown| *shutdown) => {
+                fetcher_task.abort();
+                if let Some(adjustment_task) = &adjustment_task {
+                    adjustment_task.abort();
+                }
+                let deadline = std::time::Duration::from_secs(self.graceful_shutdown_timeout_secs);
+                let drained = tokio::time::timeout(deadline, async {
+                    processor_tasks.await.expect("[Processor] Processor tasks have died");
+                    gap_detector_shutdown_sender.send_replace(true);
+                    gap_detector_task.await.expect("[Parser] Gap detector task has died");
+                })
+                .await;
+                match drained {
+                    Ok(()) => info!(
+                        processor_name = processor_name,
+                        service_type = PROCESSOR_SERVICE_TYPE,
+                        "[Parser] Shut down gracefully"
+                    ),
+                    Err(_) => warn!(
+                        processor_name = processor_name,
+                        service_type = PROCESSOR_SERVICE_TYPE,
+                        timeout_in_secs = self.graceful_shutdown_timeout_secs,
+                        "[Parser] Timed out draining in-flight batches, the next start reprocesses them"
+                    ),
+                }
+                return Ok(());
+            },
+        }
+
+        // All batches were processed, write the final processor status
+        gap_detector_shutdown_sender.send_replace(true);
+        gap_detector_task
+            .await
+            .expect("[Parser] Gap detector task has died");
+
+        let next_version = self
+            .get_start_version()
             .await
-            .expect("[Processor] Processor tasks have died");
+            .context("Failed to read the final processor status")?
+            .unwrap_or_default();
+        if let Some(ending_version) = self.ending_version {
+            anyhow::ensure!(
+                next_version > ending_version,
+                "[Parser] Stopped before ending version {}, processor status is at {}",
+                ending_version,
+                next_version as i64 - 1,
+            );
+        }
+        info!(
+            processor_name = processor_name,
+            service_type = PROCESSOR_SERVICE_TYPE,
+            start_version = starting_version,
+            end_version = next_version as i64 - 1,
+            num_of_transactions = num_transactions_processed.load(Ordering::Relaxed),
+            rows_written = ?db_rows_written_by_model(),
+            duration_in_secs = run_time.elapsed().as_secs_f64(),
+            "[Parser] Finished processing up to the ending version",
+        );
+        Ok(())
     }
 
     async fn launch_processor_task(
@@ -361,6 +687,10 @@ impl Worker {
         receiver: kanal::AsyncReceiver<TransactionsPBResponse>,
         gap_detector_sender: AsyncSender<ProcessingResult>,
         mut gap_detector: GapDetector,
+        mut shutdown: watch::Receiver<bool>,
+        num_transactions_processed: Arc<AtomicU64>,
+        completed_ranges: Arc<VersionRangeSet>,
+        in_flight_batches: Option<InFlightBatches>,
     ) -> JoinHandle<()> {
         let processor_name = self.processor_config.name();
         let stream_address = self.indexer_grpc_data_service_address.to_string();
@@ -387,6 +717,8 @@ impl Worker {
         };
 
         let concurrent_tasks = self.number_concurrent_processing_tasks;
+        let atomic_batch_writes = self.atomic_batch_writes;
+        let adaptive_concurrency = self.adaptive_concurrency.clone();
 
         let chain_id = self
             .grpc_chain_id
@@ -399,17 +731,44 @@ impl Worker {
             let mut ma = MovingAverage::new(3000);
 
             loop {
-                let txn_channel_fetch_latency = std::time::Instant::now();
-                match fetch_transactions(
-                    processor_name,
-                    &stream_address,
-                    receiver_clone.clone(),
-                    task_index,
-                )
-                .await
-                {
+                let mut txn_channel_fetch_latency = std::time::Instant::now();
+                // Only stop between batches, so the current one is always written out
+                let txn_pb_res = tokio::select! {
+                    biased;
+                    _ = shutdown.wait_for(|shutdown| *shutdown) => {
+                        info!(
+                            processor_name = processor_name,
+                            service_type = PROCESSOR_SERVICE_TYPE,
+                            task_index,
+                            "[Parser][T#{}] Shutting down processor task", task_index
+                        );
+                        break;
+                    },
+                    // Parked while adaptive concurrency runs fewer tasks than this one
+                    res = take_batch(adaptive_concurrency.as_deref(), task_index, || {
+                        txn_channel_fetch_latency = std::time::Instant::now();
+                        fetch_transactions(
+                            processor_name,
+                            &stream_address,
+                            receiver_clone.clone(),
+                            task_index,
+                        )
+                    }) => res,
+                };
+                match txn_pb_res {
                     // Fetched transactions from channel
-                    Ok(transactions_pb) => {
+                    Ok(mut transactions_pb) => {
+                        if let Some(in_flight_batches) = &in_flight_batches {
+                            in_flight_batches
+                                .insert(transactions_pb.start_version, transactions_pb.end_version);
+                        }
+                        // Already written before a restart, the batch still goes to the gap
+                        // detector so the processor status moves past it
+                        if !completed_ranges.is_empty() {
+                            transactions_pb
+                                .transactions
+                                .retain(|txn| !completed_ranges.contains(txn.version));
+                        }
                         let size_in_bytes = transactions_pb.size_in_bytes as f64;
                         let first_txn_version = transactions_pb
                             .transactions
@@ -477,6 +836,7 @@ impl Worker {
                         }
 
                         let processing_time = std::time::Instant::now();
+                        let num_transactions = transactions_pb.transactions.len() as u64;
 
                         let res = do_processor(
                             transactions_pb,
@@ -485,6 +845,7 @@ impl Worker {
                             processor_name,
                             &auth_token,
                             false, // enable_verbose_logging
+                            atomic_batch_writes,
                         )
                         .await;
 
@@ -493,6 +854,8 @@ impl Worker {
                                 PROCESSOR_SUCCESSES_COUNT
                                     .with_label_values(&[processor_name])
                                     .inc();
+                                num_transactions_processed
+                                    .fetch_add(num_transactions, Ordering::Relaxed);
                                 versions
                             },
                             Err(e) => {
@@ -598,13 +961,30 @@ impl Worker {
                                 SINGLE_BATCH_DB_INSERTION_TIME_IN_SECS
                                     .with_label_values(&[processor_name, &task_index_str])
                                     .observe(processing_result.db_insertion_duration_in_secs);
+                                if let Some(adaptive_concurrency) = &adaptive_concurrency {
+                                    adaptive_concurrency.record_db_insertion_time(
+                                        processing_result.db_insertion_duration_in_secs,
+                                    );
+                                }
 
-                                gap_detector_sender
-                                    .send(ProcessingResult::DefaultProcessingResult(
-                                        processing_result,
-                                    ))
-                                    .await
-                                    .expect("[Parser] Failed to send versions to gap detector");
+                                match &gap_detector {
+                                    // A parquet processor only returns this for a batch with
+                                    // nothing left to process, e.g. one completed before a
+                                    // restart, so its versions are already complete
+                                    GapDetector::ParquetFileGapDetector(parquet_gap_detector) => {
+                                        parquet_gap_detector.lock().unwrap().update_struct_map(
+                                            AHashMap::new(),
+                                            processing_result.start_version as i64,
+                                            processing_result.end_version as i64,
+                                        );
+                                    },
+                                    GapDetector::DefaultGapDetector(_) => gap_detector_sender
+                                        .send(ProcessingResult::DefaultProcessingResult(
+                                            processing_result,
+                                        ))
+                                        .await
+                                        .expect("[Parser] Failed to send versions to gap detector"),
+                                }
                             },
                             ProcessingResult::ParquetProcessingResult(processing_result) => {
                                 // we need to pupulate the map here so then we don't have to pass multiple times
@@ -703,7 +1083,7 @@ impl Worker {
         match ProcessorStatusQuery::get_by_processor(self.processor_config.name(), &mut conn)
             .await?
         {
-            Some(status) => Ok(Some(status.last_success_version as u64 + 1)),
+            Some(status) => Ok(Some((status.last_success_version + 1) as u64)),
             None => Ok(None),
         }
     }
@@ -752,6 +1132,99 @@ impl Worker {
     }
 }
 
+/// Resolves on SIGTERM, or on SIGINT (ctrl-c).
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("[Parser] Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = sigterm.recv() => {},
+            _ = tokio::signal::ctrl_c() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
+        .await
+        .expect("[Parser] Failed to install ctrl-c handler");
+}
+
+/// Runs several processors off a single stream, so it's fetched and deserialized once. Each
+/// processor keeps its own `processor_status`, gap detector and processing tasks; the stream
+/// starts at the lowest of their starting versions and each processor only gets the batches
+/// from its own starting version on. The first worker's stream settings are used.
+pub async fn run_with_shared_stream(mut workers: Vec<Worker>) -> Result<()> {
+    let run_time = std::time::Instant::now();
+    let mut prepared = vec![];
+    for worker in workers.iter_mut() {
+        prepared.push(worker.prepare().await);
+    }
+    let stream_starting_version = prepared
+        .iter()
+        .map(|(starting_version, _)| *starting_version)
+        .min()
+        .context("No processors to run")?;
+
+    let (tx, fetcher_receiver) = kanal::bounded_async::<TransactionsPBResponse>(BUFFER_SIZE);
+    let fetcher_task = workers[0].spawn_fetcher(tx, stream_starting_version);
+
+    let mut senders = vec![];
+    let mut processors = vec![];
+    for (worker, (starting_version, completed_ranges)) in workers.iter().zip(prepared) {
+        let (sender, receiver) = kanal::bounded_async::<TransactionsPBResponse>(BUFFER_SIZE);
+        senders.push((worker.processor_config.name(), sender, starting_version));
+        processors.push(worker.process(
+            receiver,
+            fetcher_task.abort_handle(),
+            starting_version,
+            completed_ranges,
+            run_time,
+        ));
+    }
+    tokio::spawn(fan_out_transactions(fetcher_receiver, senders));
+
+    futures::future::try_join_all(processors).await?;
+    Ok(())
+}
+
+/// Sends every batch to each processor, trimmed to the processor's starting version. The
+/// slowest processor sets the pace, as its channel fills up.
+async fn fan_out_transactions(
+    receiver: kanal::AsyncReceiver<TransactionsPBResponse>,
+    senders: Vec<(&'static str, AsyncSender<TransactionsPBResponse>, u64)>,
+) {
+    while let Ok(transactions_pb) = receiver.recv().await {
+        let end_version = transactions_pb.end_version;
+        // The fetcher only reports the stream head for the first processor
+        for (processor_name, _, _) in senders.iter() {
+            update_processor_progress(processor_name, |progress| {
+                progress.stream_head_version = Some(end_version);
+            });
+        }
+
+        let mut transactions_pb = Some(transactions_pb);
+        for (index, (_, sender, starting_version)) in senders.iter().enumerate() {
+            if end_version < *starting_version {
+                continue;
+            }
+            // Only the last processor can take the batch without a copy
+            let mut batch = if index + 1 == senders.len() {
+                transactions_pb.take().unwrap()
+            } else {
+                transactions_pb.clone().unwrap()
+            };
+            if batch.start_version < *starting_version {
+                batch
+                    .transactions
+                    .retain(|txn| txn.version >= *starting_version);
+                batch.start_version = *starting_version;
+            }
+            // A processor that has stopped, e.g. on shutdown, doesn't need any more batches
+            let _ = sender.send(batch).await;
+        }
+    }
+}
+
 async fn fetch_transactions(
     processor_name: &str,
     stream_address: &str,
@@ -790,6 +1263,7 @@ pub async fn do_processor(
     processor_name: &str,
     auth_token: &str,
     enable_verbose_logging: bool,
+    atomic_batch_writes: bool,
 ) -> Result<ProcessingResult> {
     // We use the value passed from the `transactions_pb` as it may have been filtered
     let start_version = transactions_pb.start_version;
@@ -830,14 +1304,25 @@ pub async fn do_processor(
         );
     }
 
-    let processed_result = processor
-        .process_transactions(
+    let processed_result = if atomic_batch_writes {
+        process_in_transaction(
+            processor,
             transactions_pb.transactions,
             start_version,
             end_version,
-            Some(db_chain_id),
+            db_chain_id,
         )
-        .await;
+        .await
+    } else {
+        processor
+            .process_transactions(
+                transactions_pb.transactions,
+                start_version,
+                end_version,
+                Some(db_chain_id),
+            )
+            .await
+    };
 
     if let Some(ref t) = txn_time {
         PROCESSOR_DATA_PROCESSED_LATENCY_IN_SECS
@@ -848,6 +1333,64 @@ pub async fn do_processor(
     processed_result
 }
 
+/// Processes a batch with every table it writes and its progress in a single transaction, so
+/// readers never see part of a batch. Nothing is committed if processing fails.
+async fn process_in_transaction(
+    processor: &Processor,
+    transactions: Vec<Transaction>,
+    start_version: u64,
+    end_version: u64,
+    db_chain_id: u64,
+) -> Result<ProcessingResult> {
+    let transaction = BatchTransaction::begin(&processor.get_pool()).await?;
+    let result = async {
+        let result = transaction
+            .scope(processor.process_transactions(
+                transactions,
+                start_version,
+                end_version,
+                Some(db_chain_id),
+            ))
+            .await?;
+        let last_transaction_timestamp = match &result {
+            ProcessingResult::DefaultProcessingResult(result) => result
+                .last_transaction_timestamp
+                .as_ref()
+                .map(|t| parse_timestamp(t, end_version as i64)),
+            ProcessingResult::ParquetProcessingResult(_) => None,
+        };
+        ProcessorStatus::record_batch(
+            processor.name(),
+            start_version,
+            end_version,
+            last_transaction_timestamp,
+            &mut transaction.conn().await,
+        )
+        .await?;
+        anyhow::Ok(result)
+    }
+    .await;
+
+    match result {
+        Ok(result) => {
+            transaction.commit().await?;
+            Ok(result)
+        },
+        Err(e) => {
+            if let Err(rollback_error) = transaction.rollback().await {
+                warn!(
+                    processor_name = processor.name(),
+                    start_version,
+                    end_version,
+                    error = ?rollback_error,
+                    "[Parser] Failed to roll back batch transaction"
+                );
+            }
+            Err(e)
+        },
+    }
+}
+
 pub fn build_processor_for_testing(
     processor_config: ProcessorConfig,
     db_pool: ArcDbPool,
@@ -877,7 +1420,7 @@ pub fn build_processor(
 ) -> Processor {
     match config {
         ProcessorConfig::AccountTransactionsProcessor => Processor::from(
-            AccountTransactionsProcessor::new(db_pool, per_table_chunk_sizes),
+            AccountTransactionsProcessor::new(db_pool, per_table_chunk_sizes, deprecated_tables),
         ),
         ProcessorConfig::AnsProcessor(config) => Processor::from(AnsProcessor::new(
             db_pool,
@@ -890,9 +1433,11 @@ pub fn build_processor(
             per_table_chunk_sizes,
             deprecated_tables,
         )),
-        ProcessorConfig::EventsProcessor => {
-            Processor::from(EventsProcessor::new(db_pool, per_table_chunk_sizes))
-        },
+        ProcessorConfig::EventsProcessor => Processor::from(EventsProcessor::new(
+            db_pool,
+            per_table_chunk_sizes,
+            deprecated_tables,
+        )),
         ProcessorConfig::FungibleAssetProcessor => Processor::from(FungibleAssetProcessor::new(
             db_pool,
             per_table_chunk_sizes,
@@ -912,6 +1457,7 @@ pub fn build_processor(
             db_pool,
             config.clone(),
             per_table_chunk_sizes,
+            deprecated_tables,
         )),
         ProcessorConfig::TokenV2Processor(config) => Processor::from(TokenV2Processor::new(
             db_pool,
@@ -920,7 +1466,7 @@ pub fn build_processor(
             deprecated_tables,
         )),
         ProcessorConfig::TransactionMetadataProcessor => Processor::from(
-            TransactionMetadataProcessor::new(db_pool, per_table_chunk_sizes),
+            TransactionMetadataProcessor::new(db_pool, per_table_chunk_sizes, deprecated_tables),
         ),
         ProcessorConfig::UserTransactionProcessor => Processor::from(
             UserTransactionProcessor::new(db_pool, per_table_chunk_sizes, deprecated_tables),
@@ -983,6 +1529,7 @@ pub fn build_processor(
             db_pool,
             config.clone(),
             per_table_chunk_sizes,
+            deprecated_tables,
         )),
     }
 }
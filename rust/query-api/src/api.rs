@@ -0,0 +1,509 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::models::{
+    MarketState, Position, ProcessorStatusResponse, Trade, TradeCursor, TradeFilter, VaultHealth,
+};
+use processor::{
+    db::postgres::models::processor_status::ProcessorStatusQuery,
+    utils::{
+        database::{ArcDbPool, DbPoolConnection},
+        util::standardize_address,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::time::Instant;
+use warp::{http::StatusCode, reject::Reject, Filter, Rejection, Reply};
+
+const DEFAULT_TRADES_LIMIT: i64 = 100;
+const MAX_TRADES_LIMIT: i64 = 1000;
+/// How often `processor_status` is checked while waiting for a version to be indexed.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct ApiContext {
+    pub pool: ArcDbPool,
+    pub processor_name: String,
+    pub max_wait: Duration,
+}
+
+impl ApiContext {
+    async fn conn(&self) -> Result<DbPoolConnection<'_>, ApiError> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| ApiError::internal(format!("Failed to get connection: {:?}", e)))
+    }
+
+    async fn status(&self) -> Result<ProcessorStatusQuery, ApiError> {
+        let mut conn = self.conn().await?;
+        ProcessorStatusQuery::get_by_processor(&self.processor_name, &mut conn)
+            .await
+            .map_err(ApiError::from)?
+            .ok_or_else(|| {
+                ApiError::not_found(format!("No status for processor {}", self.processor_name))
+            })
+    }
+
+    /// Waits until `version` has been indexed, for at most `timeout` capped at `max_wait`.
+    async fn wait_for_version(
+        &self,
+        version: i64,
+        timeout: Option<Duration>,
+    ) -> Result<ProcessorStatusQuery, ApiError> {
+        let timeout = timeout.map_or(self.max_wait, |t| t.min(self.max_wait));
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.status().await?;
+            if status.last_success_version >= version {
+                return Ok(status);
+            }
+            if Instant::now() + WAIT_POLL_INTERVAL > deadline {
+                return Err(ApiError::not_indexed(version, status.last_success_version));
+            }
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Lets requests read their own writes by waiting for `min_version`, when given.
+    async fn wait_for_min_version(&self, min_version: Option<i64>) -> Result<(), ApiError> {
+        if let Some(version) = min_version {
+            self.wait_for_version(version, None).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: String) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }
+    }
+
+    fn not_found(message: String) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message,
+        }
+    }
+
+    fn not_indexed(version: i64, last_success_version: i64) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: format!(
+                "Version {} not indexed yet, last success version is {}",
+                version, last_success_version
+            ),
+        }
+    }
+
+    fn internal(message: String) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message,
+        }
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self::internal(format!("Query failed: {:?}", e))
+    }
+}
+
+impl Reject for ApiError {}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct MinVersionQuery {
+    min_version: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct WaitQuery {
+    version: i64,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PositionsQuery {
+    owner: String,
+    #[serde(default)]
+    include_closed: bool,
+    min_version: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct TradesQuery {
+    owner: Option<String>,
+    market_id: Option<String>,
+    position_id: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    min_version: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct TradesResponse {
+    trades: Vec<Trade>,
+    // Pass as `cursor` to get the next page, absent on the last page
+    next_cursor: Option<String>,
+}
+
+/// Serves the query API on `port` until the process exits.
+pub async fn serve(context: Arc<ApiContext>, port: u16) {
+    warp::serve(routes(context)).run(([0, 0, 0, 0], port)).await;
+}
+
+fn routes(
+    context: Arc<ApiContext>,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone {
+    let status = warp::path!("v1" / "status")
+        .and(warp::get())
+        .and(with_context(context.clone()))
+        .and_then(get_status);
+    let wait = warp::path!("v1" / "status" / "wait")
+        .and(warp::get())
+        .and(warp::query::<WaitQuery>())
+        .and(with_context(context.clone()))
+        .and_then(wait_for_version);
+    let positions = warp::path!("v1" / "positions")
+        .and(warp::get())
+        .and(warp::query::<PositionsQuery>())
+        .and(with_context(context.clone()))
+        .and_then(get_positions);
+    let market = warp::path!("v1" / "markets" / String)
+        .and(warp::get())
+        .and(warp::query::<MinVersionQuery>())
+        .and(with_context(context.clone()))
+        .and_then(get_market);
+    let vault_health = warp::path!("v1" / "vaults" / String / "health")
+        .and(warp::get())
+        .and(warp::query::<MinVersionQuery>())
+        .and(with_context(context.clone()))
+        .and_then(get_vault_health);
+    let trades = warp::path!("v1" / "trades")
+        .and(warp::get())
+        .and(warp::query::<TradesQuery>())
+        .and(with_context(context))
+        .and_then(get_trades);
+
+    status
+        .or(wait)
+        .or(positions)
+        .or(market)
+        .or(vault_health)
+        .or(trades)
+        .recover(handle_rejection)
+}
+
+fn with_context(
+    context: Arc<ApiContext>,
+) -> impl Filter<Extract = (Arc<ApiContext>,), Error = Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+async fn get_status(context: Arc<ApiContext>) -> Result<impl Reply, Rejection> {
+    let status = context.status().await?;
+    Ok(warp::reply::json(&ProcessorStatusResponse::from(status)))
+}
+
+async fn wait_for_version(
+    query: WaitQuery,
+    context: Arc<ApiContext>,
+) -> Result<impl Reply, Rejection> {
+    let status = context
+        .wait_for_version(query.version, query.timeout_ms.map(Duration::from_millis))
+        .await?;
+    Ok(warp::reply::json(&ProcessorStatusResponse::from(status)))
+}
+
+async fn get_positions(
+    query: PositionsQuery,
+    context: Arc<ApiContext>,
+) -> Result<impl Reply, Rejection> {
+    context.wait_for_min_version(query.min_version).await?;
+    let owner_addr = standardize_address(&query.owner);
+    let mut conn = context.conn().await?;
+    let positions = Position::get_by_owner(&owner_addr, query.include_closed, &mut conn)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(warp::reply::json(&positions))
+}
+
+async fn get_market(
+    market_id: String,
+    query: MinVersionQuery,
+    context: Arc<ApiContext>,
+) -> Result<impl Reply, Rejection> {
+    context.wait_for_min_version(query.min_version).await?;
+    let market_id = standardize_address(&market_id);
+    let mut conn = context.conn().await?;
+    let market = MarketState::get(&market_id, &mut conn)
+        .await
+        .map_err(ApiError::from)?
+        .ok_or_else(|| ApiError::not_found(format!("Market {} not found", market_id)))?;
+    Ok(warp::reply::json(&market))
+}
+
+async fn get_vault_health(
+    vault_id: String,
+    query: MinVersionQuery,
+    context: Arc<ApiContext>,
+) -> Result<impl Reply, Rejection> {
+    context.wait_for_min_version(query.min_version).await?;
+    let vault_id = standardize_address(&vault_id);
+    let mut conn = context.conn().await?;
+    let vault_health = VaultHealth::get(&vault_id, &mut conn)
+        .await
+        .map_err(ApiError::from)?
+        .ok_or_else(|| ApiError::not_found(format!("Vault {} not found", vault_id)))?;
+    Ok(warp::reply::json(&vault_health))
+}
+
+async fn get_trades(query: TradesQuery, context: Arc<ApiContext>) -> Result<impl Reply, Rejection> {
+    let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT);
+    if !(1..=MAX_TRADES_LIMIT).contains(&limit) {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_TRADES_LIMIT
+        ))
+        .into());
+    }
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(str::parse::<TradeCursor>)
+        .transpose()
+        .map_err(|e| ApiError::bad_request(format!("{:#}", e)))?;
+    let filter = TradeFilter {
+        owner_addr: query.owner.as_deref().map(standardize_address),
+        market_id: query.market_id.as_deref().map(standardize_address),
+        position_id: query.position_id.as_deref().map(standardize_address),
+        cursor,
+    };
+
+    context.wait_for_min_version(query.min_version).await?;
+    let mut conn = context.conn().await?;
+    let trades = Trade::get_page(&filter, limit, &mut conn)
+        .await
+        .map_err(ApiError::from)?;
+    let next_cursor = if trades.len() as i64 == limit {
+        trades.last().map(|trade| trade.cursor().to_string())
+    } else {
+        None
+    };
+    Ok(warp::reply::json(&TradesResponse {
+        trades,
+        next_cursor,
+    }))
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    let (status, error) = if let Some(e) = rejection.find::<ApiError>() {
+        (e.status, e.message.clone())
+    } else if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else if let Some(e) = rejection.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed".to_string(),
+        )
+    } else {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unhandled rejection: {:?}", rejection),
+        )
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ErrorResponse { error }),
+        status,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_indexer_testing_framework::database::{PostgresTestDatabase, TestDatabase};
+    use diesel::{sql_query, Connection, PgConnection, RunQueryDsl};
+    use processor::utils::database::{new_db_pool, run_pending_migrations};
+    use serde_json::Value;
+
+    const PROCESSOR_NAME: &str = "mirage_processor";
+
+    /// Migrated database with `processor_status` at `last_success_version`. The database is
+    /// dropped along with the returned `PostgresTestDatabase`.
+    async fn setup(
+        last_success_version: i64,
+        max_wait: Duration,
+    ) -> (PostgresTestDatabase, PgConnection, Arc<ApiContext>) {
+        let mut db = PostgresTestDatabase::new();
+        db.setup().await.unwrap();
+        let db_url = db.get_db_url();
+        let mut conn = PgConnection::establish(&db_url).unwrap();
+        run_pending_migrations(&mut conn);
+        set_last_success_version(&mut conn, last_success_version);
+
+        let context = Arc::new(ApiContext {
+            pool: new_db_pool(&db_url, Some(2)).await.unwrap(),
+            processor_name: PROCESSOR_NAME.to_string(),
+            max_wait,
+        });
+        (db, conn, context)
+    }
+
+    fn set_last_success_version(conn: &mut PgConnection, version: i64) {
+        sql_query(format!(
+            "INSERT INTO processor_status (processor, last_success_version) VALUES ('{}', {}) \
+             ON CONFLICT (processor) DO UPDATE SET last_success_version = {}",
+            PROCESSOR_NAME, version, version
+        ))
+        .execute(conn)
+        .unwrap();
+    }
+
+    fn insert_trade(conn: &mut PgConnection, transaction_version: i64, position_id: &str) {
+        sql_query(format!(
+            "INSERT INTO trade_datas (transaction_version, market_id, position_id, owner_addr, \
+             is_long, position_size, price, fee, pnl, event_type, transaction_timestamp) \
+             VALUES ({}, '0xa', '{}', '0xb', true, 1, 1, 0, 0, 'TradeEvent', NOW())",
+            transaction_version, position_id
+        ))
+        .execute(conn)
+        .unwrap();
+    }
+
+    async fn get(context: &Arc<ApiContext>, path: &str) -> (StatusCode, Value) {
+        let response = warp::test::request()
+            .path(path)
+            .reply(&routes(context.clone()))
+            .await;
+        (
+            response.status(),
+            serde_json::from_slice(response.body()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_version() {
+        let (_db, mut conn, context) = setup(10, Duration::from_secs(10)).await;
+
+        // Already indexed versions return right away
+        let (status, body) = get(&context, "/v1/status/wait?version=10").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["last_success_version"], 10);
+
+        // Versions indexed while waiting return once they are
+        let updater = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            set_last_success_version(&mut conn, 11);
+        });
+        let (status, body) = get(&context, "/v1/status/wait?version=11&timeout_ms=5000").await;
+        updater.await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["last_success_version"], 11);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_version_times_out() {
+        let (_db, _conn, context) = setup(10, Duration::from_secs(10)).await;
+
+        let start = Instant::now();
+        let (status, body) = get(&context, "/v1/status/wait?version=11&timeout_ms=500").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body["error"],
+            "Version 11 not indexed yet, last success version is 10"
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_version_is_capped_at_max_wait() {
+        let (_db, _conn, context) = setup(10, Duration::from_millis(500)).await;
+
+        // Neither a longer timeout nor waiting on `min_version` outlasts `max_wait`
+        for path in [
+            "/v1/status/wait?version=11&timeout_ms=60000",
+            "/v1/trades?min_version=11",
+        ] {
+            let start = Instant::now();
+            let (status, _) = get(&context, path).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert!(start.elapsed() < Duration::from_secs(2), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trades_limit_bounds() {
+        let (_db, mut conn, context) = setup(10, Duration::from_secs(10)).await;
+        insert_trade(&mut conn, 1, "0x1");
+
+        for limit in [0, -1, MAX_TRADES_LIMIT + 1] {
+            let (status, body) = get(&context, &format!("/v1/trades?limit={}", limit)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "limit {}", limit);
+            assert_eq!(body["error"], "limit must be between 1 and 1000");
+        }
+        for limit in [1, MAX_TRADES_LIMIT] {
+            let (status, body) = get(&context, &format!("/v1/trades?limit={}", limit)).await;
+            assert_eq!(status, StatusCode::OK, "limit {}", limit);
+            assert_eq!(body["trades"].as_array().unwrap().len(), 1);
+        }
+
+        let (status, _) = get(&context, "/v1/trades?cursor=0x1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_trades_keyset_pagination() {
+        let (_db, mut conn, context) = setup(10, Duration::from_secs(10)).await;
+        for (transaction_version, position_id) in
+            [(1, "0x1"), (2, "0x1"), (2, "0x2"), (3, "0x1"), (4, "0x1")]
+        {
+            insert_trade(&mut conn, transaction_version, position_id);
+        }
+
+        // Pages are newest first, with trades of the same version split across pages by position
+        let mut pages = vec![];
+        let mut path = "/v1/trades?limit=2".to_string();
+        loop {
+            let (status, body) = get(&context, &path).await;
+            assert_eq!(status, StatusCode::OK);
+            let page: Vec<(i64, String)> = body["trades"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|trade| {
+                    (
+                        trade["transaction_version"].as_i64().unwrap(),
+                        trade["position_id"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect();
+            pages.push(page);
+            match body["next_cursor"].as_str() {
+                Some(cursor) => path = format!("/v1/trades?limit=2&cursor={}", cursor),
+                None => break,
+            }
+        }
+        assert_eq!(pages, vec![
+            vec![(4, "0x1".to_string()), (3, "0x1".to_string())],
+            vec![(2, "0x2".to_string()), (2, "0x1".to_string())],
+            vec![(1, "0x1".to_string())],
+        ]);
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::api::{serve, ApiContext};
+use anyhow::Result;
+use processor::utils::database::new_db_pool;
+use serde::{Deserialize, Serialize};
+use server_framework::RunnableConfig;
+use std::{sync::Arc, time::Duration};
+use tracing::info;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryApiConfig {
+    pub postgres_connection_string: String,
+    #[serde(default = "QueryApiConfig::default_db_pool_size")]
+    pub db_pool_size: u32,
+    pub api_port: u16,
+    // Processor whose `processor_status` tells how far the data has been indexed
+    #[serde(default = "QueryApiConfig::default_processor_name")]
+    pub processor_name: String,
+    // Upper bound on how long a request waits for a version to be indexed
+    #[serde(default = "QueryApiConfig::default_max_wait_ms")]
+    pub max_wait_ms: u64,
+}
+
+impl QueryApiConfig {
+    pub const fn default_db_pool_size() -> u32 {
+        30
+    }
+
+    pub fn default_processor_name() -> String {
+        "mirage_processor".to_string()
+    }
+
+    pub const fn default_max_wait_ms() -> u64 {
+        10_000
+    }
+}
+
+#[async_trait::async_trait]
+impl RunnableConfig for QueryApiConfig {
+    async fn run(&self) -> Result<()> {
+        let pool = new_db_pool(&self.postgres_connection_string, Some(self.db_pool_size))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create connection pool: {:?}", e))?;
+        let context = Arc::new(ApiContext {
+            pool,
+            processor_name: self.processor_name.clone(),
+            max_wait: Duration::from_millis(self.max_wait_ms),
+        });
+
+        info!(port = self.api_port, "Starting query API");
+        serve(context, self.api_port).await;
+        Ok(())
+    }
+
+    fn get_server_name(&self) -> String {
+        "query_api".to_string()
+    }
+}
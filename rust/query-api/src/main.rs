@@ -0,0 +1,14 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use clap::Parser;
+use query_api::config::QueryApiConfig;
+use server_framework::ServerArgs;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = ServerArgs::parse();
+    args.run::<QueryApiConfig>(tokio::runtime::Handle::current())
+        .await
+}
@@ -0,0 +1,363 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+#![allow(clippy::extra_unused_lifetimes)]
+
+use anyhow::Context;
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use processor::{
+    db::postgres::models::processor_status::ProcessorStatusQuery,
+    schema::{
+        current_positions, current_vaults, market_configs, market_datas, trade_datas,
+        vault_collection_configs, vault_collection_datas,
+    },
+    utils::database::DbPoolConnection,
+};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Serialize)]
+pub struct ProcessorStatusResponse {
+    pub processor: String,
+    pub last_success_version: i64,
+    pub last_updated: chrono::NaiveDateTime,
+    pub last_transaction_timestamp: Option<chrono::NaiveDateTime>,
+}
+
+impl From<ProcessorStatusQuery> for ProcessorStatusResponse {
+    fn from(status: ProcessorStatusQuery) -> Self {
+        Self {
+            processor: status.processor,
+            last_success_version: status.last_success_version,
+            last_updated: status.last_updated,
+            last_transaction_timestamp: status.last_transaction_timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = current_positions)]
+pub struct Position {
+    pub position_id: String,
+    pub market_id: String,
+    pub owner_addr: String,
+    pub is_closed: bool,
+    pub mark_price: Option<BigDecimal>,
+    pub unrealized_pnl: Option<BigDecimal>,
+    pub accrued_funding: Option<BigDecimal>,
+    pub liquidation_price: Option<BigDecimal>,
+    pub last_transaction_version: i64,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
+impl Position {
+    pub async fn get_by_owner(
+        owner_addr: &str,
+        include_closed: bool,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        let mut query = current_positions::table
+            .filter(current_positions::owner_addr.eq(owner_addr))
+            .select(Self::as_select())
+            .order(current_positions::last_transaction_version.desc())
+            .into_boxed();
+        if !include_closed {
+            query = query.filter(current_positions::is_closed.eq(false));
+        }
+        query.load(conn).await
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = market_datas)]
+pub struct MarketData {
+    pub market_id: String,
+    pub margin_token_id: String,
+    pub perp_symbol: String,
+    pub total_long_margin: BigDecimal,
+    pub total_short_margin: BigDecimal,
+    pub long_oi: BigDecimal,
+    pub short_oi: BigDecimal,
+    pub next_funding_rate: BigDecimal,
+    pub last_funding_round: chrono::NaiveDateTime,
+    pub is_long_close_only: bool,
+    pub is_short_close_only: bool,
+    pub transaction_version: i64,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = market_configs)]
+pub struct MarketConfig {
+    pub min_taker_fee: BigDecimal,
+    pub max_taker_fee: BigDecimal,
+    pub min_maker_fee: BigDecimal,
+    pub max_maker_fee: BigDecimal,
+    pub max_oi: BigDecimal,
+    pub max_oi_imbalance: BigDecimal,
+    pub maintenance_margin: Option<BigDecimal>,
+    pub max_leverage: BigDecimal,
+    pub min_order_size: BigDecimal,
+    pub max_order_size: BigDecimal,
+    pub min_margin_amount: BigDecimal,
+    pub transaction_version: i64,
+}
+
+/// Latest data and config written for a market.
+#[derive(Debug, Serialize)]
+pub struct MarketState {
+    pub data: MarketData,
+    pub config: Option<MarketConfig>,
+}
+
+impl MarketState {
+    pub async fn get(
+        market_id: &str,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<Option<Self>> {
+        let data = market_datas::table
+            .filter(market_datas::market_id.eq(market_id))
+            .select(MarketData::as_select())
+            .order((
+                market_datas::transaction_version.desc(),
+                market_datas::write_set_change_index.desc(),
+            ))
+            .first(conn)
+            .await
+            .optional()?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let config = market_configs::table
+            .filter(market_configs::market_id.eq(market_id))
+            .select(MarketConfig::as_select())
+            .order((
+                market_configs::transaction_version.desc(),
+                market_configs::write_set_change_index.desc(),
+            ))
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(Some(Self { data, config }))
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = current_vaults)]
+pub struct Vault {
+    pub vault_id: String,
+    pub collection_id: String,
+    pub owner_addr: String,
+    pub collateral_amount: BigDecimal,
+    pub borrow_part: BigDecimal,
+    pub is_closed: bool,
+    pub merged_into_vault_id: Option<String>,
+    pub last_transaction_version: i64,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = vault_collection_datas)]
+pub struct VaultCollectionData {
+    pub total_collateral: BigDecimal,
+    pub borrow_elastic: BigDecimal,
+    pub borrow_base: BigDecimal,
+    pub cached_exchange_rate: BigDecimal,
+    pub is_emergency: bool,
+    pub transaction_version: i64,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = vault_collection_configs)]
+pub struct VaultCollectionConfig {
+    pub initial_collateralization_rate: BigDecimal,
+    pub maintenance_collateralization_rate: BigDecimal,
+    pub liquidation_multiplier: BigDecimal,
+    pub transaction_version: i64,
+}
+
+/// A vault with what its health is derived from: its debt, and the latest exchange rate and
+/// collateralization rates of its collection, all in on-chain units.
+#[derive(Debug, Serialize)]
+pub struct VaultHealth {
+    pub vault: Vault,
+    pub debt_amount: Option<BigDecimal>,
+    pub collection: Option<VaultCollectionData>,
+    pub config: Option<VaultCollectionConfig>,
+}
+
+impl VaultHealth {
+    pub async fn get(
+        vault_id: &str,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<Option<Self>> {
+        let vault = current_vaults::table
+            .filter(current_vaults::vault_id.eq(vault_id))
+            .select(Vault::as_select())
+            .first(conn)
+            .await
+            .optional()?;
+        let Some(vault) = vault else {
+            return Ok(None);
+        };
+        let collection = vault_collection_datas::table
+            .filter(vault_collection_datas::collection_id.eq(&vault.collection_id))
+            .select(VaultCollectionData::as_select())
+            .order((
+                vault_collection_datas::transaction_version.desc(),
+                vault_collection_datas::write_set_change_index.desc(),
+            ))
+            .first(conn)
+            .await
+            .optional()?;
+        let config = vault_collection_configs::table
+            .filter(vault_collection_configs::collection_id.eq(&vault.collection_id))
+            .select(VaultCollectionConfig::as_select())
+            .order((
+                vault_collection_configs::transaction_version.desc(),
+                vault_collection_configs::write_set_change_index.desc(),
+            ))
+            .first(conn)
+            .await
+            .optional()?;
+
+        let debt_amount = collection
+            .as_ref()
+            .map(|collection| debt_amount(&vault.borrow_part, collection));
+        Ok(Some(Self {
+            vault,
+            debt_amount,
+            collection,
+            config,
+        }))
+    }
+}
+
+/// Converts a vault's share of the collection's borrows into an amount, rounding down.
+fn debt_amount(borrow_part: &BigDecimal, collection: &VaultCollectionData) -> BigDecimal {
+    if collection.borrow_base.is_zero() {
+        return BigDecimal::zero();
+    }
+    (borrow_part * &collection.borrow_elastic / &collection.borrow_base).with_scale(0)
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = trade_datas)]
+pub struct Trade {
+    pub transaction_version: i64,
+    pub position_id: String,
+    pub market_id: String,
+    pub owner_addr: String,
+    pub is_long: bool,
+    pub position_size: BigDecimal,
+    pub price: BigDecimal,
+    pub fee: BigDecimal,
+    pub pnl: BigDecimal,
+    pub event_type: String,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+}
+
+/// Position in the trade history, which is ordered by descending
+/// `(transaction_version, position_id)`. Formatted as `{transaction_version}:{position_id}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TradeCursor {
+    pub transaction_version: i64,
+    pub position_id: String,
+}
+
+impl fmt::Display for TradeCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.transaction_version, self.position_id)
+    }
+}
+
+impl FromStr for TradeCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transaction_version, position_id) = s
+            .split_once(':')
+            .context("Expected cursor of the form {transaction_version}:{position_id}")?;
+        Ok(Self {
+            transaction_version: transaction_version
+                .parse()
+                .context("Invalid transaction version in cursor")?,
+            position_id: position_id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TradeFilter {
+    pub owner_addr: Option<String>,
+    pub market_id: Option<String>,
+    pub position_id: Option<String>,
+    // Only trades after this cursor are returned
+    pub cursor: Option<TradeCursor>,
+}
+
+impl Trade {
+    /// Latest `limit` trades matching `filter`, newest first.
+    pub async fn get_page(
+        filter: &TradeFilter,
+        limit: i64,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        let mut query = trade_datas::table
+            .select(Self::as_select())
+            .order((
+                trade_datas::transaction_version.desc(),
+                trade_datas::position_id.desc(),
+            ))
+            .limit(limit)
+            .into_boxed();
+        if let Some(owner_addr) = &filter.owner_addr {
+            query = query.filter(trade_datas::owner_addr.eq(owner_addr));
+        }
+        if let Some(market_id) = &filter.market_id {
+            query = query.filter(trade_datas::market_id.eq(market_id));
+        }
+        if let Some(position_id) = &filter.position_id {
+            query = query.filter(trade_datas::position_id.eq(position_id));
+        }
+        if let Some(cursor) = &filter.cursor {
+            query = query.filter(
+                trade_datas::transaction_version
+                    .lt(cursor.transaction_version)
+                    .or(trade_datas::transaction_version
+                        .eq(cursor.transaction_version)
+                        .and(trade_datas::position_id.lt(&cursor.position_id))),
+            );
+        }
+        query.load(conn).await
+    }
+
+    pub fn cursor(&self) -> TradeCursor {
+        TradeCursor {
+            transaction_version: self.transaction_version,
+            position_id: self.position_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trade_cursor_round_trip() {
+        let cursor = TradeCursor {
+            transaction_version: 123,
+            position_id: "0xabc".to_string(),
+        };
+        assert_eq!(cursor.to_string(), "123:0xabc");
+        assert_eq!(cursor.to_string().parse::<TradeCursor>().unwrap(), cursor);
+        assert!("0xabc".parse::<TradeCursor>().is_err());
+        assert!("abc:0xabc".parse::<TradeCursor>().is_err());
+    }
+}
@@ -1,4 +1,8 @@
+use processor::bq_analytics::{
+    object_store::ObjectStoreConfig, schema_versions::SchemaChangePolicy,
+};
 use serde::{Deserialize, Serialize};
+use url::Url;
 
 /// This enum captures the configs for all the different db storages that are defined.
 /// The configs for each db storage should only contain configuration specific to that
@@ -33,6 +37,7 @@ use serde::{Deserialize, Serialize};
 pub enum DbConfig {
     PostgresConfig(PostgresConfig),
     ParquetConfig(ParquetConfig),
+    ClickhouseConfig(ClickhouseConfig),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -64,4 +69,52 @@ pub struct ParquetConfig {
     pub bucket_name: String,
     #[serde(default)]
     pub bucket_root: String,
+    // Where files are uploaded, GCS unless set
+    #[serde(default)]
+    pub object_store: ObjectStoreConfig,
+    // Whether an incompatible schema change fails startup or writes a new version of the table
+    #[serde(default)]
+    pub schema_change_policy: SchemaChangePolicy,
+}
+
+/// Writes processor output to ClickHouse, with the processor status tracked per table in
+/// Postgres like the parquet processors. Rows are inserted over the native protocol, into tables
+/// created on startup with the columns of their Postgres counterparts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClickhouseConfig {
+    // Postgres database the processor status is tracked in
+    pub connection_string: String,
+    // Size of the pool for writes/reads to the DB. Limits maximum number of queries in flight
+    #[serde(default = "PostgresConfig::default_db_pool_size")]
+    pub db_pool_size: u32,
+    // Native protocol endpoint of the ClickHouse server, e.g. tcp://localhost:9000
+    pub clickhouse_url: Url,
+    #[serde(default = "ClickhouseConfig::default_clickhouse_database")]
+    pub clickhouse_database: String,
+    #[serde(default)]
+    pub clickhouse_user: Option<String>,
+    #[serde(default)]
+    pub clickhouse_password: Option<String>,
+    // Rows buffered per table before they're inserted
+    #[serde(default = "ClickhouseConfig::default_max_batch_rows")]
+    pub max_batch_rows: usize,
+    // Seconds after which buffered rows are inserted, however few
+    #[serde(default = "ClickhouseConfig::default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+impl ClickhouseConfig {
+    pub fn default_clickhouse_database() -> String {
+        "default".to_string()
+    }
+
+    /// ClickHouse prefers few large inserts over many small ones
+    pub const fn default_max_batch_rows() -> usize {
+        100_000
+    }
+
+    pub const fn default_flush_interval_secs() -> u64 {
+        10
+    }
 }
@@ -1,7 +1,7 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{db_config::DbConfig, processor_config::ProcessorConfig};
+use super::{db_config::DbConfig, processor_config::ProcessorConfig, stream_config::StreamConfig};
 use crate::{
     parquet_processors::{
         parquet_account_transactions_processor::ParquetAccountTransactionsProcessor,
@@ -20,9 +20,9 @@ use crate::{
         account_transactions_processor::AccountTransactionsProcessor, ans_processor::AnsProcessor,
         default_processor::DefaultProcessor, events_processor::EventsProcessor,
         fungible_asset_processor::FungibleAssetProcessor,
-        monitoring_processor::MonitoringProcessor, objects_processor::ObjectsProcessor,
-        stake_processor::StakeProcessor, token_v2_processor::TokenV2Processor,
-        user_transaction_processor::UserTransactionProcessor,
+        mirage_trades_processor::MirageTradesProcessor, monitoring_processor::MonitoringProcessor,
+        objects_processor::ObjectsProcessor, stake_processor::StakeProcessor,
+        token_v2_processor::TokenV2Processor, user_transaction_processor::UserTransactionProcessor,
     },
 };
 use anyhow::Result;
@@ -57,6 +57,7 @@ pub struct IndexerProcessorConfig {
     pub backfill_config: Option<BackfillConfig>,
     pub bootstrap_config: Option<BootStrapConfig>,
     pub testing_config: Option<TestingConfig>,
+    pub stream_config: Option<StreamConfig>,
     #[serde(default)]
     pub mode: ProcessorMode,
 }
@@ -96,6 +97,7 @@ impl<'de> Deserialize<'de> for IndexerProcessorConfig {
             backfill_config: Option<BackfillConfig>,
             bootstrap_config: Option<BootStrapConfig>,
             testing_config: Option<TestingConfig>,
+            stream_config: Option<StreamConfig>,
             #[serde(default)]
             mode: ProcessorMode,
         }
@@ -108,6 +110,7 @@ impl<'de> Deserialize<'de> for IndexerProcessorConfig {
             backfill_config: inner.backfill_config,
             bootstrap_config: inner.bootstrap_config,
             testing_config: inner.testing_config,
+            stream_config: inner.stream_config,
             mode: inner.mode,
         };
 
@@ -156,6 +159,10 @@ impl RunnableConfig for IndexerProcessorConfig {
                 let monitoring_processor = MonitoringProcessor::new(self.clone()).await?;
                 monitoring_processor.run_processor().await
             },
+            ProcessorConfig::MirageTradesProcessor(_) => {
+                let mirage_trades_processor = MirageTradesProcessor::new(self.clone()).await?;
+                mirage_trades_processor.run_processor().await
+            },
             ProcessorConfig::TokenV2Processor(_) => {
                 let token_v2_processor = TokenV2Processor::new(self.clone()).await?;
                 token_v2_processor.run_processor().await
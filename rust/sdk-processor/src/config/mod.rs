@@ -1,3 +1,4 @@
 pub mod db_config;
 pub mod indexer_processor_config;
 pub mod processor_config;
+pub mod stream_config;
@@ -1,12 +1,14 @@
 use crate::{
     parquet_processors::parquet_ans_processor::ParquetAnsProcessorConfig,
     processors::{
-        ans_processor::AnsProcessorConfig, objects_processor::ObjectsProcessorConfig,
-        stake_processor::StakeProcessorConfig, token_v2_processor::TokenV2ProcessorConfig,
+        ans_processor::AnsProcessorConfig, mirage_trades_processor::MirageTradesProcessorConfig,
+        objects_processor::ObjectsProcessorConfig, stake_processor::StakeProcessorConfig,
+        token_v2_processor::TokenV2ProcessorConfig,
     },
     utils::parquet_processor_table_mapping::{format_table_name, VALID_TABLE_NAMES},
 };
 use ahash::AHashMap;
+use parquet::basic::{Compression, ZstdLevel};
 use processor::{
     bq_analytics::generic_parquet_processor::NamedTable,
     db::parquet::models::{
@@ -98,6 +100,8 @@ pub enum ProcessorConfig {
     TokenV2Processor(TokenV2ProcessorConfig),
     ObjectsProcessor(ObjectsProcessorConfig),
     MonitoringProcessor(DefaultProcessorConfig),
+    // ClickHouse only, Postgres is written by the mirage_processor
+    MirageTradesProcessor(MirageTradesProcessorConfig),
     // ParquetProcessor
     ParquetDefaultProcessor(ParquetDefaultProcessorConfig),
     ParquetEventsProcessor(ParquetDefaultProcessorConfig),
@@ -286,9 +290,27 @@ pub struct ParquetDefaultProcessorConfig {
     // Set of table name to backfill. Using HashSet for fast lookups, and for future extensibility.
     #[serde(default)]
     pub backfill_table: HashSet<String>,
+    // Rolling policies of individual tables, by table name
+    #[serde(default = "AHashMap::new")]
+    pub rolling_policies: AHashMap<String, ParquetRollingPolicy>,
 }
 
 impl ParquetDefaultProcessorConfig {
+    /// Checks that rolling policies are only set for `table_names`, with positive limits.
+    pub fn validate_rolling_policies(&self, table_names: &HashSet<String>) -> anyhow::Result<()> {
+        for (table_name, policy) in self.rolling_policies.iter() {
+            if !table_names.contains(table_name) {
+                anyhow::bail!(
+                    "Invalid table name '{}' in rolling_policies. Expected one of: {:?}",
+                    table_name,
+                    table_names
+                );
+            }
+            policy.validate(table_name)?;
+        }
+        Ok(())
+    }
+
     /// Make the default very large on purpose so that by default it's not chunked
     /// This prevents any unexpected changes in behavior
     pub const fn default_channel_size() -> usize {
@@ -306,6 +328,65 @@ impl ParquetDefaultProcessorConfig {
     }
 }
 
+/// When a table's buffer is rolled into a file, and how the file is written. Unset fields fall
+/// back to the processor's `max_buffer_size` and `upload_interval`, no row limit, a single row
+/// group per file and LZ4.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParquetRollingPolicy {
+    // Max size of the buffer in bytes
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_rows: Option<usize>,
+    // Max seconds since the table was last uploaded
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    // Max rows per row group
+    #[serde(default)]
+    pub row_group_size: Option<usize>,
+    #[serde(default)]
+    pub compression: Option<ParquetCompression>,
+}
+
+impl ParquetRollingPolicy {
+    fn validate(&self, table_name: &str) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("max_bytes", self.max_bytes.map(|v| v as u64)),
+            ("max_rows", self.max_rows.map(|v| v as u64)),
+            ("max_age_secs", self.max_age_secs),
+            ("row_group_size", self.row_group_size.map(|v| v as u64)),
+        ] {
+            if value == Some(0) {
+                anyhow::bail!("{} of table '{}' must be positive", name, table_name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Lz4,
+    Lz4Raw,
+    Zstd,
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Self {
+        match compression {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Lz4 => Compression::LZ4,
+            ParquetCompression::Lz4Raw => Compression::LZ4_RAW,
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,6 +398,7 @@ mod tests {
             channel_size: 10,
             max_buffer_size: 100000,
             upload_interval: 1800,
+            rolling_policies: AHashMap::new(),
         });
 
         let result = config.get_processor_status_table_names();
@@ -336,6 +418,7 @@ mod tests {
             channel_size: 10,
             max_buffer_size: 100000,
             upload_interval: 1800,
+            rolling_policies: AHashMap::new(),
         });
 
         let result = config.get_processor_status_table_names();
@@ -353,6 +436,7 @@ mod tests {
             channel_size: 10,
             max_buffer_size: 100000,
             upload_interval: 1800,
+            rolling_policies: AHashMap::new(),
         });
         let result = config.get_processor_status_table_names();
         assert!(result.is_ok());
@@ -383,6 +467,7 @@ mod tests {
             channel_size: 10,
             max_buffer_size: 100000,
             upload_interval: 1800,
+            rolling_policies: AHashMap::new(),
         });
 
         let result = config.get_processor_status_table_names();
@@ -391,4 +476,46 @@ mod tests {
         let table_names = result.unwrap();
         assert_eq!(table_names, vec!["transactions".to_string(),]);
     }
+
+    #[test]
+    fn test_validate_rolling_policies() {
+        let table_names = HashSet::from(["transactions".to_string()]);
+        let mut config = ParquetDefaultProcessorConfig {
+            backfill_table: HashSet::new(),
+            channel_size: 10,
+            max_buffer_size: 100000,
+            upload_interval: 1800,
+            rolling_policies: AHashMap::new(),
+        };
+        config
+            .rolling_policies
+            .insert("transactions".to_string(), ParquetRollingPolicy {
+                max_rows: Some(1000),
+                compression: Some(ParquetCompression::Zstd),
+                ..Default::default()
+            });
+        assert!(config.validate_rolling_policies(&table_names).is_ok());
+
+        config
+            .rolling_policies
+            .insert("transactions".to_string(), ParquetRollingPolicy {
+                row_group_size: Some(0),
+                ..Default::default()
+            });
+        let error_message = config
+            .validate_rolling_policies(&table_names)
+            .unwrap_err()
+            .to_string();
+        assert!(error_message.contains("row_group_size of table 'transactions' must be positive"));
+
+        config.rolling_policies.clear();
+        config
+            .rolling_policies
+            .insert("events".to_string(), ParquetRollingPolicy::default());
+        let error_message = config
+            .validate_rolling_policies(&table_names)
+            .unwrap_err()
+            .to_string();
+        assert!(error_message.contains("Invalid table name 'events' in rolling_policies"));
+    }
 }
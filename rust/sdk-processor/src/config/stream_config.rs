@@ -0,0 +1,25 @@
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+/// Publishes the rows a processor extracts to a Kafka-compatible broker, alongside storing them.
+/// Rows of each table go to the `{topic_prefix}.{table_name}` topic as JSON, keyed by the id of
+/// the entity they belong to, so all rows of an entity land on one partition in version order.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamConfig {
+    // Comma separated list of brokers, e.g. localhost:9092
+    pub bootstrap_servers: String,
+    pub topic_prefix: String,
+    // How long a message may take to be acknowledged before the batch fails
+    #[serde(default = "StreamConfig::default_message_timeout_ms")]
+    pub message_timeout_ms: u64,
+    // Additional librdkafka producer settings, e.g. security.protocol or sasl.username
+    #[serde(default = "AHashMap::new")]
+    pub producer_config: AHashMap<String, String>,
+}
+
+impl StreamConfig {
+    pub const fn default_message_timeout_ms() -> u64 {
+        30_000
+    }
+}
@@ -1,17 +1,20 @@
 use crate::{
-    config::db_config::DbConfig,
+    config::{
+        db_config::{DbConfig, ParquetConfig},
+        processor_config::ParquetDefaultProcessorConfig,
+    },
     steps::common::{
-        gcs_uploader::{create_new_writer, GCSUploader},
-        parquet_buffer_step::ParquetBufferStep,
+        parquet_buffer_step::{ParquetBufferStep, RollingPolicy},
+        parquet_uploader::{create_new_writer, ParquetUploader, WriterSettings},
     },
     utils::database::{new_db_pool, ArcDbPool},
 };
 use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
-use google_cloud_storage::client::{Client as GCSClient, ClientConfig as GcsClientConfig};
 use parquet::schema::types::Type;
 use processor::{
+    bq_analytics::{object_store::ObjectStore, schema_versions::SchemaChangePolicy},
     db::parquet::models::{
         account_transaction_models::parquet_account_transactions::AccountTransaction,
         ans_models::{
@@ -152,10 +155,11 @@ pub enum ParquetTypeEnum {
 pub trait ParquetTypeTrait: std::fmt::Debug + Send + Sync {
     fn parquet_type(&self) -> ParquetTypeEnum;
     fn calculate_size(&self) -> usize;
+    fn num_rows(&self) -> usize;
 
-    async fn upload_to_gcs(
+    async fn upload(
         &self,
-        uploader: &mut GCSUploader,
+        uploader: &mut ParquetUploader,
         parquet_type: ParquetTypeEnum,
         table_name: &str,
     ) -> anyhow::Result<()>;
@@ -174,9 +178,13 @@ macro_rules! impl_parquet_trait {
                 allocative::size_of_unique(self)
             }
 
-            async fn upload_to_gcs(
+            fn num_rows(&self) -> usize {
+                self.len()
+            }
+
+            async fn upload(
                 &self,
-                uploader: &mut GCSUploader,
+                uploader: &mut ParquetUploader,
                 parquet_type: ParquetTypeEnum,
                 table_name: &str,
             ) -> anyhow::Result<()> {
@@ -605,17 +613,12 @@ impl ParquetTypeStructs {
     }
 }
 
-async fn initialize_gcs_client(credentials: Option<String>) -> Arc<GCSClient> {
-    if let Some(credentials) = credentials {
+async fn initialize_object_store(config: &ParquetConfig) -> anyhow::Result<Arc<ObjectStore>> {
+    if let Some(credentials) = &config.google_application_credentials {
         std::env::set_var(GOOGLE_APPLICATION_CREDENTIALS, credentials);
     }
 
-    let gcs_config = GcsClientConfig::default()
-        .with_auth()
-        .await
-        .expect("Failed to create GCS client config");
-
-    Arc::new(GCSClient::new(gcs_config))
+    ObjectStore::new(&config.object_store, &config.bucket_name).await
 }
 
 /// Initializes the database connection pool.
@@ -642,36 +645,77 @@ async fn initialize_database_pool(config: &DbConfig) -> anyhow::Result<ArcDbPool
 
 /// Initializes the Parquet buffer step.
 async fn initialize_parquet_buffer_step(
-    gcs_client: Arc<GCSClient>,
+    object_store: Arc<ObjectStore>,
     parquet_type_to_schemas: HashMap<ParquetTypeEnum, Arc<Type>>,
-    upload_interval: u64,
-    max_buffer_size: usize,
-    bucket_name: String,
+    parquet_processor_config: &ParquetDefaultProcessorConfig,
     bucket_root: String,
+    schema_change_policy: SchemaChangePolicy,
     processor_name: String,
 ) -> anyhow::Result<ParquetBufferStep> {
+    let table_names = parquet_type_to_schemas
+        .keys()
+        .map(|parquet_type| parquet_type.to_string())
+        .collect();
+    parquet_processor_config.validate_rolling_policies(&table_names)?;
+
+    let default_rolling_policy = RollingPolicy {
+        max_bytes: parquet_processor_config.max_buffer_size,
+        max_rows: None,
+        max_age: Duration::from_secs(parquet_processor_config.upload_interval),
+    };
+    let mut rolling_policies = HashMap::new();
+    let mut parquet_type_to_settings = HashMap::new();
+    for parquet_type in parquet_type_to_schemas.keys() {
+        let Some(policy) = parquet_processor_config
+            .rolling_policies
+            .get(&parquet_type.to_string())
+        else {
+            continue;
+        };
+        rolling_policies.insert(*parquet_type, RollingPolicy {
+            max_bytes: policy.max_bytes.unwrap_or(default_rolling_policy.max_bytes),
+            max_rows: policy.max_rows,
+            max_age: policy
+                .max_age_secs
+                .map_or(default_rolling_policy.max_age, Duration::from_secs),
+        });
+        let default_settings = WriterSettings::default();
+        parquet_type_to_settings.insert(*parquet_type, WriterSettings {
+            compression: policy
+                .compression
+                .map_or(default_settings.compression, Into::into),
+            row_group_size: policy.row_group_size,
+        });
+    }
+
     let parquet_type_to_writer = parquet_type_to_schemas
         .iter()
         .map(|(key, schema)| {
-            let writer = create_new_writer(schema.clone()).expect("Failed to create writer");
+            let compression = parquet_type_to_settings
+                .get(key)
+                .copied()
+                .unwrap_or_default()
+                .compression;
+            let writer =
+                create_new_writer(schema.clone(), compression).expect("Failed to create writer");
             (*key, writer)
         })
         .collect();
 
-    let buffer_uploader = GCSUploader::new(
-        gcs_client,
+    let mut buffer_uploader = ParquetUploader::new(
+        object_store,
         parquet_type_to_schemas,
         parquet_type_to_writer,
-        bucket_name,
+        parquet_type_to_settings,
         bucket_root,
         processor_name,
     )?;
+    buffer_uploader
+        .resolve_table_paths(schema_change_policy)
+        .await?;
 
-    let default_size_buffer_step = ParquetBufferStep::new(
-        Duration::from_secs(upload_interval),
-        buffer_uploader,
-        max_buffer_size,
-    );
+    let default_size_buffer_step =
+        ParquetBufferStep::new(buffer_uploader, default_rolling_policy, rolling_policies);
 
     Ok(default_size_buffer_step)
 }
@@ -5,7 +5,7 @@ use crate::{
         processor_config::ProcessorConfig,
     },
     parquet_processors::{
-        initialize_database_pool, initialize_gcs_client, initialize_parquet_buffer_step,
+        initialize_database_pool, initialize_object_store, initialize_parquet_buffer_step,
         set_backfill_table_flag, ParquetTypeEnum,
     },
     steps::{
@@ -137,8 +137,7 @@ impl ProcessorTrait for ParquetFungibleAssetProcessor {
             .bootstrap_fa_to_coin_mapping(self.db_pool.clone())
             .await?;
 
-        let gcs_client =
-            initialize_gcs_client(parquet_db_config.google_application_credentials.clone()).await;
+        let object_store = initialize_object_store(parquet_db_config).await?;
 
         let parquet_type_to_schemas: HashMap<ParquetTypeEnum, Arc<Type>> = [
             (
@@ -166,12 +165,11 @@ impl ProcessorTrait for ParquetFungibleAssetProcessor {
         .collect();
 
         let default_size_buffer_step = initialize_parquet_buffer_step(
-            gcs_client.clone(),
+            object_store.clone(),
             parquet_type_to_schemas,
-            parquet_processor_config.upload_interval,
-            parquet_processor_config.max_buffer_size,
-            parquet_db_config.bucket_name.clone(),
+            &parquet_processor_config,
             parquet_db_config.bucket_root.clone(),
+            parquet_db_config.schema_change_policy,
             self.name().to_string(),
         )
         .await
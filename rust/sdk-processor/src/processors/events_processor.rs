@@ -5,13 +5,17 @@ use crate::{
         processor_config::ProcessorConfig,
     },
     steps::{
-        common::get_processor_status_saver,
+        common::{
+            clickhouse_storer::ClickhouseStorer, get_processor_status_saver,
+            parquet_version_tracker_step::ParquetVersionTrackerStep,
+            stream_publisher::StreamPublisher,
+        },
         events_processor::{EventsExtractor, EventsStorer},
     },
     utils::{
         chain_id::check_or_update_chain_id,
         database::{new_db_pool, run_migrations, ArcDbPool},
-        starting_version::get_starting_version,
+        starting_version::{get_min_last_success_version_parquet, get_starting_version},
     },
 };
 use anyhow::Result;
@@ -23,6 +27,7 @@ use aptos_indexer_processor_sdk::{
     },
     traits::{processor_trait::ProcessorTrait, IntoRunnableStep},
 };
+use processor::db::postgres::models::events_models::events::EventPG;
 use tracing::{debug, info};
 
 pub struct EventsProcessor {
@@ -51,6 +56,25 @@ impl EventsProcessor {
                     db_pool: conn_pool,
                 })
             },
+            // Processor status is still tracked in Postgres when storing in ClickHouse
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                let conn_pool = new_db_pool(
+                    &clickhouse_config.connection_string,
+                    Some(clickhouse_config.db_pool_size),
+                )
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to create connection pool for ClickhouseConfig: {:?}",
+                        e
+                    )
+                })?;
+
+                Ok(Self {
+                    config,
+                    db_pool: conn_pool,
+                })
+            },
             _ => Err(anyhow::anyhow!(
                 "Invalid db config for EventsProcessor {:?}",
                 config.db_config
@@ -67,16 +91,37 @@ impl ProcessorTrait for EventsProcessor {
 
     async fn run_processor(&self) -> Result<()> {
         // Run migrations
-        if let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config {
-            run_migrations(
-                postgres_config.connection_string.clone(),
-                self.db_pool.clone(),
-            )
-            .await;
+        match self.config.db_config {
+            DbConfig::PostgresConfig(ref postgres_config) => {
+                run_migrations(
+                    postgres_config.connection_string.clone(),
+                    self.db_pool.clone(),
+                )
+                .await;
+            },
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                run_migrations(
+                    clickhouse_config.connection_string.clone(),
+                    self.db_pool.clone(),
+                )
+                .await;
+                ClickhouseStorer::<Vec<EventPG>>::create_tables(clickhouse_config).await?;
+            },
+            _ => {},
         }
 
         //  Merge the starting version from config and the latest processed version from the DB
-        let starting_version = get_starting_version(&self.config, self.db_pool.clone()).await?;
+        let starting_version = match self.config.db_config {
+            DbConfig::ClickhouseConfig(_) => {
+                get_min_last_success_version_parquet(
+                    &self.config,
+                    self.db_pool.clone(),
+                    ClickhouseStorer::<Vec<EventPG>>::processor_status_table_names(self.name()),
+                )
+                .await?
+            },
+            _ => get_starting_version(&self.config, self.db_pool.clone()).await?,
+        };
 
         // Check and update the ledger chain id to ensure we're indexing the correct chain
         let grpc_chain_id = TransactionStream::new(self.config.transaction_stream_config.clone())
@@ -116,20 +161,46 @@ impl ProcessorTrait for EventsProcessor {
         })
         .await?;
         let events_extractor = EventsExtractor {};
-        let events_storer = EventsStorer::new(self.db_pool.clone(), processor_config);
-        let version_tracker = VersionTrackerStep::new(
-            get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
-            DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
-        );
+        let stream_publisher =
+            StreamPublisher::<Vec<EventPG>>::new(self.config.stream_config.as_ref())?;
 
         // Connect processor steps together
-        let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
-            transaction_stream.into_runnable_step(),
-        )
-        .connect_to(events_extractor.into_runnable_step(), channel_size)
-        .connect_to(events_storer.into_runnable_step(), channel_size)
-        .connect_to(version_tracker.into_runnable_step(), channel_size)
-        .end_and_return_output_receiver(channel_size);
+        let buffer_receiver = match self.config.db_config {
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                let clickhouse_storer = ClickhouseStorer::<Vec<EventPG>>::new(clickhouse_config)?;
+                let version_tracker = ParquetVersionTrackerStep::<_, &'static str>::new(
+                    get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
+                    DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
+                );
+
+                let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
+                    transaction_stream.into_runnable_step(),
+                )
+                .connect_to(events_extractor.into_runnable_step(), channel_size)
+                .connect_to(stream_publisher.into_runnable_step(), channel_size)
+                .connect_to(clickhouse_storer.into_runnable_step(), channel_size)
+                .connect_to(version_tracker.into_runnable_step(), channel_size)
+                .end_and_return_output_receiver(channel_size);
+                buffer_receiver
+            },
+            _ => {
+                let events_storer = EventsStorer::new(self.db_pool.clone(), processor_config);
+                let version_tracker = VersionTrackerStep::new(
+                    get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
+                    DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
+                );
+
+                let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
+                    transaction_stream.into_runnable_step(),
+                )
+                .connect_to(events_extractor.into_runnable_step(), channel_size)
+                .connect_to(stream_publisher.into_runnable_step(), channel_size)
+                .connect_to(events_storer.into_runnable_step(), channel_size)
+                .connect_to(version_tracker.into_runnable_step(), channel_size)
+                .end_and_return_output_receiver(channel_size);
+                buffer_receiver
+            },
+        };
 
         // (Optional) Parse the results
         loop {
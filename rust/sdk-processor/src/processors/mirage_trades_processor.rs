@@ -0,0 +1,191 @@
+use crate::{
+    config::{
+        db_config::DbConfig,
+        indexer_processor_config::{IndexerProcessorConfig, ProcessorMode},
+        processor_config::{DefaultProcessorConfig, ProcessorConfig},
+    },
+    steps::{
+        common::{
+            clickhouse_storer::ClickhouseStorer, get_processor_status_saver,
+            parquet_version_tracker_step::ParquetVersionTrackerStep,
+            stream_publisher::StreamPublisher,
+        },
+        mirage_trades_processor::MirageTradesExtractor,
+    },
+    utils::{
+        chain_id::check_or_update_chain_id,
+        database::{new_db_pool, run_migrations, ArcDbPool},
+        starting_version::get_min_last_success_version_parquet,
+    },
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::{TransactionStream, TransactionStreamConfig},
+    builder::ProcessorBuilder,
+    common_steps::{TransactionStreamStep, DEFAULT_UPDATE_PROCESSOR_STATUS_SECS},
+    traits::{processor_trait::ProcessorTrait, IntoRunnableStep},
+};
+use processor::{
+    db::common::models::market_models::market_activities::Trade,
+    processors::mirage_processor::MirageProcessorConfig,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirageTradesProcessorConfig {
+    #[serde(flatten)]
+    pub default_config: DefaultProcessorConfig,
+    // Falls back to MIRAGE_PROCESSOR_DEPLOYER_ADDRESS, like the mirage_processor
+    #[serde(default)]
+    pub deployer_address: Option<String>,
+}
+
+/// Stores Mirage trades in ClickHouse, and publishes them with a `stream_config`. Postgres is
+/// still written by the `mirage_processor`, which tracks the vault and position state the trades
+/// are a history of; position updates aren't stored or published here.
+pub struct MirageTradesProcessor {
+    pub config: IndexerProcessorConfig,
+    pub db_pool: ArcDbPool,
+}
+
+impl MirageTradesProcessor {
+    pub async fn new(config: IndexerProcessorConfig) -> Result<Self> {
+        match config.db_config {
+            // Processor status is still tracked in Postgres when storing in ClickHouse
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                let conn_pool = new_db_pool(
+                    &clickhouse_config.connection_string,
+                    Some(clickhouse_config.db_pool_size),
+                )
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to create connection pool for ClickhouseConfig: {:?}",
+                        e
+                    )
+                })?;
+
+                Ok(Self {
+                    config,
+                    db_pool: conn_pool,
+                })
+            },
+            _ => Err(anyhow::anyhow!(
+                "Invalid db config for MirageTradesProcessor {:?}",
+                config.db_config
+            )),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ProcessorTrait for MirageTradesProcessor {
+    fn name(&self) -> &'static str {
+        self.config.processor_config.name()
+    }
+
+    async fn run_processor(&self) -> Result<()> {
+        let clickhouse_config = match self.config.db_config {
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => clickhouse_config,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid db config for MirageTradesProcessor {:?}",
+                    self.config.db_config
+                ))
+            },
+        };
+
+        // Run migrations
+        run_migrations(
+            clickhouse_config.connection_string.clone(),
+            self.db_pool.clone(),
+        )
+        .await;
+        ClickhouseStorer::<Vec<Trade>>::create_tables(clickhouse_config).await?;
+
+        //  Merge the starting version from config and the latest processed version from the DB
+        let starting_version = get_min_last_success_version_parquet(
+            &self.config,
+            self.db_pool.clone(),
+            ClickhouseStorer::<Vec<Trade>>::processor_status_table_names(self.name()),
+        )
+        .await?;
+
+        // Check and update the ledger chain id to ensure we're indexing the correct chain
+        let grpc_chain_id = TransactionStream::new(self.config.transaction_stream_config.clone())
+            .await?
+            .get_chain_id()
+            .await?;
+        check_or_update_chain_id(grpc_chain_id as i64, self.db_pool.clone()).await?;
+
+        let processor_config = match self.config.processor_config.clone() {
+            ProcessorConfig::MirageTradesProcessor(processor_config) => processor_config,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid processor config for MirageTradesProcessor: {:?}",
+                    self.config.processor_config
+                ))
+            },
+        };
+        let channel_size = processor_config.default_config.channel_size;
+        let deployer_address = MirageProcessorConfig::new(processor_config.deployer_address, None)?
+            .deployer_address
+            .expect("Deployer address is resolved by MirageProcessorConfig::new");
+
+        // Define processor steps
+        let transaction_stream = TransactionStreamStep::new(TransactionStreamConfig {
+            starting_version: Some(starting_version),
+            request_ending_version: match self.config.mode {
+                ProcessorMode::Default => None,
+                ProcessorMode::Backfill => self
+                    .config
+                    .backfill_config
+                    .as_ref()
+                    .map(|c| c.ending_version),
+                ProcessorMode::Testing => self
+                    .config
+                    .testing_config
+                    .as_ref()
+                    .map(|c| c.ending_version),
+            },
+            ..self.config.transaction_stream_config.clone()
+        })
+        .await?;
+        let mirage_trades_extractor = MirageTradesExtractor::new(deployer_address);
+        let stream_publisher =
+            StreamPublisher::<Vec<Trade>>::new(self.config.stream_config.as_ref())?;
+        let clickhouse_storer = ClickhouseStorer::<Vec<Trade>>::new(clickhouse_config)?;
+        let version_tracker = ParquetVersionTrackerStep::<_, &'static str>::new(
+            get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
+            DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
+        );
+
+        // Connect processor steps together
+        let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
+            transaction_stream.into_runnable_step(),
+        )
+        .connect_to(mirage_trades_extractor.into_runnable_step(), channel_size)
+        .connect_to(stream_publisher.into_runnable_step(), channel_size)
+        .connect_to(clickhouse_storer.into_runnable_step(), channel_size)
+        .connect_to(version_tracker.into_runnable_step(), channel_size)
+        .end_and_return_output_receiver(channel_size);
+
+        // (Optional) Parse the results
+        loop {
+            match buffer_receiver.recv().await {
+                Ok(txn_context) => {
+                    debug!(
+                        "Finished processing Mirage trades from versions [{:?}, {:?}]",
+                        txn_context.metadata.start_version, txn_context.metadata.end_version,
+                    );
+                },
+                Err(e) => {
+                    info!("No more transactions in channel: {:?}", e);
+                    break Ok(());
+                },
+            }
+        }
+    }
+}
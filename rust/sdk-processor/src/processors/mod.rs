@@ -4,6 +4,7 @@ pub mod ans_processor;
 pub mod default_processor;
 pub mod events_processor;
 pub mod fungible_asset_processor;
+pub mod mirage_trades_processor;
 pub mod monitoring_processor;
 pub mod objects_processor;
 pub mod stake_processor;
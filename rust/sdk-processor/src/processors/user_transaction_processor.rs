@@ -5,13 +5,17 @@ use crate::{
         processor_config::ProcessorConfig,
     },
     steps::{
-        common::get_processor_status_saver,
+        common::{
+            clickhouse_storer::ClickhouseStorer, get_processor_status_saver,
+            parquet_version_tracker_step::ParquetVersionTrackerStep,
+            stream_publisher::StreamPublisher,
+        },
         user_transaction_processor::{UserTransactionExtractor, UserTransactionStorer},
     },
     utils::{
         chain_id::check_or_update_chain_id,
         database::{new_db_pool, run_migrations, ArcDbPool},
-        starting_version::get_starting_version,
+        starting_version::{get_min_last_success_version_parquet, get_starting_version},
     },
 };
 use anyhow::Result;
@@ -23,9 +27,17 @@ use aptos_indexer_processor_sdk::{
     },
     traits::{processor_trait::ProcessorTrait, IntoRunnableStep},
 };
-use processor::utils::table_flags::TableFlags;
+use processor::{
+    db::postgres::models::user_transactions_models::{
+        signatures::Signature, user_transactions::UserTransactionModel,
+    },
+    utils::table_flags::TableFlags,
+};
 use tracing::{debug, info};
 
+type UserTransactionClickhouseStorer =
+    ClickhouseStorer<(Vec<UserTransactionModel>, Vec<Signature>)>;
+
 pub struct UserTransactionProcessor {
     pub config: IndexerProcessorConfig,
     pub db_pool: ArcDbPool,
@@ -52,6 +64,25 @@ impl UserTransactionProcessor {
                     db_pool: conn_pool,
                 })
             },
+            // Processor status is still tracked in Postgres when storing in ClickHouse
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                let conn_pool = new_db_pool(
+                    &clickhouse_config.connection_string,
+                    Some(clickhouse_config.db_pool_size),
+                )
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to create connection pool for ClickhouseConfig: {:?}",
+                        e
+                    )
+                })?;
+
+                Ok(Self {
+                    config,
+                    db_pool: conn_pool,
+                })
+            },
             _ => Err(anyhow::anyhow!(
                 "Invalid db config for UserTransactionProcessor {:?}",
                 config.db_config
@@ -68,16 +99,37 @@ impl ProcessorTrait for UserTransactionProcessor {
 
     async fn run_processor(&self) -> Result<()> {
         // Run migrations
-        if let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config {
-            run_migrations(
-                postgres_config.connection_string.clone(),
-                self.db_pool.clone(),
-            )
-            .await;
+        match self.config.db_config {
+            DbConfig::PostgresConfig(ref postgres_config) => {
+                run_migrations(
+                    postgres_config.connection_string.clone(),
+                    self.db_pool.clone(),
+                )
+                .await;
+            },
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                run_migrations(
+                    clickhouse_config.connection_string.clone(),
+                    self.db_pool.clone(),
+                )
+                .await;
+                UserTransactionClickhouseStorer::create_tables(clickhouse_config).await?;
+            },
+            _ => {},
         }
 
         //  Merge the starting version from config and the latest processed version from the DB
-        let starting_version = get_starting_version(&self.config, self.db_pool.clone()).await?;
+        let starting_version = match self.config.db_config {
+            DbConfig::ClickhouseConfig(_) => {
+                get_min_last_success_version_parquet(
+                    &self.config,
+                    self.db_pool.clone(),
+                    UserTransactionClickhouseStorer::processor_status_table_names(self.name()),
+                )
+                .await?
+            },
+            _ => get_starting_version(&self.config, self.db_pool.clone()).await?,
+        };
 
         // Check and update the ledger chain id to ensure we're indexing the correct chain
         let grpc_chain_id = TransactionStream::new(self.config.transaction_stream_config.clone())
@@ -118,20 +170,48 @@ impl ProcessorTrait for UserTransactionProcessor {
         })
         .await?;
         let user_txn_extractor = UserTransactionExtractor::new(deprecated_tables);
-        let user_txn_storer = UserTransactionStorer::new(self.db_pool.clone(), processor_config);
-        let version_tracker = VersionTrackerStep::new(
-            get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
-            DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
-        );
+        let stream_publisher = StreamPublisher::<(Vec<UserTransactionModel>, Vec<Signature>)>::new(
+            self.config.stream_config.as_ref(),
+        )?;
 
         // Connect processor steps together
-        let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
-            transaction_stream.into_runnable_step(),
-        )
-        .connect_to(user_txn_extractor.into_runnable_step(), channel_size)
-        .connect_to(user_txn_storer.into_runnable_step(), channel_size)
-        .connect_to(version_tracker.into_runnable_step(), channel_size)
-        .end_and_return_output_receiver(channel_size);
+        let buffer_receiver = match self.config.db_config {
+            DbConfig::ClickhouseConfig(ref clickhouse_config) => {
+                let clickhouse_storer = UserTransactionClickhouseStorer::new(clickhouse_config)?;
+                let version_tracker = ParquetVersionTrackerStep::<_, &'static str>::new(
+                    get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
+                    DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
+                );
+
+                let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
+                    transaction_stream.into_runnable_step(),
+                )
+                .connect_to(user_txn_extractor.into_runnable_step(), channel_size)
+                .connect_to(stream_publisher.into_runnable_step(), channel_size)
+                .connect_to(clickhouse_storer.into_runnable_step(), channel_size)
+                .connect_to(version_tracker.into_runnable_step(), channel_size)
+                .end_and_return_output_receiver(channel_size);
+                buffer_receiver
+            },
+            _ => {
+                let user_txn_storer =
+                    UserTransactionStorer::new(self.db_pool.clone(), processor_config);
+                let version_tracker = VersionTrackerStep::new(
+                    get_processor_status_saver(self.db_pool.clone(), self.config.clone()),
+                    DEFAULT_UPDATE_PROCESSOR_STATUS_SECS,
+                );
+
+                let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
+                    transaction_stream.into_runnable_step(),
+                )
+                .connect_to(user_txn_extractor.into_runnable_step(), channel_size)
+                .connect_to(stream_publisher.into_runnable_step(), channel_size)
+                .connect_to(user_txn_storer.into_runnable_step(), channel_size)
+                .connect_to(version_tracker.into_runnable_step(), channel_size)
+                .end_and_return_output_receiver(channel_size);
+                buffer_receiver
+            },
+        };
 
         loop {
             match buffer_receiver.recv().await {
@@ -0,0 +1,903 @@
+use crate::{
+    config::db_config::ClickhouseConfig,
+    utils::{clickhouse::ClickhouseClient, parquet_processor_table_mapping::format_table_name},
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    traits::{
+        pollable_async_step::PollableAsyncRunType, NamedStep, PollableAsyncStep, Processable,
+    },
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+    utils::errors::ProcessorError,
+};
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use clickhouse_rs::Block;
+use processor::db::{
+    common::models::market_models::market_activities::Trade,
+    postgres::models::{
+        coin_models::coin_supply::CoinSupply,
+        events_models::events::EventPG,
+        fungible_asset_models::{
+            v2_fungible_asset_activities::FungibleAssetActivity,
+            v2_fungible_asset_balances::{
+                CurrentUnifiedFungibleAssetBalance, FungibleAssetBalance,
+            },
+            v2_fungible_asset_to_coin_mappings::FungibleAssetToCoinMapping,
+            v2_fungible_metadata::FungibleAssetMetadataModel,
+        },
+        user_transactions_models::{
+            signatures::Signature, user_transactions::UserTransactionModel,
+        },
+    },
+};
+use std::{collections::HashMap, marker::PhantomData, time::Duration};
+use tracing::debug;
+
+/// Values of a column, of the type of the ClickHouse column they're inserted into.
+pub enum ClickhouseColumn {
+    Int64(Vec<i64>),
+    UInt64(Vec<u64>),
+    NullableUInt64(Vec<Option<u64>>),
+    Bool(Vec<bool>),
+    NullableBool(Vec<Option<bool>>),
+    String(Vec<String>),
+    NullableString(Vec<Option<String>>),
+    DateTime(Vec<DateTime<Tz>>),
+}
+
+macro_rules! clickhouse_column_from {
+    ($($variant:ident($type:ty)),*) => {
+        $(
+            impl From<Vec<$type>> for ClickhouseColumn {
+                fn from(values: Vec<$type>) -> Self {
+                    Self::$variant(values)
+                }
+            }
+        )*
+    };
+}
+
+clickhouse_column_from!(
+    Int64(i64),
+    UInt64(u64),
+    NullableUInt64(Option<u64>),
+    Bool(bool),
+    NullableBool(Option<bool>),
+    String(String),
+    NullableString(Option<String>),
+    DateTime(DateTime<Tz>)
+);
+
+impl ClickhouseColumn {
+    fn len(&self) -> usize {
+        match self {
+            Self::Int64(values) => values.len(),
+            Self::UInt64(values) => values.len(),
+            Self::NullableUInt64(values) => values.len(),
+            Self::Bool(values) => values.len(),
+            Self::NullableBool(values) => values.len(),
+            Self::String(values) => values.len(),
+            Self::NullableString(values) => values.len(),
+            Self::DateTime(values) => values.len(),
+        }
+    }
+
+    /// Appends the values of `other`, which has to be a column of the same type.
+    fn extend(&mut self, other: Self) -> Result<(), ProcessorError> {
+        match (self, other) {
+            (Self::Int64(values), Self::Int64(other)) => values.extend(other),
+            (Self::UInt64(values), Self::UInt64(other)) => values.extend(other),
+            (Self::NullableUInt64(values), Self::NullableUInt64(other)) => values.extend(other),
+            (Self::Bool(values), Self::Bool(other)) => values.extend(other),
+            (Self::NullableBool(values), Self::NullableBool(other)) => values.extend(other),
+            (Self::String(values), Self::String(other)) => values.extend(other),
+            (Self::NullableString(values), Self::NullableString(other)) => values.extend(other),
+            (Self::DateTime(values), Self::DateTime(other)) => values.extend(other),
+            _ => {
+                return Err(ProcessorError::ProcessError {
+                    message: "Mismatched ClickHouse column types".to_string(),
+                })
+            },
+        }
+        Ok(())
+    }
+
+    fn add_to_block(self, block: Block, name: &str) -> Block {
+        match self {
+            Self::Int64(values) => block.column(name, values),
+            Self::UInt64(values) => block.column(name, values),
+            Self::NullableUInt64(values) => block.column(name, values),
+            Self::Bool(values) => block.column(name, values),
+            Self::NullableBool(values) => block.column(name, values),
+            Self::String(values) => block.column(name, values),
+            Self::NullableString(values) => block.column(name, values),
+            Self::DateTime(values) => block.column(name, values),
+        }
+    }
+}
+
+/// Rows of a table, as columns in the order of the table's columns.
+pub struct ClickhouseTableRows {
+    pub table_name: &'static str,
+    pub num_rows: usize,
+    pub columns: Vec<(&'static str, ClickhouseColumn)>,
+}
+
+impl ClickhouseTableRows {
+    pub fn new(
+        table_name: &'static str,
+        columns: Vec<(&'static str, ClickhouseColumn)>,
+    ) -> Result<Self, ProcessorError> {
+        let num_rows = columns.first().map_or(0, |(_, values)| values.len());
+        if let Some((name, _)) = columns.iter().find(|(_, values)| values.len() != num_rows) {
+            return Err(ProcessorError::ProcessError {
+                message: format!(
+                    "Column {} of {} doesn't have {} rows",
+                    name, table_name, num_rows
+                ),
+            });
+        }
+        Ok(Self {
+            table_name,
+            num_rows,
+            columns,
+        })
+    }
+
+    fn into_block(self) -> Block {
+        self.columns
+            .into_iter()
+            .fold(Block::new(), |block, (name, values)| {
+                values.add_to_block(block, name)
+            })
+    }
+}
+
+fn column<R, T>(rows: &[R], value: impl Fn(&R) -> T) -> ClickhouseColumn
+where
+    ClickhouseColumn: From<Vec<T>>,
+{
+    rows.iter().map(value).collect::<Vec<_>>().into()
+}
+
+fn try_column<R, T>(
+    rows: &[R],
+    value: impl Fn(&R) -> Result<T, ProcessorError>,
+) -> Result<ClickhouseColumn, ProcessorError>
+where
+    ClickhouseColumn: From<Vec<T>>,
+{
+    Ok(rows
+        .iter()
+        .map(value)
+        .collect::<Result<Vec<_>, _>>()?
+        .into())
+}
+
+/// Converts an on-chain u64 amount, stored as numeric in Postgres, for a UInt64 column.
+fn to_u64(table_name: &str, value: &BigDecimal) -> Result<u64, ProcessorError> {
+    value.to_u64().ok_or_else(|| ProcessorError::ProcessError {
+        message: format!("{} value {} doesn't fit a UInt64", table_name, value),
+    })
+}
+
+/// Converts a signed amount whose magnitude is an on-chain u64, e.g. a pnl, for an Int64 column.
+fn to_i64(table_name: &str, value: &BigDecimal) -> Result<i64, ProcessorError> {
+    value.to_i64().ok_or_else(|| ProcessorError::ProcessError {
+        message: format!("{} value {} doesn't fit an Int64", table_name, value),
+    })
+}
+
+fn to_datetime(timestamp: &NaiveDateTime) -> DateTime<Tz> {
+    Tz::UTC.from_utc_datetime(timestamp)
+}
+
+/// Output of an extractor step that can be stored in ClickHouse.
+pub trait ClickhouseRows: Send + 'static {
+    const TABLE_NAMES: &'static [&'static str];
+
+    /// `CREATE TABLE IF NOT EXISTS` of each table in `TABLE_NAMES`. Tables are ReplacingMergeTrees
+    /// ordered by the Postgres primary key, so rows reinserted after a restart are merged away.
+    /// Amounts that are numeric in Postgres are on-chain u64s, so they're UInt64 columns.
+    const CREATE_TABLES: &'static [&'static str];
+
+    /// Rows of every table in `TABLE_NAMES`, even without any, so the progress of each is tracked.
+    fn clickhouse_rows(&self) -> Result<Vec<ClickhouseTableRows>, ProcessorError>;
+}
+
+impl ClickhouseRows for Vec<EventPG> {
+    const CREATE_TABLES: &'static [&'static str] = &["CREATE TABLE IF NOT EXISTS events (
+        sequence_number Int64,
+        creation_number Int64,
+        account_address String,
+        transaction_version Int64,
+        transaction_block_height Int64,
+        type_ String,
+        data String,
+        event_index Int64,
+        indexed_type String
+    ) ENGINE = ReplacingMergeTree ORDER BY (transaction_version, event_index)"];
+    const TABLE_NAMES: &'static [&'static str] = &["events"];
+
+    fn clickhouse_rows(&self) -> Result<Vec<ClickhouseTableRows>, ProcessorError> {
+        ClickhouseTableRows::new("events", vec![
+            ("sequence_number", column(self, |e| e.sequence_number)),
+            ("creation_number", column(self, |e| e.creation_number)),
+            (
+                "account_address",
+                column(self, |e| e.account_address.clone()),
+            ),
+            (
+                "transaction_version",
+                column(self, |e| e.transaction_version),
+            ),
+            (
+                "transaction_block_height",
+                column(self, |e| e.transaction_block_height),
+            ),
+            ("type_", column(self, |e| e.type_.clone())),
+            ("data", column(self, |e| e.data.to_string())),
+            ("event_index", column(self, |e| e.event_index)),
+            ("indexed_type", column(self, |e| e.indexed_type.clone())),
+        ])
+        .map(|rows| vec![rows])
+    }
+}
+
+impl ClickhouseRows for (Vec<UserTransactionModel>, Vec<Signature>) {
+    const CREATE_TABLES: &'static [&'static str] = &[
+        "CREATE TABLE IF NOT EXISTS user_transactions (
+            version Int64,
+            block_height Int64,
+            parent_signature_type String,
+            sender String,
+            sequence_number Int64,
+            max_gas_amount UInt64,
+            expiration_timestamp_secs DateTime64(6),
+            gas_unit_price UInt64,
+            timestamp DateTime64(6),
+            entry_function_id_str String,
+            epoch Int64,
+            entry_function_contract_address Nullable(String),
+            entry_function_module_name Nullable(String),
+            entry_function_function_name Nullable(String)
+        ) ENGINE = ReplacingMergeTree ORDER BY version",
+        "CREATE TABLE IF NOT EXISTS signatures (
+            transaction_version Int64,
+            multi_agent_index Int64,
+            multi_sig_index Int64,
+            transaction_block_height Int64,
+            signer String,
+            is_sender_primary Bool,
+            type_ String,
+            public_key String,
+            signature String,
+            threshold Int64,
+            public_key_indices String
+        ) ENGINE = ReplacingMergeTree
+        ORDER BY (transaction_version, multi_agent_index, multi_sig_index, is_sender_primary)",
+    ];
+    const TABLE_NAMES: &'static [&'static str] = &["user_transactions", "signatures"];
+
+    fn clickhouse_rows(&self) -> Result<Vec<ClickhouseTableRows>, ProcessorError> {
+        let (user_transactions, signatures) = self;
+        Ok(vec![
+            ClickhouseTableRows::new("user_transactions", vec![
+                ("version", column(user_transactions, |t| t.version)),
+                (
+                    "block_height",
+                    column(user_transactions, |t| t.block_height),
+                ),
+                (
+                    "parent_signature_type",
+                    column(user_transactions, |t| t.parent_signature_type.clone()),
+                ),
+                ("sender", column(user_transactions, |t| t.sender.clone())),
+                (
+                    "sequence_number",
+                    column(user_transactions, |t| t.sequence_number),
+                ),
+                (
+                    "max_gas_amount",
+                    try_column(user_transactions, |t| {
+                        to_u64("user_transactions", &t.max_gas_amount)
+                    })?,
+                ),
+                (
+                    "expiration_timestamp_secs",
+                    column(user_transactions, |t| {
+                        to_datetime(&t.expiration_timestamp_secs)
+                    }),
+                ),
+                (
+                    "gas_unit_price",
+                    try_column(user_transactions, |t| {
+                        to_u64("user_transactions", &t.gas_unit_price)
+                    })?,
+                ),
+                (
+                    "timestamp",
+                    column(user_transactions, |t| to_datetime(&t.timestamp)),
+                ),
+                (
+                    "entry_function_id_str",
+                    column(user_transactions, |t| t.entry_function_id_str.clone()),
+                ),
+                ("epoch", column(user_transactions, |t| t.epoch)),
+                (
+                    "entry_function_contract_address",
+                    column(user_transactions, |t| {
+                        t.entry_function_contract_address.clone()
+                    }),
+                ),
+                (
+                    "entry_function_module_name",
+                    column(user_transactions, |t| t.entry_function_module_name.clone()),
+                ),
+                (
+                    "entry_function_function_name",
+                    column(user_transactions, |t| {
+                        t.entry_function_function_name.clone()
+                    }),
+                ),
+            ])?,
+            ClickhouseTableRows::new("signatures", vec![
+                (
+                    "transaction_version",
+                    column(signatures, |s| s.transaction_version),
+                ),
+                (
+                    "multi_agent_index",
+                    column(signatures, |s| s.multi_agent_index),
+                ),
+                ("multi_sig_index", column(signatures, |s| s.multi_sig_index)),
+                (
+                    "transaction_block_height",
+                    column(signatures, |s| s.transaction_block_height),
+                ),
+                ("signer", column(signatures, |s| s.signer.clone())),
+                (
+                    "is_sender_primary",
+                    column(signatures, |s| s.is_sender_primary),
+                ),
+                ("type_", column(signatures, |s| s.type_.clone())),
+                ("public_key", column(signatures, |s| s.public_key.clone())),
+                ("signature", column(signatures, |s| s.signature.clone())),
+                ("threshold", column(signatures, |s| s.threshold)),
+                (
+                    "public_key_indices",
+                    column(signatures, |s| s.public_key_indices.to_string()),
+                ),
+            ])?,
+        ])
+    }
+}
+
+/// Only activities are stored, balances and metadata are current state that ClickHouse tables
+/// would have to merge.
+impl ClickhouseRows
+    for (
+        Vec<FungibleAssetActivity>,
+        Vec<FungibleAssetMetadataModel>,
+        Vec<FungibleAssetBalance>,
+        (
+            Vec<CurrentUnifiedFungibleAssetBalance>,
+            Vec<CurrentUnifiedFungibleAssetBalance>,
+        ),
+        Vec<CoinSupply>,
+        Vec<FungibleAssetToCoinMapping>,
+    )
+{
+    const CREATE_TABLES: &'static [&'static str] =
+        &["CREATE TABLE IF NOT EXISTS fungible_asset_activities (
+            transaction_version Int64,
+            event_index Int64,
+            owner_address Nullable(String),
+            storage_id String,
+            asset_type Nullable(String),
+            is_frozen Nullable(Bool),
+            amount Nullable(UInt64),
+            type_ String,
+            is_gas_fee Bool,
+            gas_fee_payer_address Nullable(String),
+            is_transaction_success Bool,
+            entry_function_id_str Nullable(String),
+            block_height Int64,
+            token_standard String,
+            transaction_timestamp DateTime64(6),
+            storage_refund_amount UInt64
+        ) ENGINE = ReplacingMergeTree ORDER BY (transaction_version, event_index)"];
+    const TABLE_NAMES: &'static [&'static str] = &["fungible_asset_activities"];
+
+    fn clickhouse_rows(&self) -> Result<Vec<ClickhouseTableRows>, ProcessorError> {
+        let activities = &self.0;
+        ClickhouseTableRows::new("fungible_asset_activities", vec![
+            (
+                "transaction_version",
+                column(activities, |a| a.transaction_version),
+            ),
+            ("event_index", column(activities, |a| a.event_index)),
+            (
+                "owner_address",
+                column(activities, |a| a.owner_address.clone()),
+            ),
+            ("storage_id", column(activities, |a| a.storage_id.clone())),
+            ("asset_type", column(activities, |a| a.asset_type.clone())),
+            ("is_frozen", column(activities, |a| a.is_frozen)),
+            (
+                "amount",
+                try_column(activities, |a| {
+                    a.amount
+                        .as_ref()
+                        .map(|amount| to_u64("fungible_asset_activities", amount))
+                        .transpose()
+                })?,
+            ),
+            ("type_", column(activities, |a| a.type_.clone())),
+            ("is_gas_fee", column(activities, |a| a.is_gas_fee)),
+            (
+                "gas_fee_payer_address",
+                column(activities, |a| a.gas_fee_payer_address.clone()),
+            ),
+            (
+                "is_transaction_success",
+                column(activities, |a| a.is_transaction_success),
+            ),
+            (
+                "entry_function_id_str",
+                column(activities, |a| a.entry_function_id_str.clone()),
+            ),
+            ("block_height", column(activities, |a| a.block_height)),
+            (
+                "token_standard",
+                column(activities, |a| a.token_standard.clone()),
+            ),
+            (
+                "transaction_timestamp",
+                column(activities, |a| to_datetime(&a.transaction_timestamp)),
+            ),
+            (
+                "storage_refund_amount",
+                try_column(activities, |a| {
+                    to_u64("fungible_asset_activities", &a.storage_refund_amount)
+                })?,
+            ),
+        ])
+        .map(|rows| vec![rows])
+    }
+}
+
+impl ClickhouseRows for Vec<Trade> {
+    const CREATE_TABLES: &'static [&'static str] = &["CREATE TABLE IF NOT EXISTS trade_datas (
+        transaction_version Int64,
+        market_id String,
+        position_id String,
+        owner_addr String,
+        is_long Bool,
+        position_size UInt64,
+        price UInt64,
+        fee UInt64,
+        pnl Int64,
+        event_type String,
+        transaction_timestamp DateTime64(6)
+    ) ENGINE = ReplacingMergeTree ORDER BY (transaction_version, position_id)"];
+    const TABLE_NAMES: &'static [&'static str] = &["trade_datas"];
+
+    fn clickhouse_rows(&self) -> Result<Vec<ClickhouseTableRows>, ProcessorError> {
+        ClickhouseTableRows::new("trade_datas", vec![
+            (
+                "transaction_version",
+                column(self, |t| t.transaction_version),
+            ),
+            ("market_id", column(self, |t| t.market_id.clone())),
+            ("position_id", column(self, |t| t.position_id.clone())),
+            ("owner_addr", column(self, |t| t.owner_addr.clone())),
+            ("is_long", column(self, |t| t.is_long)),
+            (
+                "position_size",
+                try_column(self, |t| to_u64("trade_datas", &t.position_size))?,
+            ),
+            (
+                "price",
+                try_column(self, |t| to_u64("trade_datas", &t.price))?,
+            ),
+            ("fee", try_column(self, |t| to_u64("trade_datas", &t.fee))?),
+            ("pnl", try_column(self, |t| to_i64("trade_datas", &t.pnl))?),
+            ("event_type", column(self, |t| t.event_type.clone())),
+            (
+                "transaction_timestamp",
+                column(self, |t| to_datetime(&t.transaction_timestamp)),
+            ),
+        ])
+        .map(|rows| vec![rows])
+    }
+}
+
+#[derive(Default)]
+struct TableBuffer {
+    rows: Option<ClickhouseTableRows>,
+    metadata: Option<TransactionMetadata>,
+}
+
+impl TableBuffer {
+    fn num_rows(&self) -> usize {
+        self.rows.as_ref().map_or(0, |rows| rows.num_rows)
+    }
+}
+
+/// Buffers the rows of each table and inserts them into ClickHouse once `max_batch_rows` are
+/// buffered, or every `flush_interval`. Outputs the versions inserted per table, for the
+/// `ParquetVersionTrackerStep` to track.
+///
+/// Important: this step assumes ordered transactions.
+pub struct ClickhouseStorer<T>
+where
+    T: ClickhouseRows,
+{
+    client: ClickhouseClient,
+    buffers: HashMap<&'static str, TableBuffer>,
+    max_batch_rows: usize,
+    flush_interval: Duration,
+    _rows: PhantomData<fn(T)>,
+}
+
+impl<T> ClickhouseStorer<T>
+where
+    T: ClickhouseRows,
+{
+    pub fn new(config: &ClickhouseConfig) -> Result<Self> {
+        Ok(Self {
+            client: ClickhouseClient::new(config)?,
+            buffers: HashMap::new(),
+            max_batch_rows: config.max_batch_rows,
+            flush_interval: Duration::from_secs(config.flush_interval_secs),
+            _rows: PhantomData,
+        })
+    }
+
+    /// Names the progress of each table is tracked under in the processor status table.
+    pub fn processor_status_table_names(processor_name: &str) -> Vec<String> {
+        T::TABLE_NAMES
+            .iter()
+            .map(|table_name| format_table_name(processor_name, table_name))
+            .collect()
+    }
+
+    /// Creates the tables in ClickHouse, if they don't exist yet.
+    pub async fn create_tables(config: &ClickhouseConfig) -> Result<()> {
+        let client = ClickhouseClient::new(config)?;
+        for statement in T::CREATE_TABLES {
+            client.execute(statement).await?;
+        }
+        Ok(())
+    }
+
+    fn append(
+        buffer: &mut TableBuffer,
+        rows: ClickhouseTableRows,
+        metadata: &TransactionMetadata,
+    ) -> Result<(), ProcessorError> {
+        let table_name = rows.table_name;
+        match &mut buffer.metadata {
+            Some(buffer_metadata) => {
+                if buffer_metadata.end_version + 1 != metadata.start_version {
+                    return Err(ProcessorError::ProcessError {
+                        message: format!(
+                            "Gap found for {}: buffer end_version {} != start_version {}",
+                            table_name, buffer_metadata.end_version, metadata.start_version
+                        ),
+                    });
+                }
+                buffer_metadata.end_version = metadata.end_version;
+                buffer_metadata.end_transaction_timestamp = metadata.end_transaction_timestamp;
+                buffer_metadata.total_size_in_bytes += metadata.total_size_in_bytes;
+            },
+            None => buffer.metadata = Some(metadata.clone()),
+        }
+        match &mut buffer.rows {
+            Some(buffer_rows) => {
+                for ((_, values), (_, other)) in buffer_rows.columns.iter_mut().zip(rows.columns) {
+                    values.extend(other)?;
+                }
+                buffer_rows.num_rows += rows.num_rows;
+            },
+            None => buffer.rows = Some(rows),
+        }
+        Ok(())
+    }
+
+    /// Inserts the rows buffered for `table_name`, returning the versions they cover.
+    async fn flush(
+        client: &ClickhouseClient,
+        table_name: &'static str,
+        buffer: &mut TableBuffer,
+    ) -> Result<Option<TransactionMetadata>, ProcessorError> {
+        let Some(metadata) = buffer.metadata.take() else {
+            return Ok(None);
+        };
+        let rows = buffer.rows.take();
+        if let Some(rows) = rows.filter(|rows| rows.num_rows > 0) {
+            let num_rows = rows.num_rows;
+            client
+                .insert(table_name, rows.into_block())
+                .await
+                .map_err(|e| ProcessorError::DBStoreError {
+                    message: format!(
+                        "Failed to store {} versions {} to {}: {:?}",
+                        table_name, metadata.start_version, metadata.end_version, e,
+                    ),
+                    query: None,
+                })?;
+            debug!(
+                table_name = table_name,
+                num_rows = num_rows,
+                start_version = metadata.start_version,
+                end_version = metadata.end_version,
+                "Inserted rows into ClickHouse"
+            );
+        }
+        Ok(Some(metadata))
+    }
+
+    async fn flush_all(
+        &mut self,
+    ) -> Result<
+        Option<Vec<TransactionContext<HashMap<&'static str, TransactionMetadata>>>>,
+        ProcessorError,
+    > {
+        let mut metadata_map = HashMap::new();
+        for (&table_name, buffer) in self.buffers.iter_mut() {
+            if let Some(metadata) = Self::flush(&self.client, table_name, buffer).await? {
+                metadata_map.insert(table_name, metadata);
+            }
+        }
+        if metadata_map.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(vec![TransactionContext {
+            data: metadata_map,
+            metadata: TransactionMetadata::default(),
+        }]))
+    }
+}
+
+#[async_trait]
+impl<T> Processable for ClickhouseStorer<T>
+where
+    T: ClickhouseRows,
+{
+    type Input = T;
+    type Output = HashMap<&'static str, TransactionMetadata>;
+    type RunType = PollableAsyncRunType;
+
+    async fn process(
+        &mut self,
+        item: TransactionContext<T>,
+    ) -> Result<Option<TransactionContext<Self::Output>>, ProcessorError> {
+        let mut metadata_map = HashMap::new();
+        for rows in item.data.clickhouse_rows()? {
+            let table_name = rows.table_name;
+            let buffer = self.buffers.entry(table_name).or_default();
+            Self::append(buffer, rows, &item.metadata)?;
+            if buffer.num_rows() >= self.max_batch_rows {
+                if let Some(metadata) = Self::flush(&self.client, table_name, buffer).await? {
+                    metadata_map.insert(table_name, metadata);
+                }
+            }
+        }
+
+        if metadata_map.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(TransactionContext {
+            data: metadata_map,
+            metadata: item.metadata,
+        }))
+    }
+
+    async fn cleanup(
+        &mut self,
+    ) -> Result<Option<Vec<TransactionContext<Self::Output>>>, ProcessorError> {
+        self.flush_all().await
+    }
+}
+
+#[async_trait]
+impl<T> PollableAsyncStep for ClickhouseStorer<T>
+where
+    T: ClickhouseRows,
+{
+    fn poll_interval(&self) -> Duration {
+        self.flush_interval
+    }
+
+    /// Inserts whatever is buffered, so rows of quiet tables don't wait for a full batch.
+    async fn poll(
+        &mut self,
+    ) -> Result<Option<Vec<TransactionContext<Self::Output>>>, ProcessorError> {
+        self.flush_all().await
+    }
+}
+
+impl<T> NamedStep for ClickhouseStorer<T>
+where
+    T: ClickhouseRows,
+{
+    fn name(&self) -> String {
+        "ClickhouseStorer".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(transaction_version: i64, type_: &str) -> EventPG {
+        EventPG {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: "0x1".to_string(),
+            transaction_version,
+            transaction_block_height: 0,
+            type_: type_.to_string(),
+            data: serde_json::json!({ "amount": "1" }),
+            event_index: 0,
+            indexed_type: type_.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_clickhouse_table_rows() {
+        let mut rows = vec![
+            event(1, "0x1::coin::CoinDeposit"),
+            event(2, "0x1::coin::CoinWithdraw"),
+        ]
+        .clickhouse_rows()
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        let rows = rows.remove(0);
+        assert_eq!(rows.table_name, "events");
+        assert_eq!(rows.num_rows, 2);
+
+        let block = rows.into_block();
+        assert_eq!(block.row_count(), 2);
+        assert_eq!(block.column_count(), 9);
+        assert_eq!(block.get::<i64, _>(1, "transaction_version").unwrap(), 2);
+        assert_eq!(
+            block.get::<String, _>(0, "type_").unwrap(),
+            "0x1::coin::CoinDeposit"
+        );
+        assert_eq!(
+            block.get::<String, _>(0, "data").unwrap(),
+            "{\"amount\":\"1\"}"
+        );
+    }
+
+    #[test]
+    fn test_clickhouse_table_rows_rejects_uneven_columns() {
+        let result = ClickhouseTableRows::new("events", vec![
+            ("transaction_version", vec![1_i64, 2].into()),
+            ("type_", vec!["0x1::coin::CoinDeposit".to_string()].into()),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_amounts_must_fit_u64() {
+        assert_eq!(
+            to_u64("trade_datas", &BigDecimal::from(u64::MAX)).unwrap(),
+            u64::MAX
+        );
+        assert!(to_u64("trade_datas", &(BigDecimal::from(u64::MAX) + 1)).is_err());
+        assert!(to_u64("trade_datas", &BigDecimal::from(-1)).is_err());
+    }
+
+    fn metadata(start_version: u64, end_version: u64) -> TransactionMetadata {
+        TransactionMetadata {
+            start_version,
+            end_version,
+            total_size_in_bytes: 10,
+            ..TransactionMetadata::default()
+        }
+    }
+
+    fn storer() -> ClickhouseStorer<Vec<EventPG>> {
+        ClickhouseStorer::new(&ClickhouseConfig {
+            connection_string: "postgres://localhost/test".to_string(),
+            db_pool_size: 1,
+            // Nothing is inserted, so nothing is listening
+            clickhouse_url: "tcp://localhost:1".parse().unwrap(),
+            clickhouse_database: ClickhouseConfig::default_clickhouse_database(),
+            clickhouse_user: None,
+            clickhouse_password: None,
+            max_batch_rows: ClickhouseConfig::default_max_batch_rows(),
+            flush_interval_secs: ClickhouseConfig::default_flush_interval_secs(),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_clickhouse_storer_buffers_rows_of_batches() {
+        let mut storer = storer();
+        for version in [0, 1] {
+            storer
+                .process(TransactionContext {
+                    data: vec![event(version, "0x1::coin::CoinDeposit")],
+                    metadata: metadata(version as u64, version as u64),
+                })
+                .await
+                .unwrap();
+        }
+
+        let rows = storer.buffers["events"].rows.as_ref().unwrap();
+        assert_eq!(rows.num_rows, 2);
+        match &rows.columns[3] {
+            ("transaction_version", ClickhouseColumn::Int64(versions)) => {
+                assert_eq!(versions, &vec![0, 1])
+            },
+            _ => panic!("Expected the transaction versions"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clickhouse_storer_merges_metadata_until_flushed() {
+        let mut storer = storer();
+        for (start_version, end_version) in [(0, 9), (10, 19)] {
+            let output = storer
+                .process(TransactionContext {
+                    data: vec![],
+                    metadata: metadata(start_version, end_version),
+                })
+                .await
+                .unwrap();
+            assert!(output.is_none());
+        }
+
+        let flushed = storer.poll().await.unwrap().unwrap();
+        assert_eq!(flushed.len(), 1);
+        let events_metadata = &flushed[0].data["events"];
+        assert_eq!(events_metadata.start_version, 0);
+        assert_eq!(events_metadata.end_version, 19);
+        assert_eq!(events_metadata.total_size_in_bytes, 20);
+
+        // Nothing is left to flush, and the next batch starts a new range
+        assert!(storer.poll().await.unwrap().is_none());
+        storer
+            .process(TransactionContext {
+                data: vec![],
+                metadata: metadata(20, 29),
+            })
+            .await
+            .unwrap();
+        let flushed = storer.poll().await.unwrap().unwrap();
+        assert_eq!(flushed[0].data["events"].start_version, 20);
+        assert_eq!(flushed[0].data["events"].end_version, 29);
+    }
+
+    #[tokio::test]
+    async fn test_clickhouse_storer_rejects_gaps() {
+        let mut storer = storer();
+        storer
+            .process(TransactionContext {
+                data: vec![],
+                metadata: metadata(0, 9),
+            })
+            .await
+            .unwrap();
+        let result = storer
+            .process(TransactionContext {
+                data: vec![],
+                metadata: metadata(11, 19),
+            })
+            .await;
+        match result {
+            Err(ProcessorError::ProcessError { message }) => {
+                assert!(message.starts_with("Gap found for events"), "{}", message)
+            },
+            _ => panic!("Expected a gap to be found"),
+        }
+    }
+}
@@ -1,6 +1,8 @@
-pub mod gcs_uploader;
+pub mod clickhouse_storer;
 pub mod parquet_buffer_step;
+pub mod parquet_uploader;
 pub mod parquet_version_tracker_step;
 pub mod processor_status_saver;
+pub mod stream_publisher;
 
 pub use processor_status_saver::get_processor_status_saver;
@@ -2,7 +2,7 @@ use crate::parquet_processors::ParquetTypeTrait;
 #[allow(unused_imports)]
 use crate::{
     parquet_processors::{ParquetTypeEnum, ParquetTypeStructs},
-    steps::common::gcs_uploader::{GCSUploader, Uploadable},
+    steps::common::parquet_uploader::{ParquetUploader, Uploadable},
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
@@ -13,26 +13,53 @@ use aptos_indexer_processor_sdk::{
     utils::errors::ProcessorError,
 };
 use async_trait::async_trait;
-use std::{collections::HashMap, time::Duration};
-use tracing::debug;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+/// Limits at which the buffer of a table is uploaded, whichever is reached first.
+#[derive(Clone, Copy, Debug)]
+pub struct RollingPolicy {
+    pub max_bytes: usize,
+    pub max_rows: Option<usize>,
+    // Max time since the table was last uploaded
+    pub max_age: Duration,
+}
 
 /// `ParquetBuffer` is a struct that holds `ParquetTypeStructs` data
 /// and tracks the buffer size in bytes, along with metadata about the data in the buffer.
 struct ParquetBuffer {
     pub buffer: ParquetTypeStructs,
     pub buffer_size_bytes: usize,
+    pub num_rows: usize,
+    pub last_upload_time: Instant,
     current_batch_metadata: Option<TransactionMetadata>,
 }
 
 impl ParquetBuffer {
-    fn new(parquet_type: &ParquetTypeEnum) -> Self {
+    fn new(parquet_type: &ParquetTypeEnum, last_upload_time: Instant) -> Self {
         Self {
             buffer: ParquetTypeStructs::default_for_type(parquet_type),
             buffer_size_bytes: 0,
+            num_rows: 0,
+            last_upload_time,
             current_batch_metadata: None,
         }
     }
 
+    /// Takes the buffered data to upload, leaving the buffer empty.
+    fn take(&mut self, parquet_type: &ParquetTypeEnum, upload_time: Instant) -> ParquetTypeStructs {
+        self.buffer_size_bytes = 0;
+        self.num_rows = 0;
+        self.last_upload_time = upload_time;
+        std::mem::replace(
+            &mut self.buffer,
+            ParquetTypeStructs::default_for_type(parquet_type),
+        )
+    }
+
     /// Updates the metadata of the internal buffer with the latest information from `cur_batch_metadata`.
     /// This is used to track the end version and timestamp of the batch data in the buffer.
     pub fn update_current_batch_metadata(
@@ -63,9 +90,10 @@ impl ParquetBuffer {
     }
 }
 
-/// `ParquetBufferStep` is a step that accumulates data in buffers until they reach a specified size limit.
+/// `ParquetBufferStep` is a step that accumulates data in buffers until they reach the limits of
+/// their table's `RollingPolicy`.
 ///
-/// It then uploads the buffered data to Google Cloud Storage (GCS) through an uploader.
+/// It then uploads the buffered data to the configured object store through an uploader.
 /// This step is typically used to manage large data volumes efficiently by buffering and uploading
 /// only when necessary.
 ///
@@ -75,37 +103,56 @@ impl ParquetBuffer {
 pub struct ParquetBufferStep {
     internal_buffers: HashMap<ParquetTypeEnum, ParquetBuffer>,
     pub poll_interval: Duration,
-    pub buffer_uploader: GCSUploader,
-    pub buffer_max_size: usize,
+    pub buffer_uploader: ParquetUploader,
+    pub default_rolling_policy: RollingPolicy,
+    pub rolling_policies: HashMap<ParquetTypeEnum, RollingPolicy>,
+    started_at: Instant,
 }
 
 impl ParquetBufferStep {
+    /// Tables without a policy in `rolling_policies` are uploaded with `default_rolling_policy`.
     pub fn new(
-        poll_interval: Duration,
-        buffer_uploader: GCSUploader,
-        buffer_max_size: usize,
+        buffer_uploader: ParquetUploader,
+        default_rolling_policy: RollingPolicy,
+        rolling_policies: HashMap<ParquetTypeEnum, RollingPolicy>,
     ) -> Self {
+        // Polled often enough to upload every table right when it reaches its max age
+        let poll_interval_secs = rolling_policies
+            .values()
+            .map(|policy| policy.max_age.as_secs())
+            .fold(default_rolling_policy.max_age.as_secs(), gcd)
+            .max(1);
         Self {
             internal_buffers: HashMap::new(),
-            poll_interval,
+            poll_interval: Duration::from_secs(poll_interval_secs),
             buffer_uploader,
-            buffer_max_size,
+            default_rolling_policy,
+            rolling_policies,
+            started_at: Instant::now(),
         }
     }
 
+    fn rolling_policy(&self, parquet_type: &ParquetTypeEnum) -> RollingPolicy {
+        self.rolling_policies
+            .get(parquet_type)
+            .copied()
+            .unwrap_or(self.default_rolling_policy)
+    }
+
     fn append_to_buffer(
         buffer: &mut ParquetBuffer,
         parquet_data: ParquetTypeStructs,
     ) -> Result<(), ProcessorError> {
         buffer.buffer_size_bytes += parquet_data.calculate_size();
+        buffer.num_rows += parquet_data.num_rows();
         buffer.buffer.append(parquet_data)?;
         Ok(())
     }
 
     /// Handles the addition of `parquet_data` to the buffer for a specified `ParquetTypeEnum`.
     ///
-    /// We check the size of the buffer + the size of the incoming data before appending it.
-    /// If the sum of the two exceeds the maximum limit size, it uploads the buffer content to GCS to avoid
+    /// We check the size and rows of the buffer + those of the incoming data before appending it.
+    /// If either sum exceeds the table's limit, it uploads the buffer content to avoid
     /// spliting the batch data, allowing for more efficient and simpler version tracking.
     async fn upload_buffer_append(
         &mut self,
@@ -114,6 +161,8 @@ impl ParquetBufferStep {
         cur_batch_metadata: &TransactionMetadata,
         upload_metadata_map: &mut HashMap<ParquetTypeEnum, TransactionMetadata>,
     ) -> Result<(), ProcessorError> {
+        let policy = self.rolling_policy(&parquet_type);
+        let started_at = self.started_at;
         // Get or initialize the buffer for the specific ParquetTypeEnum
         let buffer = self
             .internal_buffers
@@ -123,34 +172,42 @@ impl ParquetBufferStep {
                     "Initializing buffer for ParquetTypeEnum: {:?}",
                     parquet_type,
                 );
-                ParquetBuffer::new(&parquet_type)
+                ParquetBuffer::new(&parquet_type, started_at)
             });
 
         let curr_batch_size_bytes = parquet_data.calculate_size();
+        let curr_batch_num_rows = parquet_data.num_rows();
 
         debug!(
             "Current batch size for {:?}: {} bytes, buffer size before append: {} bytes",
             parquet_type, curr_batch_size_bytes, buffer.buffer_size_bytes,
         );
 
-        // If the current buffer size + new batch exceeds max size, upload the buffer
-        if buffer.buffer_size_bytes + curr_batch_size_bytes > self.buffer_max_size {
-            println!(
-                "Buffer size {} + batch size {} exceeds max size {}. Uploading buffer for {:?}.",
-                buffer.buffer_size_bytes, curr_batch_size_bytes, self.buffer_max_size, parquet_type
+        // If the current buffer + new batch exceeds the max size or rows, upload the buffer
+        let exceeds_max_rows = policy
+            .max_rows
+            .is_some_and(|max_rows| buffer.num_rows + curr_batch_num_rows > max_rows);
+        if buffer.buffer_size_bytes > 0
+            && (buffer.buffer_size_bytes + curr_batch_size_bytes > policy.max_bytes
+                || exceeds_max_rows)
+        {
+            info!(
+                "Buffer of {} bytes, {} rows + batch of {} bytes, {} rows exceeds {:?}. Uploading buffer for {:?}.",
+                buffer.buffer_size_bytes,
+                buffer.num_rows,
+                curr_batch_size_bytes,
+                curr_batch_num_rows,
+                policy,
+                parquet_type
             );
 
             // Take the current buffer to upload and reset the buffer in place
-            let struct_buffer = std::mem::replace(
-                &mut buffer.buffer,
-                ParquetTypeStructs::default_for_type(&parquet_type),
-            );
+            let struct_buffer = buffer.take(&parquet_type, Instant::now());
             self.buffer_uploader.upload_buffer(struct_buffer).await?;
 
             // update this metadata before insert
             upload_metadata_map
                 .insert(parquet_type, buffer.current_batch_metadata.clone().unwrap());
-            buffer.buffer_size_bytes = 0;
             buffer.current_batch_metadata = None;
         }
 
@@ -209,15 +266,13 @@ impl Processable for ParquetBufferStep {
         debug!("Starting cleanup: uploading all remaining buffers.");
         for (parquet_type, mut buffer) in self.internal_buffers.drain() {
             if buffer.buffer_size_bytes > 0 {
-                let struct_buffer = std::mem::replace(
-                    &mut buffer.buffer,
-                    ParquetTypeStructs::default_for_type(&parquet_type),
-                );
+                let buffer_size_bytes = buffer.buffer_size_bytes;
+                let struct_buffer = buffer.take(&parquet_type, Instant::now());
 
                 self.buffer_uploader.upload_buffer(struct_buffer).await?;
 
                 if let Some(buffer_metadata) = &mut buffer.current_batch_metadata {
-                    buffer_metadata.total_size_in_bytes = buffer.buffer_size_bytes as u64;
+                    buffer_metadata.total_size_in_bytes = buffer_size_bytes as u64;
                     metadata_map.insert(parquet_type, buffer_metadata.clone());
                 } else {
                     // This should never happen
@@ -247,7 +302,7 @@ impl PollableAsyncStep for ParquetBufferStep {
         self.poll_interval
     }
 
-    /// Polls all buffers to check if any should be uploaded based on the current size.
+    /// Polls all buffers to check if any should be uploaded based on their max age.
     /// Uploads data and clears the buffer if necessary, and returns upload metadata.
     async fn poll(
         &mut self,
@@ -255,19 +310,23 @@ impl PollableAsyncStep for ParquetBufferStep {
         let mut metadata_map = HashMap::new();
         debug!("Polling to check if any buffers need uploading.");
 
-        for (parquet_type, mut buffer) in self.internal_buffers.drain() {
-            if buffer.buffer_size_bytes > 0 {
-                let struct_buffer = std::mem::replace(
-                    &mut buffer.buffer,
-                    ParquetTypeStructs::default_for_type(&parquet_type),
-                );
+        let now = Instant::now();
+        let max_ages: HashMap<ParquetTypeEnum, Duration> = self
+            .internal_buffers
+            .keys()
+            .map(|parquet_type| (*parquet_type, self.rolling_policy(parquet_type).max_age))
+            .collect();
+        for (parquet_type, buffer) in self.internal_buffers.iter_mut() {
+            if buffer.buffer_size_bytes > 0
+                && now.duration_since(buffer.last_upload_time) >= max_ages[parquet_type]
+            {
+                let struct_buffer = buffer.take(parquet_type, now);
 
                 self.buffer_uploader.upload_buffer(struct_buffer).await?;
 
                 let metadata = buffer.current_batch_metadata.clone().unwrap();
-                metadata_map.insert(parquet_type, metadata);
+                metadata_map.insert(*parquet_type, metadata);
 
-                buffer.buffer_size_bytes = 0;
                 buffer.current_batch_metadata = None;
             }
         }
@@ -282,6 +341,14 @@ impl PollableAsyncStep for ParquetBufferStep {
     }
 }
 
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 impl NamedStep for ParquetBufferStep {
     fn name(&self) -> String {
         "ParquetBufferStep".to_string()
@@ -293,29 +360,44 @@ mod tests {
     use crate::{
         config::db_config::ParquetConfig,
         steps::common::{
-            gcs_uploader::{create_new_writer, GCSUploader},
-            parquet_buffer_step::{ParquetBufferStep, ParquetTypeEnum, ParquetTypeStructs},
+            parquet_buffer_step::{
+                ParquetBufferStep, ParquetTypeEnum, ParquetTypeStructs, RollingPolicy,
+            },
+            parquet_uploader::{create_new_writer, ParquetUploader, WriterSettings},
         },
     };
     use aptos_indexer_processor_sdk::{
-        traits::Processable,
+        traits::{PollableAsyncStep, Processable},
         types::transaction_context::{TransactionContext, TransactionMetadata},
     };
-    use google_cloud_storage::client::{Client as GCSClient, ClientConfig as GcsClientConfig};
-    use parquet::schema::types::Type;
+    use parquet::{
+        basic::Compression,
+        file::reader::{FileReader, SerializedFileReader},
+        schema::types::Type,
+    };
     use processor::{
-        bq_analytics::generic_parquet_processor::HasParquetSchema,
+        bq_analytics::{
+            generic_parquet_processor::HasParquetSchema,
+            object_store::{LocalStoreConfig, ObjectStore, ObjectStoreConfig},
+            schema_versions::SchemaChangePolicy,
+        },
         db::parquet::models::default_models::parquet_move_resources::MoveResource,
     };
-    use std::{collections::HashMap, sync::Arc, time::Duration};
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
 
     #[tokio::test]
     #[allow(clippy::needless_return)]
     async fn test_parquet_buffer_step_no_upload() -> anyhow::Result<()> {
         let db_config = create_parquet_db_config();
-        let buffer_uploader = create_parquet_uploader(&db_config).await?;
+        let buffer_uploader =
+            create_parquet_uploader(&db_config, WriterSettings::default()).await?;
         let mut parquet_step =
-            ParquetBufferStep::new(Duration::from_secs(10), buffer_uploader, 100);
+            ParquetBufferStep::new(buffer_uploader, rolling_policy(100, None), HashMap::new());
 
         let data = HashMap::from([(
             ParquetTypeEnum::MoveResources,
@@ -342,9 +424,13 @@ mod tests {
         let buffer_max_size = 25; // Default ParquetTypeStructs for MoveResource is 24 bytes
         let db_config = create_parquet_db_config();
 
-        let buffer_uploader = create_parquet_uploader(&db_config).await?;
-        let mut parquet_step =
-            ParquetBufferStep::new(Duration::from_secs(10), buffer_uploader, buffer_max_size);
+        let buffer_uploader =
+            create_parquet_uploader(&db_config, WriterSettings::default()).await?;
+        let mut parquet_step = ParquetBufferStep::new(
+            buffer_uploader,
+            rolling_policy(buffer_max_size, None),
+            HashMap::new(),
+        );
 
         // Test data below `buffer_max_size`
         let data = HashMap::from([(
@@ -382,12 +468,197 @@ mod tests {
         Ok(())
     }
 
-    async fn create_parquet_uploader(db_config: &ParquetConfig) -> anyhow::Result<GCSUploader> {
-        let gcs_config = GcsClientConfig::default()
-            .with_auth()
+    #[tokio::test]
+    #[allow(clippy::needless_return)]
+    async fn test_parquet_buffer_step_table_max_rows() -> anyhow::Result<()> {
+        let db_config = create_parquet_db_config();
+        let buffer_uploader =
+            create_parquet_uploader(&db_config, WriterSettings::default()).await?;
+        let mut parquet_step = ParquetBufferStep::new(
+            buffer_uploader,
+            rolling_policy(usize::MAX, None),
+            HashMap::from([(
+                ParquetTypeEnum::MoveResources,
+                rolling_policy(usize::MAX, Some(2)),
+            )]),
+        );
+        let move_resources = |num_rows| {
+            HashMap::from([(
+                ParquetTypeEnum::MoveResources,
+                ParquetTypeStructs::MoveResource(vec![MoveResource::default(); num_rows]),
+            )])
+        };
+
+        let result = parquet_step
+            .process(TransactionContext {
+                data: move_resources(2),
+                metadata: TransactionMetadata::default(),
+            })
+            .await
+            .unwrap();
+        assert!(
+            result.is_none(),
+            "Expected no upload for rows up to max_rows"
+        );
+
+        let result = parquet_step
+            .process(TransactionContext {
+                data: move_resources(1),
+                metadata: TransactionMetadata::default(),
+            })
             .await
-            .expect("Failed to create GCS client config");
-        let gcs_client = Arc::new(GCSClient::new(gcs_config));
+            .unwrap();
+        assert!(
+            result.is_some(),
+            "Expected upload when rows exceed the table's max_rows"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parquet_buffer_step_poll_max_age() -> anyhow::Result<()> {
+        let db_config = create_parquet_db_config();
+        let buffer_uploader =
+            create_parquet_uploader(&db_config, WriterSettings::default()).await?;
+        let parquet_step = ParquetBufferStep::new(
+            buffer_uploader,
+            rolling_policy(usize::MAX, None),
+            HashMap::from([(ParquetTypeEnum::MoveResources, RollingPolicy {
+                max_age: Duration::from_secs(4),
+                ..rolling_policy(usize::MAX, None)
+            })]),
+        );
+        // Polled often enough for every max age
+        assert_eq!(parquet_step.poll_interval(), Duration::from_secs(2));
+
+        for (max_age, expect_upload) in [(Duration::from_secs(10), false), (Duration::ZERO, true)] {
+            let buffer_uploader =
+                create_parquet_uploader(&db_config, WriterSettings::default()).await?;
+            let mut parquet_step = ParquetBufferStep::new(
+                buffer_uploader,
+                RollingPolicy {
+                    max_age,
+                    ..rolling_policy(usize::MAX, None)
+                },
+                HashMap::new(),
+            );
+            let result = parquet_step
+                .process(TransactionContext {
+                    data: HashMap::from([(
+                        ParquetTypeEnum::MoveResources,
+                        ParquetTypeStructs::MoveResource(vec![MoveResource::default()]),
+                    )]),
+                    metadata: TransactionMetadata::default(),
+                })
+                .await
+                .unwrap();
+            assert!(result.is_none(), "Expected no upload below the size limits");
+
+            let result = parquet_step.poll().await.unwrap();
+            assert_eq!(
+                result.is_some(),
+                expect_upload,
+                "Expected an upload on poll only once the buffer is max_age old"
+            );
+            if let Some(result) = result {
+                assert!(result[0].data.contains_key(&ParquetTypeEnum::MoveResources));
+                assert!(
+                    parquet_step.poll().await.unwrap().is_none(),
+                    "Expected the uploaded buffer to be empty"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parquet_buffer_step_writer_settings() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "parquet_buffer_step_writer_settings_{}",
+            std::process::id()
+        ));
+        let db_config = ParquetConfig {
+            object_store: ObjectStoreConfig::Local(LocalStoreConfig { path: path.clone() }),
+            ..create_parquet_db_config()
+        };
+        let buffer_uploader = create_parquet_uploader(&db_config, WriterSettings {
+            compression: Compression::SNAPPY,
+            row_group_size: Some(2),
+        })
+        .await?;
+        let mut parquet_step = ParquetBufferStep::new(
+            buffer_uploader,
+            rolling_policy(usize::MAX, Some(3)),
+            HashMap::new(),
+        );
+
+        // The second batch goes over max_rows, so the 3 rows of the first are uploaded
+        for num_rows in [3, 1] {
+            parquet_step
+                .process(TransactionContext {
+                    data: HashMap::from([(
+                        ParquetTypeEnum::MoveResources,
+                        ParquetTypeStructs::MoveResource(vec![MoveResource::default(); num_rows]),
+                    )]),
+                    metadata: TransactionMetadata::default(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let files = parquet_files(&path);
+        assert_eq!(files.len(), 1);
+        let reader = SerializedFileReader::new(std::fs::File::open(&files[0])?)?;
+        let row_groups = reader.metadata().row_groups();
+        assert_eq!(
+            row_groups
+                .iter()
+                .map(|row_group| row_group.num_rows())
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert!(row_groups
+            .iter()
+            .flat_map(|row_group| row_group.columns())
+            .all(|column| column.compression() == Compression::SNAPPY));
+
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    /// Parquet files under `dir`, at any depth
+    fn parquet_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(parquet_files(&path));
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "parquet")
+            {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    fn rolling_policy(max_bytes: usize, max_rows: Option<usize>) -> RollingPolicy {
+        RollingPolicy {
+            max_bytes,
+            max_rows,
+            max_age: Duration::from_secs(10),
+        }
+    }
+
+    async fn create_parquet_uploader(
+        db_config: &ParquetConfig,
+        settings: WriterSettings,
+    ) -> anyhow::Result<ParquetUploader> {
+        let object_store =
+            ObjectStore::new(&db_config.object_store, &db_config.bucket_name).await?;
 
         let parquet_type_to_schemas: HashMap<ParquetTypeEnum, Arc<Type>> =
             [(ParquetTypeEnum::MoveResources, MoveResource::schema())]
@@ -397,16 +668,17 @@ mod tests {
         let parquet_type_to_writer = parquet_type_to_schemas
             .iter()
             .map(|(key, schema)| {
-                let writer = create_new_writer(schema.clone()).expect("Failed to create writer");
+                let writer = create_new_writer(schema.clone(), settings.compression)
+                    .expect("Failed to create writer");
                 (*key, writer)
             })
             .collect();
 
-        GCSUploader::new(
-            gcs_client,
+        ParquetUploader::new(
+            object_store,
             parquet_type_to_schemas,
             parquet_type_to_writer,
-            db_config.bucket_name.clone(),
+            HashMap::from([(ParquetTypeEnum::MoveResources, settings)]),
             db_config.bucket_root.clone(),
             "processor_name".to_string(),
         )
@@ -419,6 +691,11 @@ mod tests {
             bucket_name: "bucket_name".to_string(),
             bucket_root: "bucket_root".to_string(),
             google_application_credentials: None,
+            // Uploads stay on disk, so the tests don't need GCP credentials
+            object_store: ObjectStoreConfig::Local(LocalStoreConfig {
+                path: std::env::temp_dir().join("parquet_buffer_step_test"),
+            }),
+            schema_change_policy: SchemaChangePolicy::Fail,
         }
     }
 }
@@ -2,26 +2,32 @@ use crate::parquet_processors::{ParquetTypeEnum, ParquetTypeStructs, ParquetType
 use anyhow::Context;
 use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
 use async_trait::async_trait;
-use google_cloud_storage::client::Client as GCSClient;
 use parquet::{
+    basic::Compression,
     file::{properties::WriterProperties, writer::SerializedFileWriter},
     record::RecordWriter,
     schema::types::Type,
 };
 use processor::bq_analytics::{
-    gcs_handler::upload_parquet_to_gcs,
+    gcs_handler::upload_parquet,
     generic_parquet_processor::{GetTimeStamp, HasParquetSchema, HasVersion},
+    manifest::ManifestWriter,
+    object_store::ObjectStore,
+    schema_versions::{resolve_table_path, SchemaChangePolicy},
 };
 use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tracing::{debug, error};
 
-pub struct GCSUploader {
-    gcs_client: Arc<GCSClient>,
+pub struct ParquetUploader {
+    object_store: Arc<ObjectStore>,
     parquet_type_to_schemas: HashMap<ParquetTypeEnum, Arc<Type>>,
     parquet_type_to_writer: HashMap<ParquetTypeEnum, SerializedFileWriter<Vec<u8>>>,
-    pub bucket_name: String,
+    parquet_type_to_settings: HashMap<ParquetTypeEnum, WriterSettings>,
     pub bucket_root: String,
     pub processor_name: String,
+    manifest_writer: ManifestWriter,
+    // Paths of the tables' files under bucket_root, versioned on incompatible schema changes
+    table_paths: HashMap<ParquetTypeEnum, String>,
 }
 
 #[async_trait]
@@ -33,15 +39,19 @@ pub trait Uploadable {
 }
 
 #[async_trait]
-impl Uploadable for GCSUploader {
+impl Uploadable for ParquetUploader {
     async fn upload_buffer(
         &mut self,
         buffer: ParquetTypeStructs,
     ) -> anyhow::Result<(), ProcessorError> {
         let parquet_type = buffer.parquet_type();
-        let table_name = parquet_type.to_string();
+        let table_name = self
+            .table_paths
+            .get(&parquet_type)
+            .cloned()
+            .unwrap_or_else(|| parquet_type.to_string());
 
-        let result = buffer.upload_to_gcs(self, parquet_type, &table_name).await;
+        let result = buffer.upload(self, parquet_type, &table_name).await;
         if let Err(e) = result {
             error!("Failed to upload buffer: {}", e);
             return Err(ProcessorError::ProcessError {
@@ -52,34 +62,81 @@ impl Uploadable for GCSUploader {
     }
 }
 
-pub fn create_new_writer(schema: Arc<Type>) -> anyhow::Result<SerializedFileWriter<Vec<u8>>> {
+/// How the files of a table are written.
+#[derive(Clone, Copy, Debug)]
+pub struct WriterSettings {
+    pub compression: Compression,
+    // Max rows per row group, all rows of a file are written in one unless set
+    pub row_group_size: Option<usize>,
+}
+
+impl Default for WriterSettings {
+    fn default() -> Self {
+        Self {
+            compression: Compression::LZ4,
+            row_group_size: None,
+        }
+    }
+}
+
+pub fn create_new_writer(
+    schema: Arc<Type>,
+    compression: Compression,
+) -> anyhow::Result<SerializedFileWriter<Vec<u8>>> {
     let props = WriterProperties::builder()
-        .set_compression(parquet::basic::Compression::LZ4)
+        .set_compression(compression)
         .build();
     let props_arc = Arc::new(props);
 
     SerializedFileWriter::new(Vec::new(), schema, props_arc).context("Failed to create new writer")
 }
 
-impl GCSUploader {
+impl ParquetUploader {
     pub fn new(
-        gcs_client: Arc<GCSClient>,
+        object_store: Arc<ObjectStore>,
         parquet_type_to_schemas: HashMap<ParquetTypeEnum, Arc<Type>>,
         parquet_type_to_writer: HashMap<ParquetTypeEnum, SerializedFileWriter<Vec<u8>>>,
-        bucket_name: String,
+        parquet_type_to_settings: HashMap<ParquetTypeEnum, WriterSettings>,
         bucket_root: String,
         processor_name: String,
     ) -> anyhow::Result<Self> {
         Ok(Self {
-            gcs_client,
+            manifest_writer: ManifestWriter::new(&PathBuf::from(&bucket_root)),
+            table_paths: HashMap::new(),
+            object_store,
             parquet_type_to_schemas,
             parquet_type_to_writer,
-            bucket_name,
+            parquet_type_to_settings,
             bucket_root,
             processor_name,
         })
     }
 
+    /// Checks the schema of every table against the last one written, failing on incompatible
+    /// changes unless `policy` is to write a new version of the table.
+    pub async fn resolve_table_paths(&mut self, policy: SchemaChangePolicy) -> anyhow::Result<()> {
+        let bucket_root = PathBuf::from(&self.bucket_root);
+        for (parquet_type, schema) in self.parquet_type_to_schemas.iter() {
+            let table_path = resolve_table_path(
+                &self.object_store,
+                &bucket_root,
+                &parquet_type.to_string(),
+                schema,
+                policy,
+            )
+            .await?;
+            self.table_paths.insert(*parquet_type, table_path);
+        }
+        Ok(())
+    }
+
+    fn settings(&self, parquet_type: ParquetTypeEnum) -> WriterSettings {
+        self.parquet_type_to_settings
+            .get(&parquet_type)
+            .copied()
+            .unwrap_or_default()
+    }
+
     fn create_new_writer(
         &self,
         parquet_type: ParquetTypeEnum,
@@ -90,7 +147,7 @@ impl GCSUploader {
             .context("Parquet type not found in schemas")?
             .clone();
 
-        create_new_writer(schema)
+        create_new_writer(schema, self.settings(parquet_type).compression)
     }
 
     /// # Context: Why we replace our writer
@@ -127,23 +184,28 @@ impl GCSUploader {
         for<'a> &'a [ParquetType]: RecordWriter<ParquetType>,
     {
         if data.is_empty() {
-            println!("Buffer is empty, skipping upload.");
+            debug!("Buffer is empty, skipping upload.");
             return Ok(());
         }
 
+        let row_group_size = self.settings(parquet_type).row_group_size;
         let writer = self
             .parquet_type_to_writer
             .get_mut(&parquet_type)
             .context("Writer not found for specified parquet type")?;
 
-        let mut row_group_writer = writer.next_row_group().context("Failed to get row group")?;
+        for row_group in data.chunks(row_group_size.unwrap_or(data.len())) {
+            let mut row_group_writer =
+                writer.next_row_group().context("Failed to get row group")?;
 
-        data.write_to_row_group(&mut row_group_writer)
-            .context("Failed to write to row group")?;
+            row_group
+                .write_to_row_group(&mut row_group_writer)
+                .context("Failed to write to row group")?;
 
-        row_group_writer
-            .close()
-            .context("Failed to close row group")?;
+            row_group_writer
+                .close()
+                .context("Failed to close row group")?;
+        }
 
         let old_writer = self
             .get_and_replace_writer(parquet_type)
@@ -153,21 +215,36 @@ impl GCSUploader {
             .context("Failed to get inner buffer")?;
 
         let bucket_root = PathBuf::from(&self.bucket_root);
-        upload_parquet_to_gcs(
-            &self.gcs_client,
+        let file_name = upload_parquet(
+            &self.object_store,
             upload_buffer,
             table_name,
-            &self.bucket_name,
             &bucket_root,
             self.processor_name.clone(),
         )
         .await?;
+        let schema = self
+            .parquet_type_to_schemas
+            .get(&parquet_type)
+            .context("Parquet type not found in schemas")?;
+        // Batches can be buffered out of order, so the first and last rows aren't the bounds
+        let start_version = data.iter().map(|item| item.version()).min().unwrap();
+        let end_version = data.iter().map(|item| item.version()).max().unwrap();
+        self.manifest_writer
+            .commit(
+                &self.object_store,
+                table_name,
+                file_name,
+                start_version,
+                end_version,
+                data.len(),
+                schema,
+            )
+            .await?;
 
         debug!(
-            "Uploaded parquet to GCS for table: {}, start_version: {}, end_version: {}",
-            table_name,
-            data[0].version(),
-            data[data.len() - 1].version()
+            "Uploaded parquet for table: {}, start_version: {}, end_version: {}",
+            table_name, start_version, end_version
         );
 
         Ok(())
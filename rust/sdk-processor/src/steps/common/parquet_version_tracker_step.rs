@@ -8,7 +8,7 @@ use aptos_indexer_processor_sdk::{
     utils::errors::ProcessorError,
 };
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, hash::Hash};
 use tracing::debug;
 
 /// The ParquetProcessorStatusSaver trait object is intended to save
@@ -24,25 +24,27 @@ pub trait ParquetProcessorStatusSaver {
 }
 
 /// Tracks the versioned processing of sequential transactions, ensuring no gaps
-/// occur between them.
+/// occur between them. Progress is tracked per table, keyed by `K`.
 ///
 /// Important: this step assumes ordered transactions. Please use the `OrederByVersionStep` before this step
 /// if the transactions are not ordered.
-pub struct ParquetVersionTrackerStep<S>
+pub struct ParquetVersionTrackerStep<S, K = ParquetTypeEnum>
 where
     Self: Sized + Send + 'static,
     S: ParquetProcessorStatusSaver + Send + 'static,
+    K: Copy + Display + Eq + Hash + Send + 'static,
 {
     // Last successful batch of sequentially processed transactions. Includes metadata to write to storage.
-    last_success_batch: HashMap<ParquetTypeEnum, TransactionContext<()>>,
+    last_success_batch: HashMap<K, TransactionContext<()>>,
     polling_interval_secs: u64,
     processor_status_saver: S,
 }
 
-impl<S> ParquetVersionTrackerStep<S>
+impl<S, K> ParquetVersionTrackerStep<S, K>
 where
     Self: Sized + Send + 'static,
     S: ParquetProcessorStatusSaver + Send + 'static,
+    K: Copy + Display + Eq + Hash + Send + 'static,
 {
     pub fn new(processor_status_saver: S, polling_interval_secs: u64) -> Self {
         Self {
@@ -64,12 +66,13 @@ where
 }
 
 #[async_trait]
-impl<S> Processable for ParquetVersionTrackerStep<S>
+impl<S, K> Processable for ParquetVersionTrackerStep<S, K>
 where
     Self: Sized + Send + 'static,
     S: ParquetProcessorStatusSaver + Send + 'static,
+    K: Copy + Display + Eq + Hash + Send + 'static,
 {
-    type Input = HashMap<ParquetTypeEnum, TransactionMetadata>;
+    type Input = HashMap<K, TransactionMetadata>;
     type Output = ();
     type RunType = PollableAsyncRunType;
 
@@ -133,10 +136,11 @@ where
 }
 
 #[async_trait]
-impl<S> PollableAsyncStep for ParquetVersionTrackerStep<S>
+impl<S, K> PollableAsyncStep for ParquetVersionTrackerStep<S, K>
 where
     Self: Sized + Send + Sync + 'static,
     S: ParquetProcessorStatusSaver + Send + Sync + 'static,
+    K: Copy + Display + Eq + Hash + Send + Sync + 'static,
 {
     fn poll_interval(&self) -> std::time::Duration {
         std::time::Duration::from_secs(self.polling_interval_secs)
@@ -150,10 +154,11 @@ where
     }
 }
 
-impl<S> NamedStep for ParquetVersionTrackerStep<S>
+impl<S, K> NamedStep for ParquetVersionTrackerStep<S, K>
 where
     Self: Sized + Send + 'static,
     S: ParquetProcessorStatusSaver + Send + 'static,
+    K: Copy + Display + Eq + Hash + Send + 'static,
 {
     fn name(&self) -> String {
         "ParquetVersionTrackerStep".to_string()
@@ -46,7 +46,11 @@ pub fn get_processor_status_saver(
         },
         _ => {
             let processor_name = config.processor_config.name().to_string();
-            if let DbConfig::ParquetConfig(_) = config.db_config {
+            // ClickHouse tables are stored independently, so their progress is tracked per table
+            if matches!(
+                config.db_config,
+                DbConfig::ParquetConfig(_) | DbConfig::ClickhouseConfig(_)
+            ) {
                 ProcessorStatusSaverEnum::Parquet {
                     conn_pool,
                     processor_name,
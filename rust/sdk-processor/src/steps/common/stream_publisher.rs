@@ -0,0 +1,351 @@
+use crate::config::stream_config::StreamConfig;
+#[cfg(feature = "kafka")]
+use anyhow::Context;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use async_trait::async_trait;
+use processor::db::{
+    common::models::market_models::market_activities::Trade,
+    postgres::models::{
+        coin_models::coin_supply::CoinSupply,
+        events_models::events::EventPG,
+        fungible_asset_models::{
+            v2_fungible_asset_activities::FungibleAssetActivity,
+            v2_fungible_asset_balances::{
+                CurrentUnifiedFungibleAssetBalance, FungibleAssetBalance,
+            },
+            v2_fungible_asset_to_coin_mappings::FungibleAssetToCoinMapping,
+            v2_fungible_metadata::FungibleAssetMetadataModel,
+        },
+        user_transactions_models::{
+            signatures::Signature, user_transactions::UserTransactionModel,
+        },
+    },
+};
+#[cfg(feature = "kafka")]
+use rdkafka::{
+    error::{KafkaError, RDKafkaErrorCode},
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig, Message,
+};
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// How long to wait for the producer queue to drain when it is full.
+#[cfg(feature = "kafka")]
+const QUEUE_FULL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// A row serialized as JSON, keyed by the id of the entity it belongs to.
+pub struct StreamMessage {
+    pub table_name: &'static str,
+    pub key: String,
+    pub payload: Vec<u8>,
+}
+
+impl StreamMessage {
+    pub fn new<T: Serialize>(
+        table_name: &'static str,
+        key: String,
+        row: &T,
+    ) -> Result<Self, ProcessorError> {
+        let payload = serde_json::to_vec(row).map_err(|e| ProcessorError::ProcessError {
+            message: format!("Failed to serialize {} row: {}", table_name, e),
+        })?;
+        Ok(Self {
+            table_name,
+            key,
+            payload,
+        })
+    }
+}
+
+/// Output of an extractor step that can be published to a stream.
+pub trait StreamRows: Send + 'static {
+    /// Messages for the rows, in transaction version order.
+    fn stream_messages(&self) -> Result<Vec<StreamMessage>, ProcessorError>;
+}
+
+/// Events are keyed by their event handle, i.e. the creation number and account address.
+impl StreamRows for Vec<EventPG> {
+    fn stream_messages(&self) -> Result<Vec<StreamMessage>, ProcessorError> {
+        self.iter()
+            .map(|event| {
+                let key = format!("{}-{}", event.creation_number, event.account_address);
+                StreamMessage::new("events", key, event)
+            })
+            .collect()
+    }
+}
+
+/// User transactions are keyed by sender, signatures aren't published.
+impl StreamRows for (Vec<UserTransactionModel>, Vec<Signature>) {
+    fn stream_messages(&self) -> Result<Vec<StreamMessage>, ProcessorError> {
+        self.0
+            .iter()
+            .map(|txn| StreamMessage::new("user_transactions", txn.sender.clone(), txn))
+            .collect()
+    }
+}
+
+/// Activities are keyed by the store they change, balances and metadata aren't published.
+impl StreamRows
+    for (
+        Vec<FungibleAssetActivity>,
+        Vec<FungibleAssetMetadataModel>,
+        Vec<FungibleAssetBalance>,
+        (
+            Vec<CurrentUnifiedFungibleAssetBalance>,
+            Vec<CurrentUnifiedFungibleAssetBalance>,
+        ),
+        Vec<CoinSupply>,
+        Vec<FungibleAssetToCoinMapping>,
+    )
+{
+    fn stream_messages(&self) -> Result<Vec<StreamMessage>, ProcessorError> {
+        self.0
+            .iter()
+            .map(|activity| {
+                StreamMessage::new(
+                    "fungible_asset_activities",
+                    activity.storage_id.clone(),
+                    activity,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Mirage trades are keyed by position. Only trades are extracted by the Mirage trades
+/// processor, so position updates aren't published.
+impl StreamRows for Vec<Trade> {
+    fn stream_messages(&self) -> Result<Vec<StreamMessage>, ProcessorError> {
+        self.iter()
+            .map(|trade| StreamMessage::new("trade_datas", trade.position_id.clone(), trade))
+            .collect()
+    }
+}
+
+/// Publishes the rows of each batch to the configured stream and passes the batch on unchanged.
+/// A batch is only passed on once all its messages are acknowledged, so rows are published at
+/// least once, and in version order per key. Without a `StreamConfig` this step does nothing.
+///
+/// Publishing needs the `kafka` feature, without it a `StreamConfig` fails startup.
+pub struct StreamPublisher<T>
+where
+    T: StreamRows,
+{
+    #[cfg(feature = "kafka")]
+    producer: Option<FutureProducer>,
+    #[cfg(feature = "kafka")]
+    topic_prefix: String,
+    _rows: PhantomData<fn(T)>,
+}
+
+impl<T> StreamPublisher<T>
+where
+    T: StreamRows,
+{
+    pub fn new(config: Option<&StreamConfig>) -> Result<Self> {
+        match config {
+            Some(config) => Self::with_producer(config),
+            None => Ok(Self {
+                #[cfg(feature = "kafka")]
+                producer: None,
+                #[cfg(feature = "kafka")]
+                topic_prefix: String::new(),
+                _rows: PhantomData,
+            }),
+        }
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    fn with_producer(_config: &StreamConfig) -> Result<Self> {
+        anyhow::bail!("stream_config requires sdk-processor to be built with the `kafka` feature")
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl<T> StreamPublisher<T>
+where
+    T: StreamRows,
+{
+    fn with_producer(config: &StreamConfig) -> Result<Self> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.bootstrap_servers)
+            .set("message.timeout.ms", config.message_timeout_ms.to_string())
+            // Keeps messages in order within a partition when they are retried
+            .set("enable.idempotence", "true");
+        for (key, value) in config.producer_config.iter() {
+            client_config.set(key, value);
+        }
+        let producer = client_config
+            .create()
+            .context("Failed to create stream producer")?;
+
+        Ok(Self {
+            producer: Some(producer),
+            topic_prefix: config.topic_prefix.clone(),
+            _rows: PhantomData,
+        })
+    }
+
+    fn topic(&self, table_name: &str) -> String {
+        format!("{}.{}", self.topic_prefix, table_name)
+    }
+
+    async fn publish(
+        &self,
+        producer: &FutureProducer,
+        messages: Vec<StreamMessage>,
+    ) -> Result<(), ProcessorError> {
+        let topics: Vec<String> = messages
+            .iter()
+            .map(|message| self.topic(message.table_name))
+            .collect();
+
+        // Messages are enqueued one by one, waiting out a full queue rather than skipping ahead,
+        // so the producer sends them in order.
+        let mut deliveries = Vec::with_capacity(messages.len());
+        for (message, topic) in messages.iter().zip(topics.iter()) {
+            let mut record = FutureRecord::to(topic)
+                .key(&message.key)
+                .payload(&message.payload);
+            loop {
+                match producer.send_result(record) {
+                    Ok(delivery) => {
+                        deliveries.push(delivery);
+                        break;
+                    },
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rec)) => {
+                        record = rec;
+                        tokio::time::sleep(QUEUE_FULL_BACKOFF).await;
+                    },
+                    Err((e, _)) => {
+                        return Err(ProcessorError::ProcessError {
+                            message: format!("Failed to publish to {}: {:?}", topic, e),
+                        });
+                    },
+                }
+            }
+        }
+
+        for delivery in deliveries {
+            match delivery.await {
+                Ok(Ok(_)) => {},
+                Ok(Err((e, message))) => {
+                    return Err(ProcessorError::ProcessError {
+                        message: format!("Failed to publish to {}: {:?}", message.topic(), e),
+                    });
+                },
+                Err(_) => {
+                    return Err(ProcessorError::ProcessError {
+                        message: "Stream producer dropped a message before delivery".to_string(),
+                    });
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T> Processable for StreamPublisher<T>
+where
+    T: StreamRows,
+{
+    type Input = T;
+    type Output = T;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        item: TransactionContext<T>,
+    ) -> Result<Option<TransactionContext<T>>, ProcessorError> {
+        #[cfg(feature = "kafka")]
+        if let Some(producer) = &self.producer {
+            let messages = item.data.stream_messages()?;
+            self.publish(producer, messages).await?;
+        }
+        Ok(Some(item))
+    }
+}
+
+impl<T> AsyncStep for StreamPublisher<T> where T: StreamRows {}
+
+impl<T> NamedStep for StreamPublisher<T>
+where
+    T: StreamRows,
+{
+    fn name(&self) -> String {
+        "StreamPublisher".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_stream_messages() {
+        let events = vec![EventPG {
+            sequence_number: 3,
+            creation_number: 2,
+            account_address: "0x1".to_string(),
+            transaction_version: 100,
+            transaction_block_height: 10,
+            type_: "0x1::coin::DepositEvent".to_string(),
+            data: serde_json::json!({ "amount": "5" }),
+            event_index: 0,
+            indexed_type: "0x1::coin::DepositEvent".to_string(),
+        }];
+        let messages = events.stream_messages().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].table_name, "events");
+        assert_eq!(messages[0].key, "2-0x1");
+        let payload: serde_json::Value = serde_json::from_slice(&messages[0].payload).unwrap();
+        assert_eq!(payload["transaction_version"], 100);
+        assert_eq!(payload["data"]["amount"], "5");
+    }
+
+    #[test]
+    fn test_trade_stream_messages() {
+        let trades = vec![Trade {
+            transaction_version: 100,
+            market_id: "0xmarket".to_string(),
+            position_id: "0xposition".to_string(),
+            owner_addr: "0xowner".to_string(),
+            is_long: true,
+            position_size: 5.into(),
+            price: 7.into(),
+            fee: 1.into(),
+            pnl: 0.into(),
+            event_type: "OpenPositionEvent".to_string(),
+            transaction_timestamp: chrono::NaiveDateTime::default(),
+        }];
+        let messages = trades.stream_messages().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].table_name, "trade_datas");
+        assert_eq!(messages[0].key, "0xposition");
+        let payload: serde_json::Value = serde_json::from_slice(&messages[0].payload).unwrap();
+        assert_eq!(payload["transaction_version"], 100);
+        assert_eq!(payload["event_type"], "OpenPositionEvent");
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    #[test]
+    fn test_stream_config_requires_kafka_feature() {
+        assert!(StreamPublisher::<Vec<EventPG>>::new(None).is_ok());
+        let config = StreamConfig {
+            bootstrap_servers: "localhost:9092".to_string(),
+            topic_prefix: "aptos".to_string(),
+            message_timeout_ms: StreamConfig::default_message_timeout_ms(),
+            producer_config: Default::default(),
+        };
+        assert!(StreamPublisher::<Vec<EventPG>>::new(Some(&config)).is_err());
+    }
+}
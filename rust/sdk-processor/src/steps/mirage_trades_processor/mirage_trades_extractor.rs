@@ -0,0 +1,57 @@
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::Transaction,
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::TransactionContext,
+    utils::errors::ProcessorError,
+};
+use async_trait::async_trait;
+use processor::{
+    db::common::models::market_models::market_activities::Trade,
+    processors::mirage_processor::parse_mirage_protocol, utils::table_flags::TableFlags,
+};
+
+/// Extracts Mirage trades. Trades only depend on the transactions themselves, unlike the vault
+/// state the Postgres processor tracks, so no prior state is needed.
+pub struct MirageTradesExtractor
+where
+    Self: Sized + Send + 'static,
+{
+    deployer_address: String,
+}
+
+impl MirageTradesExtractor {
+    pub fn new(deployer_address: String) -> Self {
+        Self { deployer_address }
+    }
+}
+
+#[async_trait]
+impl Processable for MirageTradesExtractor {
+    type Input = Vec<Transaction>;
+    type Output = Vec<Trade>;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        item: TransactionContext<Vec<Transaction>>,
+    ) -> Result<Option<TransactionContext<Vec<Trade>>>, ProcessorError> {
+        let (_, _, _, _, _, _, _, _, _, _, _, _, trades, _, _, _, _, _) = parse_mirage_protocol(
+            &item.data,
+            &self.deployer_address,
+            TableFlags::all() - TableFlags::TRADE_DATAS,
+        )
+        .await;
+        Ok(Some(TransactionContext {
+            data: trades,
+            metadata: item.metadata,
+        }))
+    }
+}
+
+impl AsyncStep for MirageTradesExtractor {}
+
+impl NamedStep for MirageTradesExtractor {
+    fn name(&self) -> String {
+        "MirageTradesExtractor".to_string()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod mirage_trades_extractor;
+
+pub use mirage_trades_extractor::MirageTradesExtractor;
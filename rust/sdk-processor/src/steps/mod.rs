@@ -5,6 +5,7 @@ pub mod common;
 pub mod default_processor;
 pub mod events_processor;
 pub mod fungible_asset_processor;
+pub mod mirage_trades_processor;
 pub mod objects_processor;
 pub mod stake_processor;
 pub mod token_v2_processor;
@@ -0,0 +1,46 @@
+use crate::config::db_config::ClickhouseConfig;
+use anyhow::{Context, Result};
+use clickhouse_rs::{Block, Options, Pool};
+use std::str::FromStr;
+
+/// Talks to ClickHouse over its native protocol. Each insert sends one block, so batching is up
+/// to the caller.
+pub struct ClickhouseClient {
+    pool: Pool,
+}
+
+impl ClickhouseClient {
+    pub fn new(config: &ClickhouseConfig) -> Result<Self> {
+        let mut options = Options::from_str(config.clickhouse_url.as_str())
+            .context("Invalid ClickHouse url, expected e.g. tcp://localhost:9000")?
+            .database(&config.clickhouse_database);
+        if let Some(user) = &config.clickhouse_user {
+            options = options.username(user);
+        }
+        if let Some(password) = &config.clickhouse_password {
+            options = options.password(password);
+        }
+        Ok(Self {
+            pool: Pool::new(options),
+        })
+    }
+
+    /// Runs a statement that returns no rows, e.g. DDL, in the configured database.
+    pub async fn execute(&self, statement: &str) -> Result<()> {
+        let mut handle = self.pool.get_handle().await?;
+        handle
+            .execute(statement)
+            .await
+            .with_context(|| format!("Failed to execute ClickHouse statement {}", statement))
+    }
+
+    /// Inserts `block` into `table_name` in a single insert. The columns of the block have to be
+    /// of the types of the table's columns, the native protocol doesn't convert them.
+    pub async fn insert(&self, table_name: &str, block: Block) -> Result<()> {
+        let mut handle = self.pool.get_handle().await?;
+        handle
+            .insert(table_name, block)
+            .await
+            .with_context(|| format!("Failed to insert into ClickHouse table {}", table_name))
+    }
+}
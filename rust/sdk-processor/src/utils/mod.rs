@@ -1,4 +1,5 @@
 pub mod chain_id;
+pub mod clickhouse;
 pub mod database;
 pub mod parquet_extractor_helper;
 pub mod parquet_processor_table_mapping;
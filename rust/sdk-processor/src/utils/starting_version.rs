@@ -314,6 +314,7 @@ mod tests {
             backfill_config,
             bootstrap_config,
             testing_config,
+            stream_config: None,
             mode,
         }
     }
@@ -17,6 +17,8 @@ use tracing::error;
 use tracing_subscriber::EnvFilter;
 use warp::{http::Response, Filter};
 
+pub mod status;
+
 /// ServerArgs bootstraps a server with all common pieces. And then triggers the run method for
 /// the specific service.
 #[derive(Parser)]
@@ -45,9 +47,10 @@ where
     C: RunnableConfig,
 {
     let health_port = config.health_check_port;
+    let readiness_max_lag_secs = config.readiness_max_lag_secs;
     // Start liveness and readiness probes.
     let task_handler = handle.spawn(async move {
-        register_probes_and_metrics_handler(health_port).await;
+        register_probes_and_metrics_handler(health_port, readiness_max_lag_secs).await;
         anyhow::Ok(())
     });
     let main_task_handler = handle.spawn(async move { config.run().await });
@@ -65,6 +68,9 @@ where
 pub struct GenericConfig<T> {
     // Shared configuration among all services.
     pub health_check_port: u16,
+    // Readiness fails while a processor's last transaction is more than this many seconds old
+    #[serde(default)]
+    pub readiness_max_lag_secs: Option<u64>,
 
     // Specific configuration for each service.
     pub server_config: T,
@@ -154,10 +160,35 @@ pub fn setup_logging() {
         .init();
 }
 
-/// Register readiness and liveness probes and set up metrics endpoint.
-async fn register_probes_and_metrics_handler(port: u16) {
-    let readiness = warp::path("readiness")
-        .map(move || warp::reply::with_status("ready", warp::http::StatusCode::OK));
+/// Register readiness and liveness probes and set up metrics and status endpoints.
+async fn register_probes_and_metrics_handler(port: u16, readiness_max_lag_secs: Option<u64>) {
+    let readiness = warp::path("readiness").map(move || {
+        let Some(max_lag_secs) = readiness_max_lag_secs else {
+            return warp::reply::with_status("ready".to_string(), warp::http::StatusCode::OK);
+        };
+        let statuses = status::processor_statuses();
+        let lagging = status::lagging_processors(&statuses, max_lag_secs);
+        if lagging.is_empty() {
+            return warp::reply::with_status("ready".to_string(), warp::http::StatusCode::OK);
+        }
+        let lagging = lagging
+            .iter()
+            .map(|processor| {
+                format!(
+                    "{} is {}s behind",
+                    processor.processor_name,
+                    processor.lag_secs.unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        warp::reply::with_status(
+            format!("not ready: {}", lagging),
+            warp::http::StatusCode::SERVICE_UNAVAILABLE,
+        )
+    });
+    let status_endpoint =
+        warp::path("status").map(|| warp::reply::json(&status::processor_statuses()));
     let metrics_endpoint = warp::path("metrics").map(|| {
         // Metrics encoding.
         let metrics = prometheus::gather();
@@ -201,11 +232,16 @@ async fn register_probes_and_metrics_handler(port: u16) {
             })
         });
         #[cfg(target_os = "linux")]
-        warp::serve(readiness.or(metrics_endpoint).or(profilez))
-            .run(([0, 0, 0, 0], port))
-            .await;
+        warp::serve(
+            readiness
+                .or(metrics_endpoint)
+                .or(status_endpoint)
+                .or(profilez),
+        )
+        .run(([0, 0, 0, 0], port))
+        .await;
     } else {
-        warp::serve(readiness.or(metrics_endpoint))
+        warp::serve(readiness.or(metrics_endpoint).or(status_endpoint))
             .run(([0, 0, 0, 0], port))
             .await;
     }
@@ -253,6 +289,7 @@ mod tests {
 
         let config = load::<GenericConfig<TestConfig>>(&file_path).unwrap();
         assert_eq!(config.health_check_port, 12345);
+        assert_eq!(config.readiness_max_lag_secs, None);
         assert_eq!(config.server_config.test, 123);
         assert_eq!(config.server_config.test_name, "test");
     }
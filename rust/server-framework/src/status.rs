@@ -0,0 +1,141 @@
+// Copyright © Aptos Foundation
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Progress of each processor running in this process, by processor name.
+static PROCESSOR_PROGRESS: Mutex<BTreeMap<String, ProcessorProgress>> = Mutex::new(BTreeMap::new());
+
+/// Progress a processor reports about itself, served on the `/status` endpoint.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ProcessorProgress {
+    // Latest version processed with no gaps before it
+    pub last_processed_version: Option<u64>,
+    pub last_transaction_timestamp: Option<NaiveDateTime>,
+    // Latest version received from the transaction stream
+    pub stream_head_version: Option<u64>,
+    // Batches processed ahead of a gap, waiting for it to be filled
+    pub gap_detector_backlog: u64,
+    // Number of batches being processed concurrently
+    pub batch_concurrency: u64,
+}
+
+impl ProcessorProgress {
+    /// How many versions the processor is behind the stream head, once both are known. The
+    /// stream head is only as far as the processor has fetched, so this is the processing
+    /// backlog, not how far behind the chain the processor is.
+    pub fn lag_versions(&self) -> Option<u64> {
+        Some(
+            self.stream_head_version?
+                .saturating_sub(self.last_processed_version?),
+        )
+    }
+
+    /// How many seconds the last processed transaction is older than `now`, once known. This is
+    /// how far behind the chain the processor is, e.g. during a backfill.
+    pub fn lag_secs(&self, now: NaiveDateTime) -> Option<u64> {
+        let lag = now - self.last_transaction_timestamp?;
+        Some(lag.num_seconds().max(0) as u64)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessorStatus {
+    pub processor_name: String,
+    #[serde(flatten)]
+    pub progress: ProcessorProgress,
+    pub lag_versions: Option<u64>,
+    pub lag_secs: Option<u64>,
+}
+
+/// Updates the progress reported for `processor_name`, registering it if needed.
+pub fn update_processor_progress(
+    processor_name: &str,
+    update: impl FnOnce(&mut ProcessorProgress),
+) {
+    let mut progress = PROCESSOR_PROGRESS.lock().unwrap();
+    update(progress.entry(processor_name.to_string()).or_default());
+}
+
+/// Status of every processor that has reported progress, ordered by name.
+pub fn processor_statuses() -> Vec<ProcessorStatus> {
+    statuses(
+        &PROCESSOR_PROGRESS.lock().unwrap(),
+        chrono::Utc::now().naive_utc(),
+    )
+}
+
+fn statuses(
+    progress: &BTreeMap<String, ProcessorProgress>,
+    now: NaiveDateTime,
+) -> Vec<ProcessorStatus> {
+    progress
+        .iter()
+        .map(|(processor_name, progress)| ProcessorStatus {
+            processor_name: processor_name.clone(),
+            progress: progress.clone(),
+            lag_versions: progress.lag_versions(),
+            lag_secs: progress.lag_secs(now),
+        })
+        .collect()
+}
+
+/// Processors whose last processed transaction is more than `max_lag_secs` old. Processors
+/// whose lag isn't known yet, e.g. because they are still starting up, aren't considered lagging.
+pub fn lagging_processors(
+    statuses: &[ProcessorStatus],
+    max_lag_secs: u64,
+) -> Vec<&ProcessorStatus> {
+    statuses
+        .iter()
+        .filter(|status| status.lag_secs.is_some_and(|lag| lag > max_lag_secs))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_lagging_processors() {
+        let now =
+            NaiveDateTime::parse_from_str("2025-03-14 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let caught_up = ProcessorProgress {
+            last_processed_version: Some(95),
+            last_transaction_timestamp: Some(now - Duration::seconds(2)),
+            stream_head_version: Some(100),
+            ..Default::default()
+        };
+        // Processed everything fetched, but the transactions are an hour old
+        let backfilling = ProcessorProgress {
+            last_processed_version: Some(100),
+            last_transaction_timestamp: Some(now - Duration::hours(1)),
+            stream_head_version: Some(100),
+            ..Default::default()
+        };
+        let starting = ProcessorProgress {
+            stream_head_version: Some(100),
+            ..Default::default()
+        };
+        let progress = BTreeMap::from([
+            ("backfilling".to_string(), backfilling),
+            ("caught_up".to_string(), caught_up),
+            ("starting".to_string(), starting),
+        ]);
+
+        let statuses = statuses(&progress, now);
+        assert_eq!(statuses[0].lag_versions, Some(0));
+        assert_eq!(statuses[0].lag_secs, Some(3600));
+        assert_eq!(statuses[1].lag_versions, Some(5));
+        assert_eq!(statuses[1].lag_secs, Some(2));
+        assert_eq!(statuses[2].lag_versions, None);
+        assert_eq!(statuses[2].lag_secs, None);
+
+        let lagging = lagging_processors(&statuses, 60);
+        assert_eq!(lagging.len(), 1);
+        assert_eq!(lagging[0].processor_name, "backfilling");
+        assert!(lagging_processors(&statuses, 3600).is_empty());
+    }
+}